@@ -17,17 +17,26 @@
 //
 
 use crate::base::linked_list::LinkedList;
+use crate::base::strings;
+use crate::base::uint256::UINT256_BYTES;
 use crate::codec::row_codec::{Data, RowRecordBatch};
 use crate::error::{DB3Error, Result};
-use crate::proto::db3_base_proto::{Db3SchemaDesc, Db3Type};
+use crate::proto::db3_base_proto::{
+    Db3ColumnDesc, Db3SchemaDesc, Db3Type, ParquetCompression, StorageBackendConfig,
+};
 use arrow::array::{
-    Array, ArrayRef, BooleanBuilder, Date32Builder, Int16Builder, Int32Builder, Int64Builder,
-    Int8Builder, StringBuilder, TimestampMillisecondBuilder, UInt16Builder, UInt32Builder,
-    UInt64Builder, UInt8Builder,
+    Array, ArrayData, ArrayRef, BinaryBuilder, BooleanArray, BooleanBufferBuilder, BooleanBuilder,
+    Date32Builder, DecimalBuilder, FixedSizeBinaryBuilder, Float32Builder, Float64Builder,
+    Int16Builder, Int32Builder, Int64Builder, Int8Builder, ListArray, MapArray, PrimitiveArray,
+    StringBuilder, StructArray, TimestampMicrosecondBuilder, TimestampMillisecondBuilder,
+    TimestampSecondBuilder, UInt16Builder, UInt32Builder, UInt64Builder, UInt8Builder,
 };
+use arrow::buffer::Buffer;
+use arrow::compute;
 use arrow::datatypes::{
-    DataType, Field as ArrowField, Schema, SchemaRef, TimeUnit, DECIMAL_MAX_PRECISION,
-    DECIMAL_MAX_SCALE,
+    ArrowNumericType, DataType, Field as ArrowField, Schema, SchemaRef, TimeUnit,
+    TimestampMicrosecondType, TimestampMillisecondType, TimestampNanosecondType,
+    TimestampSecondType, DECIMAL_MAX_PRECISION, DECIMAL_MAX_SCALE,
 };
 
 use datafusion::datasource::listing::PartitionedFile;
@@ -36,8 +45,11 @@ use arrow::record_batch::RecordBatch;
 use datafusion::datafusion_data_access::{FileMeta, SizedFile};
 use datafusion::scalar::ScalarValue;
 use parquet::arrow::arrow_writer::ArrowWriter;
+use parquet::arrow::{ArrowReader, ParquetFileArrowReader};
 use parquet::basic::Compression;
 use parquet::file::properties::WriterProperties;
+use parquet::file::serialized_reader::SerializedFileReader;
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::path::Path;
 use std::sync::Arc;
@@ -69,48 +81,311 @@ pub fn batches_to_paths(batches: &[RecordBatch]) -> Vec<PartitionedFile> {
         .collect()
 }
 
+/// read all the record batches in a parquet file
+pub fn read_recordbatch(path: &Path) -> Result<Vec<RecordBatch>> {
+    let file_reader = SerializedFileReader::new(File::open(path)?)?;
+    let mut arrow_reader = ParquetFileArrowReader::new(Arc::new(file_reader));
+    let mut batches: Vec<RecordBatch> = Vec::new();
+    for batch in arrow_reader.get_record_reader(8 * 1024)? {
+        batches.push(batch?);
+    }
+    Ok(batches)
+}
+
+/// merge the batches into one which is sorted by the column
+pub fn merge_and_sort_batches(
+    schema: &SchemaRef,
+    batches: &[RecordBatch],
+    sort_column_index: Option<usize>,
+) -> Result<RecordBatch> {
+    let merged = RecordBatch::concat(schema, batches)?;
+    match sort_column_index {
+        Some(index) => {
+            let indices = compute::sort_to_indices(merged.column(index), None, None)?;
+            let columns = merged
+                .columns()
+                .iter()
+                .map(|c| compute::take(c.as_ref(), &indices, None))
+                .collect::<std::result::Result<Vec<ArrayRef>, _>>()?;
+            Ok(RecordBatch::try_new(schema.clone(), columns)?)
+        }
+        None => Ok(merged),
+    }
+}
+
+/// the first timestamp column is the time column of table
+pub fn get_time_column_index(schema: &SchemaRef) -> Option<usize> {
+    schema
+        .fields()
+        .iter()
+        .position(|f| matches!(f.data_type(), DataType::Timestamp(_, _)))
+}
+
+fn timestamp_min_max<T>(array: &ArrayRef) -> Option<(i64, i64)>
+where
+    T: ArrowNumericType<Native = i64>,
+{
+    let array = array.as_any().downcast_ref::<PrimitiveArray<T>>()?;
+    Some((compute::min(array)?, compute::max(array)?))
+}
+
+/// the min and max value of the time column in batches
+pub fn get_time_range<'a>(
+    batches: impl Iterator<Item = &'a RecordBatch>,
+    index: usize,
+) -> Option<(i64, i64)> {
+    let mut range: Option<(i64, i64)> = None;
+    for batch in batches {
+        let column = batch.column(index);
+        let batch_range = match column.data_type() {
+            DataType::Timestamp(TimeUnit::Second, _) => {
+                timestamp_min_max::<TimestampSecondType>(column)
+            }
+            DataType::Timestamp(TimeUnit::Millisecond, _) => {
+                timestamp_min_max::<TimestampMillisecondType>(column)
+            }
+            DataType::Timestamp(TimeUnit::Microsecond, _) => {
+                timestamp_min_max::<TimestampMicrosecondType>(column)
+            }
+            DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+                timestamp_min_max::<TimestampNanosecondType>(column)
+            }
+            _ => None,
+        };
+        if let Some((min, max)) = batch_range {
+            range = match range {
+                Some((l, r)) => Some((l.min(min), r.max(max))),
+                None => Some((min, max)),
+            };
+        }
+    }
+    range
+}
+
+/// convert a time in millisecond to the value of a timestamp column
+pub fn timestamp_from_ms(data_type: &DataType, time_ms: i64) -> i64 {
+    match data_type {
+        DataType::Timestamp(TimeUnit::Second, _) => time_ms / 1000,
+        DataType::Timestamp(TimeUnit::Microsecond, _) => time_ms.saturating_mul(1000),
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => time_ms.saturating_mul(1000 * 1000),
+        _ => time_ms,
+    }
+}
+
+fn timestamp_not_before<T>(array: &ArrayRef, min_ts: i64) -> Option<BooleanArray>
+where
+    T: ArrowNumericType<Native = i64>,
+{
+    let array = array.as_any().downcast_ref::<PrimitiveArray<T>>()?;
+    Some(
+        array
+            .iter()
+            .map(|v| Some(v.map_or(true, |ts| ts >= min_ts)))
+            .collect(),
+    )
+}
+
+/// drop the rows whose time column is before the min time in millisecond, the rows with a null
+/// time are kept
+pub fn filter_batch_by_time(
+    batch: &RecordBatch,
+    index: usize,
+    min_time_ms: i64,
+) -> Result<RecordBatch> {
+    let column = batch.column(index);
+    let min_ts = timestamp_from_ms(column.data_type(), min_time_ms);
+    let mask = match column.data_type() {
+        DataType::Timestamp(TimeUnit::Second, _) => {
+            timestamp_not_before::<TimestampSecondType>(column, min_ts)
+        }
+        DataType::Timestamp(TimeUnit::Millisecond, _) => {
+            timestamp_not_before::<TimestampMillisecondType>(column, min_ts)
+        }
+        DataType::Timestamp(TimeUnit::Microsecond, _) => {
+            timestamp_not_before::<TimestampMicrosecondType>(column, min_ts)
+        }
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+            timestamp_not_before::<TimestampNanosecondType>(column, min_ts)
+        }
+        _ => None,
+    };
+    match mask {
+        Some(mask) => Ok(compute::filter_record_batch(batch, &mask)?),
+        None => Ok(batch.clone()),
+    }
+}
+
+/// the precision and scale of a decimal column, the old columns without precision use the max
+pub fn get_decimal_precision_scale(column: &Db3ColumnDesc) -> (usize, usize) {
+    if column.precision == 0 {
+        (DECIMAL_MAX_PRECISION, DECIMAL_MAX_SCALE)
+    } else {
+        (column.precision as usize, column.scale as usize)
+    }
+}
+
 pub fn table_desc_to_arrow_schema(desc: &Db3SchemaDesc) -> Result<SchemaRef> {
     let mut fields: Vec<ArrowField> = Vec::new();
     for column in &desc.columns {
-        let dt = match Db3Type::from_i32(column.ctype) {
-            Some(t) => match t {
-                Db3Type::KBool => Ok(DataType::Boolean),
-                Db3Type::KTinyInt => Ok(DataType::Int8),
-                Db3Type::KSmallInt => Ok(DataType::Int16),
-                Db3Type::KInt => Ok(DataType::Int32),
-                Db3Type::KBigInt => Ok(DataType::Int64),
-                Db3Type::KFloat => Ok(DataType::Float32),
-                Db3Type::KDouble => Ok(DataType::Float64),
-                Db3Type::KDate => Ok(DataType::Date32),
-                Db3Type::KDecimal => {
-                    Ok(DataType::Decimal(DECIMAL_MAX_PRECISION, DECIMAL_MAX_SCALE))
-                }
-                Db3Type::KTimestampSecond => Ok(DataType::Timestamp(TimeUnit::Second, None)),
-                Db3Type::KTimestampMillsSecond => {
-                    Ok(DataType::Timestamp(TimeUnit::Millisecond, None))
+        fields.push(column_to_arrow_field(column)?);
+    }
+    Ok(Arc::new(Schema::new(fields)))
+}
+
+// the metadata key of the db3 type which arrow has no type for
+const DB3_TYPE_KEY: &str = "db3_type";
+const UINT256_TYPE: &str = "uint256";
+
+/// the field of uint256 column, it's a fixed size binary marked in the metadata
+pub fn uint256_field(name: &str, nullable: bool) -> ArrowField {
+    let metadata = BTreeMap::from([(DB3_TYPE_KEY.to_string(), UINT256_TYPE.to_string())]);
+    ArrowField::new(
+        name,
+        DataType::FixedSizeBinary(UINT256_BYTES as i32),
+        nullable,
+    )
+    .with_metadata(Some(metadata))
+}
+
+pub fn is_uint256_field(field: &ArrowField) -> bool {
+    matches!(field.data_type(), DataType::FixedSizeBinary(width) if *width as usize == UINT256_BYTES)
+        && field
+            .metadata()
+            .as_ref()
+            .and_then(|m| m.get(DB3_TYPE_KEY))
+            .is_some_and(|t| t == UINT256_TYPE)
+}
+
+/// convert a column to arrow field, the children of nested column are converted recursively
+pub fn column_to_arrow_field(column: &Db3ColumnDesc) -> Result<ArrowField> {
+    let invalid = |reason: &str| DB3Error::TableSchemaInvalidError {
+        name: format!("{} of column {}", reason, column.name),
+    };
+    let dt = match Db3Type::from_i32(column.ctype) {
+        Some(t) => match t {
+            Db3Type::KBool => Ok(DataType::Boolean),
+            Db3Type::KTinyInt => Ok(DataType::Int8),
+            Db3Type::KSmallInt => Ok(DataType::Int16),
+            Db3Type::KInt => Ok(DataType::Int32),
+            Db3Type::KBigInt => Ok(DataType::Int64),
+            Db3Type::KUTinyInt => Ok(DataType::UInt8),
+            Db3Type::KUSmallInt => Ok(DataType::UInt16),
+            Db3Type::KUInt => Ok(DataType::UInt32),
+            Db3Type::KUBigInt => Ok(DataType::UInt64),
+            Db3Type::KUInt256 => return Ok(uint256_field(&column.name, column.null_allowed)),
+            Db3Type::KFixedBinary if column.byte_width == 0 => {
+                Err(invalid("fixed binary without width"))
+            }
+            Db3Type::KFixedBinary => Ok(DataType::FixedSizeBinary(column.byte_width as i32)),
+            Db3Type::KBinary => Ok(DataType::Binary),
+            Db3Type::KFloat => Ok(DataType::Float32),
+            Db3Type::KDouble => Ok(DataType::Float64),
+            Db3Type::KDate => Ok(DataType::Date32),
+            Db3Type::KDecimal => {
+                let (precision, scale) = get_decimal_precision_scale(column);
+                if precision > DECIMAL_MAX_PRECISION || scale > precision {
+                    Err(invalid(&format!("decimal({}, {})", precision, scale)))
+                } else {
+                    Ok(DataType::Decimal(precision, scale))
                 }
-                Db3Type::KTimestampMicroSecond => {
-                    Ok(DataType::Timestamp(TimeUnit::Microsecond, None))
+            }
+            Db3Type::KTimestampSecond => Ok(DataType::Timestamp(TimeUnit::Second, None)),
+            Db3Type::KTimestampMillsSecond => Ok(DataType::Timestamp(TimeUnit::Millisecond, None)),
+            Db3Type::KTimestampMicroSecond => Ok(DataType::Timestamp(TimeUnit::Microsecond, None)),
+            Db3Type::KStringUtf8 => Ok(DataType::Utf8),
+            Db3Type::KStruct if column.children.is_empty() => Err(invalid("struct without fields")),
+            Db3Type::KStruct => column
+                .children
+                .iter()
+                .map(column_to_arrow_field)
+                .collect::<Result<Vec<ArrowField>>>()
+                .map(DataType::Struct),
+            Db3Type::KList => match column.children.as_slice() {
+                [item] => Ok(DataType::List(Box::new(column_to_arrow_field(item)?))),
+                _ => Err(invalid("list without one item")),
+            },
+            Db3Type::KMap => match column.children.as_slice() {
+                // the key of map is not nullable
+                [key, value] => {
+                    let key = column_to_arrow_field(key)?;
+                    let entries = DataType::Struct(vec![
+                        ArrowField::new("key", key.data_type().clone(), false)
+                            .with_metadata(key.metadata().clone()),
+                        column_to_arrow_field(value)?,
+                    ]);
+                    Ok(DataType::Map(
+                        Box::new(ArrowField::new("entries", entries, false)),
+                        false,
+                    ))
                 }
-                Db3Type::KStringUtf8 => Ok(DataType::Utf8),
+                _ => Err(invalid("map without key and value")),
             },
-            _ => Err(DB3Error::TableSchemaConvertError(column.ctype)),
-        }?;
-        fields.push(ArrowField::new(&column.name, dt, column.null_allowed));
+        },
+        _ => Err(DB3Error::TableSchemaConvertError(column.ctype)),
+    }?;
+    Ok(ArrowField::new(&column.name, dt, column.null_allowed))
+}
+
+/// the parquet writer properties from the storage config of table
+/// reject the options of storage config which the parquet writer can not apply
+pub fn check_storage_config(config: &StorageBackendConfig) -> Result<()> {
+    if !config.bloom_filter_columns.is_empty() {
+        return Err(DB3Error::CellStoreInvalidConfigError {
+            name: "bloom_filter_columns".to_string(),
+            err: "bloom filters are unsupported by parquet 16".to_string(),
+        });
+    }
+    Ok(())
+}
+
+pub fn storage_config_to_writer_properties(config: &StorageBackendConfig) -> WriterProperties {
+    let compression = match ParquetCompression::from_i32(config.compression) {
+        Some(ParquetCompression::KUncompressed) => Compression::UNCOMPRESSED,
+        Some(ParquetCompression::KSnappy) => Compression::SNAPPY,
+        Some(ParquetCompression::KLz4) => Compression::LZ4,
+        Some(ParquetCompression::KZstd) => Compression::ZSTD,
+        _ => Compression::GZIP,
+    };
+    let mut builder = WriterProperties::builder()
+        .set_compression(compression)
+        .set_dictionary_enabled(!config.dictionary_disabled)
+        .set_statistics_enabled(!config.statistics_disabled);
+    if config.row_group_size > 0 {
+        builder = builder.set_max_row_group_size(config.row_group_size as usize);
+    }
+    builder.build()
+}
+
+/// the file extension of the compression codec, empty for the uncompressed file
+pub fn compression_extension(compression: Compression) -> &'static str {
+    match compression {
+        Compression::UNCOMPRESSED => "",
+        Compression::SNAPPY => "snappy",
+        Compression::GZIP => "gz",
+        Compression::LZO => "lzo",
+        Compression::BROTLI => "br",
+        Compression::LZ4 => "lz4",
+        Compression::ZSTD => "zstd",
+    }
+}
+
+/// the file name like `00000001.zstd.parquet` named by the compression codec
+pub fn parquet_file_name(file_sequence: u64, compression: Compression) -> String {
+    let seq = strings::to_readable_num_str(file_sequence as usize, 8);
+    match compression_extension(compression) {
+        "" => format!("{}.parquet", seq),
+        ext => format!("{}.{}.parquet", seq, ext),
     }
-    Ok(Arc::new(Schema::new(fields)))
 }
 
 pub fn dump_recordbatch(
     path: &Path,
     batches: &LinkedList<RecordBatch>,
     schema: &SchemaRef,
+    properties: &WriterProperties,
 ) -> Result<()> {
-    let properties = WriterProperties::builder()
-        .set_compression(Compression::GZIP)
-        .build();
     let fd = File::create(path)?;
-    let mut writer = ArrowWriter::try_new(fd, schema.clone(), Some(properties))?;
+    let mut writer = ArrowWriter::try_new(fd, schema.clone(), Some(properties.clone()))?;
     for batch in batches.iter() {
         writer.write(batch)?;
     }
@@ -129,54 +404,282 @@ enum DB3ColumnBuilder {
     DB3UInt32Builder(UInt32Builder),
     DB3Int64Builder(Int64Builder),
     DB3UInt64Builder(UInt64Builder),
+    DB3Float32Builder(Float32Builder),
+    DB3Float64Builder(Float64Builder),
+    DB3DecimalBuilder(DecimalBuilder),
+    // uint256 and fixed binary
+    DB3FixedBinaryBuilder(FixedSizeBinaryBuilder),
+    DB3BinaryBuilder(BinaryBuilder),
+    // the children are built with their own builders
+    DB3StructBuilder {
+        fields: Vec<ArrowField>,
+        children: Vec<DB3ColumnBuilder>,
+        validity: BooleanBufferBuilder,
+    },
+    // list and map, the item of map is the struct of key and value
+    DB3ListBuilder {
+        data_type: DataType,
+        item: Box<DB3ColumnBuilder>,
+        offsets: Vec<i32>,
+        validity: BooleanBufferBuilder,
+    },
     DB3StrBuilder(StringBuilder),
     DB3DateBuilder(Date32Builder),
+    DB3TimestampSecondBuilder(TimestampSecondBuilder),
     DB3TimestampMillsBuilder(TimestampMillisecondBuilder),
+    DB3TimestampMicrosBuilder(TimestampMicrosecondBuilder),
 }
 
 impl DB3ColumnBuilder {
-    pub fn finish(&mut self) -> ArrayRef {
+    fn new(data_type: &DataType, capacity: usize) -> Result<Self> {
+        match data_type {
+            DataType::Boolean => Ok(Self::DB3BooleanBuilder(BooleanBuilder::new(capacity))),
+            DataType::Int8 => Ok(Self::DB3Int8Builder(Int8Builder::new(capacity))),
+            DataType::UInt8 => Ok(Self::DB3UInt8Builder(UInt8Builder::new(capacity))),
+            DataType::Int16 => Ok(Self::DB3Int16Builder(Int16Builder::new(capacity))),
+            DataType::UInt16 => Ok(Self::DB3UInt16Builder(UInt16Builder::new(capacity))),
+            DataType::Int32 => Ok(Self::DB3Int32Builder(Int32Builder::new(capacity))),
+            DataType::UInt32 => Ok(Self::DB3UInt32Builder(UInt32Builder::new(capacity))),
+            DataType::Int64 => Ok(Self::DB3Int64Builder(Int64Builder::new(capacity))),
+            DataType::UInt64 => Ok(Self::DB3UInt64Builder(UInt64Builder::new(capacity))),
+            DataType::Float32 => Ok(Self::DB3Float32Builder(Float32Builder::new(capacity))),
+            DataType::Float64 => Ok(Self::DB3Float64Builder(Float64Builder::new(capacity))),
+            DataType::Decimal(precision, scale) => Ok(Self::DB3DecimalBuilder(
+                DecimalBuilder::new(capacity, *precision, *scale),
+            )),
+            DataType::FixedSizeBinary(width) => Ok(Self::DB3FixedBinaryBuilder(
+                FixedSizeBinaryBuilder::new(capacity, *width),
+            )),
+            DataType::Binary => Ok(Self::DB3BinaryBuilder(BinaryBuilder::new(capacity))),
+            DataType::Struct(fields) => Ok(Self::DB3StructBuilder {
+                fields: fields.clone(),
+                children: fields
+                    .iter()
+                    .map(|f| Self::new(f.data_type(), capacity))
+                    .collect::<Result<Vec<_>>>()?,
+                validity: BooleanBufferBuilder::new(capacity),
+            }),
+            DataType::List(item) | DataType::Map(item, _) => {
+                let mut offsets = Vec::with_capacity(capacity + 1);
+                offsets.push(0);
+                Ok(Self::DB3ListBuilder {
+                    data_type: data_type.clone(),
+                    item: Box::new(Self::new(item.data_type(), capacity)?),
+                    offsets,
+                    validity: BooleanBufferBuilder::new(capacity),
+                })
+            }
+            DataType::Utf8 => Ok(Self::DB3StrBuilder(StringBuilder::new(capacity))),
+            DataType::Date32 => Ok(Self::DB3DateBuilder(Date32Builder::new(capacity))),
+            DataType::Timestamp(TimeUnit::Second, _) => Ok(Self::DB3TimestampSecondBuilder(
+                TimestampSecondBuilder::new(capacity),
+            )),
+            DataType::Timestamp(TimeUnit::Millisecond, _) => Ok(Self::DB3TimestampMillsBuilder(
+                TimestampMillisecondBuilder::new(capacity),
+            )),
+            DataType::Timestamp(TimeUnit::Microsecond, _) => Ok(Self::DB3TimestampMicrosBuilder(
+                TimestampMicrosecondBuilder::new(capacity),
+            )),
+            _ => Err(DB3Error::TableTypeMismatchError {
+                left: format!("{:?}", data_type),
+                right: "Null".to_string(),
+            }),
+        }
+    }
+
+    fn append_null(&mut self) -> Result<()> {
         match self {
+            Self::DB3BooleanBuilder(b) => b.append_null()?,
+            Self::DB3Int8Builder(b) => b.append_null()?,
+            Self::DB3UInt8Builder(b) => b.append_null()?,
+            Self::DB3Int16Builder(b) => b.append_null()?,
+            Self::DB3UInt16Builder(b) => b.append_null()?,
+            Self::DB3Int32Builder(b) => b.append_null()?,
+            Self::DB3UInt32Builder(b) => b.append_null()?,
+            Self::DB3Int64Builder(b) => b.append_null()?,
+            Self::DB3UInt64Builder(b) => b.append_null()?,
+            Self::DB3Float32Builder(b) => b.append_null()?,
+            Self::DB3Float64Builder(b) => b.append_null()?,
+            Self::DB3DecimalBuilder(b) => b.append_null()?,
+            Self::DB3FixedBinaryBuilder(b) => b.append_null()?,
+            Self::DB3BinaryBuilder(b) => b.append_null()?,
+            // the fields of null struct are null too
+            Self::DB3StructBuilder {
+                children, validity, ..
+            } => {
+                validity.append(false);
+                for child in children {
+                    child.append_null()?;
+                }
+            }
+            Self::DB3ListBuilder {
+                offsets, validity, ..
+            } => {
+                validity.append(false);
+                offsets.push(offsets[offsets.len() - 1]);
+            }
+            Self::DB3StrBuilder(b) => b.append_null()?,
+            Self::DB3DateBuilder(b) => b.append_null()?,
+            Self::DB3TimestampSecondBuilder(b) => b.append_null()?,
+            Self::DB3TimestampMillsBuilder(b) => b.append_null()?,
+            Self::DB3TimestampMicrosBuilder(b) => b.append_null()?,
+        }
+        Ok(())
+    }
+
+    // append the value of field, the width of fixed binary is checked by the builder
+    fn append_value(&mut self, field: &ArrowField, value: &Data) -> Result<()> {
+        let mismatch = || DB3Error::TableTypeMismatchError {
+            left: format!("{:?}", field.data_type()),
+            right: value.name().to_string(),
+        };
+        match (self, value) {
+            (_, Data::Null) if !field.is_nullable() => {
+                return Err(DB3Error::TableColumnNotNullError {
+                    name: field.name().to_string(),
+                });
+            }
+            (b, Data::Null) => b.append_null()?,
+            (Self::DB3BooleanBuilder(b), Data::Bool(v)) => b.append_value(*v)?,
+            (Self::DB3Int8Builder(b), Data::Int8(v)) => b.append_value(*v)?,
+            (Self::DB3UInt8Builder(b), Data::UInt8(v)) => b.append_value(*v)?,
+            (Self::DB3Int16Builder(b), Data::Int16(v)) => b.append_value(*v)?,
+            (Self::DB3UInt16Builder(b), Data::UInt16(v)) => b.append_value(*v)?,
+            (Self::DB3Int32Builder(b), Data::Int32(v)) => b.append_value(*v)?,
+            (Self::DB3UInt32Builder(b), Data::UInt32(v)) => b.append_value(*v)?,
+            (Self::DB3Int64Builder(b), Data::Int64(v)) => b.append_value(*v)?,
+            (Self::DB3UInt64Builder(b), Data::UInt64(v)) => b.append_value(*v)?,
+            (Self::DB3Float32Builder(b), Data::Float(v)) => b.append_value(*v)?,
+            (Self::DB3Float64Builder(b), Data::Double(v)) => b.append_value(*v)?,
+            (Self::DB3DecimalBuilder(b), Data::Decimal(v)) => b.append_value(*v)?,
+            (Self::DB3FixedBinaryBuilder(b), Data::UInt256(v)) if is_uint256_field(field) => {
+                b.append_value(v)?
+            }
+            (Self::DB3FixedBinaryBuilder(b), Data::Binary(v)) if !is_uint256_field(field) => {
+                b.append_value(v)?
+            }
+            (Self::DB3BinaryBuilder(b), Data::Binary(v)) => b.append_value(v)?,
+            (b @ Self::DB3StructBuilder { .. }, Data::Struct(values)) => {
+                b.append_fields(values.iter())?
+            }
+            (
+                Self::DB3ListBuilder {
+                    data_type: DataType::List(item_field),
+                    item,
+                    offsets,
+                    validity,
+                },
+                Data::List(values),
+            ) => {
+                for v in values {
+                    item.append_value(item_field, v)?;
+                }
+                validity.append(true);
+                offsets.push(offsets[offsets.len() - 1] + values.len() as i32);
+            }
+            (
+                Self::DB3ListBuilder {
+                    data_type: DataType::Map(..),
+                    item,
+                    offsets,
+                    validity,
+                },
+                Data::Map(entries),
+            ) => {
+                for (k, v) in entries {
+                    item.append_fields([k, v].into_iter())?;
+                }
+                validity.append(true);
+                offsets.push(offsets[offsets.len() - 1] + entries.len() as i32);
+            }
+            (Self::DB3StrBuilder(b), Data::Varchar(v)) => b.append_value(v)?,
+            (Self::DB3DateBuilder(b), Data::Date(v)) => b.append_value(*v)?,
+            (Self::DB3TimestampSecondBuilder(b), Data::Timestamp(v)) => b.append_value(*v)?,
+            (Self::DB3TimestampMillsBuilder(b), Data::Timestamp(v)) => b.append_value(*v)?,
+            (Self::DB3TimestampMicrosBuilder(b), Data::Timestamp(v)) => b.append_value(*v)?,
+            _ => return Err(mismatch()),
+        }
+        Ok(())
+    }
+
+    // append the values of struct in the order of fields
+    fn append_fields<'a>(&mut self, values: impl ExactSizeIterator<Item = &'a Data>) -> Result<()> {
+        match self {
+            Self::DB3StructBuilder {
+                fields,
+                children,
+                validity,
+            } if fields.len() == values.len() => {
+                for ((field, child), v) in fields.iter().zip(children.iter_mut()).zip(values) {
+                    child.append_value(field, v)?;
+                }
+                validity.append(true);
+                Ok(())
+            }
+            _ => Err(DB3Error::TableTypeMismatchError {
+                left: "struct".to_string(),
+                right: format!("{} values", values.len()),
+            }),
+        }
+    }
+
+    pub fn finish(&mut self) -> Result<ArrayRef> {
+        let array: ArrayRef = match self {
             Self::DB3BooleanBuilder(b) => Arc::new(b.finish()),
             Self::DB3Int8Builder(b) => Arc::new(b.finish()),
             Self::DB3UInt8Builder(b) => Arc::new(b.finish()),
             Self::DB3Int16Builder(b) => Arc::new(b.finish()),
             Self::DB3UInt16Builder(b) => Arc::new(b.finish()),
-            Self::DB3UInt16Builder(b) => Arc::new(b.finish()),
             Self::DB3Int32Builder(b) => Arc::new(b.finish()),
             Self::DB3UInt32Builder(b) => Arc::new(b.finish()),
             Self::DB3Int64Builder(b) => Arc::new(b.finish()),
             Self::DB3UInt64Builder(b) => Arc::new(b.finish()),
+            Self::DB3Float32Builder(b) => Arc::new(b.finish()),
+            Self::DB3Float64Builder(b) => Arc::new(b.finish()),
+            Self::DB3DecimalBuilder(b) => Arc::new(b.finish()),
+            Self::DB3FixedBinaryBuilder(b) => Arc::new(b.finish()),
+            Self::DB3BinaryBuilder(b) => Arc::new(b.finish()),
+            Self::DB3StructBuilder {
+                fields,
+                children,
+                validity,
+            } => {
+                let mut builder = ArrayData::builder(DataType::Struct(fields.clone()))
+                    .len(validity.len())
+                    .null_bit_buffer(Some(validity.finish()));
+                for child in children {
+                    builder = builder.add_child_data(child.finish()?.data().clone());
+                }
+                Arc::new(StructArray::from(builder.build()?))
+            }
+            Self::DB3ListBuilder {
+                data_type,
+                item,
+                offsets,
+                validity,
+            } => {
+                let data = ArrayData::builder(data_type.clone())
+                    .len(validity.len())
+                    .add_buffer(Buffer::from_slice_ref(&std::mem::replace(offsets, vec![0])))
+                    .add_child_data(item.finish()?.data().clone())
+                    .null_bit_buffer(Some(validity.finish()))
+                    .build()?;
+                if let DataType::Map(..) = data_type {
+                    Arc::new(MapArray::from(data))
+                } else {
+                    Arc::new(ListArray::from(data))
+                }
+            }
             Self::DB3StrBuilder(b) => Arc::new(b.finish()),
             Self::DB3DateBuilder(b) => Arc::new(b.finish()),
+            Self::DB3TimestampSecondBuilder(b) => Arc::new(b.finish()),
             Self::DB3TimestampMillsBuilder(b) => Arc::new(b.finish()),
-        }
+            Self::DB3TimestampMicrosBuilder(b) => Arc::new(b.finish()),
+        };
+        Ok(array)
     }
 }
 
-macro_rules! primary_type_convert {
-    ($left_builder:ident, $right_builder:ident, $data_type:ident,
-     $builders:ident, $index:ident, $column:ident,
-     $rows:ident) => {
-        let bsize = $builders.len();
-        if bsize <= $index {
-            let builder = DB3ColumnBuilder::$left_builder($right_builder::new($rows.batch.len()));
-            $builders.push(builder);
-        }
-        let builder = &mut $builders[$index];
-        if let (DB3ColumnBuilder::$left_builder(internal_builder), Data::$data_type(internal_v)) =
-            (builder, $column)
-        {
-            internal_builder.append_value(*internal_v)?;
-        } else {
-            return Err(DB3Error::TableTypeMismatchError {
-                left: "$data_type".to_string(),
-                right: $column.name().to_string(),
-            });
-        }
-    };
-}
-
 pub fn rows_to_columns(
     schema: &SchemaRef,
     rows_batch: &LinkedList<RowRecordBatch>,
@@ -184,174 +687,72 @@ pub fn rows_to_columns(
     if rows_batch.is_empty() {
         return Ok(RecordBatch::new_empty(schema.clone()));
     }
-    let mut builders: Vec<DB3ColumnBuilder> = Vec::new();
+    let capacity = rows_batch.iter().map(|rows| rows.batch.len()).sum();
+    let mut builders = schema
+        .fields()
+        .iter()
+        .map(|field| DB3ColumnBuilder::new(field.data_type(), capacity))
+        .collect::<Result<Vec<DB3ColumnBuilder>>>()?;
     for rows in rows_batch.iter() {
-        for r_index in 0..rows.batch.len() {
-            let r = &rows.batch[r_index];
-            for index in 0..schema.fields().len() {
-                let field = &schema.fields()[index];
-                let column = &r[index];
-                match field.data_type() {
-                    DataType::Boolean => {
-                        primary_type_convert!(
-                            DB3BooleanBuilder,
-                            BooleanBuilder,
-                            Bool,
-                            builders,
-                            index,
-                            column,
-                            rows
-                        );
-                    }
-                    DataType::UInt8 => {
-                        primary_type_convert!(
-                            DB3UInt8Builder,
-                            UInt8Builder,
-                            UInt8,
-                            builders,
-                            index,
-                            column,
-                            rows
-                        );
-                    }
-                    DataType::Int8 => {
-                        primary_type_convert!(
-                            DB3Int8Builder,
-                            Int8Builder,
-                            Int8,
-                            builders,
-                            index,
-                            column,
-                            rows
-                        );
-                    }
-                    DataType::Int16 => {
-                        primary_type_convert!(
-                            DB3Int16Builder,
-                            Int16Builder,
-                            Int16,
-                            builders,
-                            index,
-                            column,
-                            rows
-                        );
-                    }
-                    DataType::UInt16 => {
-                        primary_type_convert!(
-                            DB3UInt16Builder,
-                            UInt16Builder,
-                            UInt16,
-                            builders,
-                            index,
-                            column,
-                            rows
-                        );
-                    }
-                    DataType::Int32 => {
-                        primary_type_convert!(
-                            DB3Int32Builder,
-                            Int32Builder,
-                            Int32,
-                            builders,
-                            index,
-                            column,
-                            rows
-                        );
-                    }
-                    DataType::Int64 => {
-                        primary_type_convert!(
-                            DB3Int64Builder,
-                            Int64Builder,
-                            Int64,
-                            builders,
-                            index,
-                            column,
-                            rows
-                        );
-                    }
-                    DataType::UInt64 => {
-                        primary_type_convert!(
-                            DB3UInt64Builder,
-                            UInt64Builder,
-                            UInt64,
-                            builders,
-                            index,
-                            column,
-                            rows
-                        );
-                    }
-                    DataType::Date32 => {
-                        if builders.len() <= index {
-                            let builder = DB3ColumnBuilder::DB3DateBuilder(Date32Builder::new(
-                                rows.batch.len(),
-                            ));
-                            builders.push(builder);
-                        }
-                        let builder = &mut builders[index];
-                        if let (DB3ColumnBuilder::DB3DateBuilder(date_builder), Data::Date(s)) =
-                            (builder, column)
-                        {
-                            date_builder.append_value(*s as i32)?;
-                        } else {
-                            return Err(DB3Error::TableTypeMismatchError {
-                                left: "date".to_string(),
-                                right: column.name().to_string(),
-                            });
-                        }
-                    }
-                    DataType::Timestamp(_, _) => {
-                        if builders.len() <= index {
-                            let builder = DB3ColumnBuilder::DB3TimestampMillsBuilder(
-                                TimestampMillisecondBuilder::new(rows.batch.len()),
-                            );
-                            builders.push(builder);
-                        }
-                        let builder = &mut builders[index];
-                        if let (
-                            DB3ColumnBuilder::DB3TimestampMillsBuilder(ts_builder),
-                            Data::Timestamp(s),
-                        ) = (builder, column)
-                        {
-                            ts_builder.append_value(*s as i64)?;
-                        } else {
-                            return Err(DB3Error::TableTypeMismatchError {
-                                left: "timestamp".to_string(),
-                                right: column.name().to_string(),
-                            });
-                        }
-                    }
-                    DataType::Utf8 => {
-                        if builders.len() <= index {
-                            let builder = DB3ColumnBuilder::DB3StrBuilder(StringBuilder::new(
-                                rows.batch.len(),
-                            ));
-                            builders.push(builder);
-                        }
-                        let builder = &mut builders[index];
-                        if let (DB3ColumnBuilder::DB3StrBuilder(str_builder), Data::Varchar(s)) =
-                            (builder, column)
-                        {
-                            str_builder.append_value(s)?;
-                        } else {
-                            return Err(DB3Error::TableTypeMismatchError {
-                                left: "utf8".to_string(),
-                                right: column.name().to_string(),
-                            });
-                        }
-                    }
-                    _ => {}
-                }
+        for r in rows.batch.iter() {
+            for (index, field) in schema.fields().iter().enumerate() {
+                builders[index].append_value(field, &r[index])?;
             }
         }
     }
     let mut array_refs: Vec<ArrayRef> = Vec::new();
     for mut builder in builders {
-        array_refs.push(builder.finish());
+        array_refs.push(builder.finish()?);
     }
     let record_batch = RecordBatch::try_new(schema.clone(), array_refs)?;
     Ok(record_batch)
 }
 
+/// the sql type name of field, the children of nested field are in the name
+fn field_type_name(f: &ArrowField) -> String {
+    match f.data_type() {
+        DataType::Utf8 => "varchar(255)".to_string(),
+        DataType::Int8 => "tinyint".to_string(),
+        DataType::Int16 => "smallint".to_string(),
+        DataType::Int32 => "int".to_string(),
+        DataType::Int64 => "bigint".to_string(),
+        DataType::Float32 => "float".to_string(),
+        DataType::Float64 => "double".to_string(),
+        DataType::Boolean => "boolean".to_string(),
+        DataType::UInt8 => "tinyint unsigned".to_string(),
+        DataType::UInt16 => "smallint unsigned".to_string(),
+        DataType::UInt32 => "int unsigned".to_string(),
+        DataType::UInt64 => "bigint unsigned".to_string(),
+        DataType::Decimal(precision, scale) => format!("decimal({},{})", precision, scale),
+        DataType::Date32 => "date".to_string(),
+        DataType::FixedSizeBinary(_) if is_uint256_field(f) => "uint256".to_string(),
+        DataType::FixedSizeBinary(width) => format!("binary({})", width),
+        DataType::Binary => "varbinary".to_string(),
+        DataType::Struct(fields) => format!(
+            "struct<{}>",
+            fields
+                .iter()
+                .map(|c| format!("{} {}", c.name(), field_type_name(c)))
+                .collect::<Vec<String>>()
+                .join(",")
+        ),
+        // the same as the array type of sql
+        DataType::List(item) => format!("{}[]", field_type_name(item)),
+        DataType::Map(entries, _) => match entries.data_type() {
+            DataType::Struct(kv) if kv.len() == 2 => format!(
+                "map<{},{}>",
+                field_type_name(&kv[0]),
+                field_type_name(&kv[1])
+            ),
+            _ => "map".to_string(),
+        },
+        DataType::Timestamp(TimeUnit::Second, _) => "timestamp(0)".to_string(),
+        DataType::Timestamp(TimeUnit::Microsecond, _) => "timestamp(6)".to_string(),
+        DataType::Timestamp(_, _) => "timestamp".to_string(),
+        _ => "unknow".to_string(),
+    }
+}
+
 pub fn schema_to_recordbatch(schema: &SchemaRef) -> Result<RecordBatch> {
     let output_schema = Arc::new(Schema::new(vec![
         ArrowField::new("Field", DataType::Utf8, false),
@@ -367,39 +768,12 @@ pub fn schema_to_recordbatch(schema: &SchemaRef) -> Result<RecordBatch> {
         let f = &schema.fields()[i];
         info!("{} field", f);
         row.push(Data::Varchar(f.name().clone()));
-        match f.data_type() {
-            DataType::Utf8 => {
-                row.push(Data::Varchar("varchar(255)".to_string()));
-            }
-            DataType::Int8 => {
-                row.push(Data::Varchar("tinyint".to_string()));
-            }
-            DataType::Int16 => {
-                row.push(Data::Varchar("smallint".to_string()));
-            }
-            DataType::Int32 => {
-                row.push(Data::Varchar("int".to_string()));
-            }
-            DataType::Int64 => {
-                row.push(Data::Varchar("bigint".to_string()));
-            }
-            DataType::Float32 => {
-                row.push(Data::Varchar("float".to_string()));
-            }
-            DataType::Float64 => {
-                row.push(Data::Varchar("double".to_string()));
-            }
-            DataType::Date32 => {
-                row.push(Data::Varchar("date".to_string()));
-            }
-            DataType::Timestamp(_, _) => {
-                row.push(Data::Varchar("timestamp".to_string()));
-            }
-            _ => {
-                row.push(Data::Varchar("unknow".to_string()));
-            }
+        row.push(Data::Varchar(field_type_name(f)));
+        if f.is_nullable() {
+            row.push(Data::Varchar("YES".to_string()));
+        } else {
+            row.push(Data::Varchar("NO".to_string()));
         }
-        row.push(Data::Varchar("YES".to_string()));
         row.push(Data::Varchar("".to_string()));
         row.push(Data::Varchar("".to_string()));
         row.push(Data::Varchar("".to_string()));
@@ -451,9 +825,21 @@ pub fn schema_to_ddl_recordbatch(name: &str, schema: &SchemaRef) -> Result<Recor
             DataType::Date32 => {
                 builder.append(format!("{} date", f.name()));
             }
+            DataType::FixedSizeBinary(_) if is_uint256_field(f) => {
+                builder.append(format!("{} uint256", f.name()));
+            }
+            DataType::FixedSizeBinary(width) => {
+                builder.append(format!("{} binary({})", f.name(), width));
+            }
+            DataType::Binary => {
+                builder.append(format!("{} varbinary", f.name()));
+            }
             DataType::Timestamp(_, _) => {
                 builder.append(format!("{} timestamp", f.name()));
             }
+            DataType::Struct(_) | DataType::List(_) | DataType::Map(..) => {
+                builder.append(format!("{} {}", f.name(), field_type_name(f)));
+            }
             _ => {
                 warn!("{:?} is unsupported", f);
             }
@@ -471,14 +857,32 @@ pub fn schema_to_ddl_recordbatch(name: &str, schema: &SchemaRef) -> Result<Recor
     rows_to_columns(&output_schema, &data)
 }
 
+/// a nullable column with children for the tests of nested types
+#[cfg(test)]
+pub(crate) fn gen_nested_column(
+    name: &str,
+    ctype: Db3Type,
+    children: Vec<Db3ColumnDesc>,
+) -> Db3ColumnDesc {
+    Db3ColumnDesc {
+        name: name.to_string(),
+        ctype: ctype as i32,
+        null_allowed: true,
+        children,
+        ..Default::default()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::error::Result;
-    use crate::proto::db3_base_proto::Db3ColumnDesc;
     use arrow::array::{
-        Int16Array, Int32Array, Int64Array, Int8Array, UInt16Array, UInt64Array, UInt8Array,
+        BinaryArray, DecimalArray, FixedSizeBinaryArray, Int16Array, Int32Array, Int64Array,
+        Int8Array, StringArray, TimestampMicrosecondArray, TimestampMillisecondArray, UInt16Array,
+        UInt32Array, UInt64Array, UInt8Array,
     };
+    use parquet::file::reader::FileReader;
 
     macro_rules! test_schema_convert {
         ($func:ident, $type:ident, $target_type:ident) => {
@@ -488,6 +892,7 @@ mod tests {
                     name: "col1".to_string(),
                     ctype: Db3Type::$type as i32,
                     null_allowed: true,
+                    ..Default::default()
                 }];
                 let schema = Db3SchemaDesc {
                     columns,
@@ -509,7 +914,70 @@ mod tests {
     test_schema_convert!(test_type_convert_float, KFloat, Float32);
     test_schema_convert!(test_type_convert_double, KDouble, Float64);
     test_schema_convert!(test_type_convert_string, KStringUtf8, Utf8);
+
+    #[test]
+    fn test_type_convert_uint256() -> Result<()> {
+        let columns = vec![Db3ColumnDesc {
+            name: "amount".to_string(),
+            ctype: Db3Type::KUInt256 as i32,
+            null_allowed: true,
+            ..Default::default()
+        }];
+        let schema = Db3SchemaDesc {
+            columns,
+            version: 1,
+        };
+        let schema_ref = table_desc_to_arrow_schema(&schema)?;
+        assert_eq!(
+            &DataType::FixedSizeBinary(32),
+            schema_ref.fields()[0].data_type()
+        );
+        assert!(is_uint256_field(&schema_ref.fields()[0]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_type_convert_fixed_binary() -> Result<()> {
+        let mut column = Db3ColumnDesc {
+            name: "hash".to_string(),
+            ctype: Db3Type::KFixedBinary as i32,
+            null_allowed: true,
+            byte_width: 32,
+            ..Default::default()
+        };
+        let field = column_to_arrow_field(&column)?;
+        assert_eq!(&DataType::FixedSizeBinary(32), field.data_type());
+        assert!(!is_uint256_field(&field));
+        let schema = Arc::new(Schema::new(vec![field]));
+        let rows = LinkedList::<RowRecordBatch>::new();
+        rows.push_front(RowRecordBatch {
+            batch: vec![vec![Data::Binary(vec![1; 32])]],
+            schema_version: 0,
+        })?;
+        assert_eq!(1, rows_to_columns(&schema, &rows)?.num_rows());
+        // uint256 could not be written to fixed binary
+        let rows = LinkedList::<RowRecordBatch>::new();
+        rows.push_front(RowRecordBatch {
+            batch: vec![vec![Data::UInt256([1; 32])]],
+            schema_version: 0,
+        })?;
+        assert!(rows_to_columns(&schema, &rows).is_err());
+        let ddl = schema_to_ddl_recordbatch("t1", &schema)?;
+        let ddl = ddl
+            .column(1)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!("create table `t1` (hash binary(32))", ddl.value(0));
+        column.byte_width = 0;
+        assert!(column_to_arrow_field(&column).is_err());
+        Ok(())
+    }
     test_schema_convert!(test_type_convert_date, KDate, Date32);
+    test_schema_convert!(test_type_convert_utiny_int, KUTinyInt, UInt8);
+    test_schema_convert!(test_type_convert_usmall_int, KUSmallInt, UInt16);
+    test_schema_convert!(test_type_convert_uint, KUInt, UInt32);
+    test_schema_convert!(test_type_convert_ubigint, KUBigInt, UInt64);
 
     #[test]
     fn test_schema_convert_complexe() -> Result<()> {
@@ -518,21 +986,25 @@ mod tests {
                 name: "col1".to_string(),
                 ctype: Db3Type::KDecimal as i32,
                 null_allowed: true,
+                ..Default::default()
             },
             Db3ColumnDesc {
                 name: "col2".to_string(),
                 ctype: Db3Type::KTimestampSecond as i32,
                 null_allowed: true,
+                ..Default::default()
             },
             Db3ColumnDesc {
                 name: "col3".to_string(),
                 ctype: Db3Type::KTimestampMillsSecond as i32,
                 null_allowed: true,
+                ..Default::default()
             },
             Db3ColumnDesc {
                 name: "col4".to_string(),
                 ctype: Db3Type::KTimestampMicroSecond as i32,
                 null_allowed: true,
+                ..Default::default()
             },
         ];
         let schema = Db3SchemaDesc {
@@ -598,4 +1070,299 @@ mod tests {
     test_num_convert!(test_uint16_convert, UInt16, u16, UInt16Array);
     test_num_convert!(test_int64_convert, Int64, i64, Int64Array);
     test_num_convert!(test_uint64_convert, UInt64, u64, UInt64Array);
+    test_num_convert!(test_uint32_convert, UInt32, u32, UInt32Array);
+
+    #[test]
+    fn test_all_types_to_parquet() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            ArrowField::new("c1", DataType::Boolean, true),
+            ArrowField::new("c2", DataType::Float32, true),
+            ArrowField::new("c3", DataType::Float64, true),
+            ArrowField::new("c4", DataType::Decimal(10, 2), true),
+            ArrowField::new("c5", DataType::Date32, true),
+            ArrowField::new("c6", DataType::Timestamp(TimeUnit::Second, None), true),
+            ArrowField::new("c7", DataType::Timestamp(TimeUnit::Microsecond, None), true),
+            ArrowField::new("c8", DataType::UInt32, true),
+            uint256_field("c9", true),
+            ArrowField::new("c10", DataType::Binary, true),
+        ]));
+        let rows: LinkedList<RowRecordBatch> = LinkedList::new();
+        rows.push_front(RowRecordBatch {
+            batch: vec![
+                vec![
+                    Data::Bool(true),
+                    Data::Float(1.5),
+                    Data::Double(2.5),
+                    Data::Decimal(12345),
+                    Data::Date(19000),
+                    Data::Timestamp(1_600_000_000),
+                    Data::Timestamp(1_600_000_000_000_001),
+                    Data::UInt32(u32::MAX),
+                    Data::UInt256([0xff; 32]),
+                    Data::Binary(vec![0xab; 20]),
+                ],
+                vec![
+                    Data::Null,
+                    Data::Null,
+                    Data::Null,
+                    Data::Null,
+                    Data::Null,
+                    Data::Null,
+                    Data::Null,
+                    Data::Null,
+                    Data::Null,
+                    Data::Null,
+                ],
+            ],
+            schema_version: 1,
+        })?;
+        let batch = rows_to_columns(&schema, &rows)?;
+        let decimal = batch
+            .column(3)
+            .as_any()
+            .downcast_ref::<DecimalArray>()
+            .expect("fail to down cast");
+        assert_eq!("123.45", decimal.value_as_string(0));
+        let ts = batch
+            .column(6)
+            .as_any()
+            .downcast_ref::<TimestampMicrosecondArray>()
+            .expect("fail to down cast");
+        assert_eq!(1_600_000_000_000_001, ts.value(0));
+        let amount = batch
+            .column(8)
+            .as_any()
+            .downcast_ref::<FixedSizeBinaryArray>()
+            .expect("fail to down cast");
+        assert_eq!(&[0xff; 32], amount.value(0));
+        assert!(amount.is_null(1));
+        let address = batch
+            .column(9)
+            .as_any()
+            .downcast_ref::<BinaryArray>()
+            .expect("fail to down cast");
+        assert_eq!(&[0xab; 20], address.value(0));
+        let tmp_dir = tempdir::TempDir::new("all_types").expect("create temp dir");
+        let path = tmp_dir.path().join("all.parquet");
+        let columns: LinkedList<RecordBatch> = LinkedList::new();
+        columns.push_front(batch.clone())?;
+        let properties = storage_config_to_writer_properties(&StorageBackendConfig::default());
+        dump_recordbatch(&path, &columns, &schema, &properties)?;
+        let read = read_recordbatch(&path)?;
+        assert_eq!(1, read.len());
+        for i in 0..schema.fields().len() {
+            assert_eq!(batch.column(i), read[0].column(i));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_nested_types_to_parquet() -> Result<()> {
+        // block {number, transactions: [{hash, value}], labels: {string: bigint}}
+        let transaction = gen_nested_column(
+            "item",
+            Db3Type::KStruct,
+            vec![
+                gen_nested_column("hash", Db3Type::KBinary, vec![]),
+                gen_nested_column("value", Db3Type::KUInt256, vec![]),
+            ],
+        );
+        let block = gen_nested_column(
+            "block",
+            Db3Type::KStruct,
+            vec![
+                gen_nested_column("number", Db3Type::KBigInt, vec![]),
+                gen_nested_column("transactions", Db3Type::KList, vec![transaction]),
+                gen_nested_column(
+                    "labels",
+                    Db3Type::KMap,
+                    vec![
+                        gen_nested_column("key", Db3Type::KStringUtf8, vec![]),
+                        gen_nested_column("value", Db3Type::KBigInt, vec![]),
+                    ],
+                ),
+            ],
+        );
+        let schema = table_desc_to_arrow_schema(&Db3SchemaDesc {
+            columns: vec![block],
+            version: 1,
+        })?;
+        let rows: LinkedList<RowRecordBatch> = LinkedList::new();
+        rows.push_front(RowRecordBatch {
+            batch: vec![
+                vec![Data::Struct(vec![
+                    Data::Int64(15048327),
+                    Data::List(vec![
+                        Data::Struct(vec![Data::Binary(vec![0x14]), Data::UInt256([1; 32])]),
+                        Data::Null,
+                    ]),
+                    Data::Map(vec![(Data::Varchar("miner".to_string()), Data::Int64(1))]),
+                ])],
+                vec![Data::Null],
+                vec![Data::Struct(vec![
+                    Data::Int64(15048328),
+                    Data::List(vec![]),
+                    Data::Null,
+                ])],
+            ],
+            schema_version: 1,
+        })?;
+        let batch = rows_to_columns(&schema, &rows)?;
+        let blocks = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<StructArray>()
+            .expect("fail to down cast");
+        assert!(blocks.is_null(1));
+        let transactions = blocks
+            .column_by_name("transactions")
+            .expect("transactions")
+            .as_any()
+            .downcast_ref::<ListArray>()
+            .expect("fail to down cast");
+        assert_eq!(2, transactions.value_length(0));
+        assert_eq!(0, transactions.value_length(2));
+        let tmp_dir = tempdir::TempDir::new("nested_types").expect("create temp dir");
+        let path = tmp_dir.path().join("nested.parquet");
+        let columns: LinkedList<RecordBatch> = LinkedList::new();
+        columns.push_front(batch.clone())?;
+        let properties = storage_config_to_writer_properties(&StorageBackendConfig::default());
+        dump_recordbatch(&path, &columns, &schema, &properties)?;
+        let read = read_recordbatch(&path)?;
+        assert_eq!(batch.column(0), read[0].column(0));
+        // describe prints the child types
+        let describe = schema_to_recordbatch(&schema)?;
+        let types = describe
+            .column(1)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .expect("fail to down cast");
+        assert_eq!(
+            "struct<number bigint,transactions struct<hash varbinary,value uint256>[],labels map<varchar(255),bigint>>",
+            types.value(0)
+        );
+
+        // the list of struct requires struct values
+        let rows: LinkedList<RowRecordBatch> = LinkedList::new();
+        rows.push_front(RowRecordBatch {
+            batch: vec![vec![Data::Struct(vec![
+                Data::Int64(1),
+                Data::List(vec![Data::Int64(1)]),
+                Data::Null,
+            ])]],
+            schema_version: 1,
+        })?;
+        assert!(rows_to_columns(&schema, &rows).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_time_range() -> Result<()> {
+        let fields = vec![
+            ArrowField::new("col1", DataType::Int64, false),
+            ArrowField::new(
+                "ts",
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                false,
+            ),
+        ];
+        let schema = Arc::new(Schema::new(fields));
+        assert_eq!(Some(1), get_time_column_index(&schema));
+        let batch = vec![
+            vec![Data::Int64(1), Data::Timestamp(1000)],
+            vec![Data::Int64(2), Data::Timestamp(3000)],
+            vec![Data::Int64(3), Data::Timestamp(2000)],
+        ];
+        let row_batch = RowRecordBatch {
+            batch,
+            schema_version: 1,
+        };
+        let ll: LinkedList<RowRecordBatch> = LinkedList::new();
+        ll.push_front(row_batch)?;
+        let record_batch = rows_to_columns(&schema, &ll)?;
+        assert_eq!(
+            Some((1000, 3000)),
+            get_time_range(std::iter::once(&record_batch), 1)
+        );
+        assert_eq!(None, get_time_range(std::iter::once(&record_batch), 0));
+        let filtered = filter_batch_by_time(&record_batch, 1, 2000)?;
+        assert_eq!(2, filtered.num_rows());
+        assert_eq!(
+            Some((2000, 3000)),
+            get_time_range(std::iter::once(&filtered), 1)
+        );
+        assert_eq!(
+            2,
+            timestamp_from_ms(&DataType::Timestamp(TimeUnit::Second, None), 2500)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rows_to_columns_with_null() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            ArrowField::new("c1", DataType::Int64, false),
+            ArrowField::new("c2", DataType::Utf8, true),
+        ]));
+        let rows: LinkedList<RowRecordBatch> = LinkedList::new();
+        rows.push_front(RowRecordBatch {
+            batch: vec![
+                vec![Data::Int64(1), Data::Null],
+                vec![Data::Int64(2), Data::Varchar("a".to_string())],
+            ],
+            schema_version: 1,
+        })?;
+        let batch = rows_to_columns(&schema, &rows)?;
+        assert_eq!(1, batch.column(1).null_count());
+        assert!(batch.column(1).is_null(0));
+        let rows: LinkedList<RowRecordBatch> = LinkedList::new();
+        rows.push_front(RowRecordBatch {
+            batch: vec![vec![Data::Null, Data::Null]],
+            schema_version: 1,
+        })?;
+        assert!(rows_to_columns(&schema, &rows).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_parquet_files() -> Result<()> {
+        let fields = vec![ArrowField::new(
+            "ts",
+            DataType::Timestamp(TimeUnit::Millisecond, None),
+            false,
+        )];
+        let schema = Arc::new(Schema::new(fields));
+        let tmp_dir = tempdir::TempDir::new("merge_parquet").expect("create temp dir");
+        let properties = storage_config_to_writer_properties(&StorageBackendConfig {
+            compression: ParquetCompression::KZstd as i32,
+            ..Default::default()
+        });
+        let mut batches: Vec<RecordBatch> = Vec::new();
+        for (i, ts) in [vec![3000, 1000], vec![2000]].iter().enumerate() {
+            let row_batch = RowRecordBatch {
+                batch: ts.iter().map(|t| vec![Data::Timestamp(*t)]).collect(),
+                schema_version: 1,
+            };
+            let rows: LinkedList<RowRecordBatch> = LinkedList::new();
+            rows.push_front(row_batch)?;
+            let columns: LinkedList<RecordBatch> = LinkedList::new();
+            columns.push_front(rows_to_columns(&schema, &rows)?)?;
+            let path = tmp_dir.path().join(format!("{}.parquet", i));
+            dump_recordbatch(&path, &columns, &schema, &properties)?;
+            let reader = SerializedFileReader::new(File::open(&path)?)?;
+            assert_eq!(
+                Compression::ZSTD,
+                reader.metadata().row_group(0).column(0).compression()
+            );
+            batches.append(&mut read_recordbatch(&path)?);
+        }
+        let merged = merge_and_sort_batches(&schema, &batches, get_time_column_index(&schema))?;
+        let array = merged
+            .column(0)
+            .as_any()
+            .downcast_ref::<TimestampMillisecondArray>()
+            .expect("fail to down cast");
+        assert_eq!(vec![1000, 2000, 3000], array.values().to_vec());
+        Ok(())
+    }
 }
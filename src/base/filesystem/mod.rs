@@ -71,6 +71,13 @@ pub trait FileSystem: Send + Sync {
         self.open_writable_file_writer(file_name)
     }
 
+    /// open an existing file to write after the offset
+    fn open_appendable_file_writer(
+        &self,
+        file_name: &Path,
+        offset: usize,
+    ) -> Result<Box<WritableFileWriter>>;
+
     fn open_sequential_file(&self, path: &Path) -> Result<Box<SequentialFileReader>>;
 
     fn read_file_content(&self, path: &Path) -> Result<Vec<u8>> {
@@ -188,6 +188,17 @@ impl PosixWritableFile {
         Ok(Self::new(Arc::new(fd), file_size))
     }
 
+    /// open a file to write from the offset, the bytes after it are dropped
+    pub fn open_for_append<P: ?Sized + NixPath>(path: &P, offset: usize) -> Result<Self> {
+        let fd = RawFile::create(path)?;
+        fd.truncate(offset)?;
+        Ok(Self {
+            inner: Arc::new(fd),
+            offset,
+            capacity: offset,
+        })
+    }
+
     pub fn new(fd: Arc<RawFile>, capacity: usize) -> Self {
         Self {
             inner: fd,
@@ -230,6 +241,10 @@ impl WritableFile for PosixWritableFile {
         self.inner.sync()?;
         Ok(())
     }
+
+    fn get_file_size(&self) -> usize {
+        self.offset
+    }
 }
 
 impl Write for PosixWritableFile {
@@ -310,6 +325,16 @@ impl FileSystem for SyncPosixFileSystem {
         Ok(Box::new(writer))
     }
 
+    fn open_appendable_file_writer(
+        &self,
+        path: &Path,
+        offset: usize,
+    ) -> Result<Box<WritableFileWriter>> {
+        let f = PosixWritableFile::open_for_append(path, offset)?;
+        let writer = WritableFileWriter::new(Box::new(f), 0);
+        Ok(Box::new(writer))
+    }
+
     fn open_sequential_file(&self, path: &Path) -> Result<Box<SequentialFileReader>> {
         let f = PosixSequentialFile::open(path)?;
         let reader = SequentialFileReader::new(
@@ -133,4 +133,62 @@ mod tests {
             panic!("should not be here");
         }
     }
+
+    #[test]
+    fn batch_write_read_test() {
+        let tmp_dir_path = TempDir::new("log_batch_test").expect("create temp dir");
+        let log_path_str = format!("{}/xxxx.log", tmp_dir_path.path().to_str().unwrap());
+        let log_path = Path::new(&log_path_str);
+        let fs = SyncPosixFileSystem {};
+        let writer = fs.open_writable_file_writer(log_path).unwrap();
+        let mut log_writer = LogWriter::new(writer, 1);
+        let big_data = vec![7u8; 40000];
+        let records: Vec<&[u8]> = vec!["hello".as_bytes(), &big_data, "world".as_bytes()];
+        assert!(log_writer.add_records(&records).is_ok());
+        assert!(log_writer.fsync().is_ok());
+        let reader = fs.open_sequential_file(log_path).unwrap();
+        let mut log_reader = LogReader::new(reader);
+        let mut buffer: Vec<u8> = Vec::new();
+        for record in records {
+            assert!(log_reader.read_record(&mut buffer).unwrap());
+            assert_eq!(record, buffer.as_slice());
+        }
+    }
+
+    #[test]
+    fn reopen_write_read_test() {
+        let tmp_dir_path = TempDir::new("log_reopen_test").expect("create temp dir");
+        let log_path_str = format!("{}/xxxx.log", tmp_dir_path.path().to_str().unwrap());
+        let log_path = Path::new(&log_path_str);
+        let fs = SyncPosixFileSystem {};
+        let big_data = vec![7u8; 10000];
+        {
+            let writer = fs.open_writable_file_writer(log_path).unwrap();
+            let mut log_writer = LogWriter::new(writer, 1);
+            assert!(log_writer.add_record("hello".as_bytes()).is_ok());
+            assert!(log_writer.add_record(&big_data).is_ok());
+            assert!(log_writer.fsync().is_ok());
+        }
+        let reader = fs.open_sequential_file(log_path).unwrap();
+        let mut log_reader = LogReader::new(reader);
+        let mut buffer: Vec<u8> = Vec::new();
+        while let Ok(true) = log_reader.read_record(&mut buffer) {}
+        let end_offset = log_reader.get_last_record_end_offset();
+        // the big record is split to three fragments
+        assert_eq!(4 * HEADER_SIZE + 5 + 10000, end_offset);
+        // the records appended after reopening follow the old ones
+        let writer = fs
+            .open_appendable_file_writer(log_path, end_offset)
+            .unwrap();
+        let mut log_writer = LogWriter::reopen(writer, 1);
+        assert!(log_writer.add_record("world".as_bytes()).is_ok());
+        assert!(log_writer.fsync().is_ok());
+        let reader = fs.open_sequential_file(log_path).unwrap();
+        let mut log_reader = LogReader::new(reader);
+        let records: Vec<&[u8]> = vec!["hello".as_bytes(), &big_data, "world".as_bytes()];
+        for record in records {
+            assert!(log_reader.read_record(&mut buffer).unwrap());
+            assert_eq!(record, buffer.as_slice());
+        }
+    }
 }
@@ -27,6 +27,8 @@ pub struct LogReader {
     data: Slice,
     end_of_buffer_offset: usize,
     eof: bool,
+    // the offset in file after the last record read
+    last_record_end_offset: usize,
 }
 
 impl LogReader {
@@ -37,9 +39,14 @@ impl LogReader {
             data: Slice::default(),
             end_of_buffer_offset: 0,
             eof: false,
+            last_record_end_offset: 0,
         }
     }
 
+    pub fn get_last_record_end_offset(&self) -> usize {
+        self.last_record_end_offset
+    }
+
     pub fn read_record(&mut self, record: &mut Vec<u8>) -> Result<bool> {
         let mut in_fragmented_record = false;
         record.clear();
@@ -54,6 +61,7 @@ impl LogReader {
                         record.extend_from_slice(&self.buffer[fragment.offset..fragment.limit]);
                         // prospective_record_offset = physical_record_offset;
                         // self.last_record_offset = prospective_record_offset;
+                        self.last_record_end_offset = self.end_of_buffer_offset - self.data.len();
                         return Ok(true);
                     }
                     RecordType::FirstType => {
@@ -79,6 +87,7 @@ impl LogReader {
                             )));
                         }
                         record.extend_from_slice(&self.buffer[fragment.offset..fragment.limit]);
+                        self.last_record_end_offset = self.end_of_buffer_offset - self.data.len();
                         return Ok(true);
                     }
                     _ => {
@@ -46,6 +46,15 @@ impl LogWriter {
         }
     }
 
+    /// continue a log file after its last record, the writer is positioned at the end of it
+    pub fn reopen(writer: Box<WritableFileWriter>, log_number: u64) -> Self {
+        let block_offset = writer.file_size() % BLOCK_SIZE;
+        LogWriter {
+            block_offset,
+            ..Self::new(writer, log_number)
+        }
+    }
+
     pub fn get_log_number(&self) -> u64 {
         self.log_number
     }
@@ -63,6 +72,21 @@ impl LogWriter {
     }
 
     pub fn add_record(&mut self, data: &[u8]) -> Result<()> {
+        self.emit_record(data)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// write a group of records with only one flush
+    pub fn add_records(&mut self, records: &[&[u8]]) -> Result<()> {
+        for data in records {
+            self.emit_record(data)?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn emit_record(&mut self, data: &[u8]) -> Result<()> {
         if data.is_empty() {
             return Ok(());
         }
@@ -93,7 +117,6 @@ impl LogWriter {
             left -= fragment_length;
             begin = false;
         }
-        self.writer.flush()?;
         Ok(())
     }
 
@@ -21,7 +21,9 @@ pub mod filesystem;
 pub mod linked_list;
 pub mod log;
 pub mod mysql_utils;
+pub mod partition_utils;
 pub mod slice;
 pub mod strings;
 pub mod test_base;
 pub mod time_utils;
+pub mod uint256;
@@ -16,32 +16,59 @@
 // limitations under the License.
 //
 
+use crate::base::arrow_parquet_utils;
+use crate::base::strings;
+use crate::base::uint256;
 use crate::codec::row_codec::{Data, RowRecordBatch};
 use crate::error::{DB3Error, Result};
-use crate::proto::db3_base_proto::{Db3ColumnDesc, Db3SchemaDesc, Db3Type};
+use crate::proto::db3_base_proto::{
+    BinlogDurability, Db3ColumnDesc, Db3PartitionDesc, Db3SchemaDesc, Db3Type, ParquetCompression,
+    StorageBackendConfig,
+};
 use arrow::array::{
-    Float32Array, Float64Array, Int16Array, Int32Array, Int64Array, Int8Array, StringArray,
-    TimestampMicrosecondArray, TimestampMillisecondArray, TimestampNanosecondArray,
-    TimestampSecondArray,
+    Array, ArrayRef, BinaryArray, BooleanArray, Date32Array, DecimalArray, FixedSizeBinaryArray,
+    Float32Array, Float64Array, Int16Array, Int32Array, Int64Array, Int8Array, ListArray, MapArray,
+    StringArray, StructArray, TimestampMicrosecondArray, TimestampMillisecondArray,
+    TimestampNanosecondArray, TimestampSecondArray, UInt16Array, UInt32Array, UInt64Array,
+    UInt8Array,
 };
-use arrow::datatypes::{DataType, SchemaRef, TimeUnit};
+use arrow::datatypes::{DataType, Field, SchemaRef, TimeUnit};
 use arrow::record_batch::RecordBatch;
-use chrono::NaiveDateTime;
+use arrow::util::display::array_value_to_string;
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
 use msql_srv::Column as MySQLColumn;
 use msql_srv::ColumnFlags;
 use msql_srv::ColumnType;
+use msql_srv::ErrorKind;
 use msql_srv::OkResponse;
 use msql_srv::QueryResultWriter;
-use sqlparser::ast::{ColumnDef, ColumnOption, DataType as SPDataType, Expr, Value};
+use serde_json::{Map as JsonMap, Value as JsonValue};
+use sqlparser::ast::{
+    ColumnDef, ColumnOption, DataType as SPDataType, Expr, Ident, ObjectName, SqlOption, Value,
+};
+use sqlparser::dialect::MySqlDialect;
+use sqlparser::parser::Parser;
+use sqlparser::tokenizer::Tokenizer;
+use std::str::FromStr;
 uselog!(info, warn);
 
+// the days from 0001-01-01 to 1970-01-01
+const EPOCH_DAYS_FROM_CE: i32 = 719_163;
+
 macro_rules! type_mapping {
     ($columns:ident, $right_type:ident, $field:ident) => {
+        type_mapping!($columns, $right_type, $field, ColumnFlags::empty());
+    };
+    ($columns:ident, $right_type:ident, $field:ident, $flags:expr) => {
         let col = MySQLColumn {
             table: "".to_string(),
             column: $field.name().to_string(),
             coltype: ColumnType::$right_type,
-            colflags: ColumnFlags::empty(),
+            colflags: if $field.is_nullable() {
+                $flags
+            } else {
+                $flags | ColumnFlags::NOT_NULL_FLAG
+            },
         };
         $columns.push(col);
     };
@@ -51,9 +78,15 @@ pub fn sql_to_row_batch(schema: &Db3SchemaDesc, values: &[Expr]) -> Result<RowRe
     let mut row: Vec<Data> = Vec::new();
     for (i, item) in values.iter().enumerate().take(schema.columns.len()) {
         let column_desc = &schema.columns[i];
-        let ctype = Db3Type::from_i32(column_desc.ctype);
-        if let (Expr::Value(v), Some(local_type)) = (item, ctype) {
-            let data = sql_value_to_data(v, &local_type)?;
+        if let Expr::Value(Value::Null) = item {
+            if !column_desc.null_allowed {
+                return Err(DB3Error::TableColumnNotNullError {
+                    name: column_desc.name.to_string(),
+                });
+            }
+            row.push(Data::Null);
+        } else if let Expr::Value(v) = item {
+            let data = sql_value_to_data(v, column_desc)?;
             row.push(data);
         } else {
             warn!("invalid expr {}", item);
@@ -65,42 +98,320 @@ pub fn sql_to_row_batch(schema: &Db3SchemaDesc, values: &[Expr]) -> Result<RowRe
     })
 }
 
-pub fn sql_value_to_data(val: &Value, store_type: &Db3Type) -> Result<Data> {
-    match (store_type, val) {
-        (Db3Type::KStringUtf8, Value::SingleQuotedString(s)) => Ok(Data::Varchar(s.to_string())),
-        (Db3Type::KStringUtf8, Value::DoubleQuotedString(s)) => Ok(Data::Varchar(s.to_string())),
-        (Db3Type::KBigInt, Value::Number(v, _)) => {
-            let val_int: i64 = v.parse().unwrap();
-            Ok(Data::Int64(val_int))
+fn parse_number<T: FromStr>(v: &str) -> Option<T> {
+    v.trim().parse().ok()
+}
+
+/// parse a decimal string to the unscaled value with the scale, the dropped digits are rounded
+fn parse_decimal(v: &str, scale: usize) -> Option<i128> {
+    let v = v.trim();
+    let (negative, digits) = match v.strip_prefix('-') {
+        Some(d) => (true, d),
+        None => (false, v.strip_prefix('+').unwrap_or(v)),
+    };
+    let (int_part, frac_part) = digits.split_once('.').unwrap_or((digits, ""));
+    if int_part.is_empty() && frac_part.is_empty() {
+        return None;
+    }
+    let frac = frac_part.chars().chain(std::iter::repeat('0')).take(scale);
+    let mut unscaled: i128 = 0;
+    for c in int_part.chars().chain(frac) {
+        unscaled = unscaled
+            .checked_mul(10)?
+            .checked_add(c.to_digit(10)? as i128)?;
+    }
+    match frac_part.chars().nth(scale) {
+        Some(c) if c.is_ascii_digit() && c >= '5' => unscaled = unscaled.checked_add(1)?,
+        Some(c) if !c.is_ascii_digit() => return None,
+        _ => {}
+    }
+    if frac_part
+        .chars()
+        .skip(scale + 1)
+        .any(|c| !c.is_ascii_digit())
+    {
+        return None;
+    }
+    Some(if negative { -unscaled } else { unscaled })
+}
+
+/// the bytes of a 0x prefixed hex or a x'' literal
+fn sql_value_to_bytes(val: &Value) -> Option<Vec<u8>> {
+    match val {
+        Value::HexStringLiteral(v) => strings::hex_to_bytes(v),
+        Value::Number(v, _) | Value::SingleQuotedString(v) | Value::DoubleQuotedString(v)
+            if v.starts_with("0x") || v.starts_with("0X") =>
+        {
+            strings::hex_to_bytes(v)
+        }
+        _ => None,
+    }
+}
+
+// the units in a second and the nanoseconds in a unit
+fn time_unit_scale(unit: &TimeUnit) -> (i64, i64) {
+    match unit {
+        TimeUnit::Second => (1, 1_000_000_000),
+        TimeUnit::Millisecond => (1_000, 1_000_000),
+        TimeUnit::Microsecond => (1_000_000, 1_000),
+        TimeUnit::Nanosecond => (1_000_000_000, 1),
+    }
+}
+
+/// convert a time string to the value of timestamp with the unit
+fn parse_timestamp(v: &str, unit: TimeUnit) -> Option<i64> {
+    let time = NaiveDateTime::parse_from_str(v.trim(), "%Y-%m-%d %H:%M:%S%.f").ok()?;
+    let (units_per_second, nanos_per_unit) = time_unit_scale(&unit);
+    time.timestamp()
+        .checked_mul(units_per_second)?
+        .checked_add(time.timestamp_subsec_nanos() as i64 / nanos_per_unit)
+}
+
+/// convert the value of timestamp to time, the euclidean division keeps the nanoseconds
+/// positive for the time before 1970 which the arrow conversion panics on
+fn timestamp_to_datetime(v: i64, unit: &TimeUnit) -> Option<NaiveDateTime> {
+    let (units_per_second, nanos_per_unit) = time_unit_scale(unit);
+    NaiveDateTime::from_timestamp_opt(
+        v.div_euclid(units_per_second),
+        (v.rem_euclid(units_per_second) * nanos_per_unit) as u32,
+    )
+}
+
+/// convert a sql value to the data of column
+pub fn sql_value_to_data(val: &Value, column: &Db3ColumnDesc) -> Result<Data> {
+    let store_type =
+        Db3Type::from_i32(column.ctype).ok_or(DB3Error::TableSchemaConvertError(column.ctype))?;
+    let mismatch = || DB3Error::TableTypeMismatchError {
+        left: format!("{:?} of column {}", store_type, column.name),
+        right: format!("{}", val),
+    };
+    let data = match (store_type, val) {
+        (Db3Type::KStringUtf8, Value::SingleQuotedString(s))
+        | (Db3Type::KStringUtf8, Value::DoubleQuotedString(s)) => {
+            Some(Data::Varchar(s.to_string()))
         }
-        (Db3Type::KInt, Value::Number(v, _)) => {
-            let val_int: i32 = v.parse().unwrap();
-            Ok(Data::Int32(val_int))
+        (Db3Type::KBool, Value::Boolean(b)) => Some(Data::Bool(*b)),
+        (Db3Type::KBool, Value::Number(v, _)) => match v.as_str() {
+            "0" => Some(Data::Bool(false)),
+            "1" => Some(Data::Bool(true)),
+            _ => None,
+        },
+        (Db3Type::KTinyInt, Value::Number(v, _)) => parse_number(v).map(Data::Int8),
+        (Db3Type::KSmallInt, Value::Number(v, _)) => parse_number(v).map(Data::Int16),
+        (Db3Type::KInt, Value::Number(v, _)) => parse_number(v).map(Data::Int32),
+        (Db3Type::KBigInt, Value::Number(v, _)) => parse_number(v).map(Data::Int64),
+        (Db3Type::KUTinyInt, Value::Number(v, _)) => parse_number(v).map(Data::UInt8),
+        (Db3Type::KUSmallInt, Value::Number(v, _)) => parse_number(v).map(Data::UInt16),
+        (Db3Type::KUInt, Value::Number(v, _)) => parse_number(v).map(Data::UInt32),
+        (Db3Type::KUBigInt, Value::Number(v, _)) => parse_number(v).map(Data::UInt64),
+        (Db3Type::KFloat, Value::Number(v, _)) => parse_number(v).map(Data::Float),
+        (Db3Type::KDouble, Value::Number(v, _)) => parse_number(v).map(Data::Double),
+        (Db3Type::KDecimal, Value::Number(v, _))
+        | (Db3Type::KDecimal, Value::SingleQuotedString(v))
+        | (Db3Type::KDecimal, Value::DoubleQuotedString(v)) => {
+            let (precision, scale) = arrow_parquet_utils::get_decimal_precision_scale(column);
+            // the digits must fit in the precision of column
+            parse_decimal(v, scale)
+                .filter(|d| d.unsigned_abs() < 10u128.pow(precision as u32))
+                .map(Data::Decimal)
         }
-        (Db3Type::KFloat, Value::Number(v, _)) => {
-            let val: f32 = v.parse().unwrap();
-            Ok(Data::Float(val))
+        // the string could be a 0x prefixed hex
+        (Db3Type::KUInt256, Value::Number(v, _))
+        | (Db3Type::KUInt256, Value::SingleQuotedString(v))
+        | (Db3Type::KUInt256, Value::DoubleQuotedString(v)) => {
+            uint256::parse_uint256(v).map(Data::UInt256)
         }
-        (Db3Type::KDouble, Value::Number(v, _)) => {
-            let val: f64 = v.parse().unwrap();
-            Ok(Data::Double(val))
+        // the values of nested column are written as json
+        (Db3Type::KStruct, Value::SingleQuotedString(s))
+        | (Db3Type::KList, Value::SingleQuotedString(s))
+        | (Db3Type::KMap, Value::SingleQuotedString(s)) => {
+            match serde_json::from_str::<JsonValue>(s) {
+                Ok(json) => Some(json_to_data(&json, column)?),
+                Err(_) => None,
+            }
         }
+        (Db3Type::KBinary, _) => sql_value_to_bytes(val).map(Data::Binary),
+        (Db3Type::KFixedBinary, _) => sql_value_to_bytes(val)
+            .filter(|b| b.len() == column.byte_width as usize)
+            .map(Data::Binary),
         (Db3Type::KDate, Value::SingleQuotedString(s))
         | (Db3Type::KDate, Value::DoubleQuotedString(s)) => {
-            let time = NaiveDateTime::parse_from_str(s, "%Y-%m-%d").unwrap();
-            let ts = time.timestamp() / (24 * 60 * 60);
-            Ok(Data::Date(ts as u32))
+            NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d")
+                .ok()
+                .map(|d| Data::Date(d.num_days_from_ce() - EPOCH_DAYS_FROM_CE))
+        }
+        (Db3Type::KTimestampSecond, Value::SingleQuotedString(s))
+        | (Db3Type::KTimestampSecond, Value::DoubleQuotedString(s)) => {
+            parse_timestamp(s, TimeUnit::Second).map(Data::Timestamp)
         }
         (Db3Type::KTimestampMillsSecond, Value::SingleQuotedString(s))
         | (Db3Type::KTimestampMillsSecond, Value::DoubleQuotedString(s)) => {
-            let time = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap();
-            let ts = time.timestamp() * 1000;
-            Ok(Data::Timestamp(ts as u64))
+            parse_timestamp(s, TimeUnit::Millisecond).map(Data::Timestamp)
+        }
+        (Db3Type::KTimestampMicroSecond, Value::SingleQuotedString(s))
+        | (Db3Type::KTimestampMicroSecond, Value::DoubleQuotedString(s)) => {
+            parse_timestamp(s, TimeUnit::Microsecond).map(Data::Timestamp)
+        }
+        // a number is the time in the unit of column
+        (Db3Type::KTimestampSecond, Value::Number(v, _))
+        | (Db3Type::KTimestampMillsSecond, Value::Number(v, _))
+        | (Db3Type::KTimestampMicroSecond, Value::Number(v, _)) => {
+            parse_number(v).map(Data::Timestamp)
+        }
+        (_, _) => None,
+    };
+    data.ok_or_else(mismatch)
+}
+
+/// convert a json value to the data of column, the missing fields of struct are null
+fn json_to_data(value: &JsonValue, column: &Db3ColumnDesc) -> Result<Data> {
+    let store_type =
+        Db3Type::from_i32(column.ctype).ok_or(DB3Error::TableSchemaConvertError(column.ctype))?;
+    let mismatch = || DB3Error::TableTypeMismatchError {
+        left: format!("{:?} of column {}", store_type, column.name),
+        right: value.to_string(),
+    };
+    match (store_type, value) {
+        (_, JsonValue::Null) => Ok(Data::Null),
+        (Db3Type::KStruct, JsonValue::Object(fields)) => column
+            .children
+            .iter()
+            .map(|c| json_to_data(fields.get(&c.name).unwrap_or(&JsonValue::Null), c))
+            .collect::<Result<Vec<Data>>>()
+            .map(Data::Struct),
+        (Db3Type::KList, JsonValue::Array(items)) => {
+            let item = column.children.first().ok_or_else(mismatch)?;
+            items
+                .iter()
+                .map(|v| json_to_data(v, item))
+                .collect::<Result<Vec<Data>>>()
+                .map(Data::List)
+        }
+        (Db3Type::KMap, JsonValue::Object(entries)) => match column.children.as_slice() {
+            // the keys of json are strings, they are parsed as numbers for the number keys
+            [key, value] => entries
+                .iter()
+                .map(|(k, v)| {
+                    let key_data =
+                        json_to_data(&JsonValue::String(k.to_string()), key).or_else(|_| {
+                            sql_value_to_data(&Value::Number(k.to_string(), false), key)
+                        })?;
+                    Ok((key_data, json_to_data(v, value)?))
+                })
+                .collect::<Result<Vec<(Data, Data)>>>()
+                .map(Data::Map),
+            _ => Err(mismatch()),
+        },
+        (Db3Type::KStruct, _) | (Db3Type::KList, _) | (Db3Type::KMap, _) => Err(mismatch()),
+        // the scalar values are converted as the sql values
+        (_, JsonValue::Bool(b)) => sql_value_to_data(&Value::Boolean(*b), column),
+        (_, JsonValue::Number(n)) => {
+            sql_value_to_data(&Value::Number(n.to_string(), false), column)
+        }
+        (_, JsonValue::String(s)) => {
+            sql_value_to_data(&Value::SingleQuotedString(s.to_string()), column)
+        }
+        (_, _) => Err(mismatch()),
+    }
+}
+
+/// the json of a value in nested array, the bytes are hex strings and uint256 are decimal
+/// strings
+fn nested_value_to_json(field: &Field, array: &ArrayRef, i: usize) -> Result<JsonValue> {
+    if array.is_null(i) {
+        return Ok(JsonValue::Null);
+    }
+    match field.data_type() {
+        DataType::Struct(fields) => {
+            let arr = array
+                .as_any()
+                .downcast_ref::<StructArray>()
+                .expect("Failed to downcast");
+            let mut object = JsonMap::new();
+            for (field, column) in fields.iter().zip(arr.columns()) {
+                object.insert(
+                    field.name().to_string(),
+                    nested_value_to_json(field, column, i)?,
+                );
+            }
+            Ok(JsonValue::Object(object))
+        }
+        DataType::List(item) => {
+            let items = array
+                .as_any()
+                .downcast_ref::<ListArray>()
+                .expect("Failed to downcast")
+                .value(i);
+            (0..items.len())
+                .map(|j| nested_value_to_json(item, &items, j))
+                .collect::<Result<Vec<JsonValue>>>()
+                .map(JsonValue::Array)
+        }
+        DataType::Map(entries_field, _) => {
+            let (key_field, value_field) = match entries_field.data_type() {
+                DataType::Struct(fields) if fields.len() == 2 => (&fields[0], &fields[1]),
+                data_type => {
+                    return Err(DB3Error::TableTypeMismatchError {
+                        left: "map entries".to_string(),
+                        right: format!("{:?}", data_type),
+                    })
+                }
+            };
+            let entries = array
+                .as_any()
+                .downcast_ref::<MapArray>()
+                .expect("Failed to downcast")
+                .value(i);
+            let entries = entries
+                .as_any()
+                .downcast_ref::<StructArray>()
+                .expect("Failed to downcast");
+            let mut object = JsonMap::new();
+            for j in 0..entries.len() {
+                let key = match nested_value_to_json(key_field, entries.column(0), j)? {
+                    JsonValue::String(s) => s,
+                    other => other.to_string(),
+                };
+                object.insert(
+                    key,
+                    nested_value_to_json(value_field, entries.column(1), j)?,
+                );
+            }
+            Ok(JsonValue::Object(object))
+        }
+        DataType::Binary => {
+            let arr = array
+                .as_any()
+                .downcast_ref::<BinaryArray>()
+                .expect("Failed to downcast");
+            Ok(JsonValue::String(strings::bytes_to_hex(arr.value(i))))
+        }
+        DataType::FixedSizeBinary(_) => {
+            let arr = array
+                .as_any()
+                .downcast_ref::<FixedSizeBinaryArray>()
+                .expect("Failed to downcast");
+            if arrow_parquet_utils::is_uint256_field(field) {
+                Ok(JsonValue::String(uint256::uint256_to_string(arr.value(i))))
+            } else {
+                Ok(JsonValue::String(strings::bytes_to_hex(arr.value(i))))
+            }
+        }
+        DataType::Boolean => {
+            let arr = array
+                .as_any()
+                .downcast_ref::<BooleanArray>()
+                .expect("Failed to downcast");
+            Ok(JsonValue::Bool(arr.value(i)))
+        }
+        data_type => {
+            let v = array_value_to_string(array, i)?;
+            if DataType::is_numeric(data_type) && !matches!(data_type, DataType::Decimal(..)) {
+                Ok(serde_json::from_str(&v).unwrap_or(JsonValue::String(v)))
+            } else {
+                Ok(JsonValue::String(v))
+            }
         }
-        (_, _) => Err(DB3Error::TableTypeMismatchError {
-            left: "left".to_string(),
-            right: "right".to_string(),
-        }),
     }
 }
 
@@ -112,8 +423,41 @@ pub fn record_batch_schema_to_mysql_schema(schema: &SchemaRef) -> Result<Vec<MyS
         // all parquet types go to
         // https://github.com/apache/arrow-rs/blob/master/arrow/src/datatypes/datatype.rs#L43
         match field.data_type() {
+            // the bool of mysql is tinyint(1)
             DataType::Boolean => {
-                type_mapping!(mysql_cols, MYSQL_TYPE_BIT, field);
+                type_mapping!(mysql_cols, MYSQL_TYPE_TINY, field);
+            }
+            DataType::UInt8 => {
+                type_mapping!(
+                    mysql_cols,
+                    MYSQL_TYPE_TINY,
+                    field,
+                    ColumnFlags::UNSIGNED_FLAG
+                );
+            }
+            DataType::UInt16 => {
+                type_mapping!(
+                    mysql_cols,
+                    MYSQL_TYPE_SHORT,
+                    field,
+                    ColumnFlags::UNSIGNED_FLAG
+                );
+            }
+            DataType::UInt32 => {
+                type_mapping!(
+                    mysql_cols,
+                    MYSQL_TYPE_LONG,
+                    field,
+                    ColumnFlags::UNSIGNED_FLAG
+                );
+            }
+            DataType::UInt64 => {
+                type_mapping!(
+                    mysql_cols,
+                    MYSQL_TYPE_LONGLONG,
+                    field,
+                    ColumnFlags::UNSIGNED_FLAG
+                );
             }
             DataType::Int8 => {
                 type_mapping!(mysql_cols, MYSQL_TYPE_TINY, field);
@@ -131,7 +475,7 @@ pub fn record_batch_schema_to_mysql_schema(schema: &SchemaRef) -> Result<Vec<MyS
                 type_mapping!(mysql_cols, MYSQL_TYPE_FLOAT, field);
             }
             DataType::Float64 => {
-                type_mapping!(mysql_cols, MYSQL_TYPE_FLOAT, field);
+                type_mapping!(mysql_cols, MYSQL_TYPE_DOUBLE, field);
             }
             DataType::Timestamp(..) => {
                 type_mapping!(mysql_cols, MYSQL_TYPE_TIMESTAMP, field);
@@ -139,11 +483,31 @@ pub fn record_batch_schema_to_mysql_schema(schema: &SchemaRef) -> Result<Vec<MyS
             DataType::Utf8 => {
                 type_mapping!(mysql_cols, MYSQL_TYPE_STRING, field);
             }
+            // the nested values are shown as json
+            DataType::Struct(_) | DataType::List(_) | DataType::Map(..) => {
+                type_mapping!(mysql_cols, MYSQL_TYPE_JSON, field);
+            }
+            // the bytes are shown as hex strings
+            DataType::Binary => {
+                type_mapping!(mysql_cols, MYSQL_TYPE_STRING, field);
+            }
             DataType::Date32 => {
                 type_mapping!(mysql_cols, MYSQL_TYPE_DATE, field);
             }
             DataType::Decimal(..) => {
-                type_mapping!(mysql_cols, MYSQL_TYPE_DECIMAL, field);
+                type_mapping!(mysql_cols, MYSQL_TYPE_NEWDECIMAL, field);
+            }
+            // the uint256 is decimal(78, 0) unsigned for clients
+            DataType::FixedSizeBinary(_) if arrow_parquet_utils::is_uint256_field(field) => {
+                type_mapping!(
+                    mysql_cols,
+                    MYSQL_TYPE_NEWDECIMAL,
+                    field,
+                    ColumnFlags::UNSIGNED_FLAG
+                );
+            }
+            DataType::FixedSizeBinary(_) => {
+                type_mapping!(mysql_cols, MYSQL_TYPE_STRING, field);
             }
             _ => {
                 return Err(DB3Error::TableSchemaConvertError(0));
@@ -167,6 +531,17 @@ macro_rules! mysql_data_convert {
     };
 }
 
+macro_rules! downcast_value {
+    ($record_batch:ident, $column_idx:ident, $row_idx:ident, $array_type:ident) => {
+        $record_batch
+            .column($column_idx)
+            .as_any()
+            .downcast_ref::<$array_type>()
+            .expect("Failed to downcast")
+            .value($row_idx)
+    };
+}
+
 pub fn write_batch_to_resultset<'a, W: std::io::Write + Send>(
     record_batches: &[RecordBatch],
     results: QueryResultWriter<'a, W>,
@@ -181,11 +556,75 @@ pub fn write_batch_to_resultset<'a, W: std::io::Write + Send>(
     for batch in record_batches {
         for i in 0..batch.num_rows() {
             for j in 0..batch.num_columns() {
+                if batch.column(j).is_null(i) {
+                    rw.write_col(None::<i64>)?;
+                    continue;
+                }
                 let data_type = schema.field(j).data_type();
                 match data_type {
+                    DataType::Boolean => {
+                        let arr = batch
+                            .column(j)
+                            .as_any()
+                            .downcast_ref::<BooleanArray>()
+                            .expect("Failed to downcast");
+                        rw.write_col(arr.value(i) as i8)?;
+                    }
                     DataType::Int8 => {
                         mysql_data_convert!(i, j, Int8Array, rw, batch);
                     }
+                    DataType::UInt8 => {
+                        mysql_data_convert!(i, j, UInt8Array, rw, batch);
+                    }
+                    DataType::UInt16 => {
+                        mysql_data_convert!(i, j, UInt16Array, rw, batch);
+                    }
+                    DataType::UInt32 => {
+                        mysql_data_convert!(i, j, UInt32Array, rw, batch);
+                    }
+                    DataType::UInt64 => {
+                        mysql_data_convert!(i, j, UInt64Array, rw, batch);
+                    }
+                    DataType::Date32 => {
+                        let arr = batch
+                            .column(j)
+                            .as_any()
+                            .downcast_ref::<Date32Array>()
+                            .expect("Failed to downcast");
+                        rw.write_col(arr.value_as_date(i))?;
+                    }
+                    DataType::Decimal(..) => {
+                        let arr = batch
+                            .column(j)
+                            .as_any()
+                            .downcast_ref::<DecimalArray>()
+                            .expect("Failed to downcast");
+                        rw.write_col(arr.value_as_string(i))?;
+                    }
+                    DataType::FixedSizeBinary(_) => {
+                        let arr = batch
+                            .column(j)
+                            .as_any()
+                            .downcast_ref::<FixedSizeBinaryArray>()
+                            .expect("Failed to downcast");
+                        if arrow_parquet_utils::is_uint256_field(schema.field(j)) {
+                            rw.write_col(uint256::uint256_to_string(arr.value(i)))?;
+                        } else {
+                            rw.write_col(strings::bytes_to_hex(arr.value(i)))?;
+                        }
+                    }
+                    DataType::Binary => {
+                        let arr = batch
+                            .column(j)
+                            .as_any()
+                            .downcast_ref::<BinaryArray>()
+                            .expect("Failed to downcast");
+                        rw.write_col(strings::bytes_to_hex(arr.value(i)))?;
+                    }
+                    DataType::Struct(_) | DataType::List(_) | DataType::Map(..) => {
+                        let json = nested_value_to_json(schema.field(j), batch.column(j), i)?;
+                        rw.write_col(json.to_string())?;
+                    }
                     DataType::Int16 => {
                         mysql_data_convert!(i, j, Int16Array, rw, batch);
                     }
@@ -204,44 +643,29 @@ pub fn write_batch_to_resultset<'a, W: std::io::Write + Send>(
                     DataType::Utf8 => {
                         mysql_data_convert!(i, j, StringArray, rw, batch);
                     }
-                    DataType::Timestamp(tu, _) => match tu {
-                        TimeUnit::Second => {
-                            let arr = batch
-                                .column(j)
-                                .as_any()
-                                .downcast_ref::<TimestampSecondArray>()
-                                .expect("Failed to downcast");
-                            let v = arr.value_as_datetime(i);
-                            rw.write_col(v)?;
-                        }
-                        TimeUnit::Millisecond => {
-                            let arr = batch
-                                .column(j)
-                                .as_any()
-                                .downcast_ref::<TimestampMillisecondArray>()
-                                .expect("Failed to downcast");
-                            let v = arr.value_as_datetime(i);
-                            rw.write_col(v)?;
-                        }
-                        TimeUnit::Microsecond => {
-                            let arr = batch
-                                .column(j)
-                                .as_any()
-                                .downcast_ref::<TimestampMicrosecondArray>()
-                                .expect("Failed to downcast");
-                            let v = arr.value_as_datetime(i);
-                            rw.write_col(v)?;
-                        }
-                        TimeUnit::Nanosecond => {
-                            let arr = batch
-                                .column(j)
-                                .as_any()
-                                .downcast_ref::<TimestampNanosecondArray>()
-                                .expect("Failed to downcast");
-                            let v = arr.value_as_datetime(i);
-                            rw.write_col(v)?;
-                        }
-                    },
+                    DataType::Timestamp(tu, _) => {
+                        let v = match tu {
+                            TimeUnit::Second => {
+                                downcast_value!(batch, j, i, TimestampSecondArray)
+                            }
+                            TimeUnit::Millisecond => {
+                                downcast_value!(batch, j, i, TimestampMillisecondArray)
+                            }
+                            TimeUnit::Microsecond => {
+                                downcast_value!(batch, j, i, TimestampMicrosecondArray)
+                            }
+                            TimeUnit::Nanosecond => {
+                                downcast_value!(batch, j, i, TimestampNanosecondArray)
+                            }
+                        };
+                        let time = timestamp_to_datetime(v, tu).ok_or_else(|| {
+                            DB3Error::TableTypeMismatchError {
+                                left: format!("{:?}", data_type),
+                                right: v.to_string(),
+                            }
+                        })?;
+                        rw.write_col(time)?;
+                    }
                     _ => {
                         return Err(DB3Error::TableSchemaConvertError(0));
                     }
@@ -257,35 +681,1098 @@ pub fn write_batch_to_resultset<'a, W: std::io::Write + Send>(
 pub fn sql_to_table_desc(columns: &Vec<ColumnDef>) -> Result<Db3SchemaDesc> {
     let mut db3_columns: Vec<Db3ColumnDesc> = Vec::new();
     for column in columns {
-        let db3_type = match column.data_type {
-            SPDataType::TinyInt(_) => Ok(Db3Type::KTinyInt),
-            SPDataType::SmallInt(_) => Ok(Db3Type::KSmallInt),
-            SPDataType::Int(_) => Ok(Db3Type::KInt),
-            SPDataType::BigInt(_) => Ok(Db3Type::KBigInt),
-            SPDataType::Float(_) => Ok(Db3Type::KFloat),
-            SPDataType::Timestamp => Ok(Db3Type::KTimestampMillsSecond),
-            SPDataType::Varchar(_) | SPDataType::String => Ok(Db3Type::KStringUtf8),
-            SPDataType::Double => Ok(Db3Type::KDouble),
-            SPDataType::Decimal(..) => Ok(Db3Type::KDecimal),
-            _ => {
-                warn!("{} is not supported currently", column);
-                Err(DB3Error::TableSchemaConvertError(0))
-            }
-        }?;
-        let mut null_allowed = true;
-
-        if !column.options.is_empty() && ColumnOption::NotNull == column.options[0].option {
-            null_allowed = false;
-        }
-        let db3_column = Db3ColumnDesc {
-            name: column.name.value.to_string(),
-            ctype: db3_type as i32,
+        let null_allowed = !column
+            .options
+            .iter()
+            .any(|o| ColumnOption::NotNull == o.option);
+        db3_columns.push(sql_type_to_column_desc(
+            &column.name.value,
+            &column.data_type,
             null_allowed,
-        };
-        db3_columns.push(db3_column);
+        )?);
     }
     Ok(Db3SchemaDesc {
         columns: db3_columns,
         version: 1,
     })
 }
+
+/// convert a sql type to column, the item of array is a nullable child column
+fn sql_type_to_column_desc(
+    name: &str,
+    data_type: &SPDataType,
+    null_allowed: bool,
+) -> Result<Db3ColumnDesc> {
+    let db3_type = match data_type {
+        SPDataType::Boolean => Ok(Db3Type::KBool),
+        SPDataType::TinyInt(_) => Ok(Db3Type::KTinyInt),
+        SPDataType::SmallInt(_) => Ok(Db3Type::KSmallInt),
+        SPDataType::Int(_) => Ok(Db3Type::KInt),
+        SPDataType::BigInt(_) => Ok(Db3Type::KBigInt),
+        SPDataType::UnsignedTinyInt(_) => Ok(Db3Type::KUTinyInt),
+        SPDataType::UnsignedSmallInt(_) => Ok(Db3Type::KUSmallInt),
+        SPDataType::UnsignedInt(_) => Ok(Db3Type::KUInt),
+        SPDataType::UnsignedBigInt(_) => Ok(Db3Type::KUBigInt),
+        SPDataType::Date => Ok(Db3Type::KDate),
+        SPDataType::Float(_) => Ok(Db3Type::KFloat),
+        SPDataType::Timestamp => Ok(Db3Type::KTimestampMillsSecond),
+        SPDataType::Varchar(_) | SPDataType::String => Ok(Db3Type::KStringUtf8),
+        SPDataType::Double => Ok(Db3Type::KDouble),
+        SPDataType::Decimal(..) => Ok(Db3Type::KDecimal),
+        SPDataType::Binary(_) => Ok(Db3Type::KFixedBinary),
+        SPDataType::Varbinary(_) | SPDataType::Blob(_) | SPDataType::Bytea => Ok(Db3Type::KBinary),
+        SPDataType::Custom(name) if name.to_string().eq_ignore_ascii_case("uint256") => {
+            Ok(Db3Type::KUInt256)
+        }
+        // the precision of timestamp is the digits of fractional seconds
+        SPDataType::Custom(type_name) if type_name.to_string().starts_with("timestamp(") => {
+            match type_name.to_string().as_str() {
+                "timestamp(0)" => Ok(Db3Type::KTimestampSecond),
+                "timestamp(3)" => Ok(Db3Type::KTimestampMillsSecond),
+                "timestamp(6)" => Ok(Db3Type::KTimestampMicroSecond),
+                _ => Err(DB3Error::SQLParseError(format!(
+                    "{} of column {} is not supported, the precision should be 0, 3 or 6",
+                    type_name, name
+                ))),
+            }
+        }
+        SPDataType::Array(_) => Ok(Db3Type::KList),
+        // the struct and map types are folded to custom types by the parser of frontend
+        SPDataType::Custom(type_name)
+            if type_name.to_string().to_lowercase().starts_with("struct")
+                || type_name.to_string().to_lowercase().starts_with("map") =>
+        {
+            return type_name_to_column_desc(name, &type_name.to_string(), null_allowed);
+        }
+        _ => {
+            warn!(
+                "{} of column {} is not supported currently",
+                data_type, name
+            );
+            Err(DB3Error::TableSchemaConvertError(0))
+        }
+    }?;
+    // the default of mysql is decimal(10, 0)
+    let (precision, scale) = match data_type {
+        SPDataType::Decimal(precision, scale) => {
+            (precision.unwrap_or(10) as u32, scale.unwrap_or(0) as u32)
+        }
+        _ => (0, 0),
+    };
+    let byte_width = match data_type {
+        SPDataType::Binary(width) => *width as u32,
+        _ => 0,
+    };
+    let children = match data_type {
+        SPDataType::Array(item) => vec![sql_type_to_column_desc("item", item, true)?],
+        _ => vec![],
+    };
+    Ok(Db3ColumnDesc {
+        name: name.to_string(),
+        ctype: db3_type as i32,
+        null_allowed,
+        precision,
+        scale,
+        byte_width,
+        children,
+    })
+}
+
+/// the arguments in the angle brackets of a nested type name like `map<varchar, bigint>`, they
+/// are split by the commas out of the nested brackets
+fn nested_type_args<'a>(type_name: &'a str, kind: &str) -> Option<Vec<&'a str>> {
+    let prefix = type_name.get(..kind.len())?;
+    if !prefix.eq_ignore_ascii_case(kind) {
+        return None;
+    }
+    let inner = type_name[kind.len()..]
+        .trim()
+        .strip_prefix('<')?
+        .strip_suffix('>')?;
+    let mut args = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in inner.char_indices() {
+        match c {
+            '<' | '(' => depth += 1,
+            '>' | ')' => depth -= 1,
+            ',' if depth == 0 => {
+                args.push(inner[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    args.push(inner[start..].trim());
+    Some(args)
+}
+
+/// convert the type name of a struct, map or a type in them to column, the fields of struct are
+/// like `name type` and the key of map is not nullable
+fn type_name_to_column_desc(
+    name: &str,
+    type_name: &str,
+    null_allowed: bool,
+) -> Result<Db3ColumnDesc> {
+    let type_name = type_name.trim();
+    let invalid =
+        || DB3Error::SQLParseError(format!("{} of column {} is not supported", type_name, name));
+    let (ctype, children) = if let Some(fields) = nested_type_args(type_name, "struct") {
+        let children = fields
+            .iter()
+            .map(|field| {
+                let (field_name, field_type) =
+                    field.split_once(char::is_whitespace).ok_or_else(invalid)?;
+                type_name_to_column_desc(field_name, field_type, true)
+            })
+            .collect::<Result<Vec<Db3ColumnDesc>>>()?;
+        (Db3Type::KStruct, children)
+    } else if let Some(args) = nested_type_args(type_name, "map") {
+        match args.as_slice() {
+            [key, value] => (
+                Db3Type::KMap,
+                vec![
+                    type_name_to_column_desc("key", key, false)?,
+                    type_name_to_column_desc("value", value, true)?,
+                ],
+            ),
+            _ => return Err(invalid()),
+        }
+    } else if let Some(args) = nested_type_args(type_name, "array") {
+        match args.as_slice() {
+            [item] => (
+                Db3Type::KList,
+                vec![type_name_to_column_desc("item", item, true)?],
+            ),
+            _ => return Err(invalid()),
+        }
+    } else if type_name.to_lowercase().starts_with("struct")
+        || type_name.to_lowercase().starts_with("map")
+    {
+        return Err(invalid());
+    } else if type_name.to_lowercase().starts_with("timestamp(") {
+        let custom = SPDataType::Custom(ObjectName(vec![Ident::new(type_name.to_lowercase())]));
+        return sql_type_to_column_desc(name, &custom, null_allowed);
+    } else {
+        let dialect = MySqlDialect {};
+        let tokens = Tokenizer::new(&dialect, type_name).tokenize()?;
+        let data_type = Parser::new(tokens, &dialect).parse_data_type()?;
+        return sql_type_to_column_desc(name, &data_type, null_allowed);
+    };
+    Ok(Db3ColumnDesc {
+        name: name.to_string(),
+        ctype: ctype as i32,
+        null_allowed,
+        children,
+        ..Default::default()
+    })
+}
+
+const TABLE_OPTIONS: [&str; 14] = [
+    "durability",
+    "group_commit_interval",
+    "partition_columns",
+    "partition_count",
+    "range_column",
+    "split_record_count",
+    "merge_record_count",
+    "replication_factor",
+    "retention",
+    "compression",
+    "row_group_size",
+    "dictionary",
+    "statistics",
+    "bloom_filter_columns",
+];
+
+fn sql_option_to_count(option: &SqlOption) -> Result<i32> {
+    sql_option_to_string(option)?
+        .parse()
+        .ok()
+        .filter(|c| *c > 0)
+        .ok_or_else(|| {
+            DB3Error::SQLParseError(format!(
+                "invalid {} {}, it should be a positive number",
+                option.name, option.value
+            ))
+        })
+}
+
+fn sql_option_to_bool(option: &SqlOption) -> Result<bool> {
+    match &option.value {
+        Value::Boolean(b) => Ok(*b),
+        _ => match sql_option_to_string(option)?.to_lowercase().as_str() {
+            "true" | "on" => Ok(true),
+            "false" | "off" => Ok(false),
+            v => Err(DB3Error::SQLParseError(format!(
+                "invalid {} {}, it should be true or false",
+                option.name, v
+            ))),
+        },
+    }
+}
+
+fn sql_option_to_string(option: &SqlOption) -> Result<String> {
+    match &option.value {
+        Value::SingleQuotedString(s) | Value::DoubleQuotedString(s) => Ok(s.to_string()),
+        Value::Number(n, _) => Ok(n.to_string()),
+        _ => Err(DB3Error::SQLParseError(format!(
+            "invalid value {} for table option {}",
+            option.value, option.name
+        ))),
+    }
+}
+
+/// convert the options in `CREATE TABLE ... WITH (durability = 'fsync')` to storage config
+pub fn sql_to_storage_config(options: &[SqlOption]) -> Result<Option<StorageBackendConfig>> {
+    let mut storage_config: Option<StorageBackendConfig> = None;
+    for option in options {
+        match option.name.value.to_lowercase().as_str() {
+            "durability" => {
+                let durability = match sql_option_to_string(option)?.to_lowercase().as_str() {
+                    "none" => Ok(BinlogDurability::KNone),
+                    "buffered" => Ok(BinlogDurability::KBuffered),
+                    "fsync" => Ok(BinlogDurability::KFsync),
+                    "group_commit" => Ok(BinlogDurability::KGroupCommit),
+                    v => Err(DB3Error::SQLParseError(format!(
+                        "invalid durability {}, the valid values are none, buffered, fsync and group_commit",
+                        v
+                    ))),
+                }?;
+                storage_config
+                    .get_or_insert_with(StorageBackendConfig::default)
+                    .durability = durability as i32;
+            }
+            "group_commit_interval" => {
+                let interval: u32 = sql_option_to_string(option)?.parse().map_err(|_| {
+                    DB3Error::SQLParseError(format!(
+                        "invalid group_commit_interval {}, it should be a number in millisecond",
+                        option.value
+                    ))
+                })?;
+                storage_config
+                    .get_or_insert_with(StorageBackendConfig::default)
+                    .group_commit_interval_ms = interval;
+            }
+            "compression" => {
+                let compression = match sql_option_to_string(option)?.to_lowercase().as_str() {
+                    "gzip" => Ok(ParquetCompression::KGzip),
+                    "none" => Ok(ParquetCompression::KUncompressed),
+                    "snappy" => Ok(ParquetCompression::KSnappy),
+                    "lz4" => Ok(ParquetCompression::KLz4),
+                    "zstd" => Ok(ParquetCompression::KZstd),
+                    v => Err(DB3Error::SQLParseError(format!(
+                        "invalid compression {}, the valid values are gzip, none, snappy, lz4 and zstd",
+                        v
+                    ))),
+                }?;
+                storage_config
+                    .get_or_insert_with(StorageBackendConfig::default)
+                    .compression = compression as i32;
+            }
+            "row_group_size" => {
+                storage_config
+                    .get_or_insert_with(StorageBackendConfig::default)
+                    .row_group_size = sql_option_to_count(option)? as u32;
+            }
+            "dictionary" => {
+                storage_config
+                    .get_or_insert_with(StorageBackendConfig::default)
+                    .dictionary_disabled = !sql_option_to_bool(option)?;
+            }
+            "statistics" => {
+                storage_config
+                    .get_or_insert_with(StorageBackendConfig::default)
+                    .statistics_disabled = !sql_option_to_bool(option)?;
+            }
+            "bloom_filter_columns" => {
+                storage_config
+                    .get_or_insert_with(StorageBackendConfig::default)
+                    .bloom_filter_columns = sql_option_to_string(option)?
+                    .split(',')
+                    .map(|c| c.trim().to_string())
+                    .filter(|c| !c.is_empty())
+                    .collect();
+            }
+            _ => {}
+        }
+    }
+    // the options are kept in the config but the writer may not apply them
+    if let Some(config) = &storage_config {
+        arrow_parquet_utils::check_storage_config(config)?;
+    }
+    Ok(storage_config)
+}
+
+/// convert the options in `CREATE TABLE ... WITH (partition_columns = 'c1', partition_count = 4)`
+/// to partition desc
+pub fn sql_to_partition_desc(options: &[SqlOption]) -> Result<Option<Db3PartitionDesc>> {
+    let mut partition_desc: Option<Db3PartitionDesc> = None;
+    for option in options {
+        match option.name.value.to_lowercase().as_str() {
+            "partition_columns" => {
+                let columns: Vec<String> = sql_option_to_string(option)?
+                    .split(',')
+                    .map(|c| c.trim().to_string())
+                    .filter(|c| !c.is_empty())
+                    .collect();
+                partition_desc
+                    .get_or_insert_with(Db3PartitionDesc::default)
+                    .columns = columns;
+            }
+            "partition_count" => {
+                partition_desc
+                    .get_or_insert_with(Db3PartitionDesc::default)
+                    .partition_count = sql_option_to_count(option)?;
+            }
+            "range_column" => {
+                partition_desc
+                    .get_or_insert_with(Db3PartitionDesc::default)
+                    .range_column = sql_option_to_string(option)?.trim().to_string();
+            }
+            "split_record_count" => {
+                partition_desc
+                    .get_or_insert_with(Db3PartitionDesc::default)
+                    .split_record_count = sql_option_to_count(option)?;
+            }
+            "merge_record_count" => {
+                partition_desc
+                    .get_or_insert_with(Db3PartitionDesc::default)
+                    .merge_record_count = sql_option_to_count(option)?;
+            }
+            "replication_factor" => {
+                partition_desc
+                    .get_or_insert_with(Db3PartitionDesc::default)
+                    .replication_factor = sql_option_to_count(option)?;
+            }
+            _ => {}
+        }
+    }
+    if let Some(desc) = &partition_desc {
+        if !desc.range_column.is_empty() && !desc.columns.is_empty() {
+            return Err(DB3Error::SQLParseError(
+                "range_column and partition_columns can not be used together".to_string(),
+            ));
+        }
+        if desc.range_column.is_empty() && desc.partition_count > 1 && desc.columns.is_empty() {
+            return Err(DB3Error::SQLParseError(
+                "partition_columns is required for more than one partition".to_string(),
+            ));
+        }
+        if desc.merge_record_count > 0 && desc.merge_record_count >= desc.split_record_count {
+            return Err(DB3Error::SQLParseError(
+                "merge_record_count should be less than split_record_count".to_string(),
+            ));
+        }
+    }
+    Ok(partition_desc)
+}
+
+/// convert `retention = '90d'` to the retention in millisecond, 0 means the rows never expire
+pub fn sql_to_retention_ms(options: &[SqlOption]) -> Result<i64> {
+    for option in options {
+        if option.name.value.to_lowercase() != "retention" {
+            continue;
+        }
+        let value = sql_option_to_string(option)?.trim().to_lowercase();
+        let invalid = || {
+            DB3Error::SQLParseError(format!(
+                "invalid retention {}, it should be like 30s, 10m, 12h, 90d or 2w",
+                value
+            ))
+        };
+        let unit_ms: i64 = match value.chars().last() {
+            Some('s') => 1000,
+            Some('m') => 60 * 1000,
+            Some('h') => 60 * 60 * 1000,
+            Some('d') => 24 * 60 * 60 * 1000,
+            Some('w') => 7 * 24 * 60 * 60 * 1000,
+            _ => return Err(invalid()),
+        };
+        let count: i64 = value[..value.len() - 1]
+            .trim()
+            .parse()
+            .ok()
+            .filter(|c| *c > 0)
+            .ok_or_else(invalid)?;
+        return count.checked_mul(unit_ms).ok_or_else(invalid);
+    }
+    Ok(0)
+}
+
+/// make sure all the options in `CREATE TABLE ... WITH (..)` are supported
+pub fn check_table_options(options: &[SqlOption]) -> Result<()> {
+    for option in options {
+        let name = option.name.value.to_lowercase();
+        if !TABLE_OPTIONS.contains(&name.as_str()) {
+            return Err(DB3Error::SQLParseError(format!(
+                "table option {} is not supported, the valid options are {}",
+                option.name,
+                TABLE_OPTIONS.join(", ")
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// the mysql error code of the error, the clients retry the statement failed with a retryable code
+pub fn error_kind(e: &DB3Error) -> ErrorKind {
+    match e {
+        DB3Error::PartitionThrottledError { .. } => ErrorKind::ER_LOCK_WAIT_TIMEOUT,
+        _ => ErrorKind::ER_UNKNOWN_ERROR,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::arrow_parquet_utils::gen_nested_column;
+    use crate::base::linked_list::LinkedList;
+    use crate::frontend_node::mysql::interruptible_parser::InterruptibleParser;
+    use arrow::datatypes::Schema;
+    use async_trait::async_trait;
+    use msql_srv::{
+        AsyncMysqlIntermediary, AsyncMysqlShim, InitWriter, ParamParser, StatementMetaWriter,
+    };
+    use mysql_async::prelude::Queryable;
+    use sqlparser::ast::{SetExpr, Statement};
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_error_kind() {
+        let e = DB3Error::PartitionThrottledError {
+            pid: 1,
+            err: "busy".to_string(),
+        };
+        assert!(matches!(error_kind(&e), ErrorKind::ER_LOCK_WAIT_TIMEOUT));
+        let e = DB3Error::PartitionWriteError {
+            pid: 1,
+            err: "unavailable".to_string(),
+        };
+        assert!(matches!(error_kind(&e), ErrorKind::ER_UNKNOWN_ERROR));
+    }
+
+    fn parse_table_options(sql: &str) -> Vec<SqlOption> {
+        let dialect = MySqlDialect {};
+        let statements = Parser::parse_sql(&dialect, sql).unwrap();
+        match &statements[0] {
+            Statement::CreateTable { with_options, .. } => with_options.clone(),
+            _ => panic!("should be create table"),
+        }
+    }
+
+    #[test]
+    fn test_sql_to_storage_config() -> Result<()> {
+        let options = parse_table_options(
+            "create table t1 (c1 int) with (durability = 'group_commit', group_commit_interval = 20)",
+        );
+        let config = sql_to_storage_config(&options)?.unwrap();
+        assert_eq!(BinlogDurability::KGroupCommit as i32, config.durability);
+        assert_eq!(20, config.group_commit_interval_ms);
+        let options = parse_table_options("create table t1 (c1 int)");
+        assert!(sql_to_storage_config(&options)?.is_none());
+        let options = parse_table_options("create table t1 (c1 int) with (durability = 'disk')");
+        assert!(sql_to_storage_config(&options).is_err());
+        let options = parse_table_options("create table t1 (c1 int) with (durable = 'fsync')");
+        assert!(check_table_options(&options).is_err());
+        let options = parse_table_options(
+            "create table t1 (c1 int) with (compression = 'zstd', row_group_size = 8192, dictionary = false, statistics = 'true')",
+        );
+        assert!(check_table_options(&options).is_ok());
+        let config = sql_to_storage_config(&options)?.unwrap();
+        assert_eq!(ParquetCompression::KZstd as i32, config.compression);
+        assert_eq!(8192, config.row_group_size);
+        assert!(config.dictionary_disabled);
+        assert!(!config.statistics_disabled);
+        let options = parse_table_options("create table t1 (c1 int) with (compression = 'lzo')");
+        assert!(sql_to_storage_config(&options).is_err());
+        let options =
+            parse_table_options("create table t1 (c1 int) with (bloom_filter_columns = 'c1')");
+        assert!(check_table_options(&options).is_ok());
+        match sql_to_storage_config(&options) {
+            Err(DB3Error::CellStoreInvalidConfigError { name, err }) => {
+                assert_eq!("bloom_filter_columns", name);
+                assert!(err.contains("parquet 16"));
+            }
+            _ => panic!("bloom filters should be unsupported"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_sql_to_partition_desc() -> Result<()> {
+        let options = parse_table_options(
+            "create table t1 (c1 int, c2 int) with (partition_columns = 'c1, c2', partition_count = 8)",
+        );
+        assert!(check_table_options(&options).is_ok());
+        let desc = sql_to_partition_desc(&options)?.unwrap();
+        assert_eq!(vec!["c1".to_string(), "c2".to_string()], desc.columns);
+        assert_eq!(8, desc.partition_count);
+        let options = parse_table_options("create table t1 (c1 int) with (partition_count = 8)");
+        assert!(sql_to_partition_desc(&options).is_err());
+        let options = parse_table_options("create table t1 (c1 int) with (partition_count = 0)");
+        assert!(sql_to_partition_desc(&options).is_err());
+        let options = parse_table_options(
+            "create table t1 (c1 int) with (range_column = 'c1', split_record_count = 1000, merge_record_count = 100)",
+        );
+        let desc = sql_to_partition_desc(&options)?.unwrap();
+        assert_eq!("c1", desc.range_column);
+        assert_eq!(1000, desc.split_record_count);
+        assert_eq!(100, desc.merge_record_count);
+        let options = parse_table_options("create table t1 (c1 int) with (replication_factor = 2)");
+        assert_eq!(
+            2,
+            sql_to_partition_desc(&options)?.unwrap().replication_factor
+        );
+        let options = parse_table_options(
+            "create table t1 (c1 int) with (range_column = 'c1', split_record_count = 10, merge_record_count = 100)",
+        );
+        assert!(sql_to_partition_desc(&options).is_err());
+        Ok(())
+    }
+
+    fn gen_column(ctype: Db3Type) -> Db3ColumnDesc {
+        Db3ColumnDesc {
+            name: "c1".to_string(),
+            ctype: ctype as i32,
+            null_allowed: true,
+            precision: 5,
+            scale: 2,
+            ..Default::default()
+        }
+    }
+
+    fn number(v: &str) -> Value {
+        Value::Number(v.to_string(), false)
+    }
+
+    fn string(v: &str) -> Value {
+        Value::SingleQuotedString(v.to_string())
+    }
+
+    fn uint256_of(v: u8) -> [u8; 32] {
+        let mut value = [0u8; 32];
+        value[31] = v;
+        value
+    }
+
+    #[test]
+    fn test_sql_value_to_data() -> Result<()> {
+        let cases = vec![
+            (Db3Type::KBool, Value::Boolean(true), Data::Bool(true)),
+            (Db3Type::KBool, number("0"), Data::Bool(false)),
+            (Db3Type::KTinyInt, number("-8"), Data::Int8(-8)),
+            (Db3Type::KSmallInt, number("-16"), Data::Int16(-16)),
+            (Db3Type::KInt, number("-32"), Data::Int32(-32)),
+            (Db3Type::KBigInt, number("-64"), Data::Int64(-64)),
+            (Db3Type::KUTinyInt, number("255"), Data::UInt8(255)),
+            (Db3Type::KUSmallInt, number("65535"), Data::UInt16(65535)),
+            (Db3Type::KUInt, number("4294967295"), Data::UInt32(u32::MAX)),
+            (
+                Db3Type::KUBigInt,
+                number("18446744073709551615"),
+                Data::UInt64(u64::MAX),
+            ),
+            (Db3Type::KFloat, number("1.5"), Data::Float(1.5)),
+            (Db3Type::KDouble, number("2.5"), Data::Double(2.5)),
+            (Db3Type::KDecimal, number("123.456"), Data::Decimal(12346)),
+            (Db3Type::KDecimal, string("-0.5"), Data::Decimal(-50)),
+            (Db3Type::KDate, string("1970-01-02"), Data::Date(1)),
+            (Db3Type::KDate, string("1969-12-31"), Data::Date(-1)),
+            (
+                Db3Type::KTimestampSecond,
+                string("1970-01-01 00:00:01"),
+                Data::Timestamp(1),
+            ),
+            (
+                Db3Type::KTimestampMillsSecond,
+                string("1970-01-01 00:00:01.5"),
+                Data::Timestamp(1500),
+            ),
+            (
+                Db3Type::KTimestampMicroSecond,
+                string("1970-01-01 00:00:01.000002"),
+                Data::Timestamp(1_000_002),
+            ),
+            (
+                Db3Type::KTimestampMillsSecond,
+                string("1969-12-31 23:59:59.5"),
+                Data::Timestamp(-500),
+            ),
+            (
+                Db3Type::KTimestampMillsSecond,
+                number("1000"),
+                Data::Timestamp(1000),
+            ),
+            (
+                Db3Type::KStringUtf8,
+                string("a"),
+                Data::Varchar("a".to_string()),
+            ),
+            (
+                Db3Type::KUInt256,
+                number("255"),
+                Data::UInt256(uint256_of(255)),
+            ),
+            (
+                Db3Type::KUInt256,
+                string("0xff"),
+                Data::UInt256(uint256_of(255)),
+            ),
+        ];
+        for (ctype, value, data) in cases {
+            assert_eq!(data, sql_value_to_data(&value, &gen_column(ctype))?);
+        }
+        let invalid_cases = vec![
+            (Db3Type::KBool, number("2")),
+            (Db3Type::KTinyInt, number("128")),
+            (Db3Type::KUTinyInt, number("-1")),
+            (Db3Type::KInt, string("1")),
+            (Db3Type::KDecimal, number("1000")),
+            (Db3Type::KDecimal, string("1.2.3")),
+            (Db3Type::KDate, string("1970-13-01")),
+            (Db3Type::KTimestampSecond, string("1970-01-01")),
+            (Db3Type::KUInt256, number("-1")),
+            (Db3Type::KUInt256, string("0xfg")),
+        ];
+        for (ctype, value) in invalid_cases {
+            assert!(sql_value_to_data(&value, &gen_column(ctype)).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_null_values() -> Result<()> {
+        let dialect = MySqlDialect {};
+        let statements = Parser::parse_sql(
+            &dialect,
+            "create table t1 (c1 bigint primary key not null, c2 int)",
+        )
+        .unwrap();
+        let schema = match &statements[0] {
+            Statement::CreateTable { columns, .. } => sql_to_table_desc(columns)?,
+            _ => panic!("should be create table"),
+        };
+        assert!(!schema.columns[0].null_allowed);
+        assert!(schema.columns[1].null_allowed);
+        let values = vec![
+            Expr::Value(Value::Number("1".to_string(), false)),
+            Expr::Value(Value::Null),
+        ];
+        let row_batch = sql_to_row_batch(&schema, &values)?;
+        assert_eq!(vec![Data::Int64(1), Data::Null], row_batch.batch[0]);
+        let values = vec![
+            Expr::Value(Value::Null),
+            Expr::Value(Value::Number("1".to_string(), false)),
+        ];
+        assert!(matches!(
+            sql_to_row_batch(&schema, &values),
+            Err(DB3Error::TableColumnNotNullError { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_uint256_column() -> Result<()> {
+        let dialect = MySqlDialect {};
+        let statements =
+            Parser::parse_sql(&dialect, "create table transfers (value uint256)").unwrap();
+        let schema = match &statements[0] {
+            Statement::CreateTable { columns, .. } => sql_to_table_desc(columns)?,
+            _ => panic!("should be create table"),
+        };
+        assert_eq!(Db3Type::KUInt256 as i32, schema.columns[0].ctype);
+        let arrow_schema = arrow_parquet_utils::table_desc_to_arrow_schema(&schema)?;
+        let mysql_schema = record_batch_schema_to_mysql_schema(&arrow_schema)?;
+        assert_eq!(ColumnType::MYSQL_TYPE_NEWDECIMAL, mysql_schema[0].coltype);
+        assert!(mysql_schema[0]
+            .colflags
+            .contains(ColumnFlags::UNSIGNED_FLAG));
+        Ok(())
+    }
+
+    #[test]
+    fn test_timestamp_precision() -> Result<()> {
+        let dialect = MySqlDialect {};
+        let sql =
+            "create table t1 (c1 timestamp(0), c2 timestamp(3), c3 timestamp(6), c4 timestamp)";
+        let mut parser = InterruptibleParser::new(&dialect, sql)?;
+        let schema = match parser.parse_left()? {
+            Statement::CreateTable { columns, .. } => sql_to_table_desc(&columns)?,
+            _ => panic!("should be create table"),
+        };
+        let ctypes: Vec<i32> = schema.columns.iter().map(|c| c.ctype).collect();
+        assert_eq!(
+            vec![
+                Db3Type::KTimestampSecond as i32,
+                Db3Type::KTimestampMillsSecond as i32,
+                Db3Type::KTimestampMicroSecond as i32,
+                Db3Type::KTimestampMillsSecond as i32,
+            ],
+            ctypes
+        );
+        let mut parser = InterruptibleParser::new(&dialect, "create table t1 (c1 timestamp(2))")?;
+        match parser.parse_left()? {
+            Statement::CreateTable { columns, .. } => {
+                assert!(sql_to_table_desc(&columns).is_err())
+            }
+            _ => panic!("should be create table"),
+        };
+        Ok(())
+    }
+
+    #[test]
+    fn test_struct_and_map_column() -> Result<()> {
+        let dialect = MySqlDialect {};
+        let sql = "create table t1 (c1 bigint, c2 struct<a int, b array<varchar>>, \
+                   c3 map<varchar, bigint>, c4 array<struct<c timestamp(6)>>)";
+        let mut parser = InterruptibleParser::new(&dialect, sql)?;
+        let schema = match parser.parse_left()? {
+            Statement::CreateTable { columns, .. } => sql_to_table_desc(&columns)?,
+            _ => panic!("should be create table"),
+        };
+        let c2 = &schema.columns[1];
+        assert_eq!(Db3Type::KStruct as i32, c2.ctype);
+        assert_eq!(
+            vec!["a", "b"],
+            c2.children
+                .iter()
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Db3Type::KList as i32, c2.children[1].ctype);
+        let c3 = &schema.columns[2];
+        assert_eq!(Db3Type::KMap as i32, c3.ctype);
+        assert!(!c3.children[0].null_allowed);
+        let c4 = &schema.columns[3];
+        assert_eq!(
+            Db3Type::KTimestampMicroSecond as i32,
+            c4.children[0].children[0].ctype
+        );
+        // the rows inserted to the table created by the ddl
+        let sql = r#"insert into t1 values (1, '{"a": 2, "b": ["x", "y"]}', '{"k": 3}', null)"#;
+        let statements = Parser::parse_sql(&dialect, sql).unwrap();
+        let values = match &statements[0] {
+            Statement::Insert { source, .. } => match &source.body {
+                SetExpr::Values(values) => values.0[0].clone(),
+                _ => panic!("should be values"),
+            },
+            _ => panic!("should be insert"),
+        };
+        let row_batch = sql_to_row_batch(&schema, &values)?;
+        let arrow_schema = arrow_parquet_utils::table_desc_to_arrow_schema(&schema)?;
+        let rows: LinkedList<RowRecordBatch> = LinkedList::new();
+        rows.push_front(row_batch)?;
+        let batch = arrow_parquet_utils::rows_to_columns(&arrow_schema, &rows)?;
+        assert_eq!(1, batch.num_rows());
+        let c2 = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<StructArray>()
+            .unwrap();
+        let a = c2.column(0).as_any().downcast_ref::<Int32Array>().unwrap();
+        assert_eq!(2, a.value(0));
+        assert!(batch.column(3).is_null(0));
+        // the struct and map without their fields are rejected
+        for (sql, expected) in [
+            (
+                "create table t1 (c1 struct)",
+                "struct of column c1 is not supported",
+            ),
+            (
+                "create table t1 (c1 map<varchar>)",
+                "map<varchar> of column c1 is not supported",
+            ),
+        ] {
+            let mut parser = InterruptibleParser::new(&dialect, sql)?;
+            match parser.parse_left()? {
+                Statement::CreateTable { columns, .. } => match sql_to_table_desc(&columns) {
+                    Err(DB3Error::SQLParseError(msg)) => assert!(msg.starts_with(expected)),
+                    _ => panic!("should be sql parse error"),
+                },
+                _ => panic!("should be create table"),
+            };
+        }
+        Ok(())
+    }
+
+    // the shim writes the batch for the query of t1, the others are done with ok
+    struct BatchShim {
+        batches: Vec<RecordBatch>,
+    }
+
+    #[async_trait]
+    impl<W: std::io::Write + Send> AsyncMysqlShim<W> for BatchShim {
+        type Error = std::io::Error;
+
+        fn version(&self) -> &str {
+            "8.0.26"
+        }
+
+        fn connect_id(&self) -> u32 {
+            1
+        }
+
+        fn default_auth_plugin(&self) -> &str {
+            "mysql_native_password"
+        }
+
+        fn auth_plugin_for_username(&self, _user: &[u8]) -> &str {
+            "mysql_native_password"
+        }
+
+        fn salt(&self) -> [u8; 20] {
+            [1u8; 20]
+        }
+
+        async fn authenticate(
+            &self,
+            _auth_plugin: &str,
+            _username: &[u8],
+            _salt: &[u8],
+            _auth_data: &[u8],
+        ) -> bool {
+            true
+        }
+
+        async fn authenticate_with_db(
+            &self,
+            _auth_plugin: &str,
+            _username: &[u8],
+            _salt: &[u8],
+            _auth_data: &[u8],
+            _db: &[u8],
+        ) -> bool {
+            true
+        }
+
+        async fn on_prepare<'a>(
+            &'a mut self,
+            _query: &'a str,
+            _writer: StatementMetaWriter<'a, W>,
+        ) -> std::io::Result<()> {
+            Ok(())
+        }
+
+        async fn on_execute<'a>(
+            &'a mut self,
+            _id: u32,
+            _param: ParamParser<'a>,
+            _writer: QueryResultWriter<'a, W>,
+        ) -> std::io::Result<()> {
+            Ok(())
+        }
+
+        async fn on_close<'a>(&'a mut self, _id: u32)
+        where
+            W: 'async_trait,
+        {
+        }
+
+        async fn on_query<'a>(
+            &'a mut self,
+            sql: &'a str,
+            results: QueryResultWriter<'a, W>,
+        ) -> std::io::Result<()> {
+            if sql != "select * from t1" {
+                return results.completed(OkResponse::default());
+            }
+            write_batch_to_resultset(&self.batches, results).unwrap();
+            Ok(())
+        }
+
+        async fn on_init<'a>(
+            &'a mut self,
+            _database_name: &'a str,
+            writer: InitWriter<'a, W>,
+        ) -> std::io::Result<()> {
+            writer.ok()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resultset_round_trip() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("c1", DataType::Date32, true),
+            Field::new("c2", DataType::Timestamp(TimeUnit::Second, None), true),
+            Field::new("c3", DataType::Timestamp(TimeUnit::Millisecond, None), true),
+            Field::new("c4", DataType::Timestamp(TimeUnit::Microsecond, None), true),
+            arrow_parquet_utils::uint256_field("c5", true),
+            Field::new("c6", DataType::FixedSizeBinary(2), true),
+        ]));
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(Date32Array::from(vec![Some(-1), None])),
+            Arc::new(TimestampSecondArray::from(vec![Some(-1), Some(0)])),
+            Arc::new(TimestampMillisecondArray::from(vec![
+                Some(-500),
+                Some(1500),
+            ])),
+            Arc::new(TimestampMicrosecondArray::from(vec![
+                Some(-1_000_000),
+                Some(2),
+            ])),
+            Arc::new(FixedSizeBinaryArray::try_from_sparse_iter(
+                vec![Some(uint256_of(255)), None].into_iter(),
+            )?),
+            Arc::new(FixedSizeBinaryArray::try_from_sparse_iter(
+                vec![None, Some([0xab, 0xcd])].into_iter(),
+            )?),
+        ];
+        let batch = RecordBatch::try_new(schema, columns)?;
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let shim = BatchShim {
+                batches: vec![batch],
+            };
+            AsyncMysqlIntermediary::run_on(shim, socket).await.unwrap();
+        });
+        // the settings in url keep the client from querying them on connecting
+        let url = format!(
+            "mysql://root@127.0.0.1:{}/db1?prefer_socket=false&max_allowed_packet=16777216&wait_timeout=28800",
+            port
+        );
+        let mut conn = mysql_async::Conn::new(mysql_async::Opts::from_url(&url).unwrap())
+            .await
+            .unwrap();
+        let rows: Vec<mysql_async::Row> = conn.query("select * from t1").await.unwrap();
+        let values: Vec<Vec<Option<String>>> = rows
+            .iter()
+            .map(|row| (0..row.len()).map(|i| row.get(i).unwrap()).collect())
+            .collect();
+        let expected = vec![
+            vec![
+                Some("1969-12-31".to_string()),
+                Some("1969-12-31 23:59:59".to_string()),
+                Some("1969-12-31 23:59:59.500000".to_string()),
+                Some("1969-12-31 23:59:59".to_string()),
+                Some("255".to_string()),
+                None,
+            ],
+            vec![
+                None,
+                Some("1970-01-01 00:00:00".to_string()),
+                Some("1970-01-01 00:00:01.500000".to_string()),
+                Some("1970-01-01 00:00:00.000002".to_string()),
+                None,
+                Some("0xabcd".to_string()),
+            ],
+        ];
+        assert_eq!(expected, values);
+        Ok(())
+    }
+
+    #[test]
+    fn test_nested_column() -> Result<()> {
+        let transaction = gen_nested_column(
+            "item",
+            Db3Type::KStruct,
+            vec![
+                gen_nested_column("blockHash", Db3Type::KBinary, vec![]),
+                gen_nested_column("gas", Db3Type::KBigInt, vec![]),
+            ],
+        );
+        let block = gen_nested_column(
+            "block",
+            Db3Type::KStruct,
+            vec![
+                gen_nested_column("number", Db3Type::KBigInt, vec![]),
+                gen_nested_column("transactions", Db3Type::KList, vec![transaction]),
+                gen_nested_column(
+                    "gasUsed",
+                    Db3Type::KMap,
+                    vec![
+                        gen_nested_column("key", Db3Type::KBigInt, vec![]),
+                        gen_nested_column("value", Db3Type::KBigInt, vec![]),
+                    ],
+                ),
+            ],
+        );
+        let json = r#"{"number":15048327,"transactions":[{"blockHash":"0x14d0","gas":21000},null],"gasUsed":{"1":21000}}"#;
+        let data = sql_value_to_data(&string(json), &block)?;
+        assert_eq!(
+            Data::Struct(vec![
+                Data::Int64(15048327),
+                Data::List(vec![
+                    Data::Struct(vec![Data::Binary(vec![0x14, 0xd0]), Data::Int64(21000)]),
+                    Data::Null,
+                ]),
+                Data::Map(vec![(Data::Int64(1), Data::Int64(21000))]),
+            ]),
+            data
+        );
+        let schema = arrow_parquet_utils::table_desc_to_arrow_schema(&Db3SchemaDesc {
+            columns: vec![block.clone()],
+            version: 1,
+        })?;
+        let mysql_schema = record_batch_schema_to_mysql_schema(&schema)?;
+        assert_eq!(ColumnType::MYSQL_TYPE_JSON, mysql_schema[0].coltype);
+        let rows = LinkedList::<RowRecordBatch>::new();
+        rows.push_front(RowRecordBatch {
+            batch: vec![vec![data]],
+            schema_version: 1,
+        })?;
+        let batch = arrow_parquet_utils::rows_to_columns(&schema, &rows)?;
+        assert_eq!(
+            serde_json::from_str::<JsonValue>(json).unwrap(),
+            nested_value_to_json(schema.field(0), batch.column(0), 0)?
+        );
+        // the transactions should be a list
+        let json = r#"{"number":1,"transactions":{"gas":1}}"#;
+        assert!(sql_value_to_data(&string(json), &block).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_binary_column() -> Result<()> {
+        let dialect = MySqlDialect {};
+        let statements = Parser::parse_sql(
+            &dialect,
+            "create table txs (hash binary(32), from_address binary(20), input varbinary(1024))",
+        )
+        .unwrap();
+        let schema = match &statements[0] {
+            Statement::CreateTable { columns, .. } => sql_to_table_desc(columns)?,
+            _ => panic!("should be create table"),
+        };
+        assert_eq!(Db3Type::KFixedBinary as i32, schema.columns[0].ctype);
+        assert_eq!(32, schema.columns[0].byte_width);
+        assert_eq!(20, schema.columns[1].byte_width);
+        assert_eq!(Db3Type::KBinary as i32, schema.columns[2].ctype);
+        let address = format!("0x{}", "ab".repeat(20));
+        assert_eq!(
+            Data::Binary(vec![0xab; 20]),
+            sql_value_to_data(&string(&address), &schema.columns[1])?
+        );
+        // the width of hash is 32
+        assert!(sql_value_to_data(&string(&address), &schema.columns[0]).is_err());
+        assert_eq!(
+            Data::Binary(vec![0x12, 0x34]),
+            sql_value_to_data(
+                &Value::HexStringLiteral("1234".to_string()),
+                &schema.columns[2]
+            )?
+        );
+        // a string without 0x is not hex
+        assert!(sql_value_to_data(&string("1234"), &schema.columns[2]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sql_to_retention_ms() -> Result<()> {
+        let options = parse_table_options("create table t1 (c1 int) with (retention = '90d')");
+        assert!(check_table_options(&options).is_ok());
+        assert_eq!(90 * 24 * 3600 * 1000, sql_to_retention_ms(&options)?);
+        let options = parse_table_options("create table t1 (c1 int) with (retention = '12h')");
+        assert_eq!(12 * 3600 * 1000, sql_to_retention_ms(&options)?);
+        let options = parse_table_options("create table t1 (c1 int)");
+        assert_eq!(0, sql_to_retention_ms(&options)?);
+        let options = parse_table_options("create table t1 (c1 int) with (retention = '90')");
+        assert!(sql_to_retention_ms(&options).is_err());
+        let options = parse_table_options("create table t1 (c1 int) with (retention = '-1d')");
+        assert!(sql_to_retention_ms(&options).is_err());
+        Ok(())
+    }
+}
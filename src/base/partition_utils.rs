@@ -0,0 +1,472 @@
+//
+//
+// partition_utils.rs
+// Copyright (C) 2022 db3.network Author imotai <codego.me@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::codec::row_codec::{Data, RowRecordBatch};
+use crate::error::{DB3Error, Result};
+use crate::proto::db3_base_proto::{Db3TableDesc, PartitionStatus, PartitionToNode};
+use crc32c::crc32c_append;
+use std::collections::HashMap;
+
+/// the change of range partitions planned by meta node
+#[derive(Debug, PartialEq)]
+pub struct RangeChange {
+    // the partitions to be sealed
+    pub sealed_partitions: Vec<i32>,
+    // the ranges of the new partitions
+    pub new_ranges: Vec<(i64, i64)>,
+}
+
+/// whether the table is partitioned by the range of a column
+pub fn is_range_partitioned(table_desc: &Db3TableDesc) -> bool {
+    match &table_desc.partition_desc {
+        Some(desc) => !desc.range_column.is_empty(),
+        None => false,
+    }
+}
+
+/// the count of partitions of table, a table without partition desc has only one partition and
+/// a range partitioned table starts with one partition
+pub fn get_partition_count(table_desc: &Db3TableDesc) -> i32 {
+    match &table_desc.partition_desc {
+        Some(desc) if desc.range_column.is_empty() && desc.partition_count > 1 => {
+            desc.partition_count
+        }
+        _ => 1,
+    }
+}
+
+/// the count of memory nodes holding every partition
+pub fn get_replication_factor(table_desc: &Db3TableDesc) -> usize {
+    match &table_desc.partition_desc {
+        Some(desc) if desc.replication_factor > 1 => desc.replication_factor as usize,
+        _ => 1,
+    }
+}
+
+/// all the partition ids of table
+pub fn get_partition_ids(table_desc: &Db3TableDesc) -> Vec<i32> {
+    (0..get_partition_count(table_desc)).collect()
+}
+
+/// the indexes of partition columns in the table schema
+pub fn get_partition_column_indexes(table_desc: &Db3TableDesc) -> Result<Vec<usize>> {
+    let columns = match &table_desc.partition_desc {
+        Some(desc) => &desc.columns,
+        None => return Ok(Vec::new()),
+    };
+    let schema = table_desc
+        .schema
+        .as_ref()
+        .ok_or_else(|| DB3Error::TableSchemaInvalidError {
+            name: table_desc.name.to_string(),
+        })?;
+    let mut indexes: Vec<usize> = Vec::new();
+    for column in columns {
+        match schema.columns.iter().position(|c| &c.name == column) {
+            Some(index) => indexes.push(index),
+            None => {
+                return Err(DB3Error::TableInvalidNamesError {
+                    error: format!(
+                        "partition column {} does not exist in table {}",
+                        column, table_desc.name
+                    ),
+                });
+            }
+        }
+    }
+    Ok(indexes)
+}
+
+/// the index of range column in the table schema
+pub fn get_range_column_index(table_desc: &Db3TableDesc) -> Result<Option<usize>> {
+    if !is_range_partitioned(table_desc) {
+        return Ok(None);
+    }
+    let mut range_desc = table_desc.clone();
+    if let Some(desc) = range_desc.partition_desc.as_mut() {
+        desc.columns = vec![desc.range_column.to_string()];
+    }
+    Ok(get_partition_column_indexes(&range_desc)?.first().copied())
+}
+
+/// convert the value of range column to a range key, only integer and time types are supported
+pub fn data_to_range_key(data: &Data) -> Option<i64> {
+    match data {
+        Data::Int8(v) => Some(*v as i64),
+        Data::UInt8(v) => Some(*v as i64),
+        Data::Int16(v) => Some(*v as i64),
+        Data::UInt16(v) => Some(*v as i64),
+        Data::Int32(v) => Some(*v as i64),
+        Data::UInt32(v) => Some(*v as i64),
+        Data::Int64(v) => Some(*v),
+        Data::UInt64(v) => Some(std::cmp::min(*v, i64::MAX as u64) as i64),
+        Data::Date(v) => Some(*v as i64),
+        Data::Timestamp(v) => Some(*v),
+        _ => None,
+    }
+}
+
+/// whether the key is in the range of partition, the range with i64::MAX as end is unbounded
+#[inline]
+pub fn range_contains(mapping: &PartitionToNode, key: i64) -> bool {
+    key >= mapping.range_start && (key < mapping.range_end || mapping.range_end == i64::MAX)
+}
+
+/// build the mappings for a new table, a range partitioned table starts with the full range.
+/// the first endpoint is the primary of partition and the others are its replicas
+pub fn build_initial_mapping(
+    table_desc: &Db3TableDesc,
+    pid: i32,
+    endpoints: &[String],
+) -> PartitionToNode {
+    let (range_start, range_end) = if is_range_partitioned(table_desc) {
+        (i64::MIN, i64::MAX)
+    } else {
+        (0, 0)
+    };
+    PartitionToNode {
+        partition_id: pid,
+        node_list: endpoints.to_vec(),
+        range_start,
+        range_end,
+        sealed: false,
+    }
+}
+
+fn find_range_partition(table_desc: &Db3TableDesc, key: i64) -> Result<i32> {
+    table_desc
+        .mappings
+        .iter()
+        .find(|m| !m.sealed && range_contains(m, key))
+        .map(|m| m.partition_id)
+        .ok_or_else(|| DB3Error::TableInvalidNamesError {
+            error: format!(
+                "no partition of table {} for range key {}",
+                table_desc.name, key
+            ),
+        })
+}
+
+/// the key splitting the keys in [min, max] in two non-empty halves, the min key goes to the left
+/// and the max key goes to the right. none if all the keys are the same
+pub fn get_split_key(min: i64, max: i64) -> Option<i64> {
+    if max <= min {
+        return None;
+    }
+    // the middle of two i64 may overflow, it always fits in i64 as it is in (min, max]
+    let split_key = min as i128 + (max as i128 - min as i128) / 2 + 1;
+    i64::try_from(split_key).ok()
+}
+
+/// the active partition which takes over the files of a sealed one, it covers the start of the
+/// sealed range
+pub fn get_heir_partition<'a>(
+    table_desc: &'a Db3TableDesc,
+    sealed: &PartitionToNode,
+) -> Option<&'a PartitionToNode> {
+    table_desc
+        .mappings
+        .iter()
+        .find(|m| !m.sealed && range_contains(m, sealed.range_start))
+}
+
+/// plan a split of a hot partition or a merge of two cold neighbours with the partition status
+pub fn plan_range_change(
+    table_desc: &Db3TableDesc,
+    status: &HashMap<i32, PartitionStatus>,
+) -> Option<RangeChange> {
+    let desc = table_desc.partition_desc.as_ref()?;
+    if desc.range_column.is_empty() {
+        return None;
+    }
+    let mut active: Vec<&PartitionToNode> =
+        table_desc.mappings.iter().filter(|m| !m.sealed).collect();
+    active.sort_by_key(|m| m.range_start);
+    if desc.split_record_count > 0 {
+        for mapping in active.iter() {
+            if let Some(s) = status.get(&mapping.partition_id) {
+                if s.num_rows < desc.split_record_count as i64 || !s.has_range_key {
+                    continue;
+                }
+                if let Some(split_key) = get_split_key(s.min_range_key, s.max_range_key) {
+                    if split_key > mapping.range_start && split_key < mapping.range_end {
+                        return Some(RangeChange {
+                            sealed_partitions: vec![mapping.partition_id],
+                            new_ranges: vec![
+                                (mapping.range_start, split_key),
+                                (split_key, mapping.range_end),
+                            ],
+                        });
+                    }
+                }
+            }
+        }
+    }
+    if desc.merge_record_count > 0 {
+        for pair in active.windows(2) {
+            let (left, right) = (pair[0], pair[1]);
+            if left.range_end != right.range_start {
+                continue;
+            }
+            if let (Some(ls), Some(rs)) = (
+                status.get(&left.partition_id),
+                status.get(&right.partition_id),
+            ) {
+                if ls.num_rows + rs.num_rows < desc.merge_record_count as i64 {
+                    return Some(RangeChange {
+                        sealed_partitions: vec![left.partition_id, right.partition_id],
+                        new_ranges: vec![(left.range_start, right.range_end)],
+                    });
+                }
+            }
+        }
+    }
+    None
+}
+
+/// compute the partition of a row with the crc32c hash of its partition column values
+pub fn hash_partition(row: &[Data], column_indexes: &[usize], partition_count: i32) -> Result<i32> {
+    if partition_count <= 1 {
+        return Ok(0);
+    }
+    let mut hash: u32 = 0;
+    for index in column_indexes {
+        let data = row
+            .get(*index)
+            .ok_or_else(|| DB3Error::TableSchemaInvalidError {
+                name: format!("row without partition column {}", index),
+            })?;
+        let buf = bincode::serialize(data).map_err(DB3Error::RowCodecError)?;
+        hash = crc32c_append(hash, &buf);
+    }
+    Ok((hash % partition_count as u32) as i32)
+}
+
+/// split the rows of batch to the partitions they belong to
+pub fn split_row_batch(
+    table_desc: &Db3TableDesc,
+    batch: RowRecordBatch,
+) -> Result<HashMap<i32, RowRecordBatch>> {
+    let partition_count = get_partition_count(table_desc);
+    let mut batches: HashMap<i32, RowRecordBatch> = HashMap::new();
+    let range_column_index = get_range_column_index(table_desc)?;
+    if partition_count <= 1 && range_column_index.is_none() {
+        batches.insert(0, batch);
+        return Ok(batches);
+    }
+    let column_indexes = get_partition_column_indexes(table_desc)?;
+    let schema_version = batch.schema_version;
+    for row in batch.batch {
+        let pid = match range_column_index {
+            Some(index) => {
+                let key = row.get(index).and_then(data_to_range_key).ok_or_else(|| {
+                    DB3Error::TableTypeMismatchError {
+                        left: "range key".to_string(),
+                        right: "row value".to_string(),
+                    }
+                })?;
+                find_range_partition(table_desc, key)?
+            }
+            None => hash_partition(&row, &column_indexes, partition_count)?,
+        };
+        batches
+            .entry(pid)
+            .or_insert_with(|| RowRecordBatch {
+                batch: Vec::new(),
+                schema_version,
+            })
+            .batch
+            .push(row);
+    }
+    Ok(batches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::db3_base_proto::{Db3ColumnDesc, Db3PartitionDesc, Db3SchemaDesc, Db3Type};
+
+    fn create_table_desc(partition_count: i32) -> Db3TableDesc {
+        let columns = vec![
+            Db3ColumnDesc {
+                name: "c1".to_string(),
+                ctype: Db3Type::KBigInt as i32,
+                null_allowed: true,
+                ..Default::default()
+            },
+            Db3ColumnDesc {
+                name: "c2".to_string(),
+                ctype: Db3Type::KStringUtf8 as i32,
+                null_allowed: true,
+                ..Default::default()
+            },
+        ];
+        Db3TableDesc {
+            name: "t1".to_string(),
+            schema: Some(Db3SchemaDesc {
+                columns,
+                version: 1,
+            }),
+            partition_desc: Some(Db3PartitionDesc {
+                columns: vec!["c2".to_string()],
+                partition_count,
+                ..Default::default()
+            }),
+            db: "db1".to_string(),
+            ctime: 0,
+            mappings: Vec::new(),
+            storage_config: None,
+            retention_ms: 0,
+        }
+    }
+
+    #[test]
+    fn test_split_row_batch() -> Result<()> {
+        let table_desc = create_table_desc(4);
+        assert_eq!(vec![0, 1, 2, 3], get_partition_ids(&table_desc));
+        let mut rows: Vec<Vec<Data>> = Vec::new();
+        for i in 0..100 {
+            rows.push(vec![
+                Data::Int64(i),
+                Data::Varchar(format!("key{}", i % 10)),
+            ]);
+        }
+        let batch = RowRecordBatch {
+            batch: rows,
+            schema_version: 1,
+        };
+        let batches = split_row_batch(&table_desc, batch)?;
+        assert!(batches.len() > 1);
+        let mut total = 0;
+        for (pid, batch) in batches.iter() {
+            for row in batch.batch.iter() {
+                // the same key always goes to the same partition
+                assert_eq!(*pid, hash_partition(row, &[1], 4)?);
+            }
+            total += batch.batch.len();
+        }
+        assert_eq!(100, total);
+        Ok(())
+    }
+
+    #[test]
+    fn test_replication_factor() {
+        let mut table_desc = create_table_desc(2);
+        assert_eq!(1, get_replication_factor(&table_desc));
+        if let Some(desc) = table_desc.partition_desc.as_mut() {
+            desc.replication_factor = 3;
+        }
+        assert_eq!(3, get_replication_factor(&table_desc));
+        let endpoints = vec!["node1".to_string(), "node2".to_string()];
+        let mapping = build_initial_mapping(&table_desc, 1, &endpoints);
+        assert_eq!(endpoints, mapping.node_list);
+    }
+
+    fn create_range_table_desc() -> Db3TableDesc {
+        let mut table_desc = create_table_desc(1);
+        if let Some(desc) = table_desc.partition_desc.as_mut() {
+            desc.columns = Vec::new();
+            desc.range_column = "c1".to_string();
+            desc.split_record_count = 100;
+            desc.merge_record_count = 10;
+        }
+        table_desc.mappings = vec![build_initial_mapping(
+            &table_desc,
+            0,
+            &["node1".to_string()],
+        )];
+        table_desc
+    }
+
+    fn create_status(pid: i32, num_rows: i64, min: i64, max: i64) -> PartitionStatus {
+        PartitionStatus {
+            partition_id: pid,
+            num_rows,
+            min_range_key: min,
+            max_range_key: max,
+            has_range_key: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_range_split_and_merge() -> Result<()> {
+        let mut table_desc = create_range_table_desc();
+        let mut status: HashMap<i32, PartitionStatus> = HashMap::new();
+        status.insert(0, create_status(0, 50, 0, 99));
+        assert!(plan_range_change(&table_desc, &status).is_none());
+        status.insert(0, create_status(0, 200, 0, 99));
+        let change = plan_range_change(&table_desc, &status).unwrap();
+        assert_eq!(vec![0], change.sealed_partitions);
+        assert_eq!(vec![(i64::MIN, 50), (50, i64::MAX)], change.new_ranges);
+        table_desc.mappings[0].sealed = true;
+        for (i, (start, end)) in change.new_ranges.iter().enumerate() {
+            let mut mapping =
+                build_initial_mapping(&table_desc, i as i32 + 1, &["node1".to_string()]);
+            mapping.range_start = *start;
+            mapping.range_end = *end;
+            table_desc.mappings.push(mapping);
+        }
+        let heir = get_heir_partition(&table_desc, &table_desc.mappings[0]).unwrap();
+        assert_eq!(1, heir.partition_id);
+        let batch = RowRecordBatch {
+            batch: vec![
+                vec![Data::Int64(10), Data::Varchar("a".to_string())],
+                vec![Data::Int64(60), Data::Varchar("b".to_string())],
+            ],
+            schema_version: 1,
+        };
+        let batches = split_row_batch(&table_desc, batch)?;
+        assert_eq!(1, batches.get(&1).unwrap().batch.len());
+        assert_eq!(1, batches.get(&2).unwrap().batch.len());
+        let mut status: HashMap<i32, PartitionStatus> = HashMap::new();
+        status.insert(1, create_status(1, 2, 10, 10));
+        status.insert(2, create_status(2, 3, 60, 60));
+        let change = plan_range_change(&table_desc, &status).unwrap();
+        assert_eq!(vec![1, 2], change.sealed_partitions);
+        assert_eq!(vec![(i64::MIN, i64::MAX)], change.new_ranges);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_split_key() {
+        assert_eq!(Some(50), get_split_key(0, 99));
+        assert_eq!(Some(0), get_split_key(-1, 0));
+        assert_eq!(Some(0), get_split_key(i64::MIN, i64::MAX));
+        assert_eq!(Some(i64::MAX), get_split_key(i64::MAX - 1, i64::MAX));
+        assert!(get_split_key(7, 7).is_none());
+        // the partition holds one key only
+        let mut table_desc = create_range_table_desc();
+        table_desc.mappings[0].range_start = 5;
+        table_desc.mappings[0].range_end = 6;
+        let mut status: HashMap<i32, PartitionStatus> = HashMap::new();
+        status.insert(0, create_status(0, 200, 5, 5));
+        assert!(plan_range_change(&table_desc, &status).is_none());
+    }
+
+    #[test]
+    fn test_invalid_partition_column() {
+        let mut table_desc = create_table_desc(4);
+        if let Some(desc) = table_desc.partition_desc.as_mut() {
+            desc.columns = vec!["c3".to_string()];
+        }
+        assert!(get_partition_column_indexes(&table_desc).is_err());
+        let table_desc = create_table_desc(0);
+        assert_eq!(vec![0], get_partition_ids(&table_desc));
+    }
+}
@@ -66,6 +66,32 @@ pub fn parse_s3_url(url: &str) -> Result<(String, String)> {
     Ok((bucket.to_owned(), key.to_owned()))
 }
 
+/// the 0x prefixed hex string of bytes
+pub fn bytes_to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(2 + bytes.len() * 2);
+    hex.push_str("0x");
+    for b in bytes {
+        hex.push_str(&format!("{:02x}", b));
+    }
+    hex
+}
+
+/// parse a hex string with or without the 0x prefix to bytes
+pub fn hex_to_bytes(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.trim();
+    let hex = hex
+        .strip_prefix("0x")
+        .or_else(|| hex.strip_prefix("0X"))
+        .unwrap_or(hex);
+    if hex.len() % 2 != 0 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -92,6 +118,18 @@ mod tests {
         assert_eq!("", label);
     }
 
+    #[test]
+    fn test_hex_bytes() {
+        assert_eq!("0x00ff1a", bytes_to_hex(&[0, 255, 26]));
+        assert_eq!("0x", bytes_to_hex(&[]));
+        assert_eq!(Some(vec![0, 255, 26]), hex_to_bytes("0x00FF1a"));
+        assert_eq!(Some(vec![0, 255, 26]), hex_to_bytes("00ff1a"));
+        assert_eq!(Some(vec![]), hex_to_bytes("0x"));
+        assert_eq!(None, hex_to_bytes("0xfff"));
+        assert_eq!(None, hex_to_bytes("0xzz"));
+        assert_eq!(None, hex_to_bytes("0x+f"));
+    }
+
     #[test]
     fn test_bytes_to_readable_num_str() {
         let less_1k = 1023;
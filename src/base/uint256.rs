@@ -0,0 +1,255 @@
+//
+//
+// uint256.rs
+// Copyright (C) 2022 db3.network Author imotai <codego.me@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+// the uint256 is stored as 32 big-endian bytes, so the order of bytes is the order of values
+pub const UINT256_BYTES: usize = 32;
+
+pub type UInt256 = [u8; UINT256_BYTES];
+
+// value = value * m + a
+fn mul_add(value: &UInt256, m: u32, a: u32) -> Option<UInt256> {
+    let mut out = [0u8; UINT256_BYTES];
+    let mut carry = a;
+    for i in (0..UINT256_BYTES).rev() {
+        let x = value[i] as u32 * m + carry;
+        out[i] = (x & 0xff) as u8;
+        carry = x >> 8;
+    }
+    if carry > 0 {
+        None
+    } else {
+        Some(out)
+    }
+}
+
+/// parse a decimal or a 0x prefixed hex string to uint256, none for the invalid or overflowed
+pub fn parse_uint256(v: &str) -> Option<UInt256> {
+    let v = v.trim();
+    let (digits, radix) = match v.strip_prefix("0x").or_else(|| v.strip_prefix("0X")) {
+        Some(hex) => (hex, 16),
+        None => (v, 10),
+    };
+    if digits.is_empty() {
+        return None;
+    }
+    let mut value = [0u8; UINT256_BYTES];
+    for c in digits.chars() {
+        value = mul_add(&value, radix, c.to_digit(radix)?)?;
+    }
+    Some(value)
+}
+
+/// the decimal string of the big-endian bytes
+pub fn uint256_to_string(v: &[u8]) -> String {
+    let mut value = v.to_vec();
+    let mut digits: Vec<char> = Vec::new();
+    loop {
+        let mut rem = 0u32;
+        for b in value.iter_mut() {
+            let x = (rem << 8) | *b as u32;
+            *b = (x / 10) as u8;
+            rem = x % 10;
+        }
+        digits.push((b'0' + rem as u8) as char);
+        if value.iter().all(|b| *b == 0) {
+            break;
+        }
+    }
+    digits.iter().rev().collect()
+}
+
+/// a + b, none if it overflows or the inputs are not uint256
+pub fn checked_add(a: &[u8], b: &[u8]) -> Option<UInt256> {
+    if a.len() != UINT256_BYTES || b.len() != UINT256_BYTES {
+        return None;
+    }
+    let mut out = [0u8; UINT256_BYTES];
+    let mut carry = 0u16;
+    for i in (0..UINT256_BYTES).rev() {
+        let x = a[i] as u16 + b[i] as u16 + carry;
+        out[i] = x as u8;
+        carry = x >> 8;
+    }
+    if carry > 0 {
+        None
+    } else {
+        Some(out)
+    }
+}
+
+// a - b wrapped around 2^256, and whether it borrows
+fn overflowing_sub(a: &[u8], b: &[u8]) -> (UInt256, bool) {
+    let mut out = [0u8; UINT256_BYTES];
+    let mut borrow = 0i16;
+    for i in (0..UINT256_BYTES).rev() {
+        let mut x = a[i] as i16 - b[i] as i16 - borrow;
+        borrow = 0;
+        if x < 0 {
+            x += 256;
+            borrow = 1;
+        }
+        out[i] = x as u8;
+    }
+    (out, borrow > 0)
+}
+
+/// a - b, none if a < b or the inputs are not uint256
+pub fn checked_sub(a: &[u8], b: &[u8]) -> Option<UInt256> {
+    if a.len() != UINT256_BYTES || b.len() != UINT256_BYTES {
+        return None;
+    }
+    match overflowing_sub(a, b) {
+        (out, false) => Some(out),
+        _ => None,
+    }
+}
+
+/// a * b, none if it overflows or the inputs are not uint256
+pub fn checked_mul(a: &[u8], b: &[u8]) -> Option<UInt256> {
+    if a.len() != UINT256_BYTES || b.len() != UINT256_BYTES {
+        return None;
+    }
+    // the product in little-endian bytes, the high half must be zero
+    let mut product = [0u32; UINT256_BYTES * 2];
+    for (i, x) in a.iter().rev().enumerate() {
+        let mut carry = 0u32;
+        for (j, y) in b.iter().rev().enumerate() {
+            let v = product[i + j] + *x as u32 * *y as u32 + carry;
+            product[i + j] = v & 0xff;
+            carry = v >> 8;
+        }
+        product[i + UINT256_BYTES] += carry;
+    }
+    if product[UINT256_BYTES..].iter().any(|v| *v > 0) {
+        return None;
+    }
+    let mut out = [0u8; UINT256_BYTES];
+    for i in 0..UINT256_BYTES {
+        out[UINT256_BYTES - 1 - i] = product[i] as u8;
+    }
+    Some(out)
+}
+
+/// a / b rounded down, none if b is zero or the inputs are not uint256
+pub fn checked_div(a: &[u8], b: &[u8]) -> Option<UInt256> {
+    checked_div_rem(a, b).map(|(quotient, _)| quotient)
+}
+
+/// a % b, none if b is zero or the inputs are not uint256
+pub fn checked_rem(a: &[u8], b: &[u8]) -> Option<UInt256> {
+    checked_div_rem(a, b).map(|(_, remainder)| remainder)
+}
+
+// the long division bit by bit from the highest
+fn checked_div_rem(a: &[u8], b: &[u8]) -> Option<(UInt256, UInt256)> {
+    if a.len() != UINT256_BYTES || b.len() != UINT256_BYTES || b.iter().all(|v| *v == 0) {
+        return None;
+    }
+    let mut quotient = [0u8; UINT256_BYTES];
+    let mut remainder = [0u8; UINT256_BYTES];
+    for bit in 0..UINT256_BYTES * 8 {
+        // remainder = remainder << 1 | the bit of a, it can not overflow for remainder < b
+        let overflow = remainder[0] >> 7;
+        for i in 0..UINT256_BYTES {
+            let next = if i + 1 < UINT256_BYTES {
+                remainder[i + 1] >> 7
+            } else {
+                (a[bit / 8] >> (7 - bit % 8)) & 1
+            };
+            remainder[i] = (remainder[i] << 1) | next;
+        }
+        // with the shifted out bit the remainder is over 2^256, the wrapped difference is right
+        if overflow > 0 || remainder.as_slice() >= b {
+            remainder = overflowing_sub(&remainder, b).0;
+            quotient[bit / 8] |= 1 << (7 - bit % 8);
+        }
+    }
+    Some((quotient, remainder))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX: &str =
+        "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+
+    #[test]
+    fn test_parse_uint256() {
+        let one = parse_uint256("1").unwrap();
+        assert_eq!(1, one[31]);
+        assert!(one[..31].iter().all(|b| *b == 0));
+        assert_eq!(parse_uint256("0xff"), parse_uint256("255"));
+        assert_eq!([0xffu8; 32], parse_uint256(MAX).unwrap());
+        assert_eq!(MAX, uint256_to_string(&parse_uint256(MAX).unwrap()));
+        assert_eq!("0", uint256_to_string(&[0u8; 32]));
+        let wei = "1000000000000000000000000";
+        assert_eq!(wei, uint256_to_string(&parse_uint256(wei).unwrap()));
+        // one more than the max
+        let overflow =
+            "115792089237316195423570985008687907853269984665640564039457584007913129639936";
+        assert!(parse_uint256(overflow).is_none());
+        assert!(parse_uint256(&format!("0x1{}", "0".repeat(64))).is_none());
+        assert!(parse_uint256("-1").is_none());
+        assert!(parse_uint256("1.5").is_none());
+        assert!(parse_uint256("0x").is_none());
+    }
+
+    #[test]
+    fn test_checked_add_sub() {
+        let a = parse_uint256("18446744073709551615").unwrap();
+        let b = parse_uint256("1").unwrap();
+        let sum = checked_add(&a, &b).unwrap();
+        assert_eq!("18446744073709551616", uint256_to_string(&sum));
+        assert_eq!(a, checked_sub(&sum, &b).unwrap());
+        assert!(checked_sub(&b, &a).is_none());
+        assert!(checked_add(&[0xffu8; 32], &b).is_none());
+        assert!(checked_add(&a, &b[1..]).is_none());
+    }
+
+    #[test]
+    fn test_checked_mul_div() {
+        let a = parse_uint256("340282366920938463463374607431768211455").unwrap();
+        let b = parse_uint256("1000000007").unwrap();
+        let product = checked_mul(&a, &b).unwrap();
+        assert_eq!(
+            "340282369302915031909943851675390463477377480185",
+            uint256_to_string(&product)
+        );
+        assert_eq!(a, checked_div(&product, &b).unwrap());
+        assert_eq!(b, checked_div(&product, &a).unwrap());
+        let c = parse_uint256("7").unwrap();
+        assert_eq!(
+            "48611766702991209066196372490252601636",
+            uint256_to_string(&checked_div(&a, &c).unwrap())
+        );
+        assert_eq!("3", uint256_to_string(&checked_rem(&a, &c).unwrap()));
+        // the max divided by a value over the half of it
+        let max = [0xffu8; 32];
+        let half = parse_uint256(&format!("0x8{}1", "0".repeat(62))).unwrap();
+        assert_eq!("1", uint256_to_string(&checked_div(&max, &half).unwrap()));
+        assert!(checked_mul(&max, &parse_uint256("2").unwrap()).is_none());
+        assert!(checked_mul(
+            &a,
+            &parse_uint256("0x10000000000000000000000000000000000").unwrap()
+        )
+        .is_none());
+        assert!(checked_div(&a, &[0u8; 32]).is_none());
+        assert!(checked_rem(&a, &[0u8; 32]).is_none());
+    }
+}
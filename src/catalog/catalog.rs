@@ -121,19 +121,19 @@ impl Database {
             if partition_node.node_list.is_empty() {
                 continue;
             }
-            if !nodes.contains_key(&partition_node.node_list[0]) {
-                let node = MemoryNodeSDK::connect(&partition_node.node_list[0])
-                    .await
-                    .map_err(|e| {
+            // connect to the primary and all the replicas of partition
+            let mut sdks: Vec<MemoryNodeSDK> = Vec::new();
+            for endpoint in partition_node.node_list.iter() {
+                if !nodes.contains_key(endpoint) {
+                    let node = MemoryNodeSDK::connect(endpoint).await.map_err(|e| {
                         warn!("fail to connect to memory node for error {}", e);
                         DB3Error::RPCConnectError(e)
                     })?;
-                nodes.insert(partition_node.node_list[0].to_string(), node);
+                    nodes.insert(endpoint.to_string(), node);
+                }
+                sdks.push(nodes.get(endpoint).unwrap().clone());
             }
-            table.assign_partition_to_node(
-                partition_node.partition_id,
-                nodes.get(&partition_node.node_list[0]).unwrap().clone(),
-            )?;
+            table.assign_partition_to_nodes(partition_node.partition_id, sdks)?;
         }
 
         if !recover {
@@ -18,45 +18,50 @@
 //
 
 uselog!(info, warn);
-use super::table_scanner::TableScannerExec;
+use super::table_scanner::{self, TableScannerExec};
+use crate::base::partition_utils;
 use crate::codec::flight_codec::flight_data_to_arrow_batch;
 use crate::error::{DB3Error, Result};
-use crate::proto::db3_base_proto::Db3TableDesc;
+use crate::proto::db3_base_proto::{CellStatus, Db3TableDesc};
+use crate::proto::db3_memory_proto::PartitionSnapshot;
 use crate::sdk::memory_node_sdk::MemoryNodeSDK;
 use arrow::datatypes::SchemaRef;
 use arrow::record_batch::RecordBatch;
 use async_trait::async_trait;
 use crossbeam_skiplist_piedb::SkipMap;
+use datafusion::datafusion_data_access::{FileMeta, SizedFile};
 use datafusion::datasource::TableType;
 use datafusion::datasource::{
     file_format::parquet::ParquetFormat,
-    get_statistics_with_limit,
     listing::{ListingOptions, ListingTableUrl, PartitionedFile},
     TableProvider,
 };
 use datafusion::error::{DataFusionError, Result as DFResult};
 use datafusion::execution::context::SessionState;
+use datafusion::logical_expr::TableProviderFilterPushDown;
 use datafusion::logical_plan::{combine_filters, Expr};
 use datafusion::physical_plan::project_schema;
 use datafusion::physical_plan::{empty::EmptyExec, memory::MemoryExec};
 
 use datafusion::physical_plan::{file_format::FileScanConfig, ExecutionPlan, Statistics};
-use futures::stream::StreamExt;
+use prost::Message;
 use std::any::Any;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 pub struct MemoryTableState {
     pub num_rows: usize,
     pub total_bytes: usize,
+    // the parquet files committed at the moment the records were read
+    pub files: Vec<CellStatus>,
 }
 
 #[derive(Clone)]
 pub struct Table {
     desc: Db3TableDesc,
     parquet_schema: SchemaRef,
-    // pid -> endpoint of node
-    partition_to_nodes: Arc<SkipMap<i32, MemoryNodeSDK>>,
+    // pid -> the primary and replicas of partition
+    partition_to_nodes: Arc<SkipMap<i32, Vec<MemoryNodeSDK>>>,
     options: ListingOptions,
 }
 
@@ -74,9 +79,9 @@ impl Table {
         }
     }
 
-    pub fn assign_partition_to_node(&self, pid: i32, sdk: MemoryNodeSDK) -> Result<()> {
+    pub fn assign_partition_to_nodes(&self, pid: i32, sdks: Vec<MemoryNodeSDK>) -> Result<()> {
         self.partition_to_nodes.remove(&pid);
-        self.partition_to_nodes.get_or_insert_with(pid, || sdk);
+        self.partition_to_nodes.get_or_insert_with(pid, || sdks);
         Ok(())
     }
 
@@ -105,29 +110,104 @@ impl Table {
         &self.desc.name
     }
 
+    /// the primary and replicas of partition, the primary is the first one
     #[inline]
-    pub fn get_node_by_partition(&self, pid: i32) -> Option<MemoryNodeSDK> {
+    pub fn get_nodes_by_partition(&self, pid: i32) -> Option<Vec<MemoryNodeSDK>> {
         let node_entry = self.partition_to_nodes.get(&pid);
         match node_entry {
-            Some(entry) => {
-                let node = entry.value().clone();
-                Some(node)
-            }
+            Some(entry) if !entry.value().is_empty() => Some(entry.value().clone()),
             _ => None,
         }
     }
 
+    /// fetch the records in memory from all the partitions of table
     async fn get_memory_records(&self) -> Result<(Vec<RecordBatch>, MemoryTableState)> {
-        //TODO support table partition
-        let sdk = self.get_node_by_partition(0).ok_or_else(|| {
-            warn!("fail to get memory node for table {} ", self.get_name());
+        let mut fetches = Vec::new();
+        // range tables keep the sealed partitions in mappings until their files are taken over
+        let partition_ids: Vec<i32> = if self.desc.mappings.is_empty() {
+            partition_utils::get_partition_ids(&self.desc)
+        } else {
+            self.desc.mappings.iter().map(|m| m.partition_id).collect()
+        };
+        for pid in partition_ids {
+            let sdks = self.get_nodes_by_partition(pid).ok_or_else(|| {
+                warn!(
+                    "fail to get memory node for partition {} of table {} ",
+                    pid,
+                    self.get_name()
+                );
+                DB3Error::RPCInternalError(format!(
+                    "fail to get node by partition {} for table {}",
+                    pid,
+                    self.get_name()
+                ))
+            })?;
+            fetches.push(self.get_partition_memory_records_from_replicas(pid, sdks));
+        }
+        let mut results = vec![];
+        let mut num_rows: usize = 0;
+        let mut total_bytes: usize = 0;
+        let mut files: Vec<CellStatus> = Vec::new();
+        // the files of a sealed partition are in its heir too until it is dropped
+        let mut file_uris: HashSet<String> = HashSet::new();
+        for (mut batches, state) in futures::future::try_join_all(fetches).await? {
+            results.append(&mut batches);
+            num_rows += state.num_rows;
+            total_bytes += state.total_bytes;
+            for file in state.files {
+                if file_uris.insert(file.cell_store_uri.to_string()) {
+                    files.push(file);
+                }
+            }
+        }
+        Ok((
+            results,
+            MemoryTableState {
+                num_rows,
+                total_bytes,
+                files,
+            },
+        ))
+    }
+
+    /// read the records of partition from the first replica which responds
+    async fn get_partition_memory_records_from_replicas(
+        &self,
+        pid: i32,
+        sdks: Vec<MemoryNodeSDK>,
+    ) -> Result<(Vec<RecordBatch>, MemoryTableState)> {
+        let mut last_err = None;
+        for sdk in sdks {
+            match self.get_partition_memory_records(pid, sdk.clone()).await {
+                Ok(records) => return Ok(records),
+                Err(e) => {
+                    warn!(
+                        "fail to read partition {} of table {} from {} with err {}",
+                        pid,
+                        self.get_name(),
+                        sdk.endpoint(),
+                        e
+                    );
+                    last_err = Some(e);
+                }
+            }
+        }
+        Err(last_err.unwrap_or_else(|| {
             DB3Error::RPCInternalError(format!(
-                "fail to get node by partition for table {}",
+                "no memory node for partition {} of table {}",
+                pid,
                 self.get_name()
             ))
-        })?;
+        }))
+    }
+
+    async fn get_partition_memory_records(
+        &self,
+        pid: i32,
+        sdk: MemoryNodeSDK,
+    ) -> Result<(Vec<RecordBatch>, MemoryTableState)> {
         let resp = sdk
-            .get_head_batch_of_partition(self.get_db(), self.get_name(), 0)
+            .get_head_batch_of_partition(self.get_db(), self.get_name(), pid)
             .await
             .map_err(|e| {
                 DB3Error::RPCInternalError(format!(
@@ -137,14 +217,27 @@ impl Table {
                 ))
             })?;
         let mut stream = resp.into_inner();
-        // skip the first message
-        stream.message().await.map_err(|e| {
+        // the first message holds the schema and the snapshot of partition
+        let schema_flight_data = stream.message().await.map_err(|e| {
             DB3Error::RPCInternalError(format!(
                 "fail to get iterator stream for table {} with err {}",
                 self.get_name(),
                 e
             ))
         })?;
+        let mut files: Vec<CellStatus> = Vec::new();
+        if let Some(flight_data) = schema_flight_data {
+            let snapshot =
+                PartitionSnapshot::decode(flight_data.app_metadata.as_slice()).map_err(|e| {
+                    DB3Error::RPCInternalError(format!(
+                        "bad snapshot of partition {} for table {} with err {}",
+                        pid,
+                        self.get_name(),
+                        e
+                    ))
+                })?;
+            files = Self::files_in_snapshot(snapshot);
+        }
         let mut results = vec![];
         let dictionaries_by_field = HashMap::new();
         let mut num_rows: usize = 0;
@@ -175,62 +268,64 @@ impl Table {
             MemoryTableState {
                 num_rows,
                 total_bytes,
+                files,
             },
         ))
     }
 
-    async fn list_files(
+    /// the files committed before the snapshot of partition was taken, the file with a sequence
+    /// not smaller than the one of snapshot was committed after the rows in memory were read
+    fn files_in_snapshot(snapshot: PartitionSnapshot) -> Vec<CellStatus> {
+        if !snapshot.has_file_sequence {
+            return snapshot.files;
+        }
+        snapshot
+            .files
+            .into_iter()
+            .filter(|f| (f.cell_id as u64) < snapshot.file_sequence)
+            .collect()
+    }
+
+    /// build the file groups to scan from the manifests of cells, the files are taken until
+    /// they have enough rows for the limit
+    fn build_file_groups(
         &self,
-        ctx: &SessionState,
+        files: &[CellStatus],
         limit: Option<usize>,
-    ) -> Result<(Vec<Vec<PartitionedFile>>, Statistics)> {
-        //TODO cache the table path as member
-        let table_path = format!("{}/{}", self.get_db(), self.get_name());
-        let table_url = ListingTableUrl::parse("s3://").map_err(|e| {
-            warn!("fail to parse url {} with err {}", &table_path, e);
-            DB3Error::TableBadUrl(table_path.to_string())
-        })?;
-        let store = ctx.runtime_env.object_store(&table_url).map_err(|e| {
-            warn!("fail to get object store {} with err {}", &table_path, e);
-            DB3Error::TableBadUrl(table_path.to_string())
-        })?;
-        let stream = store.list_file(&table_path).await.map_err(|e| {
-            warn!("fail to get object store {} with err {}", &table_path, e);
-            DB3Error::TableBadUrl(table_path.to_string())
-        })?;
-        let pin_stream = Box::pin(stream);
-        let files = pin_stream.then(|file_meta| async {
-            let part_file: PartitionedFile = file_meta?.into();
-            let statistics = if self.options.collect_stat {
-                self.options
-                    .format
-                    .infer_stats(&store, self.get_schema().clone(), &part_file.file_meta)
-                    .await?
-            } else {
-                Statistics::default()
-            };
-            Ok((part_file, statistics)) as DFResult<(PartitionedFile, Statistics)>
-        });
-        let (files, statistics) =
-            get_statistics_with_limit(files, self.get_schema().clone(), limit)
-                .await
-                .map_err(|e| {
-                    warn!(
-                        "fail to get statistics for table {} with err {}",
-                        self.get_name(),
-                        e
-                    );
-                    DB3Error::TableBadUrl(table_path.to_string())
-                })?;
-        info!(
-            "files size {} rows {}",
-            files.len(),
-            statistics.num_rows.unwrap()
-        );
-        Ok((
-            self.split_files(files, self.options.target_partitions),
+    ) -> (Vec<Vec<PartitionedFile>>, Statistics) {
+        let mut partitioned_files: Vec<PartitionedFile> = Vec::new();
+        let mut num_rows: usize = 0;
+        let mut total_byte_size: usize = 0;
+        let mut is_exact = true;
+        for file in files {
+            if matches!(limit, Some(l) if num_rows >= l) {
+                is_exact = false;
+                break;
+            }
+            num_rows += file.num_rows as usize;
+            total_byte_size += file.size_in_bytes as usize;
+            partitioned_files.push(
+                FileMeta {
+                    sized_file: SizedFile {
+                        path: file.cell_store_uri.to_string(),
+                        size: file.size_in_bytes as u64,
+                    },
+                    last_modified: None,
+                }
+                .into(),
+            );
+        }
+        info!("files size {} rows {}", partitioned_files.len(), num_rows);
+        let statistics = Statistics {
+            num_rows: Some(num_rows),
+            total_byte_size: Some(total_byte_size),
+            column_statistics: None,
+            is_exact,
+        };
+        (
+            self.split_files(partitioned_files, self.options.target_partitions),
             statistics,
-        ))
+        )
     }
 
     fn split_files(
@@ -264,6 +359,12 @@ impl TableProvider for Table {
         TableType::Base
     }
 
+    // the filters prune the row groups of parquet files by statistics, they are inexact so
+    // datafusion re-applies them to all the rows scanned including the rows in memtable
+    fn supports_filter_pushdown(&self, _filter: &Expr) -> DFResult<TableProviderFilterPushDown> {
+        Ok(TableProviderFilterPushDown::Inexact)
+    }
+
     async fn scan(
         &self,
         ctx: &SessionState,
@@ -275,10 +376,7 @@ impl TableProvider for Table {
             DataFusionError::Internal(format!("fail to get memory records for err {}", e))
         })?;
         info!("memory records size {}", records.len());
-        let (partition_files, mut statistics) = self
-            .list_files(ctx, limit)
-            .await
-            .map_err(|e| DataFusionError::Internal(format!("fail to list files for err {}", e)))?;
+        let (partition_files, mut statistics) = self.build_file_groups(&memory_state.files, limit);
         if partition_files.is_empty() && memory_state.num_rows == 0 {
             let schema = self.get_schema();
             let projected_schema = project_schema(&schema, projection.as_ref())?;
@@ -304,6 +402,36 @@ impl TableProvider for Table {
         statistics.total_byte_size = new_total_byte_size;
         let table_path = format!("s3://{}/{}", self.get_db(), self.get_name());
         let table_url = ListingTableUrl::parse(&table_path)?;
+        let predicates = table_scanner::binary_eq_predicates(filters);
+        let partition_files = if predicates.is_empty() {
+            partition_files
+        } else {
+            let store = ctx.runtime_env.object_store(&table_url.object_store())?;
+            let predicates = Arc::new(predicates);
+            // the footers are read on the blocking threads at the same time, one task for a file
+            let groups = partition_files.into_iter().map(|files| {
+                futures::future::try_join_all(files.into_iter().map(|file| {
+                    let store = store.clone();
+                    let predicates = predicates.clone();
+                    tokio::task::spawn_blocking(move || {
+                        table_scanner::prune_row_groups(store.as_ref(), file, &predicates)
+                    })
+                }))
+            });
+            futures::future::try_join_all(groups)
+                .await
+                .map_err(|e| {
+                    DataFusionError::Execution(format!("fail to prune row groups for err {}", e))
+                })?
+                .into_iter()
+                .map(|pruned| {
+                    pruned
+                        .into_iter()
+                        .collect::<DFResult<Vec<_>>>()
+                        .map(|files| files.into_iter().flatten().collect())
+                })
+                .collect::<DFResult<Vec<_>>>()?
+        };
         let predicate = combine_filters(filters);
         let file_config = FileScanConfig {
             object_store_url: table_url.object_store(),
@@ -319,3 +447,76 @@ impl TableProvider for Table {
         Ok(Arc::new(exec))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::arrow_parquet_utils;
+    use crate::proto::db3_base_proto::{Db3ColumnDesc, Db3SchemaDesc, Db3Type};
+
+    fn gen_file(sequence: i32, num_rows: i64) -> CellStatus {
+        CellStatus {
+            num_rows,
+            cell_id: sequence,
+            cell_store_uri: format!("db1/t1/0/{:08}.gz.parquet", sequence),
+            size_in_bytes: 100,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_files_in_snapshot() {
+        let files = vec![gen_file(0, 10), gen_file(1, 10), gen_file(2, 10)];
+        // the file 2 is committed after the snapshot
+        let snapshot = PartitionSnapshot {
+            has_file_sequence: true,
+            file_sequence: 2,
+            files: files.clone(),
+        };
+        let kept = Table::files_in_snapshot(snapshot);
+        assert_eq!(
+            vec![0, 1],
+            kept.iter().map(|f| f.cell_id).collect::<Vec<i32>>()
+        );
+        let snapshot = PartitionSnapshot {
+            has_file_sequence: false,
+            file_sequence: 0,
+            files,
+        };
+        assert_eq!(3, Table::files_in_snapshot(snapshot).len());
+    }
+
+    #[test]
+    fn test_build_file_groups() -> Result<()> {
+        let schema = Db3SchemaDesc {
+            columns: vec![Db3ColumnDesc {
+                name: "col1".to_string(),
+                ctype: Db3Type::KBigInt as i32,
+                null_allowed: true,
+                ..Default::default()
+            }],
+            version: 1,
+        };
+        let desc = Db3TableDesc {
+            name: "t1".to_string(),
+            db: "db1".to_string(),
+            schema: Some(schema.clone()),
+            ..Default::default()
+        };
+        let table = Table::new(
+            &desc,
+            arrow_parquet_utils::table_desc_to_arrow_schema(&schema)?,
+        );
+        let files = vec![gen_file(0, 10), gen_file(1, 10), gen_file(2, 10)];
+        let (groups, statistics) = table.build_file_groups(&files, None);
+        assert_eq!(3, groups.iter().map(|g| g.len()).sum::<usize>());
+        assert_eq!(Some(30), statistics.num_rows);
+        assert_eq!(Some(300), statistics.total_byte_size);
+        assert!(statistics.is_exact);
+        let (groups, statistics) = table.build_file_groups(&files, Some(15));
+        assert_eq!(2, groups.iter().map(|g| g.len()).sum::<usize>());
+        assert_eq!(Some(20), statistics.num_rows);
+        assert!(!statistics.is_exact);
+        Ok(())
+    }
+}
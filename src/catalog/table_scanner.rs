@@ -17,20 +17,28 @@
 //
 //
 
+use crate::compute_node::binary_functions;
 use arrow::datatypes::SchemaRef;
 use arrow::error::Result as ArrowResult;
 use arrow::record_batch::RecordBatch;
 use async_trait::async_trait;
 use core::fmt;
+use datafusion::datafusion_data_access::object_store::ObjectStore;
+use datafusion::datasource::file_format::parquet::ChunkObjectReader;
+use datafusion::datasource::listing::{FileRange, PartitionedFile};
 use datafusion::error::{DataFusionError, Result};
 use datafusion::execution::context::TaskContext;
 use datafusion::logical_plan::Expr;
+use datafusion::optimizer::utils::split_conjunction;
 use datafusion::physical_plan::{
     expressions::PhysicalSortExpr, file_format::FileScanConfig, file_format::ParquetExec,
     project_schema, DisplayFormatType, ExecutionPlan, Partitioning, RecordBatchStream,
     SendableRecordBatchStream, Statistics,
 };
+use datafusion::scalar::ScalarValue;
 use futures::Stream;
+use parquet::file::footer::parse_metadata;
+use parquet::file::metadata::RowGroupMetaData;
 uselog!(debug, info);
 use std::any::Any;
 use std::sync::Arc;
@@ -87,6 +95,106 @@ impl Stream for MemTableIterator {
     }
 }
 
+/// the equality predicates of binary columns in the filters, datafusion converts the statistics
+/// of byte array to utf8 only so they are used to prune the row groups here
+pub fn binary_eq_predicates(filters: &[Expr]) -> Vec<(String, Vec<u8>)> {
+    let mut predicates: Vec<&Expr> = Vec::new();
+    for filter in filters {
+        split_conjunction(filter, &mut predicates);
+    }
+    predicates
+        .into_iter()
+        .filter_map(|predicate| match predicate {
+            Expr::ScalarUDF { fun, args } if fun.name == binary_functions::BINARY_EQ => {
+                match args.as_slice() {
+                    [Expr::Column(c), Expr::Literal(ScalarValue::Binary(Some(v)))]
+                    | [Expr::Literal(ScalarValue::Binary(Some(v))), Expr::Column(c)] => {
+                        Some((c.name.to_string(), v.to_vec()))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// the same offset as the parquet reader filters the row groups by range
+fn midpoint_offset(row_group: &RowGroupMetaData) -> i64 {
+    let column = row_group.column(0);
+    let offset = match column.dictionary_page_offset() {
+        Some(offset) if offset < column.data_page_offset() => offset,
+        _ => column.data_page_offset(),
+    };
+    offset + row_group.compressed_size() / 2
+}
+
+// whether the row group could have the value by the min and max of the column
+fn row_group_matches(row_group: &RowGroupMetaData, predicates: &[(String, Vec<u8>)]) -> bool {
+    predicates.iter().all(|(name, value)| {
+        let statistics = row_group
+            .columns()
+            .iter()
+            .find(|c| &c.column_path().string() == name)
+            .and_then(|c| c.statistics());
+        match statistics {
+            // all the values are null
+            Some(s) if s.null_count() as i64 == row_group.num_rows() => false,
+            Some(s) if s.has_min_max_set() => {
+                s.min_bytes() <= value.as_slice() && value.as_slice() <= s.max_bytes()
+            }
+            _ => true,
+        }
+    })
+}
+
+/// prune the row groups of parquet file by the equality predicates of binary columns, the file
+/// is split to the ranges of the row groups left
+pub fn prune_row_groups(
+    store: &dyn ObjectStore,
+    file: PartitionedFile,
+    predicates: &[(String, Vec<u8>)],
+) -> Result<Vec<PartitionedFile>> {
+    if predicates.is_empty() {
+        return Ok(vec![file]);
+    }
+    let reader = ChunkObjectReader {
+        object_reader: store.file_reader(file.file_meta.sized_file.clone())?,
+        bytes_scanned: None,
+    };
+    let metadata = parse_metadata(&reader)?;
+    let mut ranges: Vec<FileRange> = Vec::new();
+    let mut last_matched: Option<usize> = None;
+    for (i, row_group) in metadata.row_groups().iter().enumerate() {
+        if !row_group_matches(row_group, predicates) {
+            continue;
+        }
+        let mid = midpoint_offset(row_group);
+        match ranges.last_mut() {
+            // extend the range for the adjacent row groups are read together
+            Some(range) if last_matched == Some(i - 1) => range.end = mid + 1,
+            _ => ranges.push(FileRange {
+                start: mid,
+                end: mid + 1,
+            }),
+        }
+        last_matched = Some(i);
+    }
+    debug!(
+        "{} ranges of {} row groups left in file {}",
+        ranges.len(),
+        metadata.num_row_groups(),
+        file.file_meta.path()
+    );
+    Ok(ranges
+        .into_iter()
+        .map(|range| PartitionedFile {
+            range: Some(range),
+            ..file.clone()
+        })
+        .collect())
+}
+
 pub struct TableScannerExec {
     parquet_exec: ParquetExec,
     config: FileScanConfig,
@@ -192,8 +300,95 @@ impl fmt::Debug for TableScannerExec {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::compute_node::binary_functions::binary_eq;
+    use arrow::array::{Array, ArrayRef, BinaryArray, FixedSizeBinaryArray};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::datafusion_data_access::object_store::local::{
+        local_unpartitioned_file, LocalFileSystem,
+    };
+    use datafusion::datasource::object_store::ObjectStoreUrl;
+    use datafusion::logical_plan::{col, lit};
+    use datafusion::physical_plan::common;
+    use datafusion::prelude::SessionContext;
+    use parquet::arrow::ArrowWriter;
+    use parquet::file::properties::WriterProperties;
+
     #[tokio::test]
     async fn it_test_piedb_table_scanner() -> Result<()> {
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_prune_row_groups() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("hash", DataType::Binary, true),
+            Field::new("address", DataType::FixedSizeBinary(2), true),
+        ]));
+        let hash: ArrayRef = Arc::new(BinaryArray::from(vec![
+            Some(&[0x01][..]),
+            Some(&[0x02][..]),
+            Some(&[0x03][..]),
+            Some(&[0x04, 0x01][..]),
+        ]));
+        let address: ArrayRef = Arc::new(FixedSizeBinaryArray::try_from_sparse_iter(
+            vec![Some([0xab, 0x01]), None, None, None].into_iter(),
+        )?);
+        let batch = RecordBatch::try_new(schema.clone(), vec![hash, address])?;
+        // two row groups of two rows
+        let tmp_dir = tempdir::TempDir::new("prune_row_groups").expect("create temp dir");
+        let path = tmp_dir.path().join("00000001.parquet");
+        let properties = WriterProperties::builder()
+            .set_max_row_group_size(2)
+            .build();
+        let mut writer = ArrowWriter::try_new(
+            std::fs::File::create(&path)?,
+            schema.clone(),
+            Some(properties),
+        )?;
+        writer.write(&batch)?;
+        writer.close()?;
+        let file: PartitionedFile =
+            local_unpartitioned_file(path.to_str().unwrap().to_string()).into();
+        let filters = vec![
+            binary_eq(col("hash"), lit(ScalarValue::Binary(Some(vec![0x03])))),
+            col("hash").is_not_null(),
+        ];
+        let predicates = binary_eq_predicates(&filters);
+        assert_eq!(vec![("hash".to_string(), vec![0x03])], predicates);
+        let files = prune_row_groups(&LocalFileSystem, file.clone(), &predicates)?;
+        assert_eq!(1, files.len());
+        let config = FileScanConfig {
+            object_store_url: ObjectStoreUrl::local_filesystem(),
+            file_schema: schema.clone(),
+            file_groups: vec![files],
+            statistics: Statistics::default(),
+            projection: None,
+            limit: None,
+            table_partition_cols: vec![],
+        };
+        let exec = TableScannerExec::new(config, schema, vec![], None)?;
+        let task_ctx = SessionContext::new().task_ctx();
+        let batches = common::collect(exec.execute(1, task_ctx)?).await?;
+        // only the second row group is read
+        let hashes: Vec<&[u8]> = batches
+            .iter()
+            .flat_map(|b| {
+                let array = b.column(0).as_any().downcast_ref::<BinaryArray>().unwrap();
+                (0..array.len()).map(|i| array.value(i)).collect::<Vec<_>>()
+            })
+            .collect();
+        assert_eq!(vec![&[0x03][..], &[0x04, 0x01][..]], hashes);
+        // the fixed binary column of the second row group is all null
+        let predicates = vec![("address".to_string(), vec![0xab, 0x01])];
+        let files = prune_row_groups(&LocalFileSystem, file.clone(), &predicates)?;
+        assert_eq!(1, files.len());
+        assert!(files[0].range.as_ref().unwrap().start < files[0].range.as_ref().unwrap().end);
+        let predicates = vec![("hash".to_string(), vec![0x05])];
+        assert!(prune_row_groups(&LocalFileSystem, file.clone(), &predicates)?.is_empty());
+        // the row groups without statistics are kept and read in one range
+        let predicates = vec![("block".to_string(), vec![0x01])];
+        let files = prune_row_groups(&LocalFileSystem, file, &predicates)?;
+        assert_eq!(1, files.len());
+        Ok(())
+    }
 }
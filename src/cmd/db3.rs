@@ -24,16 +24,18 @@ shadow!(build);
 extern crate uselog_rs;
 use msql_srv::*;
 use tokio::net::TcpListener;
+use tokio::signal::unix::{signal, SignalKind};
 
 use db3::compute_node::compute_node_impl::{ComputeNodeConfig, ComputeNodeImpl};
 use db3::frontend_node::mysql::mysql_handler;
+use db3::memory_node::compaction_scheduler::CompactionSchedulerConfig;
 use db3::memory_node::memory_node_impl::{MemoryNodeConfig, MemoryNodeImpl};
 use db3::meta_node::meta_server::{MetaConfig, MetaServiceImpl};
 use db3::proto::db3_base_proto::{Db3Node, Db3NodeType};
 use db3::proto::db3_compute_proto::compute_node_server::ComputeNodeServer;
 use db3::proto::db3_memory_proto::memory_node_server::MemoryNodeServer;
 use db3::proto::db3_meta_proto::meta_server::MetaServer;
-use db3::sdk::{build_compute_node_sdk, build_memory_node_sdk, build_meta_node_sdk};
+use db3::sdk::{build_compute_node_sdk, build_meta_node_sdk};
 use db3::store::{
     build_meta_store, build_readonly_meta_store, meta_store::MetaStoreType,
     object_store::build_region,
@@ -46,6 +48,13 @@ use clap::{Parser, Subcommand};
 
 const ABOUT: &str = "web3 timeseries database for data analytics 🚀🚀🚀";
 const AUTHOR: &str = "db3.network";
+// the max bytes of rows in memory of a memory node
+const MEMORY_NODE_LIMIT_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+const BINLOG_SEGMENT_SIZE: u64 = 64 * 1024 * 1024;
+const FLUSH_MAX_AGE_MS: u64 = 5 * 60 * 1000;
+const FLUSH_MAX_BYTES: u64 = 64 * 1024 * 1024;
+const FLUSH_MAX_ROWS: u64 = 10 * 1024 * 1024;
+const MAX_CONCURRENT_UPLOADS: usize = 4;
 
 #[derive(Debug, Parser)]
 #[clap(name = "db3")]
@@ -102,6 +111,24 @@ enum Commands {
         etcd_root_path: String,
         #[clap(required = true)]
         ns: String,
+        /// the max bytes of a binlog segment
+        #[clap(long, default_value_t = BINLOG_SEGMENT_SIZE)]
+        binlog_segment_size: u64,
+        /// a cell is flushed when its oldest row in memory is older than it
+        #[clap(long, default_value_t = FLUSH_MAX_AGE_MS)]
+        flush_max_age_ms: u64,
+        /// a cell is flushed when its rows in memory take more bytes than it
+        #[clap(long, default_value_t = FLUSH_MAX_BYTES)]
+        flush_max_bytes: u64,
+        /// a cell is flushed when it has more rows in memory than it
+        #[clap(long, default_value_t = FLUSH_MAX_ROWS)]
+        flush_max_rows: u64,
+        /// the max count of cells uploading at the same time
+        #[clap(long, default_value_t = MAX_CONCURRENT_UPLOADS)]
+        max_concurrent_uploads: usize,
+        /// the max bytes of rows in memory of all the cells, the writes are throttled above it
+        #[clap(long, default_value_t = MEMORY_NODE_LIMIT_BYTES)]
+        memory_limit_bytes: u64,
     },
     /// Start Frontend Node Server
     #[clap(arg_required_else_help = true)]
@@ -117,6 +144,24 @@ enum Commands {
         #[clap(required = true)]
         var_config_path: String,
     },
+    /// Move a partition from a memory node to another one
+    #[clap(arg_required_else_help = true)]
+    MovePartition {
+        #[clap(required = true)]
+        etcd_cluster: String,
+        #[clap(required = true)]
+        etcd_root_path: String,
+        #[clap(required = true)]
+        db: String,
+        #[clap(required = true)]
+        table: String,
+        #[clap(required = true)]
+        partition_id: i32,
+        #[clap(required = true)]
+        from_node: String,
+        #[clap(required = true)]
+        to_node: String,
+    },
     Version,
 }
 
@@ -124,6 +169,22 @@ fn setup_log() {
     pretty_env_logger::init_timed();
 }
 
+/// wait for ctrl-c or SIGTERM
+async fn wait_for_shutdown_signal() {
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("fail to listen SIGTERM with err {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
 async fn start_memory_node(memory_node: &Commands) -> Result<(), Box<dyn std::error::Error>> {
     if let Commands::MemoryNode {
         port,
@@ -132,6 +193,12 @@ async fn start_memory_node(memory_node: &Commands) -> Result<(), Box<dyn std::er
         etcd_cluster,
         etcd_root_path,
         ns,
+        binlog_segment_size,
+        flush_max_age_ms,
+        flush_max_bytes,
+        flush_max_rows,
+        max_concurrent_uploads,
+        memory_limit_bytes,
     } = memory_node
     {
         if let Ok(meta_store) = build_readonly_meta_store(etcd_cluster, etcd_root_path).await {
@@ -142,22 +209,40 @@ async fn start_memory_node(memory_node: &Commands) -> Result<(), Box<dyn std::er
                 ns: ns.to_string(),
                 port: *port,
             };
+            let mut compaction_config = CompactionSchedulerConfig::new();
+            compaction_config.set_max_age_ms(*flush_max_age_ms);
+            compaction_config.set_max_bytes(*flush_max_bytes);
+            compaction_config.set_max_rows(*flush_max_rows);
+            compaction_config.set_max_concurrent_uploads(*max_concurrent_uploads);
             let config = MemoryNodeConfig {
                 binlog_root_dir: binlog_root_dir.to_string(),
                 tmp_store_root_dir: tmp_root_dir.to_string(),
                 etcd_cluster: etcd_cluster.to_string(),
                 etcd_root_path: etcd_root_path.to_string(),
                 node,
+                compaction_config,
+                memory_limit_bytes: *memory_limit_bytes,
+                binlog_segment_size: *binlog_segment_size,
             };
-            let memory_node_impl = MemoryNodeImpl::new(config, Arc::new(meta_store));
+            let memory_node_impl = Arc::new(MemoryNodeImpl::new(config, Arc::new(meta_store)));
             if let Err(e) = memory_node_impl.init().await {
                 warn!("fail to connect to meta {} with err {}", etcd_cluster, e);
                 return Ok(());
             }
             info!("start memory node server on addr {}", bind_addr);
+            let drain_node = memory_node_impl.clone();
+            // the server keeps serving during draining for meta node fences and removes the
+            // partitions on this node
+            let shutdown = async move {
+                wait_for_shutdown_signal().await;
+                info!("receive shutdown signal and start to drain memory node");
+                if let Err(e) = drain_node.drain().await {
+                    warn!("fail to drain memory node with err {}", e);
+                }
+            };
             Server::builder()
-                .add_service(MemoryNodeServer::new(memory_node_impl))
-                .serve(bind_addr.parse().unwrap())
+                .add_service(MemoryNodeServer::from_arc(memory_node_impl))
+                .serve_with_shutdown(bind_addr.parse().unwrap(), shutdown)
                 .await?;
         }
     }
@@ -264,9 +349,8 @@ async fn start_frontend_server(cmd: &Commands) -> Result<(), Box<dyn std::error:
     {
         info!("start frontend node ...");
         if let Ok(meta_store) = build_readonly_meta_store(etcd_cluster, etcd_root_path).await {
-            if let (Ok(meta_node_sdk), Ok(memory_node_sdk), Ok(compute_node_sdk)) = (
+            if let (Ok(meta_node_sdk), Ok(compute_node_sdk)) = (
                 build_meta_node_sdk(&meta_store).await,
-                build_memory_node_sdk(&meta_store).await,
                 build_compute_node_sdk(&meta_store).await,
             ) {
                 let addr = format!("{}:{}", ns, port);
@@ -275,7 +359,6 @@ async fn start_frontend_server(cmd: &Commands) -> Result<(), Box<dyn std::error:
                 let arc_store = Arc::new(meta_store);
                 if let Ok(handler) = mysql_handler::MySQLHandler::new(
                     meta_node_sdk,
-                    memory_node_sdk,
                     compute_node_sdk,
                     arc_store,
                     var_config_path,
@@ -305,6 +388,30 @@ async fn start_frontend_server(cmd: &Commands) -> Result<(), Box<dyn std::error:
     Ok(())
 }
 
+async fn move_partition(cmd: &Commands) -> Result<(), Box<dyn std::error::Error>> {
+    if let Commands::MovePartition {
+        etcd_cluster,
+        etcd_root_path,
+        db,
+        table,
+        partition_id,
+        from_node,
+        to_node,
+    } = cmd
+    {
+        let meta_store = build_readonly_meta_store(etcd_cluster, etcd_root_path).await?;
+        let meta_node_sdk = build_meta_node_sdk(&meta_store).await?;
+        meta_node_sdk
+            .move_partition(db, table, *partition_id, from_node, to_node)
+            .await?;
+        info!(
+            "move partition {} of table {} from {} to {} ok",
+            partition_id, table, from_node, to_node
+        );
+    }
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     setup_log();
@@ -314,6 +421,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         Commands::MemoryNode { .. } => start_memory_node(&args.command).await,
         Commands::FrontendNode { .. } => start_frontend_server(&args.command).await,
         Commands::ComputeNode { .. } => start_compute_node(&args.command).await,
+        Commands::MovePartition { .. } => move_partition(&args.command).await,
         Commands::Version => {
             println!("{}", build::VERSION);
             Ok(())
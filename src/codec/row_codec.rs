@@ -19,7 +19,7 @@
 use crate::error::{DB3Error, Result};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub enum Data {
     Bool(bool),
     Int8(i8),
@@ -32,9 +32,23 @@ pub enum Data {
     Float(f32),
     Double(f64),
     Varchar(String),
-    Date(u32),
-    // time in millsseconds
-    Timestamp(u64),
+    // the days since 1970-01-01, signed for the dates before it. bincode encodes the
+    // signed and unsigned of the same width in the same bytes, so the old binlog is readable
+    Date(i32),
+    // time in the unit of column, millsseconds by default
+    Timestamp(i64),
+    // new variants go after it to keep the encoded binlog readable
+    Null,
+    UInt32(u32),
+    // the unscaled value of decimal
+    Decimal(i128),
+    // the big-endian bytes of uint256
+    UInt256([u8; 32]),
+    Binary(Vec<u8>),
+    // the values in the order of fields
+    Struct(Vec<Data>),
+    List(Vec<Data>),
+    Map(Vec<(Data, Data)>),
 }
 
 impl Data {
@@ -53,6 +67,14 @@ impl Data {
             Data::Varchar(_) => "Varchar",
             Data::Date(_) => "Date",
             Data::Timestamp(_) => "Timestamp",
+            Data::Null => "Null",
+            Data::UInt32(_) => "UInt32",
+            Data::Decimal(_) => "Decimal",
+            Data::UInt256(_) => "UInt256",
+            Data::Binary(_) => "Binary",
+            Data::Struct(_) => "Struct",
+            Data::List(_) => "List",
+            Data::Map(_) => "Map",
         }
     }
 }
@@ -85,7 +107,7 @@ mod tests {
     fn it_encode() -> Result<()> {
         let batch = vec![
             vec![Data::Bool(true), Data::Int32(12)],
-            vec![Data::Bool(false), Data::Int32(11)],
+            vec![Data::Bool(false), Data::Null],
         ];
         let row_batch = RowRecordBatch {
             batch,
@@ -95,6 +117,25 @@ mod tests {
         let new_row_batch: RowRecordBatch = decode(&encoded[..])?;
         assert_eq!(row_batch.schema_version, new_row_batch.schema_version);
         assert_eq!(row_batch.batch.len(), new_row_batch.batch.len());
+        assert_eq!(Data::Null, new_row_batch.batch[1][1]);
+        Ok(())
+    }
+
+    #[test]
+    fn it_encode_nested() -> Result<()> {
+        let block = Data::Struct(vec![
+            Data::Int64(15048327),
+            Data::List(vec![Data::Struct(vec![
+                Data::Binary(vec![0x14, 0xd0]),
+                Data::Map(vec![(Data::Varchar("k".to_string()), Data::Null)]),
+            ])]),
+        ]);
+        let row_batch = RowRecordBatch {
+            batch: vec![vec![block]],
+            schema_version: 1,
+        };
+        let encoded: Vec<u8> = encode(&row_batch)?;
+        assert_eq!(row_batch, decode(&encoded[..])?);
         Ok(())
     }
 }
@@ -0,0 +1,358 @@
+//
+//
+// binary_functions.rs
+// Copyright (C) 2022 db3.network Author imotai <codego.me@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::base::strings;
+use arrow::array::{Array, ArrayRef, BinaryArray, BooleanArray, FixedSizeBinaryArray, StringArray};
+use arrow::datatypes::DataType;
+use datafusion::common::DFSchema;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::context::SessionContext;
+use datafusion::logical_expr::expr_rewriter::{ExprRewritable, ExprRewriter};
+use datafusion::logical_expr::logical_plan::Filter;
+use datafusion::logical_expr::utils::from_plan;
+use datafusion::logical_expr::{
+    Expr, ExprSchemable, LogicalPlan, Operator, ReturnTypeFunction, ScalarUDF, Signature,
+    TypeSignature, Volatility,
+};
+use datafusion::physical_plan::ColumnarValue;
+use datafusion::prelude::create_udf;
+use datafusion::scalar::ScalarValue;
+use std::sync::Arc;
+
+/// the name of the function comparing binary values
+pub const BINARY_EQ: &str = "binary_eq";
+
+// from_hex('0xab') is folded to a binary literal as it's immutable, so the predicate
+// like `hash = from_hex('0x..')` could be pushed down to parquet
+fn from_hex_fn(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    let parse = |v: &str| {
+        strings::hex_to_bytes(v)
+            .ok_or_else(|| DataFusionError::Execution(format!("{} is not a valid hex", v)))
+    };
+    match &args[0] {
+        ColumnarValue::Scalar(ScalarValue::Utf8(v)) => Ok(ColumnarValue::Scalar(
+            ScalarValue::Binary(v.as_deref().map(parse).transpose()?),
+        )),
+        ColumnarValue::Array(array) => {
+            let strings = downcast::<StringArray>(array)?;
+            let bytes = strings
+                .iter()
+                .map(|v| v.map(parse).transpose())
+                .collect::<Result<Vec<Option<Vec<u8>>>>>()?;
+            let bytes: BinaryArray = bytes.iter().map(|v| v.as_deref()).collect();
+            Ok(ColumnarValue::Array(Arc::new(bytes)))
+        }
+        other => Err(DataFusionError::Execution(format!(
+            "from_hex requires a string but {:?}",
+            other.data_type()
+        ))),
+    }
+}
+
+fn hex_fn(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    match &args[0] {
+        ColumnarValue::Scalar(ScalarValue::Binary(v)) => Ok(ColumnarValue::Scalar(
+            ScalarValue::Utf8(v.as_deref().map(strings::bytes_to_hex)),
+        )),
+        ColumnarValue::Array(array) => {
+            let hex: StringArray = match array.data_type() {
+                DataType::FixedSizeBinary(_) => {
+                    let bytes = downcast::<FixedSizeBinaryArray>(array)?;
+                    (0..bytes.len())
+                        .map(|i| (!bytes.is_null(i)).then(|| strings::bytes_to_hex(bytes.value(i))))
+                        .collect()
+                }
+                _ => {
+                    let bytes = downcast::<BinaryArray>(array)?;
+                    bytes.iter().map(|v| v.map(strings::bytes_to_hex)).collect()
+                }
+            };
+            Ok(ColumnarValue::Array(Arc::new(hex)))
+        }
+        other => Err(DataFusionError::Execution(format!(
+            "hex requires a binary but {:?}",
+            other.data_type()
+        ))),
+    }
+}
+
+// the argument of binary_eq, a scalar is the same value for all the rows
+enum Operand<'a> {
+    Binary(&'a BinaryArray),
+    FixedBinary(&'a FixedSizeBinaryArray),
+    Scalar(Option<&'a [u8]>),
+}
+
+impl<'a> Operand<'a> {
+    fn new(arg: &'a ColumnarValue) -> Result<Self> {
+        match arg {
+            ColumnarValue::Array(array) if matches!(array.data_type(), DataType::Binary) => {
+                Ok(Operand::Binary(downcast::<BinaryArray>(array)?))
+            }
+            ColumnarValue::Array(array) => Ok(Operand::FixedBinary(downcast::<
+                FixedSizeBinaryArray,
+            >(array)?)),
+            ColumnarValue::Scalar(ScalarValue::Binary(v)) => Ok(Operand::Scalar(v.as_deref())),
+            other => Err(DataFusionError::Execution(format!(
+                "binary_eq requires a binary but {:?}",
+                other.data_type()
+            ))),
+        }
+    }
+
+    fn len(&self) -> Option<usize> {
+        match self {
+            Operand::Binary(array) => Some(array.len()),
+            Operand::FixedBinary(array) => Some(array.len()),
+            Operand::Scalar(_) => None,
+        }
+    }
+
+    fn value(&self, i: usize) -> Option<&[u8]> {
+        match self {
+            Operand::Binary(array) if array.is_null(i) => None,
+            Operand::Binary(array) => Some(array.value(i)),
+            Operand::FixedBinary(array) if array.is_null(i) => None,
+            Operand::FixedBinary(array) => Some(array.value(i)),
+            Operand::Scalar(v) => *v,
+        }
+    }
+}
+
+// datafusion could not compare the binary values, so `hash = from_hex('0x..')` is rewritten to
+// binary_eq(hash, from_hex('0x..')) which is also used to prune the row groups of parquet
+fn binary_eq_fn(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    let (left, right) = (Operand::new(&args[0])?, Operand::new(&args[1])?);
+    let len = match (left.len(), right.len()) {
+        (Some(l), Some(r)) if l != r => {
+            return Err(DataFusionError::Execution(format!(
+                "binary_eq requires the arguments of the same length but {} and {}",
+                l, r
+            )))
+        }
+        (Some(len), _) | (_, Some(len)) => len,
+        (None, None) => {
+            let eq = left.value(0).zip(right.value(0)).map(|(l, r)| l == r);
+            return Ok(ColumnarValue::Scalar(ScalarValue::Boolean(eq)));
+        }
+    };
+    let eq: BooleanArray = (0..len)
+        .map(|i| left.value(i).zip(right.value(i)).map(|(l, r)| l == r))
+        .collect();
+    Ok(ColumnarValue::Array(Arc::new(eq)))
+}
+
+fn binary_eq_udf() -> ScalarUDF {
+    let signature = Signature::any(2, Volatility::Immutable);
+    let return_type: ReturnTypeFunction = Arc::new(|_| Ok(Arc::new(DataType::Boolean)));
+    ScalarUDF::new(
+        BINARY_EQ,
+        &signature,
+        &return_type,
+        &(Arc::new(binary_eq_fn) as _),
+    )
+}
+
+/// the expression of binary_eq(left, right)
+pub fn binary_eq(left: Expr, right: Expr) -> Expr {
+    Expr::ScalarUDF {
+        fun: Arc::new(binary_eq_udf()),
+        args: vec![left, right],
+    }
+}
+
+fn is_binary(expr: &Expr, schema: &DFSchema) -> bool {
+    matches!(
+        expr.get_type(schema),
+        Ok(DataType::Binary | DataType::FixedSizeBinary(_))
+    )
+}
+
+// rewrite the equality of binary values in filter to binary_eq
+struct BinaryEqRewriter<'a> {
+    schema: &'a DFSchema,
+}
+
+impl<'a> ExprRewriter for BinaryEqRewriter<'a> {
+    fn mutate(&mut self, expr: Expr) -> Result<Expr> {
+        match expr {
+            Expr::BinaryExpr {
+                left,
+                op: Operator::Eq,
+                right,
+            } if is_binary(&left, self.schema) && is_binary(&right, self.schema) => {
+                Ok(binary_eq(*left, *right))
+            }
+            expr => Ok(expr),
+        }
+    }
+}
+
+/// rewrite the equality of binary values in the filters of plan before optimizing
+pub fn rewrite_binary_eq(plan: &LogicalPlan) -> Result<LogicalPlan> {
+    let inputs = plan
+        .inputs()
+        .into_iter()
+        .map(rewrite_binary_eq)
+        .collect::<Result<Vec<_>>>()?;
+    match plan {
+        LogicalPlan::Filter(Filter { predicate, .. }) => {
+            let predicate = predicate.clone().rewrite(&mut BinaryEqRewriter {
+                schema: inputs[0].schema(),
+            })?;
+            from_plan(plan, &[predicate], &inputs)
+        }
+        _ if inputs.is_empty() => Ok(plan.clone()),
+        _ => from_plan(plan, &plan.expressions(), &inputs),
+    }
+}
+
+fn downcast<T: 'static>(array: &ArrayRef) -> Result<&T> {
+    array.as_any().downcast_ref::<T>().ok_or_else(|| {
+        DataFusionError::Execution(format!("unexpected type {:?}", array.data_type()))
+    })
+}
+
+/// register the functions converting between hex strings and binary columns
+pub fn register_binary_functions(stx: &mut SessionContext) {
+    stx.register_udf(create_udf(
+        "from_hex",
+        vec![DataType::Utf8],
+        Arc::new(DataType::Binary),
+        Volatility::Immutable,
+        Arc::new(from_hex_fn),
+    ));
+    // to_hex of datafusion is for integers and is resolved before the udf, the fixed binary has
+    // no scalar in datafusion so it's always an array
+    let signature = Signature::one_of(
+        vec![
+            TypeSignature::Exact(vec![DataType::Binary]),
+            TypeSignature::Any(1),
+        ],
+        Volatility::Immutable,
+    );
+    let return_type: ReturnTypeFunction = Arc::new(|_| Ok(Arc::new(DataType::Utf8)));
+    stx.register_udf(ScalarUDF::new(
+        "hex",
+        &signature,
+        &return_type,
+        &(Arc::new(hex_fn) as _),
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Int32Array;
+    use arrow::datatypes::{Field, Schema};
+    use arrow::record_batch::RecordBatch;
+    use datafusion::dataframe::DataFrame;
+    use datafusion::datasource::MemTable;
+
+    #[test]
+    fn test_from_hex_and_hex() -> Result<()> {
+        let hex = ColumnarValue::Scalar(ScalarValue::Utf8(Some("0xab01".to_string())));
+        let bytes = match from_hex_fn(&[hex])? {
+            ColumnarValue::Scalar(v) => v,
+            _ => panic!("should be scalar"),
+        };
+        assert_eq!(ScalarValue::Binary(Some(vec![0xab, 0x01])), bytes);
+        let hex = ColumnarValue::Scalar(ScalarValue::Utf8(Some("0xzz".to_string())));
+        assert!(from_hex_fn(&[hex]).is_err());
+
+        let array: ArrayRef = Arc::new(BinaryArray::from(vec![Some(&[0x12u8][..]), None]));
+        let hex = match hex_fn(&[ColumnarValue::Array(array)])? {
+            ColumnarValue::Array(a) => a,
+            _ => panic!("should be array"),
+        };
+        let hex = downcast::<StringArray>(&hex)?;
+        assert_eq!("0x12", hex.value(0));
+        assert!(hex.is_null(1));
+        let array: ArrayRef = Arc::new(FixedSizeBinaryArray::try_from_sparse_iter(
+            vec![None, Some([0xab, 0xcd])].into_iter(),
+        )?);
+        let hex = match hex_fn(&[ColumnarValue::Array(array)])? {
+            ColumnarValue::Array(a) => a,
+            _ => panic!("should be array"),
+        };
+        let hex = downcast::<StringArray>(&hex)?;
+        assert!(hex.is_null(0));
+        assert_eq!("0xabcd", hex.value(1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_binary_eq() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("k", DataType::Int32, false),
+            Field::new("h", DataType::Binary, true),
+            Field::new("f", DataType::FixedSizeBinary(2), true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(vec![1, 2, 3])),
+                Arc::new(BinaryArray::from(vec![
+                    Some(&[0xab][..]),
+                    Some(&[0xab, 0x01][..]),
+                    None,
+                ])),
+                Arc::new(FixedSizeBinaryArray::try_from_sparse_iter(
+                    vec![Some([1, 2]), None, Some([1, 2])].into_iter(),
+                )?),
+            ],
+        )?;
+        let mut stx = SessionContext::new();
+        register_binary_functions(&mut stx);
+        stx.register_table(
+            "t1",
+            Arc::new(MemTable::try_new(schema, vec![vec![batch]])?),
+        )?;
+        let keys = |sql: &'static str| {
+            let stx = stx.clone();
+            async move {
+                let plan = rewrite_binary_eq(&stx.create_logical_plan(sql)?)?;
+                let plan = stx.optimize(&plan)?;
+                let batches = DataFrame::new(stx.state.clone(), &plan).collect().await?;
+                let mut keys: Vec<i32> = Vec::new();
+                for batch in batches {
+                    let array = downcast::<Int32Array>(batch.column(0))?;
+                    keys.extend(array.iter().flatten());
+                }
+                Ok::<Vec<i32>, DataFusionError>(keys)
+            }
+        };
+        assert_eq!(
+            vec![1],
+            keys("select k from t1 where h = from_hex('0xab')").await?
+        );
+        assert_eq!(
+            vec![1, 3],
+            keys("select k from t1 where f = from_hex('0x0102') order by k").await?
+        );
+        assert_eq!(
+            vec![2],
+            keys("select k from t1 where from_hex('0xab01') = h and k > 1").await?
+        );
+        // the length of fixed binary is different from the literal
+        assert!(keys("select k from t1 where f = from_hex('0xab')")
+            .await?
+            .is_empty());
+        Ok(())
+    }
+}
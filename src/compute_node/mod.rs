@@ -16,5 +16,8 @@
 // limitations under the License.
 //
 
+pub mod binary_functions;
 pub mod compute_node_impl;
+pub mod nested_functions;
 pub mod sql_engine;
+pub mod uint256_functions;
@@ -0,0 +1,501 @@
+//
+//
+// nested_functions.rs
+// Copyright (C) 2022 db3.network Author imotai <codego.me@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use arrow::array::{
+    make_array, Array, ArrayData, ArrayRef, BooleanBufferBuilder, Int64Array, ListArray, MapArray,
+    StructArray, UInt32Array,
+};
+use arrow::compute;
+use arrow::datatypes::{DataType, Field};
+use datafusion::common::DFSchema;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::context::SessionContext;
+use datafusion::logical_expr::expr_rewriter::{ExprRewritable, ExprRewriter};
+use datafusion::logical_expr::logical_plan::{Filter, Projection, Sort};
+use datafusion::logical_expr::utils::from_plan;
+use datafusion::logical_expr::{
+    Expr, ExprSchemable, LogicalPlan, LogicalPlanBuilder, ReturnTypeFunction, ScalarUDF, Signature,
+    Volatility,
+};
+use datafusion::physical_plan::ColumnarValue;
+use datafusion::scalar::ScalarValue;
+use datafusion::sql::planner::ContextProvider;
+use sqlparser::ast::{
+    Expr as SQLExpr, Function, FunctionArg, FunctionArgExpr, Ident, ObjectName, Query, SelectItem,
+    SetExpr, Statement as SQLStatement, TableFactor, Value,
+};
+use std::iter;
+use std::sync::Arc;
+
+/// the name of the function getting the value of a key in map
+pub const MAP_GET: &str = "map_get";
+/// the name of the function getting the item of list by the index from 1
+pub const LIST_GET: &str = "list_get";
+/// the name of the function getting the field of struct
+pub const STRUCT_GET: &str = "struct_get";
+
+// the child is null if the parent is null, the values of child under the null parent are
+// not null when they are taken by the null indices
+fn with_parent_nulls(parent: &dyn Array, child: &ArrayRef) -> Result<ArrayRef> {
+    if parent.null_count() == 0 {
+        return Ok(child.clone());
+    }
+    let data = child.data();
+    let mut validity = BooleanBufferBuilder::new(data.offset() + data.len());
+    validity.append_n(data.offset(), false);
+    for i in 0..data.len() {
+        validity.append(parent.is_valid(i) && child.is_valid(i));
+    }
+    let data = ArrayData::builder(data.data_type().clone())
+        .len(data.len())
+        .offset(data.offset())
+        .buffers(data.buffers().to_vec())
+        .child_data(data.child_data().to_vec())
+        .null_bit_buffer(Some(validity.finish()))
+        .build()?;
+    Ok(make_array(data))
+}
+
+fn struct_get_fn(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    let fields = match &args[0] {
+        ColumnarValue::Array(array) => array.as_any().downcast_ref::<StructArray>(),
+        _ => None,
+    };
+    match (fields, &args[1]) {
+        (Some(fields), ColumnarValue::Scalar(ScalarValue::Utf8(Some(name)))) => {
+            let field = fields.column_by_name(name).ok_or_else(|| {
+                DataFusionError::Execution(format!("field {} not found in struct", name))
+            })?;
+            Ok(ColumnarValue::Array(with_parent_nulls(fields, field)?))
+        }
+        _ => Err(DataFusionError::Execution(format!(
+            "struct_get requires a struct and a field name but {:?}",
+            args[0].data_type()
+        ))),
+    }
+}
+
+// the type of field is resolved when the plan is rewritten, as the udf has no value of name
+fn struct_get_udf(data_type: DataType) -> ScalarUDF {
+    let signature = Signature::any(2, Volatility::Immutable);
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(data_type.clone())));
+    ScalarUDF::new(
+        STRUCT_GET,
+        &signature,
+        &return_type,
+        &(Arc::new(struct_get_fn) as _),
+    )
+}
+
+// the item of list in each row, null if the index is out of the list
+fn list_get_fn(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    let list = match &args[0] {
+        ColumnarValue::Array(array) => array.as_any().downcast_ref::<ListArray>(),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        DataFusionError::Execution(format!(
+            "list_get requires a list but {:?}",
+            args[0].data_type()
+        ))
+    })?;
+    let index = compute::cast(&args[1].clone().into_array(list.len()), &DataType::Int64)?;
+    let index = index
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .ok_or_else(|| DataFusionError::Execution("list_get requires an integer index".into()))?;
+    let offsets = list.value_offsets();
+    let indices: UInt32Array = (0..list.len())
+        .map(|i| match index.is_valid(i) {
+            true if (1..=offsets[i + 1] - offsets[i]).contains(&(index.value(i) as i32)) => {
+                Some((offsets[i] as i64 + index.value(i) - 1) as u32)
+            }
+            _ => None,
+        })
+        .collect();
+    Ok(ColumnarValue::Array(compute::take(
+        list.values().as_ref(),
+        &indices,
+        None,
+    )?))
+}
+
+fn list_get_udf() -> ScalarUDF {
+    let signature = Signature::any(2, Volatility::Immutable);
+    let return_type: ReturnTypeFunction = Arc::new(|args| match &args[0] {
+        DataType::List(item) => Ok(Arc::new(item.data_type().clone())),
+        other => Err(DataFusionError::Plan(format!(
+            "list_get requires a list but {:?}",
+            other
+        ))),
+    });
+    ScalarUDF::new(
+        LIST_GET,
+        &signature,
+        &return_type,
+        &(Arc::new(list_get_fn) as _),
+    )
+}
+
+// the value of the first entry with the key in each row, null if the key is absent
+fn map_get_fn(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    let map = match &args[0] {
+        ColumnarValue::Array(array) => array.as_any().downcast_ref::<MapArray>(),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        DataFusionError::Execution(format!(
+            "map_get requires a map but {:?}",
+            args[0].data_type()
+        ))
+    })?;
+    let key = compute::cast(&args[1].clone().into_array(map.len()), &map.key_type())?;
+    let offsets = map.value_offsets();
+    let start = offsets[0] as usize;
+    let keys = map.keys().slice(start, offsets[map.len()] as usize - start);
+    // the key of row for each entry
+    let rows: UInt32Array = (0..map.len())
+        .flat_map(|i| (offsets[i]..offsets[i + 1]).map(move |_| i as u32))
+        .collect();
+    let eq = compute::eq_dyn(keys.as_ref(), compute::take(&key, &rows, None)?.as_ref())?;
+    let indices: UInt32Array = (0..map.len())
+        .map(|i| {
+            (offsets[i] as usize..offsets[i + 1] as usize)
+                .find(|j| eq.is_valid(j - start) && eq.value(j - start))
+                .map(|j| j as u32)
+        })
+        .collect();
+    Ok(ColumnarValue::Array(compute::take(
+        map.values().as_ref(),
+        &indices,
+        None,
+    )?))
+}
+
+fn map_get_udf() -> ScalarUDF {
+    let signature = Signature::any(2, Volatility::Immutable);
+    let return_type: ReturnTypeFunction = Arc::new(|args| match &args[0] {
+        DataType::Map(entries, _) => match entries.data_type() {
+            DataType::Struct(kv) if kv.len() == 2 => Ok(Arc::new(kv[1].data_type().clone())),
+            _ => Err(DataFusionError::Plan(format!(
+                "invalid entries {:?} of map",
+                entries
+            ))),
+        },
+        other => Err(DataFusionError::Plan(format!(
+            "map_get requires a map but {:?}",
+            other
+        ))),
+    });
+    ScalarUDF::new(
+        MAP_GET,
+        &signature,
+        &return_type,
+        &(Arc::new(map_get_fn) as _),
+    )
+}
+
+/// register the functions of nested columns
+pub fn register_nested_functions(stx: &mut SessionContext) {
+    stx.register_udf(map_get_udf());
+    stx.register_udf(list_get_udf());
+}
+
+// rewrite the indexed fields of datafusion to list_get and struct_get, datafusion drops the
+// rows of null list and the rows without the index, and takes the fields of null struct
+struct IndexedFieldRewriter<'a> {
+    schema: &'a DFSchema,
+}
+
+impl<'a> ExprRewriter for IndexedFieldRewriter<'a> {
+    fn mutate(&mut self, expr: Expr) -> Result<Expr> {
+        let fun = match &expr {
+            Expr::GetIndexedField { expr: e, key } => match (e.get_type(self.schema)?, key) {
+                (DataType::List(_), _) => list_get_udf(),
+                (DataType::Struct(fields), ScalarValue::Utf8(Some(name))) => {
+                    match fields.iter().find(|f| f.name() == name) {
+                        Some(field) => struct_get_udf(field.data_type().clone()),
+                        None => return Ok(expr),
+                    }
+                }
+                _ => return Ok(expr),
+            },
+            _ => return Ok(expr),
+        };
+        match expr {
+            Expr::GetIndexedField { expr, key } => Ok(Expr::ScalarUDF {
+                fun: Arc::new(fun),
+                args: vec![*expr, Expr::Literal(key)],
+            }),
+            expr => Ok(expr),
+        }
+    }
+}
+
+/// rewrite the indexed fields in projection, filter and sort of plan before optimizing
+pub fn rewrite_indexed_field(plan: &LogicalPlan) -> Result<LogicalPlan> {
+    let inputs = plan
+        .inputs()
+        .into_iter()
+        .map(rewrite_indexed_field)
+        .collect::<Result<Vec<_>>>()?;
+    let rewrite = |e: &Expr| {
+        e.clone().rewrite(&mut IndexedFieldRewriter {
+            schema: inputs[0].schema(),
+        })
+    };
+    match plan {
+        LogicalPlan::Projection(Projection { expr, alias, .. }) => {
+            let exprs = expr
+                .iter()
+                .map(|e| {
+                    let new_expr = rewrite(e)?;
+                    match e {
+                        // keep the name of column
+                        Expr::Alias(..) => Ok(new_expr),
+                        _ if &new_expr == e => Ok(new_expr),
+                        _ => Ok(Expr::Alias(Box::new(new_expr), e.name(inputs[0].schema())?)),
+                    }
+                })
+                .collect::<Result<Vec<_>>>()?;
+            LogicalPlanBuilder::from(inputs[0].clone())
+                .project_with_alias(exprs, alias.clone())?
+                .build()
+        }
+        LogicalPlan::Filter(Filter { predicate, .. }) => {
+            from_plan(plan, &[rewrite(predicate)?], &inputs)
+        }
+        LogicalPlan::Sort(Sort { expr, .. }) => {
+            let exprs = expr.iter().map(rewrite).collect::<Result<Vec<_>>>()?;
+            from_plan(plan, &exprs, &inputs)
+        }
+        _ if inputs.is_empty() => Ok(plan.clone()),
+        _ => from_plan(plan, &plan.expressions(), &inputs),
+    }
+}
+
+// the same as datafusion normalizes the identifiers
+fn normalize_ident(id: &Ident) -> String {
+    match id.quote_style {
+        Some(_) => id.value.clone(),
+        None => id.value.to_ascii_lowercase(),
+    }
+}
+
+// the position of map in the keys of field, the keys before it index the lists and structs
+fn map_key_position(field: &Field, keys: &[SQLExpr]) -> Option<usize> {
+    let mut data_type = field.data_type();
+    for (i, key) in keys.iter().enumerate() {
+        data_type = match (data_type, key) {
+            (DataType::Map(..), _) => return Some(i),
+            (DataType::List(item), _) => item.data_type(),
+            (DataType::Struct(fields), SQLExpr::Value(Value::SingleQuotedString(name))) => {
+                fields.iter().find(|f| f.name() == name)?.data_type()
+            }
+            _ => return None,
+        };
+    }
+    None
+}
+
+fn function(name: &str, args: Vec<SQLExpr>) -> SQLExpr {
+    SQLExpr::Function(Function {
+        name: ObjectName(vec![Ident::new(name)]),
+        args: args
+            .into_iter()
+            .map(|arg| FunctionArg::Unnamed(FunctionArgExpr::Expr(arg)))
+            .collect(),
+        over: None,
+        distinct: false,
+    })
+}
+
+// rewrite `column[..][key]` to map_get(column[..], key) if the last key is of a map, return
+// whether the expression is rewritten
+fn rewrite_expr(expr: &mut SQLExpr, fields: &[Field]) -> Result<bool> {
+    let rewritten = match expr {
+        SQLExpr::MapAccess { column, keys } => {
+            let position = match column.as_ref() {
+                SQLExpr::Identifier(id) => fields
+                    .iter()
+                    .find(|f| f.name() == &normalize_ident(id))
+                    .and_then(|f| map_key_position(f, keys)),
+                _ => None,
+            };
+            match position {
+                Some(p) if p + 1 == keys.len() => {
+                    let mut keys = std::mem::take(keys);
+                    let key = keys.pop().unwrap_or(SQLExpr::Value(Value::Null));
+                    let column = std::mem::replace(column, Box::new(SQLExpr::Value(Value::Null)));
+                    let map = match keys.is_empty() {
+                        true => *column,
+                        false => SQLExpr::MapAccess { column, keys },
+                    };
+                    *expr = function(MAP_GET, vec![map, key]);
+                    true
+                }
+                Some(_) => {
+                    return Err(DataFusionError::NotImplemented(format!(
+                        "the value of map in {} could not be indexed",
+                        expr
+                    )))
+                }
+                None => false,
+            }
+        }
+        SQLExpr::BinaryOp { left, right, .. } => {
+            rewrite_expr(left, fields)? | rewrite_expr(right, fields)?
+        }
+        SQLExpr::UnaryOp { expr, .. }
+        | SQLExpr::Nested(expr)
+        | SQLExpr::Cast { expr, .. }
+        | SQLExpr::TryCast { expr, .. }
+        | SQLExpr::IsNull(expr)
+        | SQLExpr::IsNotNull(expr) => rewrite_expr(expr, fields)?,
+        SQLExpr::Between {
+            expr, low, high, ..
+        } => rewrite_expr(expr, fields)? | rewrite_expr(low, fields)? | rewrite_expr(high, fields)?,
+        SQLExpr::InList { expr, list, .. } => {
+            let mut rewritten = rewrite_expr(expr, fields)?;
+            for e in list.iter_mut() {
+                rewritten |= rewrite_expr(e, fields)?;
+            }
+            rewritten
+        }
+        SQLExpr::Function(Function { args, .. }) => {
+            let mut rewritten = false;
+            for arg in args.iter_mut() {
+                if let FunctionArg::Unnamed(FunctionArgExpr::Expr(e))
+                | FunctionArg::Named {
+                    arg: FunctionArgExpr::Expr(e),
+                    ..
+                } = arg
+                {
+                    rewritten |= rewrite_expr(e, fields)?;
+                }
+            }
+            rewritten
+        }
+        SQLExpr::Case {
+            operand,
+            conditions,
+            results,
+            else_result,
+        } => {
+            let mut rewritten = false;
+            for e in operand
+                .iter_mut()
+                .chain(else_result.iter_mut())
+                .map(|e| e.as_mut())
+                .chain(conditions.iter_mut())
+                .chain(results.iter_mut())
+            {
+                rewritten |= rewrite_expr(e, fields)?;
+            }
+            rewritten
+        }
+        _ => false,
+    };
+    Ok(rewritten)
+}
+
+fn rewrite_set_expr(body: &mut SetExpr, provider: &dyn ContextProvider) -> Result<Vec<Field>> {
+    match body {
+        SetExpr::Select(select) => {
+            // the fields of the tables in from clause
+            let mut fields: Vec<Field> = Vec::new();
+            for relation in select.from.iter_mut().flat_map(|t| {
+                iter::once(&mut t.relation).chain(t.joins.iter_mut().map(|j| &mut j.relation))
+            }) {
+                match relation {
+                    TableFactor::Table { name, .. } => {
+                        let name = name
+                            .0
+                            .iter()
+                            .map(normalize_ident)
+                            .collect::<Vec<String>>()
+                            .join(".");
+                        // the error of unknown table is left to the planner
+                        if let Ok(table) = provider.get_table_provider(name.as_str().into()) {
+                            fields.extend(table.schema().fields().iter().cloned());
+                        }
+                    }
+                    TableFactor::Derived { subquery, .. } => rewrite_query(subquery, provider)?,
+                    _ => {}
+                }
+            }
+            for item in select.projection.iter_mut() {
+                match item {
+                    // the name of column is the same as the sql
+                    SelectItem::UnnamedExpr(expr) => {
+                        let alias = Ident::new(expr.to_string());
+                        if rewrite_expr(expr, &fields)? {
+                            *item = SelectItem::ExprWithAlias {
+                                expr: std::mem::replace(expr, SQLExpr::Value(Value::Null)),
+                                alias,
+                            };
+                        }
+                    }
+                    SelectItem::ExprWithAlias { expr, .. } => {
+                        rewrite_expr(expr, &fields)?;
+                    }
+                    _ => {}
+                }
+            }
+            for expr in select
+                .selection
+                .iter_mut()
+                .chain(select.having.iter_mut())
+                .chain(select.group_by.iter_mut())
+            {
+                rewrite_expr(expr, &fields)?;
+            }
+            Ok(fields)
+        }
+        SetExpr::Query(query) => {
+            rewrite_query(query, provider)?;
+            Ok(vec![])
+        }
+        SetExpr::SetOperation { left, right, .. } => {
+            rewrite_set_expr(left, provider)?;
+            rewrite_set_expr(right, provider)?;
+            Ok(vec![])
+        }
+        _ => Ok(vec![]),
+    }
+}
+
+fn rewrite_query(query: &mut Query, provider: &dyn ContextProvider) -> Result<()> {
+    let fields = rewrite_set_expr(&mut query.body, provider)?;
+    for order_by in query.order_by.iter_mut() {
+        rewrite_expr(&mut order_by.expr, &fields)?;
+    }
+    Ok(())
+}
+
+/// rewrite the key access of map columns in query to map_get, datafusion indexes the list and
+/// struct columns only. the map columns are found in the tables of select
+pub fn rewrite_map_access(
+    mut statement: SQLStatement,
+    provider: &dyn ContextProvider,
+) -> Result<SQLStatement> {
+    if let SQLStatement::Query(query) = &mut statement {
+        rewrite_query(query, provider)?;
+    }
+    Ok(statement)
+}
@@ -18,6 +18,7 @@
 
 uselog!(debug, info, warn);
 use crate::catalog::catalog::Catalog;
+use crate::compute_node::{binary_functions, nested_functions, uint256_functions};
 use crate::error::Result;
 use crate::frontend_node::mysql::interruptible_parser::*;
 use arrow::datatypes::DataType;
@@ -65,7 +66,7 @@ impl SQLEngine {
         Ok((keyword, statement))
     }
 
-    fn add_function(&self, stx: &mut SessionContext) {
+    fn add_function(stx: &mut SessionContext) {
         //TODO move these to frontend
         let db_fn = |_: &[ColumnarValue]| {
             Ok(ColumnarValue::Scalar(ScalarValue::Utf8(Some(
@@ -116,6 +117,28 @@ impl SQLEngine {
             Volatility::Immutable,
             Arc::new(version_fn),
         ));
+        uint256_functions::register_uint256_functions(stx);
+        binary_functions::register_binary_functions(stx);
+        nested_functions::register_nested_functions(stx);
+    }
+
+    // plan the statement with the rewrites of db3 functions and run it in the session
+    async fn run_statement(
+        stx: &SessionContext,
+        statement: SQLStatement,
+    ) -> Result<Vec<RecordBatch>> {
+        let state = stx.state.read().clone();
+        let statement = nested_functions::rewrite_map_access(statement, &state)?;
+        let query_planner = SqlToRel::new(&state);
+        let plan = query_planner.sql_statement_to_plan(statement)?;
+        let plan = uint256_functions::rewrite_uint256_plan(&plan)?;
+        let plan = binary_functions::rewrite_binary_eq(&plan)?;
+        let plan = nested_functions::rewrite_indexed_field(&plan)?;
+        let opt_plan = stx.optimize(&plan)?;
+        let ret = Arc::new(DataFrame::new(stx.state.clone(), &opt_plan));
+        // use streaming resultset
+        let batches = ret.collect().await?;
+        Ok(uint256_functions::mark_uint256_columns(&opt_plan, batches)?)
     }
 
     pub async fn execute(&self, sql: &str, db: Option<String>, id: u32) -> Result<SQLResult> {
@@ -140,18 +163,144 @@ impl SQLEngine {
         };
         //TODO use session id to cache session context
         let mut stx = SessionContext::with_config_rt(config, self.runtime.clone());
-        self.add_function(&mut stx);
+        Self::add_function(&mut stx);
         stx.register_catalog("db3", self.catalog.clone());
-        let state = stx.state.read().clone();
-        let query_planner = SqlToRel::new(&state);
-        let plan = query_planner.sql_statement_to_plan(statement)?;
-        let opt_plan = stx.optimize(&plan)?;
-        let ret = Arc::new(DataFrame::new(stx.state.clone(), &opt_plan));
-        // use streaming resultset
-        let batches = ret.collect().await?;
+        let batches = Self::run_statement(&stx, statement).await?;
         Ok(SQLResult {
             batch: Some(batches),
             effected_rows: 0,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::arrow_parquet_utils::{self, gen_nested_column};
+    use crate::base::linked_list::LinkedList;
+    use crate::codec::row_codec::{Data, RowRecordBatch};
+    use crate::proto::db3_base_proto::{Db3SchemaDesc, Db3Type};
+    use datafusion::assert_batches_eq;
+    use datafusion::datasource::MemTable;
+
+    async fn query(stx: &SessionContext, sql: &str) -> Result<Vec<RecordBatch>> {
+        let (_, statement) = SQLEngine::parse_sql(sql)?;
+        SQLEngine::run_statement(stx, statement).await
+    }
+
+    #[tokio::test]
+    async fn test_nested_column_access() -> Result<()> {
+        // blocks (block {number, transactions: [{hash, gas}]}, labels {string: bigint})
+        let transaction = gen_nested_column(
+            "item",
+            Db3Type::KStruct,
+            vec![
+                gen_nested_column("hash", Db3Type::KBinary, vec![]),
+                gen_nested_column("gas", Db3Type::KBigInt, vec![]),
+            ],
+        );
+        let block = gen_nested_column(
+            "block",
+            Db3Type::KStruct,
+            vec![
+                gen_nested_column("number", Db3Type::KBigInt, vec![]),
+                gen_nested_column("transactions", Db3Type::KList, vec![transaction]),
+            ],
+        );
+        let labels = gen_nested_column(
+            "labels",
+            Db3Type::KMap,
+            vec![
+                gen_nested_column("key", Db3Type::KStringUtf8, vec![]),
+                gen_nested_column("value", Db3Type::KBigInt, vec![]),
+            ],
+        );
+        let schema = arrow_parquet_utils::table_desc_to_arrow_schema(&Db3SchemaDesc {
+            columns: vec![block, labels],
+            version: 1,
+        })?;
+        let transaction =
+            |hash: u8, gas: i64| Data::Struct(vec![Data::Binary(vec![hash]), Data::Int64(gas)]);
+        let label = |key: &str, value: i64| (Data::Varchar(key.to_string()), Data::Int64(value));
+        let rows = LinkedList::<RowRecordBatch>::new();
+        rows.push_front(RowRecordBatch {
+            batch: vec![
+                vec![
+                    Data::Struct(vec![
+                        Data::Int64(1),
+                        Data::List(vec![transaction(1, 21000), transaction(2, 50000)]),
+                    ]),
+                    Data::Map(vec![label("miner", 1), label("uncle", 2)]),
+                ],
+                vec![Data::Null, Data::Null],
+                vec![
+                    Data::Struct(vec![
+                        Data::Int64(2),
+                        Data::List(vec![transaction(3, 30000)]),
+                    ]),
+                    Data::Map(vec![label("miner", 2)]),
+                ],
+            ],
+            schema_version: 1,
+        })?;
+        let batch = arrow_parquet_utils::rows_to_columns(&schema, &rows)?;
+        let mut stx = SessionContext::new();
+        SQLEngine::add_function(&mut stx);
+        stx.register_table(
+            "blocks",
+            Arc::new(MemTable::try_new(schema, vec![vec![batch]])?),
+        )?;
+        // the index of list is from 1, the null block and the absent key are null
+        let batches = query(
+            &stx,
+            "select block['number'] as number, block['transactions'][1]['gas'] as gas, \
+             labels['uncle'] as uncle from blocks",
+        )
+        .await?;
+        let expected = vec![
+            "+--------+-------+-------+",
+            "| number | gas   | uncle |",
+            "+--------+-------+-------+",
+            "| 1      | 21000 | 2     |",
+            "|        |       |       |",
+            "| 2      | 30000 |       |",
+            "+--------+-------+-------+",
+        ];
+        assert_batches_eq!(expected, &batches);
+        // the second transaction of block 2 is absent
+        let batches = query(
+            &stx,
+            "select block['number'] as number, block['transactions'][2]['gas'] as gas \
+             from blocks where labels['miner'] > 0",
+        )
+        .await?;
+        let expected = vec![
+            "+--------+-------+",
+            "| number | gas   |",
+            "+--------+-------+",
+            "| 1      | 50000 |",
+            "| 2      |       |",
+            "+--------+-------+",
+        ];
+        assert_batches_eq!(expected, &batches);
+        let batches = query(
+            &stx,
+            "select block['number'] as number from blocks \
+             where block['transactions'][1]['gas'] > 25000 and labels['miner'] = 2",
+        )
+        .await?;
+        let expected = vec![
+            "+--------+",
+            "| number |",
+            "+--------+",
+            "| 2      |",
+            "+--------+",
+        ];
+        assert_batches_eq!(expected, &batches);
+        // the value of map is a bigint
+        assert!(query(&stx, "select labels['miner']['x'] from blocks")
+            .await
+            .is_err());
+        Ok(())
+    }
+}
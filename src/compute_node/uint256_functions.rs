@@ -0,0 +1,683 @@
+//
+//
+// uint256_functions.rs
+// Copyright (C) 2022 db3.network Author imotai <codego.me@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::base::arrow_parquet_utils;
+use crate::base::uint256::{self, UInt256, UINT256_BYTES};
+use arrow::array::{
+    Array, ArrayRef, BinaryArray, FixedSizeBinaryArray, FixedSizeBinaryBuilder, StringArray,
+};
+use arrow::datatypes::{DataType, Schema};
+use arrow::record_batch::RecordBatch;
+use datafusion::common::Column;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::context::SessionContext;
+use datafusion::logical_expr::expr_rewriter::{ExprRewritable, ExprRewriter};
+use datafusion::logical_expr::logical_plan::{
+    Aggregate, CrossJoin, Filter, Join, Projection, TableScan, Window,
+};
+use datafusion::logical_expr::utils::from_plan;
+use datafusion::logical_expr::{
+    Expr, LogicalPlan, LogicalPlanBuilder, ReturnTypeFunction, ScalarUDF, Signature, TypeSignature,
+    Volatility,
+};
+use datafusion::logical_plan::{create_udaf, create_udf};
+use datafusion::physical_plan::{Accumulator, ColumnarValue};
+use datafusion::scalar::ScalarValue;
+use std::sync::Arc;
+
+// the functions returning uint256
+const UINT256_FUNCTIONS: [&str; 7] = [
+    "uint256",
+    "uint256_add",
+    "uint256_sub",
+    "uint256_mul",
+    "uint256_div",
+    "uint256_mod",
+    "uint256_from_bytes",
+];
+
+// the arrow type of uint256 column
+fn uint256_type() -> DataType {
+    DataType::FixedSizeBinary(UINT256_BYTES as i32)
+}
+
+fn downcast_uint256(array: &ArrayRef) -> Result<&FixedSizeBinaryArray> {
+    array
+        .as_any()
+        .downcast_ref::<FixedSizeBinaryArray>()
+        .ok_or_else(|| {
+            DataFusionError::Execution(format!("{:?} is not uint256", array.data_type()))
+        })
+}
+
+// the argument of arithmetic, a scalar is the same value for all the rows
+enum Operand<'a> {
+    Array(&'a FixedSizeBinaryArray),
+    Scalar(Option<UInt256>),
+}
+
+impl<'a> Operand<'a> {
+    // the uint256 column or a string literal like '1000' and '0x3e8'
+    fn new(arg: &'a ColumnarValue, name: &str) -> Result<Self> {
+        match arg {
+            ColumnarValue::Array(array) => Ok(Operand::Array(downcast_uint256(array)?)),
+            ColumnarValue::Scalar(ScalarValue::Utf8(v)) => v
+                .as_deref()
+                .map(|v| {
+                    uint256::parse_uint256(v).ok_or_else(|| {
+                        DataFusionError::Execution(format!("{} is not a valid uint256", v))
+                    })
+                })
+                .transpose()
+                .map(Operand::Scalar),
+            ColumnarValue::Scalar(other) => Err(DataFusionError::Execution(format!(
+                "{} requires uint256 but {:?}",
+                name,
+                other.get_datatype()
+            ))),
+        }
+    }
+
+    fn value(&self, i: usize) -> Option<&[u8]> {
+        match self {
+            Operand::Array(array) if array.is_null(i) => None,
+            Operand::Array(array) => Some(array.value(i)),
+            Operand::Scalar(v) => v.as_ref().map(|v| &v[..]),
+        }
+    }
+}
+
+/// apply the op to every pair of values, the arrays must be of the same length and a scalar
+/// is applied to all the rows
+fn binary_op(
+    args: &[ColumnarValue],
+    name: &str,
+    op: fn(&[u8], &[u8]) -> Option<UInt256>,
+) -> Result<ColumnarValue> {
+    let (left, right) = (Operand::new(&args[0], name)?, Operand::new(&args[1], name)?);
+    let len = match (&left, &right) {
+        (Operand::Array(l), Operand::Array(r)) if l.len() != r.len() => {
+            return Err(DataFusionError::Execution(format!(
+                "{} requires the arguments of the same length but {} and {}",
+                name,
+                l.len(),
+                r.len()
+            )))
+        }
+        (Operand::Array(a), _) | (_, Operand::Array(a)) => a.len(),
+        _ => {
+            return Err(DataFusionError::Execution(format!(
+                "{} requires at least one uint256 column",
+                name
+            )))
+        }
+    };
+    let mut builder = FixedSizeBinaryBuilder::new(len, UINT256_BYTES as i32);
+    for i in 0..len {
+        match (left.value(i), right.value(i)) {
+            (Some(l), Some(r)) => match op(l, r) {
+                Some(v) => builder.append_value(v)?,
+                None => {
+                    return Err(DataFusionError::Execution(format!(
+                        "{}({}, {}) is not a valid uint256",
+                        name,
+                        uint256::uint256_to_string(l),
+                        uint256::uint256_to_string(r)
+                    )))
+                }
+            },
+            _ => builder.append_null()?,
+        }
+    }
+    Ok(ColumnarValue::Array(Arc::new(builder.finish())))
+}
+
+// uint256('1000') or uint256('0x3e8'), a literal is sized by the column added as the second
+// argument for a function of literal returns only one row
+fn uint256_fn(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    let array = match (&args[0], args.get(1)) {
+        (ColumnarValue::Array(a), _) => a.clone(),
+        (ColumnarValue::Scalar(s), Some(ColumnarValue::Array(a))) => s.to_array_of_size(a.len()),
+        (ColumnarValue::Scalar(s), _) => s.to_array(),
+    };
+    let strings = array
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .ok_or_else(|| DataFusionError::Execution("uint256 requires a string".to_string()))?;
+    let mut builder = FixedSizeBinaryBuilder::new(strings.len(), UINT256_BYTES as i32);
+    for i in 0..strings.len() {
+        if strings.is_null(i) {
+            builder.append_null()?;
+            continue;
+        }
+        let value = uint256::parse_uint256(strings.value(i)).ok_or_else(|| {
+            DataFusionError::Execution(format!("{} is not a valid uint256", strings.value(i)))
+        })?;
+        builder.append_value(value)?;
+    }
+    Ok(ColumnarValue::Array(Arc::new(builder.finish())))
+}
+
+// the big-endian bytes of the sum to uint256
+fn uint256_from_bytes_fn(args: &[ColumnarValue]) -> Result<ColumnarValue> {
+    let array = match &args[0] {
+        ColumnarValue::Array(a) => a.clone(),
+        ColumnarValue::Scalar(s) => s.to_array(),
+    };
+    let bytes = array
+        .as_any()
+        .downcast_ref::<BinaryArray>()
+        .ok_or_else(|| DataFusionError::Internal("bad bytes of uint256".to_string()))?;
+    let mut builder = FixedSizeBinaryBuilder::new(bytes.len(), UINT256_BYTES as i32);
+    for i in 0..bytes.len() {
+        if bytes.is_null(i) {
+            builder.append_null()?;
+        } else {
+            builder.append_value(bytes.value(i))?;
+        }
+    }
+    Ok(ColumnarValue::Array(Arc::new(builder.finish())))
+}
+
+fn uint256_from_bytes_udf() -> ScalarUDF {
+    create_udf(
+        "uint256_from_bytes",
+        vec![DataType::Binary],
+        Arc::new(uint256_type()),
+        Volatility::Immutable,
+        Arc::new(uint256_from_bytes_fn),
+    )
+}
+
+// the arithmetic of uint256 column and uint256 column or string literal
+fn binary_udf(name: &'static str, op: fn(&[u8], &[u8]) -> Option<UInt256>) -> ScalarUDF {
+    let signature = Signature::one_of(
+        vec![
+            TypeSignature::Exact(vec![uint256_type(), uint256_type()]),
+            TypeSignature::Exact(vec![uint256_type(), DataType::Utf8]),
+            TypeSignature::Exact(vec![DataType::Utf8, uint256_type()]),
+        ],
+        Volatility::Immutable,
+    );
+    let return_type: ReturnTypeFunction = Arc::new(|_| Ok(Arc::new(uint256_type())));
+    ScalarUDF::new(
+        name,
+        &signature,
+        &return_type,
+        &(Arc::new(move |args: &[ColumnarValue]| binary_op(args, name, op)) as _),
+    )
+}
+
+/// the sum of uint256 column, the result is the big-endian bytes for datafusion has no scalar
+/// of fixed size binary, it's converted to uint256 by the projection added on the aggregate
+#[derive(Debug)]
+struct UInt256SumAccumulator {
+    sum: UInt256,
+}
+
+impl UInt256SumAccumulator {
+    fn add(&mut self, value: &[u8]) -> Result<()> {
+        self.sum = uint256::checked_add(&self.sum, value).ok_or_else(|| {
+            DataFusionError::Execution("uint256_sum out of the range of uint256".to_string())
+        })?;
+        Ok(())
+    }
+}
+
+impl Accumulator for UInt256SumAccumulator {
+    fn state(&self) -> Result<Vec<ScalarValue>> {
+        Ok(vec![ScalarValue::Binary(Some(self.sum.to_vec()))])
+    }
+
+    fn update_batch(&mut self, values: &[ArrayRef]) -> Result<()> {
+        let values = downcast_uint256(&values[0])?;
+        for i in 0..values.len() {
+            if !values.is_null(i) {
+                self.add(values.value(i))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn merge_batch(&mut self, states: &[ArrayRef]) -> Result<()> {
+        let states = states[0]
+            .as_any()
+            .downcast_ref::<BinaryArray>()
+            .ok_or_else(|| DataFusionError::Internal("bad state of uint256_sum".to_string()))?;
+        for i in 0..states.len() {
+            if !states.is_null(i) {
+                self.add(states.value(i))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn evaluate(&self) -> Result<ScalarValue> {
+        Ok(ScalarValue::Binary(Some(self.sum.to_vec())))
+    }
+}
+
+fn is_uint256_sum(expr: &Expr) -> bool {
+    match expr {
+        Expr::Alias(e, _) => is_uint256_sum(e),
+        Expr::AggregateUDF { fun, .. } => fun.name == "uint256_sum",
+        _ => false,
+    }
+}
+
+// add the first column of input to uint256 of literal
+struct UInt256LiteralRewriter {
+    column: Column,
+}
+
+impl ExprRewriter for UInt256LiteralRewriter {
+    fn mutate(&mut self, expr: Expr) -> Result<Expr> {
+        match expr {
+            Expr::ScalarUDF { fun, mut args }
+                if fun.name == "uint256" && matches!(args.as_slice(), [Expr::Literal(_)]) =>
+            {
+                args.push(Expr::Column(self.column.clone()));
+                Ok(Expr::ScalarUDF { fun, args })
+            }
+            expr => Ok(expr),
+        }
+    }
+}
+
+fn size_uint256_literal(expr: Expr, input: &LogicalPlan) -> Result<Expr> {
+    match input.schema().fields().first() {
+        Some(field) => expr.rewrite(&mut UInt256LiteralRewriter {
+            column: field.qualified_column(),
+        }),
+        None => Ok(expr),
+    }
+}
+
+/// rewrite the plan before optimizing
+/// 1. size uint256 of literal in projection and filter by a column of the input
+/// 2. convert the bytes of uint256_sum to uint256 with a projection on the aggregate, the
+///    projections above are rebuilt for the type of the column changes
+pub fn rewrite_uint256_plan(plan: &LogicalPlan) -> Result<LogicalPlan> {
+    let inputs = plan
+        .inputs()
+        .into_iter()
+        .map(rewrite_uint256_plan)
+        .collect::<Result<Vec<_>>>()?;
+    let new_plan = match plan {
+        LogicalPlan::Projection(Projection { expr, alias, .. }) => {
+            let exprs = expr
+                .iter()
+                .map(|e| {
+                    let new_expr = size_uint256_literal(e.clone(), &inputs[0])?;
+                    match e {
+                        // keep the name of column
+                        Expr::Alias(..) => Ok(new_expr),
+                        _ if &new_expr == e => Ok(new_expr),
+                        _ => Ok(Expr::Alias(Box::new(new_expr), e.name(inputs[0].schema())?)),
+                    }
+                })
+                .collect::<Result<Vec<_>>>()?;
+            LogicalPlanBuilder::from(inputs[0].clone())
+                .project_with_alias(exprs, alias.clone())?
+                .build()?
+        }
+        LogicalPlan::Filter(Filter { predicate, .. }) => {
+            let predicate = size_uint256_literal(predicate.clone(), &inputs[0])?;
+            from_plan(plan, &[predicate], &inputs)?
+        }
+        _ if inputs.is_empty() => plan.clone(),
+        _ => from_plan(plan, &plan.expressions(), &inputs)?,
+    };
+    match &new_plan {
+        LogicalPlan::Aggregate(Aggregate {
+            group_expr,
+            aggr_expr,
+            schema,
+            ..
+        }) if aggr_expr.iter().any(is_uint256_sum) => {
+            let from_bytes = Arc::new(uint256_from_bytes_udf());
+            let exprs: Vec<Expr> = schema
+                .fields()
+                .iter()
+                .enumerate()
+                .map(|(i, field)| {
+                    let column = Expr::Column(field.qualified_column());
+                    if i >= group_expr.len() && is_uint256_sum(&aggr_expr[i - group_expr.len()]) {
+                        let value = Expr::ScalarUDF {
+                            fun: from_bytes.clone(),
+                            args: vec![column],
+                        };
+                        Expr::Alias(Box::new(value), field.name().to_string())
+                    } else {
+                        column
+                    }
+                })
+                .collect();
+            LogicalPlanBuilder::from(new_plan).project(exprs)?.build()
+        }
+        _ => Ok(new_plan),
+    }
+}
+
+// whether the expression evaluated on the input is uint256
+fn is_uint256_expr(expr: &Expr, input: &LogicalPlan) -> bool {
+    match expr {
+        Expr::Alias(e, _) => is_uint256_expr(e, input),
+        Expr::Column(c) => input
+            .schema()
+            .index_of_column(c)
+            .is_ok_and(|i| is_uint256_column(input, i)),
+        Expr::ScalarUDF { fun, .. } => UINT256_FUNCTIONS.contains(&fun.name.as_str()),
+        _ => false,
+    }
+}
+
+// whether the column of plan output is uint256, it's traced to the table or the function
+fn is_uint256_column(plan: &LogicalPlan, index: usize) -> bool {
+    match plan {
+        LogicalPlan::TableScan(TableScan {
+            projected_schema, ..
+        }) => arrow_parquet_utils::is_uint256_field(projected_schema.field(index).field()),
+        LogicalPlan::Projection(Projection { expr, input, .. }) => {
+            is_uint256_expr(&expr[index], input)
+        }
+        LogicalPlan::Aggregate(Aggregate {
+            group_expr,
+            aggr_expr,
+            input,
+            ..
+        }) => match group_expr.get(index) {
+            Some(expr) => is_uint256_expr(expr, input),
+            None => is_uint256_expr(&aggr_expr[index - group_expr.len()], input),
+        },
+        LogicalPlan::Window(Window {
+            input, window_expr, ..
+        }) => match input.schema().fields().len() {
+            n if index < n => is_uint256_column(input, index),
+            n => is_uint256_expr(&window_expr[index - n], input),
+        },
+        LogicalPlan::Join(Join { left, right, .. })
+        | LogicalPlan::CrossJoin(CrossJoin { left, right, .. }) => {
+            match left.schema().fields().len() {
+                n if index < n => is_uint256_column(left, index),
+                n => is_uint256_column(right, index - n),
+            }
+        }
+        // the others keep the columns of the first input
+        _ => plan
+            .inputs()
+            .first()
+            .is_some_and(|input| is_uint256_column(input, index)),
+    }
+}
+
+/// mark the uint256 columns of the result in the field metadata, datafusion drops the metadata
+/// of fields in the projection
+pub fn mark_uint256_columns(
+    plan: &LogicalPlan,
+    batches: Vec<RecordBatch>,
+) -> Result<Vec<RecordBatch>> {
+    let schema = match batches.first() {
+        Some(batch) => batch.schema(),
+        None => return Ok(batches),
+    };
+    let fields = schema
+        .fields()
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            if field.data_type() == &uint256_type() && is_uint256_column(plan, i) {
+                arrow_parquet_utils::uint256_field(field.name(), field.is_nullable())
+            } else {
+                field.clone()
+            }
+        })
+        .collect();
+    let schema = Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone()));
+    batches
+        .into_iter()
+        .map(|batch| {
+            RecordBatch::try_new(schema.clone(), batch.columns().to_vec()).map_err(|e| e.into())
+        })
+        .collect()
+}
+
+/// register the arithmetic and aggregation functions of uint256, the functions returning
+/// uint256 are volatile to be not folded as datafusion has no scalar of fixed size binary
+pub fn register_uint256_functions(stx: &mut SessionContext) {
+    let signature = Signature::one_of(
+        vec![
+            TypeSignature::Exact(vec![DataType::Utf8]),
+            TypeSignature::Any(2),
+        ],
+        Volatility::Volatile,
+    );
+    let return_type: ReturnTypeFunction = Arc::new(|_| Ok(Arc::new(uint256_type())));
+    stx.register_udf(ScalarUDF::new(
+        "uint256",
+        &signature,
+        &return_type,
+        &(Arc::new(uint256_fn) as _),
+    ));
+    stx.register_udf(binary_udf("uint256_add", uint256::checked_add));
+    stx.register_udf(binary_udf("uint256_sub", uint256::checked_sub));
+    stx.register_udf(binary_udf("uint256_mul", uint256::checked_mul));
+    stx.register_udf(binary_udf("uint256_div", uint256::checked_div));
+    stx.register_udf(binary_udf("uint256_mod", uint256::checked_rem));
+    stx.register_udf(uint256_from_bytes_udf());
+    stx.register_udaf(create_udaf(
+        "uint256_sum",
+        uint256_type(),
+        Arc::new(DataType::Binary),
+        Volatility::Immutable,
+        Arc::new(|| {
+            Ok(Box::new(UInt256SumAccumulator {
+                sum: [0u8; UINT256_BYTES],
+            }))
+        }),
+        Arc::new(vec![DataType::Binary]),
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Int32Array;
+    use arrow::datatypes::Field;
+    use datafusion::dataframe::DataFrame;
+    use datafusion::datasource::MemTable;
+
+    fn uint256_array(values: &[&str]) -> ArrayRef {
+        let mut builder = FixedSizeBinaryBuilder::new(values.len(), UINT256_BYTES as i32);
+        for v in values {
+            builder
+                .append_value(uint256::parse_uint256(v).unwrap())
+                .unwrap();
+        }
+        Arc::new(builder.finish())
+    }
+
+    #[test]
+    fn test_uint256_add_and_sum() -> Result<()> {
+        let left = ColumnarValue::Array(uint256_array(&["1", "18446744073709551615"]));
+        let right = ColumnarValue::Array(uint256_array(&["1", "1"]));
+        let sum = match binary_op(&[left, right], "uint256_add", uint256::checked_add)? {
+            ColumnarValue::Array(a) => a,
+            _ => panic!("should be array"),
+        };
+        let sum = downcast_uint256(&sum)?;
+        assert_eq!("2", uint256::uint256_to_string(sum.value(0)));
+        assert_eq!(
+            "18446744073709551616",
+            uint256::uint256_to_string(sum.value(1))
+        );
+        let left = ColumnarValue::Array(uint256_array(&["1"]));
+        let right = ColumnarValue::Array(uint256_array(&["2"]));
+        assert!(binary_op(&[left, right], "uint256_sub", uint256::checked_sub).is_err());
+
+        let mut accumulator = UInt256SumAccumulator {
+            sum: [0u8; UINT256_BYTES],
+        };
+        accumulator.update_batch(&[uint256_array(&["1000000000000000000", "0x1"])])?;
+        let mut other = UInt256SumAccumulator {
+            sum: [0u8; UINT256_BYTES],
+        };
+        other.update_batch(&[uint256_array(&["1"])])?;
+        let states = ScalarValue::iter_to_array(other.state()?)?;
+        accumulator.merge_batch(&[states])?;
+        assert_eq!(
+            ScalarValue::Binary(Some(
+                uint256::parse_uint256("1000000000000000002")
+                    .unwrap()
+                    .to_vec()
+            )),
+            accumulator.evaluate()?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_uint256_binary_op_operands() -> Result<()> {
+        let array = ColumnarValue::Array(uint256_array(&["10", "20", "30"]));
+        let scalar = ColumnarValue::Scalar(ScalarValue::Utf8(Some("0xa".to_string())));
+        let product = match binary_op(
+            &[array.clone(), scalar.clone()],
+            "uint256_mul",
+            uint256::checked_mul,
+        )? {
+            ColumnarValue::Array(a) => a,
+            _ => panic!("should be array"),
+        };
+        let product = downcast_uint256(&product)?;
+        assert_eq!(3, product.len());
+        assert_eq!("300", uint256::uint256_to_string(product.value(2)));
+        let quotient = match binary_op(
+            &[scalar.clone(), array.clone()],
+            "uint256_div",
+            uint256::checked_div,
+        )? {
+            ColumnarValue::Array(a) => a,
+            _ => panic!("should be array"),
+        };
+        let quotient = downcast_uint256(&quotient)?;
+        assert_eq!("1", uint256::uint256_to_string(quotient.value(0)));
+        assert_eq!("0", uint256::uint256_to_string(quotient.value(1)));
+        // a null literal makes all the rows null
+        let null = ColumnarValue::Scalar(ScalarValue::Utf8(None));
+        let sum = match binary_op(&[array.clone(), null], "uint256_add", uint256::checked_add)? {
+            ColumnarValue::Array(a) => a,
+            _ => panic!("should be array"),
+        };
+        assert_eq!(3, sum.null_count());
+        // the empty array
+        let empty = ColumnarValue::Array(uint256_array(&[]));
+        let sum = match binary_op(
+            &[empty, scalar.clone()],
+            "uint256_add",
+            uint256::checked_add,
+        )? {
+            ColumnarValue::Array(a) => a,
+            _ => panic!("should be array"),
+        };
+        assert_eq!(0, sum.len());
+        let other = ColumnarValue::Array(uint256_array(&["1"]));
+        assert!(binary_op(&[array.clone(), other], "uint256_add", uint256::checked_add).is_err());
+        assert!(binary_op(
+            &[scalar.clone(), scalar],
+            "uint256_add",
+            uint256::checked_add
+        )
+        .is_err());
+        let zero = ColumnarValue::Scalar(ScalarValue::Utf8(Some("0".to_string())));
+        assert!(binary_op(&[array, zero], "uint256_mod", uint256::checked_rem).is_err());
+        Ok(())
+    }
+
+    // run the query the way the sql engine does
+    async fn query(stx: &SessionContext, sql: &str) -> Result<Vec<RecordBatch>> {
+        let plan = rewrite_uint256_plan(&stx.create_logical_plan(sql)?)?;
+        let plan = stx.optimize(&plan)?;
+        let batches = DataFrame::new(stx.state.clone(), &plan).collect().await?;
+        mark_uint256_columns(&plan, batches)
+    }
+
+    fn uint256_marks(batch: &RecordBatch) -> Vec<bool> {
+        batch
+            .schema()
+            .fields()
+            .iter()
+            .map(arrow_parquet_utils::is_uint256_field)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_uint256_sql() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("k", DataType::Int32, false),
+            arrow_parquet_utils::uint256_field("v", true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(vec![1, 1, 2])),
+                uint256_array(&["1", "18446744073709551615", "2"]),
+            ],
+        )?;
+        let mut stx = SessionContext::new();
+        register_uint256_functions(&mut stx);
+        stx.register_table(
+            "t1",
+            Arc::new(MemTable::try_new(schema, vec![vec![batch]])?),
+        )?;
+        let sql = "select k, uint256_sum(v) as s, uint256('3') as c, k + 1 from t1 \
+                   group by k order by k";
+        let batches = query(&stx, sql).await?;
+        assert_eq!(vec![false, true, true, false], uint256_marks(&batches[0]));
+        let sums = downcast_uint256(batches[0].column(1))?;
+        assert_eq!(
+            "18446744073709551616",
+            uint256::uint256_to_string(sums.value(0))
+        );
+        assert_eq!("2", uint256::uint256_to_string(sums.value(1)));
+        let sql = "select v, uint256_mul(v, '2') as m, k, uint256_add(v, uint256('1')), \
+                   uint256('0x10') from t1 where k = 2";
+        let batches = query(&stx, sql).await?;
+        assert_eq!(
+            vec![true, true, false, true, true],
+            uint256_marks(&batches[0])
+        );
+        let products = downcast_uint256(batches[0].column(1))?;
+        assert_eq!("4", uint256::uint256_to_string(products.value(0)));
+        let sums = downcast_uint256(batches[0].column(3))?;
+        assert_eq!("3", uint256::uint256_to_string(sums.value(0)));
+        let literals = downcast_uint256(batches[0].column(4))?;
+        assert_eq!("16", uint256::uint256_to_string(literals.value(0)));
+        assert_eq!(
+            "uint256(Utf8(\"0x10\"))",
+            batches[0].schema().field(4).name()
+        );
+        let batches = query(&stx, "select uint256_sum(v) as s from t1").await?;
+        assert_eq!(vec![true], uint256_marks(&batches[0]));
+        let sums = downcast_uint256(batches[0].column(0))?;
+        assert_eq!(
+            "18446744073709551618",
+            uint256::uint256_to_string(sums.value(0))
+        );
+        Ok(())
+    }
+}
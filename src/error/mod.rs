@@ -43,6 +43,8 @@ pub enum DB3Error {
     TableNamesExistError { name: String },
     #[error("table type mismatch left {left} and right {right}")]
     TableTypeMismatchError { left: String, right: String },
+    #[error("the column {name} can not be null")]
+    TableColumnNotNullError { name: String },
     #[error("table to arrow for error : {0}")]
     TableArrowError(ArrowError),
     #[error("table {table_id} encounter encoding or decoding error {err}")]
@@ -65,12 +67,26 @@ pub enum DB3Error {
     TableSchemaInvalidError { name: String },
     #[error("create table error for {err}")]
     MetaRpcCreateTableError { err: String },
+    #[error("alter table error for {err}")]
+    MetaRpcAlterTableError { err: String },
     #[error("the {name} of cell store config is invalid for {err}")]
     CellStoreInvalidConfigError { name: String, err: String },
     #[error("the cell exist in memory node with tid {tid} and pid {pid}")]
     CellStoreExistError { tid: String, pid: i32 },
     #[error("the cell has not been found in memory node with tid {tid} and pid {pid}")]
     CellStoreNotFoundError { tid: String, pid: i32 },
+    #[error("fail to save or load the checkpoint of cell store for {0}")]
+    CellStoreCheckpointError(String),
+    #[error("fail to write binlog of cell store for {0}")]
+    CellStoreBinlogError(String),
+    #[error("the cell store is fenced for moving to another node")]
+    CellStoreFencedError,
+    #[error("the cell store is throttled for memory budget with {0} bytes used")]
+    CellStoreThrottledError(u64),
+    #[error("fail to access cell store manifest for {0}")]
+    CellStoreManifestError(String),
+    #[error("the write does not match the role of cell store, {0}")]
+    CellStoreRoleError(String),
     #[error("aws-s3: {0}")]
     StoreS3Error(String),
     #[error("row codec error : {0}")]
@@ -81,6 +97,12 @@ pub enum DB3Error {
     MemoryNodeExistError(String),
     #[error("not enough memory node")]
     MemoryNodeNotEnoughError,
+    #[error("fail to move partition for {0}")]
+    PartitionMoveError(String),
+    #[error("fail to write rows to partition {pid} for {err}")]
+    PartitionWriteError { pid: i32, err: String },
+    #[error("partition {pid} is throttled for {err}")]
+    PartitionThrottledError { pid: i32, err: String },
     #[error("fail to connect to {0}")]
     NodeRPCError(String),
     #[error("invalid endpoint for node {name}")]
@@ -95,6 +117,8 @@ pub enum DB3Error {
     MetaStoreEtcdErr(etcd_client::Error),
     #[error("no meta store found")]
     MetaStoreNotFoundErr,
+    #[error("the lease {0} of node has expired")]
+    MetaStoreLeaseExpiredErr(i64),
     #[error("fail to parse sql for error {0}")]
     SQLParseError(String),
     #[error("fail to create credentials for s3")]
@@ -198,6 +222,7 @@ impl From<DB3Error> for Status {
             DB3Error::TableInvalidNamesError { .. }
             | DB3Error::TableSchemaConvertError { .. }
             | DB3Error::TableSchemaInvalidError { .. }
+            | DB3Error::TableColumnNotNullError { .. }
             | DB3Error::MetaRpcCreateTableError { .. } => Status::invalid_argument(error),
             DB3Error::TableNotFoundError { .. } | DB3Error::CellStoreNotFoundError { .. } => {
                 Status::not_found(error)
@@ -205,6 +230,10 @@ impl From<DB3Error> for Status {
             DB3Error::TableNamesExistError { .. } | DB3Error::CellStoreExistError { .. } => {
                 Status::already_exists(error)
             }
+            DB3Error::CellStoreFencedError | DB3Error::CellStoreRoleError(_) => {
+                Status::unavailable(error)
+            }
+            DB3Error::CellStoreThrottledError(_) => Status::resource_exhausted(error),
             _ => Status::internal(error),
         }
     }
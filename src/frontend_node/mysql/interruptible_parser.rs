@@ -17,11 +17,11 @@
 //
 
 use crate::error::{DB3Error, Result};
-use sqlparser::ast::Statement;
+use sqlparser::ast::{ObjectName, SqlOption, Statement};
 use sqlparser::dialect::Dialect;
 use sqlparser::keywords::Keyword;
 use sqlparser::parser::*;
-use sqlparser::tokenizer::{Token, Tokenizer};
+use sqlparser::tokenizer::{Token, Tokenizer, Word};
 
 pub struct InterruptibleParser<'a> {
     // sql parser
@@ -40,7 +40,7 @@ pub struct InterruptibleParser<'a> {
 impl<'a> InterruptibleParser<'a> {
     pub fn new(dialect: &'a dyn Dialect, sql: &'a str) -> Result<Self> {
         let mut tokenizer = Tokenizer::new(dialect, sql);
-        let tokens = tokenizer.tokenize()?;
+        let tokens = Self::fold_nested_types(Self::fold_timestamp_precision(tokenizer.tokenize()?));
         Ok(Self {
             parser: Parser::new(tokens, dialect),
             keyword: None,
@@ -48,6 +48,105 @@ impl<'a> InterruptibleParser<'a> {
         })
     }
 
+    /// the sql parser drops the precision of `TIMESTAMP(6)`, so it's folded to a custom type
+    /// `timestamp(6)` in the create statements
+    fn fold_timestamp_precision(tokens: Vec<Token>) -> Vec<Token> {
+        let is_create = tokens
+            .iter()
+            .find(|t| !matches!(t, Token::Whitespace(_)))
+            .map_or(
+                false,
+                |t| matches!(t, Token::Word(w) if w.keyword == Keyword::CREATE),
+            );
+        if !is_create {
+            return tokens;
+        }
+        let mut folded: Vec<Token> = Vec::with_capacity(tokens.len());
+        let mut i = 0;
+        while i < tokens.len() {
+            if let [Token::Word(w), Token::LParen, Token::Number(precision, _), Token::RParen, ..] =
+                &tokens[i..]
+            {
+                if w.keyword == Keyword::TIMESTAMP {
+                    folded.push(Token::Word(Word {
+                        value: format!("timestamp({})", precision),
+                        quote_style: None,
+                        keyword: Keyword::NoKeyword,
+                    }));
+                    i += 4;
+                    continue;
+                }
+            }
+            folded.push(tokens[i].clone());
+            i += 1;
+        }
+        folded
+    }
+
+    /// the sql parser has no struct and map types, `struct<a int, b varchar>` and
+    /// `map<varchar, bigint>` in the create statements are folded to custom types with the
+    /// whole text as the name
+    fn fold_nested_types(tokens: Vec<Token>) -> Vec<Token> {
+        let is_create = tokens
+            .iter()
+            .find(|t| !matches!(t, Token::Whitespace(_)))
+            .map_or(
+                false,
+                |t| matches!(t, Token::Word(w) if w.keyword == Keyword::CREATE),
+            );
+        if !is_create {
+            return tokens;
+        }
+        let mut folded: Vec<Token> = Vec::with_capacity(tokens.len());
+        let mut i = 0;
+        while i < tokens.len() {
+            let is_nested = match &tokens[i..] {
+                [Token::Word(w), Token::Lt, ..] => {
+                    matches!(w.value.to_lowercase().as_str(), "struct" | "map")
+                }
+                _ => false,
+            };
+            if !is_nested {
+                folded.push(tokens[i].clone());
+                i += 1;
+                continue;
+            }
+            let mut type_name = tokens[i].to_string();
+            let mut depth: i32 = 0;
+            // `>>` of nested types is a shift right token, it may also close an outer array
+            let mut closes_outer = false;
+            i += 1;
+            while i < tokens.len() {
+                let token = &tokens[i];
+                i += 1;
+                match token {
+                    Token::Lt => depth += 1,
+                    Token::Gt => depth -= 1,
+                    Token::ShiftRight if depth == 1 => {
+                        type_name.push('>');
+                        closes_outer = true;
+                        break;
+                    }
+                    Token::ShiftRight => depth -= 2,
+                    _ => {}
+                }
+                type_name.push_str(&token.to_string());
+                if depth == 0 {
+                    break;
+                }
+            }
+            folded.push(Token::Word(Word {
+                value: type_name,
+                quote_style: None,
+                keyword: Keyword::NoKeyword,
+            }));
+            if closes_outer {
+                folded.push(Token::Gt);
+            }
+        }
+        folded
+    }
+
     pub fn next_keyword(&mut self) -> Result<Keyword> {
         match self.keyword {
             Some(k) => Ok(k),
@@ -73,4 +172,23 @@ impl<'a> InterruptibleParser<'a> {
         let statement = self.parser.parse_statement()?;
         Ok(statement)
     }
+
+    /// parse `ALTER TABLE t1 SET (retention = '30d')` which is not supported by the sql parser,
+    /// none for the other statements
+    pub fn parse_alter_table_options(&mut self) -> Result<Option<(ObjectName, Vec<SqlOption>)>> {
+        if !self
+            .parser
+            .parse_keywords(&[Keyword::ALTER, Keyword::TABLE])
+        {
+            return Ok(None);
+        }
+        let name = self.parser.parse_object_name()?;
+        let options = self.parser.parse_options(Keyword::SET)?;
+        if options.is_empty() {
+            return Ok(None);
+        }
+        self.parser.consume_token(&Token::SemiColon);
+        self.parser.expect_token(&Token::EOF)?;
+        Ok(Some((name, options)))
+    }
 }
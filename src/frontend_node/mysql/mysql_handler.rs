@@ -16,11 +16,10 @@
 // limitations under the License.
 //
 
-use super::sql_handler::SQLExecutor;
+use super::sql_handler::{SQLExecutor, SQLResult};
 use crate::base::mysql_utils;
 use crate::error::Result as RtStoreResult;
 use crate::sdk::compute_node_sdk::ComputeNodeSDK;
-use crate::sdk::memory_node_sdk::MemoryNodeSDK;
 use crate::sdk::meta_node_sdk::MetaNodeSDK;
 use crate::store::meta_store::MetaStore;
 use async_trait::async_trait;
@@ -68,7 +67,6 @@ impl Clone for MySQLHandler {
 impl MySQLHandler {
     pub fn new(
         meta_sdk: MetaNodeSDK,
-        memory_sdk: MemoryNodeSDK,
         compute_sdk: ComputeNodeSDK,
         meta_store: Arc<MetaStore>,
         var_config_path: &str,
@@ -77,13 +75,7 @@ impl MySQLHandler {
             version: "8.0.26-rtstore".to_string(),
             id: 0,
             salt: [0_u8; 20],
-            sql_executor: SQLExecutor::new(
-                meta_sdk,
-                meta_store,
-                memory_sdk,
-                compute_sdk,
-                var_config_path,
-            )?,
+            sql_executor: SQLExecutor::new(meta_sdk, meta_store, compute_sdk, var_config_path)?,
             db: None,
         })
     }
@@ -168,18 +160,24 @@ impl<W: std::io::Write + Send> AsyncMysqlShim<W> for MySQLHandler {
         sql: &'a str,
         results: QueryResultWriter<'a, W>,
     ) -> Result<()> {
-        if let Ok(result) = self.sql_executor.execute(sql, &self.db, self.id).await {
-            if let Some(batches) = result.batch {
+        match self.sql_executor.execute(sql, &self.db, self.id).await {
+            Ok(SQLResult {
+                batch: Some(batches),
+                ..
+            }) => {
                 mysql_utils::write_batch_to_resultset(&batches, results).unwrap();
-            } else {
+            }
+            Ok(result) => {
                 let response = OkResponse {
                     affected_rows: result.effected_rows as u64,
                     ..Default::default()
                 };
                 results.completed(response)?;
             }
-        } else {
-            results.completed(OkResponse::default())?;
+            Err(e) => {
+                debug!("fail to execute sql {} for {}", sql, e);
+                results.error(mysql_utils::error_kind(&e), format!("{}", e).as_bytes())?;
+            }
         }
         Ok(())
     }
@@ -18,7 +18,7 @@
 
 use super::interruptible_parser::*;
 use super::mysql_vars::MySQLVars;
-use crate::base::{arrow_parquet_utils, linked_list::LinkedList, mysql_utils};
+use crate::base::{arrow_parquet_utils, linked_list::LinkedList, mysql_utils, partition_utils};
 use crate::codec::flight_codec::flight_data_to_arrow_batch;
 use crate::codec::row_codec::{Data, RowRecordBatch};
 use crate::proto::db3_base_proto::Db3TableDesc;
@@ -27,19 +27,35 @@ use arrow::datatypes::{DataType, Field as ArrowField};
 use arrow::record_batch::RecordBatch;
 use datafusion::catalog::schema::SchemaProvider;
 use sqlparser::{
-    ast::{ColumnDef, Expr, Ident, SelectItem, SetExpr, Statement as SQLStatement, UnaryOperator},
+    ast::{
+        ColumnDef, Expr, Ident, ObjectName, SelectItem, SetExpr, SqlOption,
+        Statement as SQLStatement, UnaryOperator,
+    },
     dialect::{keywords::Keyword, MySqlDialect},
 };
 use std::sync::Arc;
 uselog!(debug, info, warn);
 use crate::catalog::catalog::Catalog;
-use crate::error::Result;
+use crate::error::{DB3Error, Result};
 use crate::sdk::compute_node_sdk::ComputeNodeSDK;
-use crate::sdk::memory_node_sdk::MemoryNodeSDK;
 use crate::sdk::meta_node_sdk::MetaNodeSDK;
 use arrow::datatypes::{Schema, SchemaRef};
 use regex::RegexSet;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::time::sleep;
+use tonic::{Code, Status};
+
+// the records rejected by a moving partition are retried
+const APPEND_RETRY_TIMES: usize = 10;
+const APPEND_RETRY_INTERVAL_MS: u64 = 500;
+// the records rejected by a throttled memory node are retried with backoff
+const APPEND_MAX_BACKOFF_MS: u64 = 8000;
+
+// the endpoints of memory nodes with their results of appending rows
+type AppendResults = Vec<(String, std::result::Result<(), Status>)>;
 
 pub struct SQLResult {
     pub batch: Option<Vec<RecordBatch>>,
@@ -49,7 +65,6 @@ pub struct SQLResult {
 #[derive(Clone)]
 pub struct SQLExecutor {
     meta_sdk: MetaNodeSDK,
-    memory_sdk: MemoryNodeSDK,
     compute_sdk: ComputeNodeSDK,
     catalog: Arc<Catalog>,
     system_vars: Arc<MySQLVars>,
@@ -62,7 +77,6 @@ impl SQLExecutor {
     pub fn new(
         meta_sdk: MetaNodeSDK,
         meta_store: Arc<MetaStore>,
-        memory_sdk: MemoryNodeSDK,
         compute_sdk: ComputeNodeSDK,
         var_config_path: &str,
     ) -> Result<Self> {
@@ -70,7 +84,6 @@ impl SQLExecutor {
         let system_vars = Arc::new(MySQLVars::new(var_config_path)?);
         Ok(Self {
             meta_sdk,
-            memory_sdk,
             compute_sdk,
             catalog,
             system_vars,
@@ -123,19 +136,78 @@ impl SQLExecutor {
         Ok((keyword, statement))
     }
 
+    fn parse_alter_table(sql: &str) -> Result<Option<(ObjectName, Vec<SqlOption>)>> {
+        let dialect = MySqlDialect {};
+        let mut parser = InterruptibleParser::new(&dialect, sql)?;
+        parser.parse_alter_table_options()
+    }
+
+    /// only the retention could be altered, the expiry of memory nodes follows it
+    async fn handle_alter_table(
+        &self,
+        db: &str,
+        table_name: &str,
+        options: &[SqlOption],
+    ) -> Result<()> {
+        mysql_utils::check_table_options(options)?;
+        if let Some(option) = options
+            .iter()
+            .find(|o| o.name.value.to_lowercase() != "retention")
+        {
+            return Err(DB3Error::SQLParseError(format!(
+                "table option {} could not be altered, only retention could",
+                option.name
+            )));
+        }
+        let retention_ms = mysql_utils::sql_to_retention_ms(options)?;
+        self.meta_sdk
+            .alter_table(db, table_name, retention_ms)
+            .await?;
+        info!(
+            "alter the retention of table {} to {} ms",
+            table_name, retention_ms
+        );
+        Ok(())
+    }
+
     async fn handle_insert(&self, db: &str, table_name: &str, expr: &SetExpr) -> Result<()> {
         let database = self.catalog.get_db(db)?;
         let table = database.get_table(table_name)?;
         if let SetExpr::Values(values) = expr {
-            if let Some(schema) = &table.get_table_desc().schema {
-                let row_batch = mysql_utils::sql_to_row_batch(schema, &values.0[0])?;
-                //TODO add logical for partition
-                if let Err(e) = self
-                    .memory_sdk
-                    .append_records(db, table_name, 0, &row_batch)
-                    .await
-                {
-                    warn!("fail to append record to table {} for {}", table_name, e);
+            let table_desc = table.get_table_desc();
+            if let Some(schema) = &table_desc.schema {
+                let mut rows: Vec<Vec<Data>> = Vec::new();
+                for value in values.0.iter() {
+                    let mut row_batch = mysql_utils::sql_to_row_batch(schema, value)?;
+                    rows.append(&mut row_batch.batch);
+                }
+                let row_batch = RowRecordBatch {
+                    batch: rows,
+                    schema_version: schema.version as u32,
+                };
+                // route the rows to the memory nodes which own their partitions
+                let batches = partition_utils::split_row_batch(table_desc, row_batch)?;
+                let mut written: Vec<i32> = Vec::new();
+                let mut first_error: Option<DB3Error> = None;
+                for (pid, batch) in batches {
+                    match self.append_to_partition(db, table_name, pid, &batch).await {
+                        Ok(_) => written.push(pid),
+                        Err(e) => {
+                            warn!("{}", e);
+                            first_error.get_or_insert(e);
+                        }
+                    }
+                }
+                // the statement fails if the rows of any partition fail, the rows of the others
+                // are kept so the client is told which partitions have them
+                if let Some(e) = first_error {
+                    if !written.is_empty() {
+                        warn!(
+                            "the rows of partitions {:?} of table {} have been written before the failure",
+                            written, table_name
+                        );
+                    }
+                    return Err(with_written_partitions(e, &written));
                 }
             }
         } else {
@@ -144,20 +216,92 @@ impl SQLExecutor {
         Ok(())
     }
 
+    /// replicate the rows to the primary and all the replicas of partition, the rows are written
+    /// only if all of them acknowledge. the primary takes the rows first and gives them a
+    /// sequence, the replicas keep the rows with it until the primary has flushed them. the nodes
+    /// which reject the rows for moving partition are retried with the latest mappings, the rows
+    /// carry one batch id in all the tries so a node which has applied them skips them
+    async fn append_to_partition(
+        &self,
+        db: &str,
+        table_name: &str,
+        pid: i32,
+        batch: &RowRecordBatch,
+    ) -> Result<()> {
+        // 0 means no id
+        let batch_id = rand::random::<u64>().max(1);
+        // 0 until the primary takes the rows
+        let sequence = &AtomicU64::new(0);
+        let append_round = move |done: HashSet<String>| async move {
+            let sdks = match self
+                .catalog
+                .get_db(db)
+                .and_then(|database| database.get_table(table_name))
+                .map(|table| table.get_nodes_by_partition(pid))
+            {
+                Ok(Some(sdks)) => sdks,
+                _ => {
+                    return Err(DB3Error::PartitionWriteError {
+                        pid,
+                        err: format!("no memory node for table {}", table_name),
+                    });
+                }
+            };
+            let mut results: AppendResults = Vec::new();
+            let primary = &sdks[0];
+            if !done.contains(primary.endpoint()) {
+                let result = primary
+                    .append_records(db, table_name, pid, batch, batch_id, 0)
+                    .await;
+                if let Ok(primary_sequence) = &result {
+                    sequence.store(*primary_sequence, Ordering::Relaxed);
+                }
+                results.push((primary.endpoint().to_string(), result.map(|_| ())));
+            }
+            let sequence = sequence.load(Ordering::Relaxed);
+            if sequence == 0 {
+                return Ok(results);
+            }
+            let pending: Vec<_> = sdks[1..]
+                .iter()
+                .filter(|sdk| !done.contains(sdk.endpoint()))
+                .collect();
+            let appends = pending
+                .iter()
+                .map(|sdk| sdk.append_records(db, table_name, pid, batch, batch_id, sequence));
+            let replica_results = futures::future::join_all(appends).await;
+            results.extend(
+                pending
+                    .iter()
+                    .map(|sdk| sdk.endpoint().to_string())
+                    .zip(replica_results.into_iter().map(|r| r.map(|_| ()))),
+            );
+            Ok(results)
+        };
+        retry_append(table_name, pid, APPEND_RETRY_INTERVAL_MS, append_round).await
+    }
+
     async fn handle_create_table(
         &self,
         db: &str,
         table_name: &str,
         columns: &Vec<ColumnDef>,
+        with_options: &[SqlOption],
     ) -> Result<()> {
+        mysql_utils::check_table_options(with_options)?;
         let schema_desc = mysql_utils::sql_to_table_desc(columns)?;
+        let storage_config = mysql_utils::sql_to_storage_config(with_options)?;
+        let partition_desc = mysql_utils::sql_to_partition_desc(with_options)?;
+        let retention_ms = mysql_utils::sql_to_retention_ms(with_options)?;
         let table_desc = Db3TableDesc {
             name: table_name.to_string(),
             schema: Some(schema_desc),
-            partition_desc: None,
+            partition_desc,
             db: db.to_string(),
             ctime: 0,
             mappings: Vec::new(),
+            storage_config,
+            retention_ms,
         };
         if let Err(e) = self.meta_sdk.create_table(table_desc).await {
             warn!("fail  to create table for err {}", e);
@@ -405,6 +549,17 @@ impl SQLExecutor {
             });
         }
         debug!("input sql {}", sql);
+        if let Some((name, options)) = Self::parse_alter_table(sql)? {
+            let db_str = db.as_ref().ok_or_else(|| {
+                DB3Error::SQLParseError(format!("no database is selected for {}", sql))
+            })?;
+            self.handle_alter_table(db_str, &name.0[0].value, &options)
+                .await?;
+            return Ok(SQLResult {
+                batch: None,
+                effected_rows: 0,
+            });
+        }
         let (keyword, statement) = Self::parse_sql(sql)?;
         match (keyword, statement, db) {
             (Keyword::SHOW, SQLStatement::ShowCreate { ref obj_name, .. }, _) => {
@@ -417,8 +572,17 @@ impl SQLExecutor {
                 self.handle_show_variable(&variable, db)
             }
 
-            (Keyword::CREATE, SQLStatement::CreateTable { name, columns, .. }, Some(db_str)) => {
-                self.handle_create_table(db_str, &name.0[0].value, &columns)
+            (
+                Keyword::CREATE,
+                SQLStatement::CreateTable {
+                    name,
+                    columns,
+                    with_options,
+                    ..
+                },
+                Some(db_str),
+            ) => {
+                self.handle_create_table(db_str, &name.0[0].value, &columns, &with_options)
                     .await?;
                 Ok(SQLResult {
                     batch: None,
@@ -465,9 +629,186 @@ impl SQLExecutor {
     }
 }
 
+/// run the rounds of appends until all the nodes acknowledge the rows, a round returns the
+/// result of every node which has not acknowledged. the throttled nodes are retried with backoff
+/// and the statement fails with a retryable error once the backoff is exhausted
+async fn retry_append<F, Fut>(
+    table_name: &str,
+    pid: i32,
+    interval_ms: u64,
+    mut append_round: F,
+) -> Result<()>
+where
+    F: FnMut(HashSet<String>) -> Fut,
+    Fut: Future<Output = Result<AppendResults>>,
+{
+    let mut done: HashSet<String> = HashSet::new();
+    let mut backoff_ms = interval_ms;
+    let mut throttled = false;
+    for _ in 0..APPEND_RETRY_TIMES {
+        let mut retry = false;
+        throttled = false;
+        for (endpoint, result) in append_round(done.clone()).await? {
+            match result {
+                Err(e) if e.code() == Code::ResourceExhausted => {
+                    debug!(
+                        "memory node {} is throttled for partition {} of table {}",
+                        endpoint, pid, table_name
+                    );
+                    retry = true;
+                    throttled = true;
+                }
+                Err(e) if e.code() == Code::Unavailable || e.code() == Code::NotFound => {
+                    debug!(
+                        "retry to append record to partition {} of table {} for {}",
+                        pid, table_name, e
+                    );
+                    retry = true;
+                }
+                Err(e) => {
+                    // a replica missing the rows would lose them after it is promoted
+                    return Err(DB3Error::PartitionWriteError {
+                        pid,
+                        err: format!(
+                            "memory node {} of table {} rejects the rows with {}",
+                            endpoint, table_name, e
+                        ),
+                    });
+                }
+                Ok(_) => {
+                    done.insert(endpoint);
+                }
+            }
+        }
+        if !retry {
+            return Ok(());
+        }
+        if throttled {
+            sleep(Duration::from_millis(backoff_ms)).await;
+            backoff_ms = (backoff_ms * 2).min(APPEND_MAX_BACKOFF_MS);
+        } else {
+            sleep(Duration::from_millis(interval_ms)).await;
+        }
+    }
+    if throttled {
+        Err(DB3Error::PartitionThrottledError {
+            pid,
+            err: format!("the memory nodes of table {} are busy", table_name),
+        })
+    } else {
+        Err(DB3Error::PartitionWriteError {
+            pid,
+            err: format!("the memory nodes of table {} are unavailable", table_name),
+        })
+    }
+}
+
+/// name the partitions which have committed the rows of a failed insert in its error, a retry
+/// of the statement writes their rows again
+fn with_written_partitions(e: DB3Error, written: &[i32]) -> DB3Error {
+    if written.is_empty() {
+        return e;
+    }
+    let note = format!(", the rows of partitions {:?} have been committed", written);
+    match e {
+        DB3Error::PartitionWriteError { pid, err } => DB3Error::PartitionWriteError {
+            pid,
+            err: err + &note,
+        },
+        DB3Error::PartitionThrottledError { pid, err } => DB3Error::PartitionThrottledError {
+            pid,
+            err: err + &note,
+        },
+        // the write to a partition fails with the errors above only
+        e => e,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::base::mysql_utils;
     use crate::error::Result;
+
+    #[test]
+    fn test_parse_alter_table() -> Result<()> {
+        let (name, options) =
+            SQLExecutor::parse_alter_table("ALTER TABLE t1 SET (retention = '30d');")?.unwrap();
+        assert_eq!("t1", name.0[0].value);
+        assert_eq!(
+            30 * 24 * 3600 * 1000,
+            mysql_utils::sql_to_retention_ms(&options)?
+        );
+        assert!(SQLExecutor::parse_alter_table("ALTER TABLE t1 ADD COLUMN c2 INT")?.is_none());
+        assert!(SQLExecutor::parse_alter_table("SELECT 1")?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_written_partitions() {
+        let e = DB3Error::PartitionThrottledError {
+            pid: 2,
+            err: "busy".to_string(),
+        };
+        let e = with_written_partitions(e, &[0, 1]);
+        assert!(matches!(
+            e,
+            DB3Error::PartitionThrottledError { pid: 2, .. }
+        ));
+        assert_eq!(
+            "partition 2 is throttled for busy, the rows of partitions [0, 1] have been committed",
+            e.to_string()
+        );
+        let e = DB3Error::PartitionWriteError {
+            pid: 2,
+            err: "unavailable".to_string(),
+        };
+        assert_eq!(
+            "fail to write rows to partition 2 for unavailable",
+            with_written_partitions(e, &[]).to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_throttled_append_fails() -> Result<()> {
+        let mut rounds = 0;
+        let result = retry_append("t1", 1, 1, |done: HashSet<String>| {
+            rounds += 1;
+            let first_round = rounds == 1;
+            async move {
+                // node1 acknowledges in the first round and is not retried
+                assert_eq!(first_round, !done.contains("node1"));
+                let mut results: AppendResults = vec![(
+                    "node2".to_string(),
+                    Err(Status::resource_exhausted("throttled")),
+                )];
+                if first_round {
+                    results.push(("node1".to_string(), Ok(())));
+                }
+                Ok::<_, DB3Error>(results)
+            }
+        })
+        .await;
+        assert_eq!(APPEND_RETRY_TIMES, rounds);
+        assert!(matches!(
+            result,
+            Err(DB3Error::PartitionThrottledError { pid: 1, .. })
+        ));
+        let result = retry_append("t1", 1, 1, |_| async {
+            Ok::<AppendResults, DB3Error>(vec![(
+                "node1".to_string(),
+                Err(Status::invalid_argument("bad rows")),
+            )])
+        })
+        .await;
+        assert!(matches!(
+            result,
+            Err(DB3Error::PartitionWriteError { pid: 1, .. })
+        ));
+        retry_append("t1", 1, 1, |_| async {
+            Ok::<AppendResults, DB3Error>(vec![("node1".to_string(), Ok(()))])
+        })
+        .await?;
+        Ok(())
+    }
 }
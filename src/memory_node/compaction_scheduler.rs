@@ -0,0 +1,471 @@
+//
+//
+// compaction_scheduler.rs
+// Copyright (C) 2022 db3.network Author imotai <codego.me@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use crate::memory_node::memory_node_impl::MemoryNodeState;
+use crate::store::cell_store::CellStore;
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::time::sleep;
+uselog!(debug, info, warn);
+
+/// Config for CompactionScheduler
+#[derive(Clone, Debug)]
+pub struct CompactionSchedulerConfig {
+    // the interval in millisecond to check all the cells
+    check_interval_ms: u64,
+    // a cell is flushed when its oldest row in memory is older than it
+    max_age_ms: u64,
+    // a cell is flushed when its rows in memory take more bytes than it
+    max_bytes: u64,
+    // a cell is flushed when it has more rows in memory than it
+    max_rows: u64,
+    // the max count of cells uploading at the same time
+    max_concurrent_uploads: usize,
+    // the node is under memory pressure when all its cells take more bytes than it
+    memory_pressure_bytes: u64,
+    // the interval in millisecond to merge the small files of cells
+    merge_interval_ms: u64,
+    // a parquet file smaller than it is merged
+    small_file_bytes: u64,
+    // the min count of small files to start a merge
+    merge_min_files: usize,
+    // the max bytes of small files merged into one file
+    merge_target_bytes: u64,
+    // the merged and expired files are removed after the running scans finish reading them
+    obsolete_file_ttl_ms: u64,
+    // the interval in millisecond for the replicas to drop the rows flushed by their primaries
+    replica_trim_interval_ms: u64,
+}
+
+impl CompactionSchedulerConfig {
+    pub fn new() -> Self {
+        Self {
+            check_interval_ms: 1000,
+            max_age_ms: 5 * 60 * 1000,
+            max_bytes: 64 * 1024 * 1024,
+            max_rows: 10 * 1024 * 1024,
+            max_concurrent_uploads: 4,
+            memory_pressure_bytes: 1024 * 1024 * 1024,
+            merge_interval_ms: 60 * 1000,
+            small_file_bytes: 8 * 1024 * 1024,
+            merge_min_files: 8,
+            merge_target_bytes: 128 * 1024 * 1024,
+            obsolete_file_ttl_ms: 5 * 60 * 1000,
+            replica_trim_interval_ms: 10 * 1000,
+        }
+    }
+
+    pub fn set_check_interval_ms(&mut self, check_interval_ms: u64) {
+        self.check_interval_ms = check_interval_ms;
+    }
+
+    pub fn set_max_age_ms(&mut self, max_age_ms: u64) {
+        self.max_age_ms = max_age_ms;
+    }
+
+    pub fn set_max_bytes(&mut self, max_bytes: u64) {
+        self.max_bytes = max_bytes;
+    }
+
+    pub fn set_max_rows(&mut self, max_rows: u64) {
+        self.max_rows = max_rows;
+    }
+
+    pub fn set_max_concurrent_uploads(&mut self, max_concurrent_uploads: usize) {
+        self.max_concurrent_uploads = max_concurrent_uploads;
+    }
+
+    pub fn set_memory_pressure_bytes(&mut self, memory_pressure_bytes: u64) {
+        self.memory_pressure_bytes = memory_pressure_bytes;
+    }
+
+    pub fn set_merge_interval_ms(&mut self, merge_interval_ms: u64) {
+        self.merge_interval_ms = merge_interval_ms;
+    }
+
+    pub fn set_small_file_bytes(&mut self, small_file_bytes: u64) {
+        self.small_file_bytes = small_file_bytes;
+    }
+
+    pub fn set_merge_min_files(&mut self, merge_min_files: usize) {
+        self.merge_min_files = merge_min_files;
+    }
+
+    pub fn set_merge_target_bytes(&mut self, merge_target_bytes: u64) {
+        self.merge_target_bytes = merge_target_bytes;
+    }
+
+    pub fn set_obsolete_file_ttl_ms(&mut self, obsolete_file_ttl_ms: u64) {
+        self.obsolete_file_ttl_ms = obsolete_file_ttl_ms;
+    }
+
+    pub fn set_replica_trim_interval_ms(&mut self, replica_trim_interval_ms: u64) {
+        self.replica_trim_interval_ms = replica_trim_interval_ms;
+    }
+}
+
+impl Default for CompactionSchedulerConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlushReason {
+    // the column memtable reaches the l2 rows limit of table
+    L2RowsLimit,
+    MaxRows,
+    MaxBytes,
+    MaxAge,
+    MemoryPressure,
+}
+
+/// the snapshot of a cell for scheduling
+#[derive(Clone, Debug, Default)]
+pub struct CellFlushStats {
+    pub rows_in_memory: u64,
+    pub bytes_in_memory: u64,
+    pub unflushed_age_ms: u64,
+    pub l2_compaction_needed: bool,
+    // a replica drops its rows after its primary flushes them
+    pub replica: bool,
+}
+
+impl CellFlushStats {
+    fn from_cell(cell: &CellStore) -> Self {
+        Self {
+            rows_in_memory: cell.get_total_rows_in_memory(),
+            bytes_in_memory: cell.get_memory_size_in_bytes(),
+            unflushed_age_ms: cell.get_unflushed_age_ms(),
+            l2_compaction_needed: cell.is_l2_compaction_needed(),
+            replica: cell.is_replica(),
+        }
+    }
+}
+
+/// one scheduler flushes all the cells of a memory node
+pub struct CompactionScheduler {
+    config: CompactionSchedulerConfig,
+    upload_permits: Arc<Semaphore>,
+    // the keys of cells being flushed
+    flushing: Arc<Mutex<HashSet<String>>>,
+}
+
+impl CompactionScheduler {
+    pub fn new(config: CompactionSchedulerConfig) -> Self {
+        let upload_permits = Arc::new(Semaphore::new(config.max_concurrent_uploads.max(1)));
+        Self {
+            config,
+            upload_permits,
+            flushing: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// check the cells of node periodically
+    pub fn start(self: &Arc<Self>, state: Arc<Mutex<MemoryNodeState>>) {
+        let scheduler = self.clone();
+        info!("start compaction scheduler with config {:?}", &self.config);
+        self.start_merge(state.clone());
+        self.start_trim(state.clone());
+        tokio::task::spawn(async move {
+            loop {
+                sleep(Duration::from_millis(scheduler.config.check_interval_ms)).await;
+                let cells = match state.lock() {
+                    Ok(node_state) => node_state.get_all_cells(),
+                    Err(_) => {
+                        warn!("fail to get lock for compaction");
+                        continue;
+                    }
+                };
+                scheduler.schedule(cells).await;
+            }
+        });
+    }
+
+    /// expire the old data and merge the small parquet files of every cell periodically
+    fn start_merge(self: &Arc<Self>, state: Arc<Mutex<MemoryNodeState>>) {
+        let scheduler = self.clone();
+        tokio::task::spawn(async move {
+            loop {
+                sleep(Duration::from_millis(scheduler.config.merge_interval_ms)).await;
+                let cells = match state.lock() {
+                    Ok(node_state) => node_state.get_all_cells(),
+                    Err(_) => {
+                        warn!("fail to get lock for merge compaction and retention");
+                        continue;
+                    }
+                };
+                for (db, table_id, pid, cell) in cells {
+                    let key = format!("{}/{}/{}", db, table_id, pid);
+                    scheduler.expire_cell(&key, cell.clone()).await;
+                    scheduler.merge_cell(&key, cell.clone()).await;
+                    scheduler.collect_cell(&key, cell).await;
+                }
+            }
+        });
+    }
+
+    /// drop the rows of every replica which its primary has flushed periodically
+    fn start_trim(self: &Arc<Self>, state: Arc<Mutex<MemoryNodeState>>) {
+        let scheduler = self.clone();
+        tokio::task::spawn(async move {
+            loop {
+                sleep(Duration::from_millis(
+                    scheduler.config.replica_trim_interval_ms,
+                ))
+                .await;
+                let cells = match state.lock() {
+                    Ok(node_state) => node_state.get_all_cells(),
+                    Err(_) => {
+                        warn!("fail to get lock for trimming replicas");
+                        continue;
+                    }
+                };
+                for (db, table_id, pid, cell) in cells {
+                    if !cell.is_replica() || cell.get_total_rows_in_memory() == 0 {
+                        continue;
+                    }
+                    match cell.trim_replica().await {
+                        Ok(0) => {}
+                        Ok(rows) => {
+                            debug!("trim {} rows of replica {}/{}/{}", rows, db, table_id, pid)
+                        }
+                        Err(e) => warn!(
+                            "fail to trim replica {}/{}/{} with err {}",
+                            db, table_id, pid, e
+                        ),
+                    }
+                }
+            }
+        });
+    }
+
+    async fn merge_cell(&self, key: &str, cell: Arc<CellStore>) {
+        // merges share the upload permits with flushes
+        let _permit = match self.upload_permits.acquire().await {
+            Ok(p) => p,
+            Err(e) => {
+                warn!("fail to acquire upload permit with err {}", e);
+                return;
+            }
+        };
+        let merged_keys = match cell
+            .merge_small_files(
+                self.config.small_file_bytes,
+                self.config.merge_min_files,
+                self.config.merge_target_bytes,
+            )
+            .await
+        {
+            Ok(keys) => keys,
+            Err(e) => {
+                warn!("fail to merge small files of cell {} with err {}", key, e);
+                return;
+            }
+        };
+        if !merged_keys.is_empty() {
+            debug!("merge {} files of cell {}", merged_keys.len(), key);
+        }
+    }
+
+    async fn expire_cell(&self, key: &str, cell: Arc<CellStore>) {
+        match cell.expire_data().await {
+            Ok(expired_keys) if !expired_keys.is_empty() => {
+                debug!("expire {} files of cell {}", expired_keys.len(), key);
+            }
+            Ok(_) => {}
+            Err(e) => {
+                warn!("fail to expire data of cell {} with err {}", key, e);
+            }
+        }
+    }
+
+    /// remove the files dropped from the manifest after the scans reading them finish
+    async fn collect_cell(&self, key: &str, cell: Arc<CellStore>) {
+        match cell
+            .collect_obsolete_files(self.config.obsolete_file_ttl_ms)
+            .await
+        {
+            Ok(0) => {}
+            Ok(removed) => {
+                debug!("remove {} obsolete files of cell {}", removed, key);
+            }
+            Err(e) => {
+                warn!(
+                    "fail to remove obsolete files of cell {} with err {}",
+                    key, e
+                );
+            }
+        }
+    }
+
+    /// the cells to be flushed and why, the cells which release more memory go first when the
+    /// node is under memory pressure. the replicas are never flushed, their rows are dropped
+    /// after their primaries flush them
+    pub fn plan_flushes(&self, stats: &[CellFlushStats]) -> Vec<(usize, FlushReason)> {
+        let total_bytes: u64 = stats.iter().map(|s| s.bytes_in_memory).sum();
+        let mut order: Vec<usize> = (0..stats.len()).collect();
+        order.sort_by_key(|i| Reverse(stats[*i].bytes_in_memory));
+        let mut pressure_bytes = total_bytes;
+        let mut plans: Vec<(usize, FlushReason)> = Vec::new();
+        for i in order {
+            let s = &stats[i];
+            if s.rows_in_memory == 0 || s.replica {
+                continue;
+            }
+            let reason = if pressure_bytes > self.config.memory_pressure_bytes {
+                Some(FlushReason::MemoryPressure)
+            } else if s.bytes_in_memory >= self.config.max_bytes {
+                Some(FlushReason::MaxBytes)
+            } else if s.rows_in_memory >= self.config.max_rows {
+                Some(FlushReason::MaxRows)
+            } else if s.unflushed_age_ms >= self.config.max_age_ms {
+                Some(FlushReason::MaxAge)
+            } else if s.l2_compaction_needed {
+                Some(FlushReason::L2RowsLimit)
+            } else {
+                None
+            };
+            if let Some(r) = reason {
+                pressure_bytes = pressure_bytes.saturating_sub(s.bytes_in_memory);
+                plans.push((i, r));
+            }
+        }
+        // memory pressure flushes go first
+        plans.sort_by_key(|(_, r)| *r != FlushReason::MemoryPressure);
+        plans
+    }
+
+    async fn schedule(&self, cells: Vec<(String, String, i32, Arc<CellStore>)>) {
+        let stats: Vec<CellFlushStats> = cells
+            .iter()
+            .map(|(_, _, _, cell)| CellFlushStats::from_cell(cell))
+            .collect();
+        for (index, reason) in self.plan_flushes(&stats) {
+            let (db, table_id, pid, cell) = &cells[index];
+            let key = format!("{}/{}/{}", db, table_id, pid);
+            match self.flushing.lock() {
+                Ok(mut flushing) => {
+                    if !flushing.insert(key.to_string()) {
+                        continue;
+                    }
+                }
+                Err(_) => {
+                    warn!("fail to get lock for compaction");
+                    return;
+                }
+            }
+            // the permits are taken in order, so the cells planned earlier upload earlier
+            let permit = match self.upload_permits.clone().acquire_owned().await {
+                Ok(p) => p,
+                Err(e) => {
+                    warn!("fail to acquire upload permit with err {}", e);
+                    return;
+                }
+            };
+            let cell = cell.clone();
+            let flushing = self.flushing.clone();
+            tokio::task::spawn(async move {
+                let result = match reason {
+                    FlushReason::L2RowsLimit => cell.do_l2_compaction().await,
+                    _ => cell.flush().await,
+                };
+                match result {
+                    Ok(_) => debug!("flush cell {} for {:?} done", &key, reason),
+                    Err(e) => warn!(
+                        "fail to flush cell {} for {:?} with err {}",
+                        &key, reason, e
+                    ),
+                }
+                if let Ok(mut f) = flushing.lock() {
+                    f.remove(&key);
+                }
+                drop(permit);
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gen_stats(rows: u64, bytes: u64, age_ms: u64) -> CellFlushStats {
+        CellFlushStats {
+            rows_in_memory: rows,
+            bytes_in_memory: bytes,
+            unflushed_age_ms: age_ms,
+            l2_compaction_needed: false,
+            replica: false,
+        }
+    }
+
+    #[test]
+    fn test_plan_flushes_by_triggers() {
+        let mut config = CompactionSchedulerConfig::new();
+        config.set_max_age_ms(1000);
+        config.set_max_bytes(1000);
+        config.set_max_rows(100);
+        let scheduler = CompactionScheduler::new(config);
+        let mut l2 = gen_stats(10, 10, 10);
+        l2.l2_compaction_needed = true;
+        let mut replica = gen_stats(200, 2000, 2000);
+        replica.replica = true;
+        let stats = vec![
+            gen_stats(0, 0, 0),
+            gen_stats(10, 10, 10),
+            gen_stats(10, 10, 2000),
+            gen_stats(10, 2000, 10),
+            gen_stats(200, 10, 10),
+            l2,
+            replica,
+        ];
+        let mut plans = scheduler.plan_flushes(&stats);
+        plans.sort_by_key(|(i, _)| *i);
+        assert_eq!(
+            vec![
+                (2, FlushReason::MaxAge),
+                (3, FlushReason::MaxBytes),
+                (4, FlushReason::MaxRows),
+                (5, FlushReason::L2RowsLimit)
+            ],
+            plans
+        );
+    }
+
+    #[test]
+    fn test_plan_flushes_under_memory_pressure() {
+        let mut config = CompactionSchedulerConfig::new();
+        config.set_memory_pressure_bytes(250);
+        let scheduler = CompactionScheduler::new(config);
+        let stats = vec![
+            gen_stats(10, 100, 10),
+            gen_stats(10, 200, 10),
+            gen_stats(10, 50, 60 * 60 * 1000),
+        ];
+        // the biggest cell releases enough memory, the old cell is flushed after it
+        let plans = scheduler.plan_flushes(&stats);
+        assert_eq!(
+            vec![(1, FlushReason::MemoryPressure), (2, FlushReason::MaxAge)],
+            plans
+        );
+    }
+}
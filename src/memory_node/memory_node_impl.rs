@@ -16,38 +16,51 @@
 // limitations under the License.
 //
 
-use crate::base::arrow_parquet_utils;
+use crate::base::{arrow_parquet_utils, partition_utils};
 use crate::codec::flight_codec::{flight_data_from_arrow_batch, SchemaAsIpc};
 use crate::codec::row_codec::decode;
 use crate::error::{DB3Error, Result};
+use crate::memory_node::compaction_scheduler::{CompactionScheduler, CompactionSchedulerConfig};
 use crate::proto::db3_base_proto::{
-    Db3Node, Db3TableDesc, FlightData, StorageBackendConfig, StorageRegion,
+    BinlogDurability, Db3Node, Db3TableDesc, FlightData, NodeStatus, PartitionStatus,
+    StorageBackendConfig, StorageRegion,
 };
 use crate::proto::db3_memory_proto::memory_node_server::MemoryNode;
 use crate::proto::db3_memory_proto::{
     AppendRecordsRequest, AppendRecordsResponse, AssignPartitionRequest, AssignPartitionResponse,
-    FetchPartitionRequest,
+    FencePartitionRequest, FencePartitionResponse, FetchPartitionRequest, GetNodeStatusRequest,
+    GetNodeStatusResponse, GetPartitionStatusRequest, GetPartitionStatusResponse,
+    InheritPartitionRequest, InheritPartitionResponse, PartitionSnapshot, RemovePartitionRequest,
+    RemovePartitionResponse,
 };
+use crate::sdk::build_meta_node_sdk;
 use crate::store::cell_store::{CellStore, CellStoreConfig};
+use crate::store::memory_budget::MemoryBudget;
 use crate::store::meta_store::MetaStore;
 use crate::store::object_store::build_credentials;
+use etcd_client::EventType;
 use futures::Stream;
+use prost::Message;
 use s3::creds::Credentials;
 use s3::region::Region;
 use std::collections::HashMap;
 use std::pin::Pin;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
-use tokio::time::sleep;
 use tonic::{Request, Response, Status};
-uselog!(info, warn, debug);
+uselog!(info, warn);
 pub struct MemoryNodeConfig {
     pub binlog_root_dir: String,
     pub tmp_store_root_dir: String,
     pub etcd_cluster: String,
     pub etcd_root_path: String,
     pub node: Db3Node,
+    pub compaction_config: CompactionSchedulerConfig,
+    // the max bytes of rows in memory for all cells
+    pub memory_limit_bytes: u64,
+    // the binlog of a cell rolls to a new segment when the current one exceeds the size
+    pub binlog_segment_size: u64,
 }
 
 pub struct MemoryNodeState {
@@ -93,28 +106,27 @@ impl MemoryNodeState {
 
     pub async fn build_cell_store(
         partition_ids: &[i32],
+        replica_partition_ids: &[i32],
         table_desc: &Db3TableDesc,
         storage_config: &StorageBackendConfig,
         memory_node_confg: &MemoryNodeConfig,
+        memory_budget: &Arc<MemoryBudget>,
     ) -> Result<Vec<(i32, Arc<CellStore>)>> {
         if let Some(db3_schema) = &table_desc.schema {
+            arrow_parquet_utils::check_storage_config(storage_config)?;
             let schema = arrow_parquet_utils::table_desc_to_arrow_schema(db3_schema)?;
             let region = MemoryNodeState::build_region(&storage_config.region)?;
             let name = &table_desc.name;
             let db = &table_desc.db;
+            let durability = BinlogDurability::from_i32(storage_config.durability)
+                .unwrap_or(BinlogDurability::KBuffered);
+            let range_column_index = partition_utils::get_range_column_index(table_desc)?;
             let mut cells: Vec<(i32, Arc<CellStore>)> = Vec::new();
             for id in partition_ids {
-                //TODO table id is not safe
-                let object_path = format!("{}/{}", name, id);
+                let object_path = Self::cell_object_path(name, *id);
                 let auth = MemoryNodeState::build_storage_auth()?;
-                let cell_log_path = format!(
-                    "{}/{}/{}/{}/log/",
-                    memory_node_confg.binlog_root_dir, db, name, id
-                );
-                let cell_tmp_path = format!(
-                    "{}/{}/{}/{}/tmp/",
-                    memory_node_confg.tmp_store_root_dir, db, name, id
-                );
+                let cell_log_path = Self::cell_log_path(memory_node_confg, db, name, *id);
+                let cell_tmp_path = Self::cell_tmp_path(memory_node_confg, db, name, *id);
                 let mut cell_config = CellStoreConfig::new(
                     db,
                     region.clone(),
@@ -123,12 +135,32 @@ impl MemoryNodeState {
                     auth,
                     &cell_tmp_path,
                     &object_path,
-                    false,
+                    durability,
                 )?;
                 cell_config.set_l1_rows_limit(storage_config.l1_rows_limit);
                 cell_config.set_l2_rows_limit(storage_config.l2_rows_limit);
+                cell_config.set_binlog_segment_size(memory_node_confg.binlog_segment_size);
+                if let Some(index) = range_column_index {
+                    cell_config.set_range_column_index(index);
+                }
+                cell_config.set_replica(replica_partition_ids.contains(id));
+                cell_config.set_memory_budget(memory_budget.clone());
+                cell_config.set_writer_properties(
+                    arrow_parquet_utils::storage_config_to_writer_properties(storage_config),
+                );
+                if table_desc.retention_ms > 0 {
+                    cell_config.set_retention_ms(table_desc.retention_ms as u64);
+                }
+                if storage_config.group_commit_interval_ms > 0 {
+                    cell_config.set_group_commit_interval_ms(
+                        storage_config.group_commit_interval_ms as u64,
+                    );
+                }
                 let cell_store = Arc::new(CellStore::new(cell_config)?);
                 cell_store.create_bucket().await?;
+                if !cell_store.is_replica() {
+                    cell_store.load_manifest().await?;
+                }
                 cells.push((*id, cell_store));
             }
             Ok(cells)
@@ -140,6 +172,33 @@ impl MemoryNodeState {
         }
     }
 
+    fn cell_log_path(config: &MemoryNodeConfig, db: &str, table_id: &str, pid: i32) -> String {
+        format!(
+            "{}/{}/{}/{}/log/",
+            config.binlog_root_dir, db, table_id, pid
+        )
+    }
+
+    fn cell_tmp_path(config: &MemoryNodeConfig, db: &str, table_id: &str, pid: i32) -> String {
+        format!(
+            "{}/{}/{}/{}/tmp/",
+            config.tmp_store_root_dir, db, table_id, pid
+        )
+    }
+
+    /// the object key prefix of the parquet files of cell
+    fn cell_object_path(table_id: &str, pid: i32) -> String {
+        //TODO table id is not safe
+        format!("{}/{}", table_id, pid)
+    }
+
+    pub fn remove_cell(&mut self, db: &str, table_id: &str, pid: i32) -> Option<Arc<CellStore>> {
+        self.cells
+            .get_mut(db)
+            .and_then(|db_map| db_map.get_mut(table_id))
+            .and_then(|table_map| table_map.remove(&pid))
+    }
+
     pub fn get_cell(&self, db: &str, table_id: &str, pid: i32) -> Option<Arc<CellStore>> {
         if let Some(db_map) = self.cells.get(db) {
             if let Some(table_map) = db_map.get(table_id) {
@@ -152,6 +211,27 @@ impl MemoryNodeState {
         }
     }
 
+    /// the cells of a table in node
+    pub fn get_table_cells(&self, db: &str, table_id: &str) -> Vec<Arc<CellStore>> {
+        self.cells
+            .get(db)
+            .and_then(|db_map| db_map.get(table_id))
+            .map_or_else(Vec::new, |table_map| table_map.values().cloned().collect())
+    }
+
+    /// all the cells in node with their db and table
+    pub fn get_all_cells(&self) -> Vec<(String, String, i32, Arc<CellStore>)> {
+        let mut cells = Vec::new();
+        for (db, db_map) in self.cells.iter() {
+            for (table_id, table_map) in db_map.iter() {
+                for (pid, cell) in table_map.iter() {
+                    cells.push((db.to_string(), table_id.to_string(), *pid, cell.clone()));
+                }
+            }
+        }
+        cells
+    }
+
     pub fn add_cell(
         &mut self,
         db: &str,
@@ -199,50 +279,235 @@ pub struct MemoryNodeImpl {
     state: Arc<Mutex<MemoryNodeState>>,
     config: MemoryNodeConfig,
     meta_store: Arc<MetaStore>,
+    // the rows appended since the node started, the meta node computes ingest rate with it
+    total_appended_rows: AtomicU64,
+    // the node rejects records when it is draining for shutdown
+    draining: AtomicBool,
+    compaction_scheduler: Arc<CompactionScheduler>,
+    memory_budget: Arc<MemoryBudget>,
 }
 
 impl MemoryNodeImpl {
     pub fn new(config: MemoryNodeConfig, meta_store: Arc<MetaStore>) -> Self {
+        let memory_budget = Arc::new(MemoryBudget::new(config.memory_limit_bytes));
+        // flushes start before the writes are throttled
+        let mut compaction_config = config.compaction_config.clone();
+        compaction_config.set_memory_pressure_bytes(memory_budget.get_early_flush_bytes());
+        let compaction_scheduler = Arc::new(CompactionScheduler::new(compaction_config));
         Self {
             state: Arc::new(Mutex::new(MemoryNodeState::new())),
             config,
             meta_store,
+            total_appended_rows: AtomicU64::new(0),
+            draining: AtomicBool::new(false),
+            compaction_scheduler,
+            memory_budget,
         }
     }
 
     pub async fn init(&self) -> Result<()> {
+        self.recover_cells().await?;
+        self.compaction_scheduler.start(self.state.clone());
+        self.subscribe_table_changes();
         self.meta_store.add_node(&self.config.node).await?;
         Ok(())
     }
 
-    pub fn start_l2_compaction(&self, db: &str, table_id: &str, pid: i32) {
-        let local_table_id = table_id.to_string();
-        let local_db = db.to_string();
-        let local_state = self.state.clone();
-        // TODO avoid start compaction repeated
+    /// apply the altered retention of tables to their cells
+    fn subscribe_table_changes(&self) {
+        let meta_store = self.meta_store.clone();
+        let state = self.state.clone();
         tokio::task::spawn(async move {
-            loop {
-                sleep(Duration::from_millis(1000 * 10)).await;
-                let cell_opt = match local_state.lock() {
-                    Ok(node_state) => node_state.get_cell(&local_db, &local_table_id, pid),
-                    Err(_) => None,
-                };
-                if let Some(cell) = cell_opt {
-                    if cell.do_l2_compaction().await.is_ok() {
-                        debug!(
-                            "do l2 compaction done for table {}, pid {}",
-                            &local_table_id, pid
-                        )
+            if let Ok(mut stream) = meta_store.subscribe_table_events().await {
+                while let Ok(Some(resp)) = stream.message().await {
+                    if resp.canceled() {
+                        warn!("canceled watch table event");
+                        break;
+                    }
+                    for event in resp.events() {
+                        if let (EventType::Put, Some(kv)) = (event.event_type(), event.kv()) {
+                            let table_desc = match Db3TableDesc::decode(kv.value()) {
+                                Ok(table_desc) => table_desc,
+                                Err(e) => {
+                                    warn!("fail to decode table for error {}", e);
+                                    continue;
+                                }
+                            };
+                            let cells = match state.lock() {
+                                Ok(s) => s.get_table_cells(&table_desc.db, &table_desc.name),
+                                Err(_) => {
+                                    warn!("fail to get lock for table changes");
+                                    continue;
+                                }
+                            };
+                            for cell in cells {
+                                cell.set_retention_ms(table_desc.retention_ms.max(0) as u64);
+                            }
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// drain the node before it exits. the node stops accepting records, hands its partitions
+    /// over to other memory nodes through meta node and deregisters itself from etcd. a partition
+    /// failing to be moved is flushed to object storage in place and failed over by meta node
+    pub async fn drain(&self) -> Result<()> {
+        self.draining.store(true, Ordering::SeqCst);
+        let cells = match self.state.lock() {
+            Ok(node_state) => node_state.get_all_cells(),
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to get lock".to_string()));
+            }
+        };
+        info!("start to drain {} partitions", cells.len());
+        let endpoint = &self.config.node.endpoint;
+        let meta_sdk = match build_meta_node_sdk(&self.meta_store).await {
+            Ok(sdk) => Some(sdk),
+            Err(e) => {
+                warn!("fail to connect meta node for draining with err {}", e);
+                None
+            }
+        };
+        // the partitions left on this node are failed over by meta node after it leaves
+        let mut all_moved = true;
+        for (db, table_id, pid, cell) in cells {
+            if let Some(sdk) = &meta_sdk {
+                // an empty target lets meta node choose the node by load
+                match sdk.move_partition(&db, &table_id, pid, endpoint, "").await {
+                    Ok(_) => {
+                        info!("move partition {} of {} away for draining", pid, &table_id);
+                        continue;
                     }
+                    Err(e) => {
+                        warn!(
+                            "fail to move partition {} of {} with err {}",
+                            pid, &table_id, e
+                        );
+                    }
+                }
+            }
+            all_moved = false;
+            if let Err(e) = cell.fence_and_flush().await {
+                warn!(
+                    "fail to flush partition {} of {} with err {}",
+                    pid, &table_id, e
+                );
+            }
+        }
+        if all_moved {
+            self.meta_store.mark_node_drained(&self.config.node).await?;
+        }
+        self.meta_store.remove_node(&self.config.node).await?;
+        info!("memory node {} is drained", endpoint);
+        Ok(())
+    }
+
+    /// rebuild the cells assigned to this node from meta store, every cell will replay its
+    /// binlog to recover the rows which have not been flushed to object storage
+    async fn recover_cells(&self) -> Result<()> {
+        let endpoint = &self.config.node.endpoint;
+        for db in self.meta_store.get_dbs().await? {
+            for table_desc in self.meta_store.get_tables(&db.db).await? {
+                let partition_ids: Vec<i32> = table_desc
+                    .mappings
+                    .iter()
+                    .filter(|m| m.node_list.iter().any(|n| n == endpoint))
+                    .map(|m| m.partition_id)
+                    .collect();
+                if partition_ids.is_empty() {
+                    continue;
+                }
+                // the first node in node list is the primary of partition
+                let replica_partition_ids: Vec<i32> = table_desc
+                    .mappings
+                    .iter()
+                    .filter(|m| m.node_list.iter().skip(1).any(|n| n == endpoint))
+                    .map(|m| m.partition_id)
+                    .collect();
+                if let Some(storage_config) = &table_desc.storage_config {
+                    self.add_partitions(
+                        &partition_ids,
+                        &replica_partition_ids,
+                        &table_desc,
+                        storage_config,
+                    )
+                    .await?;
+                    info!(
+                        "recover partitions {:?} of table {} in db {}",
+                        &partition_ids, &table_desc.name, &table_desc.db
+                    );
                 } else {
                     warn!(
-                        "partition {} of table {} exist from compaction",
-                        &local_table_id, pid
+                        "skip recovering table {} in db {} for no storage config",
+                        &table_desc.name, &table_desc.db
                     );
-                    break;
                 }
             }
-        });
+        }
+        Ok(())
+    }
+
+    async fn add_partitions(
+        &self,
+        partition_ids: &[i32],
+        replica_partition_ids: &[i32],
+        table_desc: &Db3TableDesc,
+        storage_config: &StorageBackendConfig,
+    ) -> Result<()> {
+        // the cells which exist only change their roles, eg a replica is promoted to primary
+        // after the primary is gone
+        let mut new_partition_ids: Vec<i32> = Vec::new();
+        for id in partition_ids {
+            match self.get_cell(&table_desc.db, &table_desc.name, *id) {
+                Some(cell) => {
+                    let replica = replica_partition_ids.contains(id);
+                    if cell.is_replica() != replica {
+                        info!(
+                            "change role of partition {} of table {} to replica {}",
+                            id, &table_desc.name, replica
+                        );
+                        if replica {
+                            cell.set_replica(true);
+                        } else {
+                            // the old primary may have uploaded files after the replica opened
+                            cell.seed_parquet_file_counter().await?;
+                            cell.promote().await?;
+                        }
+                    }
+                }
+                None => new_partition_ids.push(*id),
+            }
+        }
+        let cells = MemoryNodeState::build_cell_store(
+            &new_partition_ids,
+            replica_partition_ids,
+            table_desc,
+            storage_config,
+            &self.config,
+            &self.memory_budget,
+        )
+        .await?;
+        // the partition could have been flushed by the node it failed over from
+        for (_, cell) in cells.iter() {
+            cell.seed_parquet_file_counter().await?;
+        }
+        match self.state.lock() {
+            Ok(mut node_state) => {
+                for (id, cell) in cells {
+                    node_state.add_cell(&table_desc.db, &table_desc.name, id, cell)?;
+                }
+            }
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to get lock".to_string()));
+            }
+        }
+        Ok(())
     }
 
     pub fn get_cell(&self, db: &str, table_id: &str, pid: i32) -> Option<Arc<CellStore>> {
@@ -251,6 +516,54 @@ impl MemoryNodeImpl {
             Err(_) => None,
         }
     }
+
+    fn build_partition_status(pid: i32, display: &str, cell_store: &CellStore) -> PartitionStatus {
+        let (min_range_key, max_range_key, has_range_key) = match cell_store.get_range_keys() {
+            Some((min, max)) => (min, max, true),
+            None => (0, 0, false),
+        };
+        let group_commit_metrics = cell_store.get_group_commit_metrics();
+        PartitionStatus {
+            partition_id: pid,
+            cell_count: 1,
+            num_rows: cell_store.get_total_rows() as i64,
+            display: display.to_string(),
+            min_range_key,
+            max_range_key,
+            has_range_key,
+            size_in_bytes: cell_store.get_memory_size_in_bytes() as i64,
+            group_commit_count: group_commit_metrics.get_group_count() as i64,
+            group_commit_batch_count: group_commit_metrics.get_batch_count() as i64,
+            max_group_commit_size: group_commit_metrics.get_max_group_size() as i64,
+        }
+    }
+
+    /// the load of this node
+    pub fn build_node_status(&self) -> Result<NodeStatus> {
+        let cells = match self.state.lock() {
+            Ok(node_state) => node_state.get_all_cells(),
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to get lock".to_string()));
+            }
+        };
+        let mut status = NodeStatus {
+            endpoint: self.config.node.endpoint.to_string(),
+            ns: self.config.node.ns.to_string(),
+            partition_count: cells.len() as i32,
+            total_rows_in_memory: 0,
+            total_bytes_in_memory: 0,
+            total_appended_rows: self.total_appended_rows.load(Ordering::Relaxed) as i64,
+            partition_status_list: Vec::new(),
+        };
+        for (db, table_id, pid, cell) in cells {
+            let partition_status =
+                Self::build_partition_status(pid, &format!("{}/{}", db, table_id), &cell);
+            status.total_rows_in_memory += cell.get_total_rows_in_memory() as i64;
+            status.total_bytes_in_memory += partition_status.size_in_bytes;
+            status.partition_status_list.push(partition_status);
+        }
+        Ok(status)
+    }
 }
 
 unsafe impl Send for MemoryNodeImpl {}
@@ -272,12 +585,25 @@ impl MemoryNode for MemoryNodeImpl {
             &fetch_request.table_id,
             fetch_request.partition_id,
         ) {
-            let batches = cell_store.get_memory_batch_snapshot()?;
+            let snapshot = cell_store.get_partition_snapshot().await?;
+            let file_sequence = snapshot.file_sequence.ok_or_else(|| {
+                DB3Error::CellStoreManifestError(format!(
+                    "the manifest of partition {} of table {} is not loaded",
+                    fetch_request.partition_id, &fetch_request.table_id
+                ))
+            })?;
+            let batches = snapshot.batches;
             info!("batch size {}", batches.len());
             let options = datafusion::arrow::ipc::writer::IpcWriteOptions::default();
             //TODO  empty check
-            let schema_flight_data =
+            let mut schema_flight_data: FlightData =
                 SchemaAsIpc::new(batches[0].schema().as_ref(), &options).into();
+            let partition_snapshot = PartitionSnapshot {
+                has_file_sequence: true,
+                file_sequence,
+                files: snapshot.files,
+            };
+            schema_flight_data.app_metadata = partition_snapshot.encode_to_vec();
             let mut flights: Vec<std::result::Result<FlightData, Status>> =
                 vec![Ok(schema_flight_data)];
             let mut batches: Vec<std::result::Result<FlightData, Status>> = batches
@@ -307,6 +633,9 @@ impl MemoryNode for MemoryNodeImpl {
         &self,
         request: Request<AppendRecordsRequest>,
     ) -> std::result::Result<Response<AppendRecordsResponse>, Status> {
+        if self.is_draining() {
+            return Err(Status::unavailable("memory node is draining"));
+        }
         let append_request = request.into_inner();
         if let Some(cell_store) = self.get_cell(
             &append_request.db,
@@ -314,8 +643,12 @@ impl MemoryNode for MemoryNodeImpl {
             append_request.partition_id,
         ) {
             let row_batch = decode(&append_request.records)?;
-            cell_store.put_records(row_batch).await?;
-            Ok(Response::new(AppendRecordsResponse {}))
+            let rows = row_batch.batch.len() as u64;
+            let sequence = cell_store
+                .put_records(row_batch, append_request.batch_id, append_request.sequence)
+                .await?;
+            self.total_appended_rows.fetch_add(rows, Ordering::Relaxed);
+            Ok(Response::new(AppendRecordsResponse { sequence }))
         } else {
             Err(Status::from(DB3Error::CellStoreNotFoundError {
                 tid: append_request.table_id.to_string(),
@@ -324,6 +657,123 @@ impl MemoryNode for MemoryNodeImpl {
         }
     }
 
+    async fn get_partition_status(
+        &self,
+        request: Request<GetPartitionStatusRequest>,
+    ) -> std::result::Result<Response<GetPartitionStatusResponse>, Status> {
+        let status_request = request.into_inner();
+        let mut status_list: Vec<PartitionStatus> = Vec::new();
+        for pid in status_request.partition_ids.iter() {
+            let cell_store = self
+                .get_cell(&status_request.db, &status_request.table_id, *pid)
+                .ok_or_else(|| DB3Error::CellStoreNotFoundError {
+                    tid: status_request.table_id.to_string(),
+                    pid: *pid,
+                })?;
+            status_list.push(Self::build_partition_status(*pid, "", &cell_store));
+        }
+        Ok(Response::new(GetPartitionStatusResponse { status_list }))
+    }
+
+    async fn get_node_status(
+        &self,
+        _request: Request<GetNodeStatusRequest>,
+    ) -> std::result::Result<Response<GetNodeStatusResponse>, Status> {
+        let status = self.build_node_status()?;
+        Ok(Response::new(GetNodeStatusResponse {
+            status: Some(status),
+        }))
+    }
+
+    async fn fence_partition(
+        &self,
+        request: Request<FencePartitionRequest>,
+    ) -> std::result::Result<Response<FencePartitionResponse>, Status> {
+        let fence_request = request.into_inner();
+        let cell_store = self
+            .get_cell(
+                &fence_request.db,
+                &fence_request.table_id,
+                fence_request.partition_id,
+            )
+            .ok_or_else(|| DB3Error::CellStoreNotFoundError {
+                tid: fence_request.table_id.to_string(),
+                pid: fence_request.partition_id,
+            })?;
+        if fence_request.fence {
+            cell_store.fence_and_flush().await?;
+            info!(
+                "fence and flush partition {} of table {}",
+                fence_request.partition_id, &fence_request.table_id
+            );
+        } else {
+            cell_store.unfence();
+        }
+        Ok(Response::new(FencePartitionResponse {}))
+    }
+
+    async fn remove_partition(
+        &self,
+        request: Request<RemovePartitionRequest>,
+    ) -> std::result::Result<Response<RemovePartitionResponse>, Status> {
+        let remove_request = request.into_inner();
+        let (db, table_id, pid) = (
+            &remove_request.db,
+            &remove_request.table_id,
+            remove_request.partition_id,
+        );
+        let cell_store = match self.state.lock() {
+            Ok(mut node_state) => node_state.remove_cell(db, table_id, pid),
+            Err(_) => {
+                return Err(Status::from(DB3Error::BaseBusyError(
+                    "fail to get lock".to_string(),
+                )));
+            }
+        }
+        .ok_or_else(|| DB3Error::CellStoreNotFoundError {
+            tid: table_id.to_string(),
+            pid,
+        })?;
+        // the rows of a removed cell must have been flushed, drop its binlog
+        drop(cell_store);
+        for path in [
+            MemoryNodeState::cell_log_path(&self.config, db, table_id, pid),
+            MemoryNodeState::cell_tmp_path(&self.config, db, table_id, pid),
+        ] {
+            if let Err(e) = std::fs::remove_dir_all(&path) {
+                warn!("fail to remove dir {} with err {}", &path, e);
+            }
+        }
+        info!("remove partition {} of table {}", pid, table_id);
+        Ok(Response::new(RemovePartitionResponse {}))
+    }
+
+    async fn inherit_partition(
+        &self,
+        request: Request<InheritPartitionRequest>,
+    ) -> std::result::Result<Response<InheritPartitionResponse>, Status> {
+        let inherit_request = request.into_inner();
+        let (db, table_id, pid) = (
+            &inherit_request.db,
+            &inherit_request.table_id,
+            inherit_request.partition_id,
+        );
+        let cell_store =
+            self.get_cell(db, table_id, pid)
+                .ok_or_else(|| DB3Error::CellStoreNotFoundError {
+                    tid: table_id.to_string(),
+                    pid,
+                })?;
+        let sealed_object_path =
+            MemoryNodeState::cell_object_path(table_id, inherit_request.sealed_partition_id);
+        let count = cell_store.inherit_files(&sealed_object_path).await?;
+        info!(
+            "partition {} of table {} inherits {} files from partition {}",
+            pid, table_id, count, inherit_request.sealed_partition_id
+        );
+        Ok(Response::new(InheritPartitionResponse {}))
+    }
+
     async fn assign_partition(
         &self,
         request: Request<AssignPartitionRequest>,
@@ -332,32 +782,14 @@ impl MemoryNode for MemoryNodeImpl {
         if let (Some(table_desc), Some(config)) =
             (&assign_request.table_desc, &assign_request.config)
         {
-            let cells = MemoryNodeState::build_cell_store(
+            self.add_partitions(
                 &assign_request.partition_ids,
+                &assign_request.replica_partition_ids,
                 table_desc,
                 config,
-                &self.config,
             )
             .await?;
-            let mut cell_ids: Vec<i32> = Vec::new();
-            let result = match self.state.lock() {
-                Ok(mut node_state) => {
-                    for (id, cell) in cells {
-                        node_state.add_cell(&table_desc.db, &table_desc.name, id, cell)?;
-                        cell_ids.push(id);
-                    }
-                    Ok(Response::new(AssignPartitionResponse {}))
-                }
-                Err(_) => Err(Status::internal(DB3Error::BaseBusyError(
-                    "fail to get lock".to_string(),
-                ))),
-            };
-            if result.is_ok() {
-                for id in cell_ids {
-                    self.start_l2_compaction(&table_desc.db, &table_desc.name, id);
-                }
-            }
-            result
+            Ok(Response::new(AssignPartitionResponse {}))
         } else {
             Err(Status::invalid_argument(
                 "table desc or config is null".to_string(),
@@ -388,6 +820,9 @@ mod tests {
             etcd_cluster: "127.0.0.1:9191".to_string(),
             etcd_root_path: "/rtstore".to_string(),
             node,
+            compaction_config: CompactionSchedulerConfig::default(),
+            memory_limit_bytes: 1024 * 1024 * 1024,
+            binlog_segment_size: 64 * 1024 * 1024,
         }
     }
 
@@ -411,6 +846,50 @@ mod tests {
         assert!(memory_node.assign_partition(req).await.is_ok());
         assert!(memory_node.get_cell(db, table, 3).is_none());
         assert!(memory_node.get_cell(db, table, 0).is_some());
+        assert!(!memory_node.get_cell(db, table, 0).unwrap().is_replica());
+        assert!(memory_node.get_cell(db, table, 2).unwrap().is_replica());
+        // assign again to promote the replica
+        let mut assign_req = create_assign_partition_request(table, db);
+        assign_req.replica_partition_ids = Vec::new();
+        assert!(memory_node
+            .assign_partition(Request::new(assign_req))
+            .await
+            .is_ok());
+        assert!(!memory_node.get_cell(db, table, 2).unwrap().is_replica());
+        let fence_req = FencePartitionRequest {
+            table_id: table.to_string(),
+            db: db.to_string(),
+            partition_id: 2,
+            fence: true,
+        };
+        assert!(memory_node
+            .fence_partition(Request::new(fence_req))
+            .await
+            .is_ok());
+        assert!(memory_node.get_cell(db, table, 2).unwrap().is_fenced());
+        let remove_req = RemovePartitionRequest {
+            table_id: table.to_string(),
+            db: db.to_string(),
+            partition_id: 2,
+        };
+        assert!(memory_node
+            .remove_partition(Request::new(remove_req))
+            .await
+            .is_ok());
+        assert!(memory_node.get_cell(db, table, 2).is_none());
+        let status_req = GetPartitionStatusRequest {
+            table_id: table.to_string(),
+            db: db.to_string(),
+            partition_ids: vec![0, 1],
+        };
+        let resp = memory_node
+            .get_partition_status(Request::new(status_req))
+            .await
+            .unwrap();
+        let status_list = resp.into_inner().status_list;
+        assert_eq!(2, status_list.len());
+        assert_eq!(0, status_list[0].num_rows);
+        assert!(!status_list[0].has_range_key);
     }
 
     #[tokio::test]
@@ -431,6 +910,8 @@ mod tests {
                 partition_id: 0,
                 records: data,
                 db: db.to_string(),
+                batch_id: 0,
+                sequence: 0,
             });
             assert!(memory_node.append_records(req).await.is_ok());
         }
@@ -451,11 +932,68 @@ mod tests {
         let req = Request::new(AppendRecordsRequest {
             table_id: table.to_string(),
             partition_id: 0,
-            records: data,
+            records: data.clone(),
             db: db.to_string(),
+            batch_id: 7,
+            sequence: 0,
         });
         assert!(memory_node.get_cell(db, table, 0).is_some());
-        assert!(memory_node.append_records(req).await.is_ok());
+        let sequence = memory_node
+            .append_records(req)
+            .await
+            .unwrap()
+            .into_inner()
+            .sequence;
+        assert!(sequence > 0);
+        let status = memory_node.build_node_status()?;
+        assert_eq!(3, status.partition_count);
+        assert_eq!(3, status.total_appended_rows);
+        assert_eq!(3, status.total_rows_in_memory);
+        let group_commit_count: i64 = status
+            .partition_status_list
+            .iter()
+            .map(|s| s.group_commit_count)
+            .sum();
+        assert_eq!(1, group_commit_count);
+        // a retried batch with the same id is applied once
+        let req = Request::new(AppendRecordsRequest {
+            table_id: table.to_string(),
+            partition_id: 0,
+            records: data,
+            db: db.to_string(),
+            batch_id: 7,
+            sequence: 0,
+        });
+        // the retry gets the sequence of the first try
+        let response = memory_node.append_records(req).await.unwrap().into_inner();
+        assert_eq!(sequence, response.sequence);
+        assert_eq!(3, memory_node.build_node_status()?.total_rows_in_memory);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_drain() -> Result<()> {
+        let db = "db44";
+        let table = "ttt44";
+        let memory_node = build_memory_node().await;
+        let assign_req = create_assign_partition_request(table, db);
+        assert!(memory_node
+            .assign_partition(Request::new(assign_req))
+            .await
+            .is_ok());
+        assert!(memory_node.drain().await.is_ok());
+        assert!(memory_node.is_draining());
+        let data = encode(&gen_sample_row_batch())?;
+        let req = Request::new(AppendRecordsRequest {
+            table_id: table.to_string(),
+            partition_id: 0,
+            records: data,
+            db: db.to_string(),
+            batch_id: 0,
+            sequence: 0,
+        });
+        let status = memory_node.append_records(req).await.unwrap_err();
+        assert_eq!(tonic::Code::Unavailable, status.code());
         Ok(())
     }
 
@@ -482,6 +1020,9 @@ mod tests {
             region: Some(region),
             l1_rows_limit: 1 * 1024,
             l2_rows_limit: 5 * 1024,
+            durability: BinlogDurability::KBuffered as i32,
+            group_commit_interval_ms: 0,
+            ..Default::default()
         };
 
         let table_desc = create_simple_table_desc(tname, db);
@@ -490,6 +1031,7 @@ mod tests {
             partition_ids: pids,
             table_desc: Some(table_desc),
             config: Some(storage_config),
+            replica_partition_ids: vec![2],
         }
     }
 
@@ -498,6 +1040,7 @@ mod tests {
             name: "col1".to_string(),
             ctype: Db3Type::KBigInt as i32,
             null_allowed: true,
+            ..Default::default()
         };
         let schema = Db3SchemaDesc {
             columns: vec![col1],
@@ -510,6 +1053,8 @@ mod tests {
             db: db.to_string(),
             ctime: 0,
             mappings: Vec::new(),
+            storage_config: None,
+            retention_ms: 0,
         }
     }
 }
@@ -16,4 +16,5 @@
 // limitations under the License.
 //
 
+pub mod compaction_scheduler;
 pub mod memory_node_impl;
@@ -15,24 +15,32 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 //
+use crate::base::partition_utils;
 use crate::catalog::catalog::Catalog;
 use crate::error::{DB3Error, Result};
 use crate::proto::db3_base_proto::{
-    Db3Node, Db3NodeType, PartitionToNode, StorageBackendConfig, StorageRegion,
+    BinlogDurability, Db3Node, Db3NodeType, Db3TableDesc, NodeStatus, PartitionStatus,
+    PartitionToNode, StorageBackendConfig, StorageRegion,
 };
 use crate::proto::db3_meta_proto::meta_server::Meta;
 use crate::proto::db3_meta_proto::{
-    CreateDbRequest, CreateDbResponse, CreateTableRequest, CreateTableResponse,
+    AlterTableRequest, AlterTableResponse, CreateDbRequest, CreateDbResponse, CreateTableRequest,
+    CreateTableResponse, MovePartitionRequest, MovePartitionResponse,
 };
 use crate::sdk::memory_node_sdk::MemoryNodeSDK;
+use crate::sdk::meta_node_sdk::MetaNodeSDK;
 use crate::store::meta_store::MetaStore;
 use bytes::Bytes;
 use etcd_client::EventType;
 use prost::Message;
-use rand::prelude::*;
 use s3::region::Region;
+use std::cmp::Ordering as CmpOrdering;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
 use tonic::{Request, Response, Status};
 uselog!(debug, info, warn);
 
@@ -44,15 +52,63 @@ pub struct MetaConfig {
     pub region: Region,
 }
 
+// the weights of the load score of memory node
+const MEMORY_GB_WEIGHT: f64 = 1.0;
+const PARTITION_WEIGHT: f64 = 0.05;
+const INGEST_KROWS_PER_SEC_WEIGHT: f64 = 0.5;
+// the times to retry an alter which conflicts with other changes of table
+const ALTER_TABLE_RETRY_TIMES: usize = 3;
+
+/// the load of memory node reported by heartbeat
+#[derive(Default, Clone)]
+pub struct NodeLoad {
+    // the host of node
+    ns: String,
+    partition_count: i32,
+    total_bytes_in_memory: i64,
+    total_appended_rows: i64,
+    ingest_rows_per_sec: f64,
+    update_time: Option<Instant>,
+}
+
+impl NodeLoad {
+    fn update(&mut self, status: &NodeStatus) {
+        let now = Instant::now();
+        if let Some(last) = self.update_time {
+            let elapsed = now.duration_since(last).as_secs_f64();
+            // the counter is reset after the node restarts
+            if elapsed > 0.0 && status.total_appended_rows >= self.total_appended_rows {
+                self.ingest_rows_per_sec =
+                    (status.total_appended_rows - self.total_appended_rows) as f64 / elapsed;
+            }
+        }
+        self.ns = status.ns.to_string();
+        self.partition_count = status.partition_count;
+        self.total_bytes_in_memory = status.total_bytes_in_memory;
+        self.total_appended_rows = status.total_appended_rows;
+        self.update_time = Some(now);
+    }
+
+    /// the weighted load of node, the node with lower score is preferred
+    fn score(&self) -> f64 {
+        MEMORY_GB_WEIGHT * self.total_bytes_in_memory as f64 / (1024.0 * 1024.0 * 1024.0)
+            + PARTITION_WEIGHT * self.partition_count as f64
+            + INGEST_KROWS_PER_SEC_WEIGHT * self.ingest_rows_per_sec / 1000.0
+    }
+}
+
 pub struct MetaServiceState {
     // key is the id of table
     memory_nodes: HashMap<String, Arc<MemoryNodeSDK>>,
+    // key is the endpoint of memory node
+    node_loads: HashMap<String, NodeLoad>,
 }
 
 impl MetaServiceState {
     pub fn new() -> Self {
         Self {
             memory_nodes: HashMap::new(),
+            node_loads: HashMap::new(),
         }
     }
 
@@ -66,6 +122,104 @@ impl MetaServiceState {
             }
         }
     }
+
+    pub fn remove_memory_node(&mut self, endpoint: &str) -> Option<Arc<MemoryNodeSDK>> {
+        self.node_loads.remove(endpoint);
+        let node = self.memory_nodes.remove(endpoint);
+        if node.is_some() {
+            info!("remove memory node {}", endpoint);
+        }
+        node
+    }
+
+    pub fn update_node_load(&mut self, endpoint: &str, status: &NodeStatus) {
+        if self.memory_nodes.contains_key(endpoint) {
+            self.node_loads
+                .entry(endpoint.to_string())
+                .or_insert_with(NodeLoad::default)
+                .update(status);
+        }
+    }
+}
+
+impl MetaServiceState {
+    /// the host of memory node, a node without report is regarded as a host by itself
+    fn get_host(&self, endpoint: &str) -> String {
+        match self.node_loads.get(endpoint) {
+            Some(load) if !load.ns.is_empty() => load.ns.to_string(),
+            _ => endpoint.to_string(),
+        }
+    }
+
+    fn get_load_scores(&self) -> HashMap<String, f64> {
+        self.memory_nodes
+            .keys()
+            .map(|endpoint| {
+                let score = self.node_loads.get(endpoint).map_or(0.0, |l| l.score());
+                (endpoint.to_string(), score)
+            })
+            .collect()
+    }
+
+    /// choose the memory nodes for every partition by weighted least load, the first node of a
+    /// partition is its primary and the others are its replicas
+    fn choose_memory_nodes(
+        &self,
+        count: usize,
+        replication_factor: usize,
+    ) -> Result<Vec<Vec<Arc<MemoryNodeSDK>>>> {
+        if self.memory_nodes.is_empty() || self.memory_nodes.len() < replication_factor {
+            return Err(DB3Error::MemoryNodeNotEnoughError);
+        }
+        let mut scores = self.get_load_scores();
+        Ok((0..count)
+            .map(|_| self.choose_nodes_for_partition(&mut scores, &[], replication_factor))
+            .collect())
+    }
+
+    /// choose more nodes for a partition which is held by the nodes in node list. a node on a
+    /// host without the partition is preferred, then the node with less load. the score of
+    /// chosen node grows so the partitions in a round are spread
+    fn choose_nodes_for_partition(
+        &self,
+        scores: &mut HashMap<String, f64>,
+        node_list: &[String],
+        count: usize,
+    ) -> Vec<Arc<MemoryNodeSDK>> {
+        let mut chosen: Vec<String> = node_list.to_vec();
+        let mut nodes: Vec<Arc<MemoryNodeSDK>> = Vec::new();
+        for _ in 0..count {
+            let hosts: HashSet<String> = chosen.iter().map(|e| self.get_host(e)).collect();
+            let key = |sdk: &Arc<MemoryNodeSDK>| {
+                (
+                    hosts.contains(&self.get_host(sdk.endpoint())),
+                    scores.get(sdk.endpoint()).copied().unwrap_or(0.0),
+                )
+            };
+            let candidate = self
+                .memory_nodes
+                .values()
+                .filter(|sdk| !chosen.iter().any(|e| e == sdk.endpoint()))
+                .min_by(|l, r| {
+                    let (l_same_host, l_score) = key(l);
+                    let (r_same_host, r_score) = key(r);
+                    l_same_host
+                        .cmp(&r_same_host)
+                        .then(l_score.partial_cmp(&r_score).unwrap_or(CmpOrdering::Equal))
+                        .then(l.endpoint().cmp(r.endpoint()))
+                })
+                .cloned();
+            match candidate {
+                Some(sdk) => {
+                    *scores.entry(sdk.endpoint().to_string()).or_insert(0.0) += PARTITION_WEIGHT;
+                    chosen.push(sdk.endpoint().to_string());
+                    nodes.push(sdk);
+                }
+                None => break,
+            }
+        }
+        nodes
+    }
 }
 
 impl Default for MetaServiceState {
@@ -79,6 +233,8 @@ pub struct MetaServiceImpl {
     config: MetaConfig,
     meta_store: Arc<MetaStore>,
     catalog: Arc<Catalog>,
+    // only the leader assigns partitions and the followers forward the mutating rpcs to it
+    is_leader: Arc<AtomicBool>,
 }
 
 unsafe impl Send for MetaServiceImpl {}
@@ -92,6 +248,137 @@ impl MetaServiceImpl {
             config,
             meta_store: meta_store.clone(),
             catalog: Arc::new(Catalog::new(meta_store)),
+            is_leader: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    #[inline]
+    pub fn is_leader(&self) -> bool {
+        self.is_leader.load(Ordering::Acquire)
+    }
+
+    /// campaign for the leader of meta nodes in background, a new leader rebuilds its state
+    /// from etcd before serving and steps down when its lease is lost
+    pub fn start_leader_election(&self) {
+        let local_state = self.state.clone();
+        let local_meta_store = self.meta_store.clone();
+        let local_catalog = self.catalog.clone();
+        let local_is_leader = self.is_leader.clone();
+        let node = self.config.node.clone();
+        tokio::task::spawn(async move {
+            loop {
+                let lease_id = match local_meta_store.campaign_leader(&node).await {
+                    Ok(Some(id)) => id,
+                    Ok(None) => {
+                        sleep(Duration::from_millis(1000 * 3)).await;
+                        continue;
+                    }
+                    Err(e) => {
+                        warn!("fail to campaign for leader with err {}", e);
+                        sleep(Duration::from_millis(1000 * 3)).await;
+                        continue;
+                    }
+                };
+                info!("meta node {} becomes the leader", &node.endpoint);
+                // the lease is kept alive while the state is rebuilt, the rebuild may take longer
+                // than the ttl of lease
+                let lease_store = local_meta_store.clone();
+                let mut keepalive =
+                    tokio::task::spawn(async move { lease_store.keep_lease_alive(lease_id).await });
+                let rebuilt = tokio::select! {
+                    kept = &mut keepalive => {
+                        // another node may be the leader now, the rebuild is dropped
+                        match kept {
+                            Ok(Err(e)) => warn!("lose the leadership when rebuilding state with err {}", e),
+                            _ => warn!("lose the leadership when rebuilding state"),
+                        }
+                        local_is_leader.store(false, Ordering::Release);
+                        sleep(Duration::from_millis(1000 * 3)).await;
+                        continue;
+                    }
+                    rebuilt = Self::rebuild_state(&local_state, &local_meta_store, &local_catalog) => rebuilt,
+                };
+                if let Err(e) = rebuilt {
+                    warn!("fail to rebuild state of leader with err {}", e);
+                    keepalive.abort();
+                    if let Err(e) = local_meta_store.resign_leader(lease_id).await {
+                        warn!("fail to resign leader with err {}", e);
+                    }
+                    sleep(Duration::from_millis(1000 * 3)).await;
+                    continue;
+                }
+                local_is_leader.store(true, Ordering::Release);
+                match keepalive.await {
+                    Ok(Err(e)) => warn!("lose the leadership with err {}", e),
+                    Err(e) => warn!("lose the leadership for the keepalive stops with err {}", e),
+                    Ok(Ok(_)) => {}
+                }
+                local_is_leader.store(false, Ordering::Release);
+                info!("meta node {} steps down from leader", &node.endpoint);
+            }
+        });
+    }
+
+    /// rebuild the memory nodes and catalog from etcd, and fail over the partitions on the
+    /// nodes which died when there was no leader
+    async fn rebuild_state(
+        state: &Arc<Mutex<MetaServiceState>>,
+        meta_store: &Arc<MetaStore>,
+        catalog: &Arc<Catalog>,
+    ) -> Result<()> {
+        catalog.recover().await?;
+        let mut memory_nodes: HashMap<String, Arc<MemoryNodeSDK>> = HashMap::new();
+        // a node is dead only if its lease in etcd has expired, a node failing to connect
+        // still holds its partitions
+        let mut live_endpoints: HashSet<String> = HashSet::new();
+        for node in meta_store.get_live_nodes(Db3NodeType::KMemoryNode).await? {
+            live_endpoints.insert(node.endpoint.to_string());
+            match MemoryNodeSDK::connect(&node.endpoint).await {
+                Ok(sdk) => {
+                    memory_nodes.insert(node.endpoint.to_string(), Arc::new(sdk));
+                }
+                Err(e) => warn!(
+                    "fail to connect memory node {} with err {}",
+                    &node.endpoint, e
+                ),
+            }
+        }
+        match state.lock() {
+            Ok(mut s) => {
+                s.memory_nodes = memory_nodes;
+            }
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+            }
+        }
+        let mut dead_endpoints: HashSet<String> = HashSet::new();
+        for db in meta_store.get_dbs().await? {
+            for table in meta_store.get_tables(&db.db).await? {
+                for mapping in table.mappings.iter() {
+                    for endpoint in mapping.node_list.iter() {
+                        if !live_endpoints.contains(endpoint) {
+                            dead_endpoints.insert(endpoint.to_string());
+                        }
+                    }
+                }
+            }
+        }
+        for endpoint in dead_endpoints {
+            info!("fail over the dead memory node {}", &endpoint);
+            Self::failover_memory_node(state, meta_store, &endpoint).await?;
+        }
+        Ok(())
+    }
+
+    /// the client of leader for forwarding the mutating rpcs
+    async fn get_leader_sdk(&self) -> std::result::Result<MetaNodeSDK, Status> {
+        match self.meta_store.get_leader().await? {
+            Some(leader) if leader.endpoint != self.config.node.endpoint => {
+                MetaNodeSDK::connect(&leader.endpoint)
+                    .await
+                    .map_err(|e| Status::from(DB3Error::RPCConnectError(e)))
+            }
+            _ => Err(Status::unavailable("the leader of meta nodes is changing")),
         }
     }
 
@@ -101,7 +388,13 @@ impl MetaServiceImpl {
         db: &str,
         partition_range: &[i32],
     ) -> Result<()> {
-        let memory_node_sdk = self.random_choose_a_memory_node()?;
+        let database = self.catalog.get_db(db)?;
+        let table = database.get_table(table_id)?;
+        let replication_factor = partition_utils::get_replication_factor(table.get_table_desc());
+        let memory_nodes = match self.state.lock() {
+            Ok(state) => state.choose_memory_nodes(partition_range.len(), replication_factor),
+            Err(_) => Err(DB3Error::BaseBusyError("fail to obtain lock".to_string())),
+        }?;
         let sregion = match self.config.region {
             Region::Custom { .. } => StorageRegion {
                 region: "".to_string(),
@@ -112,61 +405,685 @@ impl MetaServiceImpl {
                 endpoint: "".to_string(),
             },
         };
-        let sconfig = StorageBackendConfig {
-            bucket: format!("/{}", db),
-            region: Some(sregion),
-            l1_rows_limit: 10 * 1024,
-            l2_rows_limit: 5 * 10 * 1024,
+        // use the binlog durability and parquet options chosen by the table owner
+        let mut sconfig = match &table.get_table_desc().storage_config {
+            Some(c) => c.clone(),
+            None => StorageBackendConfig {
+                durability: BinlogDurability::KBuffered as i32,
+                ..Default::default()
+            },
         };
-        let database = self.catalog.get_db(db)?;
-        let table = database.get_table(table_id)?;
-        if memory_node_sdk
-            .assign_partition(partition_range, table.get_table_desc(), &sconfig)
-            .await
-            .is_ok()
-        {
-            let mut mappings: Vec<PartitionToNode> = Vec::new();
-            for pid in partition_range {
-                let node_list = vec![memory_node_sdk.endpoint().to_string()];
-                let mapping = PartitionToNode {
-                    partition_id: *pid,
-                    node_list,
+        sconfig.bucket = format!("/{}", db);
+        sconfig.region = Some(sregion);
+        sconfig.l1_rows_limit = 10 * 1024;
+        sconfig.l2_rows_limit = 5 * 10 * 1024;
+        let mappings = Self::assign_partitions_to_nodes(
+            memory_nodes,
+            partition_range,
+            table.get_table_desc(),
+            &sconfig,
+        )
+        .await?;
+        let mut new_table_desc = table.get_table_desc().clone();
+        new_table_desc.mappings = mappings;
+        // memory nodes rebuild the cells with this config after restart
+        new_table_desc.storage_config = Some(sconfig);
+        // update meta of table
+        self.meta_store.add_table(&new_table_desc).await?;
+        info!(
+            "assign {} partitions of table {} to memory nodes ok",
+            partition_range.len(),
+            table_id
+        );
+        Ok(())
+    }
+
+    /// assign partitions to memory nodes which are chosen for every partition in order, the
+    /// first node of a partition is its primary and the others are its replicas
+    async fn assign_partitions_to_nodes(
+        memory_nodes: Vec<Vec<Arc<MemoryNodeSDK>>>,
+        partition_range: &[i32],
+        table_desc: &Db3TableDesc,
+        sconfig: &StorageBackendConfig,
+    ) -> Result<Vec<PartitionToNode>> {
+        // endpoint -> (sdk, all partitions, replica partitions)
+        let mut node_to_partitions: HashMap<String, (Arc<MemoryNodeSDK>, Vec<i32>, Vec<i32>)> =
+            HashMap::new();
+        let mut mappings: Vec<PartitionToNode> = Vec::new();
+        for (pid, sdks) in partition_range.iter().zip(memory_nodes) {
+            for (index, sdk) in sdks.iter().enumerate() {
+                let entry = node_to_partitions
+                    .entry(sdk.endpoint().to_string())
+                    .or_insert_with(|| (sdk.clone(), Vec::new(), Vec::new()));
+                entry.1.push(*pid);
+                if index > 0 {
+                    entry.2.push(*pid);
+                }
+            }
+            let endpoints: Vec<String> = sdks.iter().map(|s| s.endpoint().to_string()).collect();
+            mappings.push(partition_utils::build_initial_mapping(
+                table_desc, *pid, &endpoints,
+            ));
+        }
+        for (_, (sdk, pids, replica_pids)) in node_to_partitions {
+            sdk.assign_partition(&pids, &replica_pids, table_desc, sconfig)
+                .await?;
+        }
+        Ok(mappings)
+    }
+
+    /// collect the load of memory nodes periodically for placing partitions
+    pub fn start_heartbeat(&self) {
+        let local_state = self.state.clone();
+        let local_is_leader = self.is_leader.clone();
+        tokio::task::spawn(async move {
+            loop {
+                sleep(Duration::from_millis(1000 * 5)).await;
+                if !local_is_leader.load(Ordering::Acquire) {
+                    continue;
+                }
+                let nodes: Vec<Arc<MemoryNodeSDK>> = match local_state.lock() {
+                    Ok(s) => s.memory_nodes.values().cloned().collect(),
+                    Err(_) => continue,
+                };
+                for sdk in nodes {
+                    match sdk.get_node_status().await {
+                        Ok(status) => {
+                            debug!(
+                                "memory node {} has {} partitions and {} bytes in memory",
+                                sdk.endpoint(),
+                                status.partition_count,
+                                status.total_bytes_in_memory
+                            );
+                            if let Ok(mut s) = local_state.lock() {
+                                s.update_node_load(sdk.endpoint(), &status);
+                            }
+                        }
+                        Err(e) => {
+                            warn!(
+                                "fail to get status of memory node {} with err {}",
+                                sdk.endpoint(),
+                                e
+                            );
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// check the range partitioned tables periodically and split or merge their partitions
+    pub fn start_range_rebalance(&self) {
+        let local_state = self.state.clone();
+        let local_meta_store = self.meta_store.clone();
+        let local_is_leader = self.is_leader.clone();
+        tokio::task::spawn(async move {
+            loop {
+                sleep(Duration::from_millis(1000 * 30)).await;
+                if !local_is_leader.load(Ordering::Acquire) {
+                    continue;
+                }
+                let dbs = match local_meta_store.get_dbs().await {
+                    Ok(dbs) => dbs,
+                    Err(e) => {
+                        warn!("fail to get dbs for range rebalance with err {}", e);
+                        continue;
+                    }
                 };
-                mappings.push(mapping);
+                for db in dbs {
+                    let tables = match local_meta_store.get_tables(&db.db).await {
+                        Ok(tables) => tables,
+                        Err(e) => {
+                            warn!("fail to get tables of db {} with err {}", &db.db, e);
+                            continue;
+                        }
+                    };
+                    for table in tables {
+                        if !partition_utils::is_range_partitioned(&table) {
+                            continue;
+                        }
+                        if let Err(e) = Self::collect_sealed_partitions(
+                            &local_state,
+                            &local_meta_store,
+                            &table.db,
+                            &table.name,
+                        )
+                        .await
+                        {
+                            warn!(
+                                "fail to collect sealed partitions of table {} with err {}",
+                                &table.name, e
+                            );
+                        }
+                        if let Err(e) = Self::rebalance_table_ranges(
+                            &local_state,
+                            &local_meta_store,
+                            &table.db,
+                            &table.name,
+                        )
+                        .await
+                        {
+                            warn!(
+                                "fail to rebalance ranges of table {} with err {}",
+                                &table.name, e
+                            );
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    async fn rebalance_table_ranges(
+        state: &Arc<Mutex<MetaServiceState>>,
+        meta_store: &Arc<MetaStore>,
+        db: &str,
+        name: &str,
+    ) -> Result<()> {
+        let (mut table_desc, mod_revision) = meta_store.get_table(db, name).await?;
+        let sconfig = match &table_desc.storage_config {
+            Some(c) => c.clone(),
+            None => return Ok(()),
+        };
+        // collect the status of active partitions from memory nodes
+        let mut node_to_partitions: HashMap<String, Vec<i32>> = HashMap::new();
+        for mapping in table_desc.mappings.iter().filter(|m| !m.sealed) {
+            if let Some(endpoint) = mapping.node_list.first() {
+                node_to_partitions
+                    .entry(endpoint.to_string())
+                    .or_insert_with(Vec::new)
+                    .push(mapping.partition_id);
             }
-            let mut new_table_desc = table.get_table_desc().clone();
-            new_table_desc.mappings = mappings;
-            // update meta of table
-            self.meta_store.add_table(&new_table_desc).await?;
-            info!("assign table {} to memory node ok", table_id);
-        } else {
-            todo!("handle error condition");
+        }
+        let mut status: HashMap<i32, PartitionStatus> = HashMap::new();
+        for (endpoint, pids) in node_to_partitions {
+            let sdk = match state.lock() {
+                Ok(s) => s.memory_nodes.get(&endpoint).cloned(),
+                Err(_) => None,
+            }
+            .ok_or_else(|| DB3Error::NodeRPCError(endpoint.to_string()))?;
+            for s in sdk.get_partition_status(db, name, &pids).await? {
+                status.insert(s.partition_id, s);
+            }
+        }
+        let change = match partition_utils::plan_range_change(&table_desc, &status) {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        info!("plan to change ranges of table {} with {:?}", name, &change);
+        let next_pid = table_desc
+            .mappings
+            .iter()
+            .map(|m| m.partition_id)
+            .max()
+            .unwrap_or(-1)
+            + 1;
+        let new_pids: Vec<i32> = (0..change.new_ranges.len() as i32)
+            .map(|i| next_pid + i)
+            .collect();
+        let memory_nodes = match state.lock() {
+            Ok(s) => s.choose_memory_nodes(
+                new_pids.len(),
+                partition_utils::get_replication_factor(&table_desc),
+            ),
+            Err(_) => Err(DB3Error::BaseBusyError("fail to obtain lock".to_string())),
+        }?;
+        let new_cells: Vec<(i32, Arc<MemoryNodeSDK>)> = new_pids
+            .iter()
+            .zip(memory_nodes.iter())
+            .flat_map(|(pid, sdks)| sdks.iter().map(|sdk| (*pid, sdk.clone())))
+            .collect();
+        let mut new_mappings =
+            match Self::assign_partitions_to_nodes(memory_nodes, &new_pids, &table_desc, &sconfig)
+                .await
+            {
+                Ok(mappings) => mappings,
+                Err(e) => {
+                    Self::remove_partitions(db, name, &new_cells).await;
+                    return Err(e);
+                }
+            };
+        for (mapping, (range_start, range_end)) in
+            new_mappings.iter_mut().zip(change.new_ranges.iter())
+        {
+            mapping.range_start = *range_start;
+            mapping.range_end = *range_end;
+        }
+        for mapping in table_desc.mappings.iter_mut() {
+            if change.sealed_partitions.contains(&mapping.partition_id) {
+                mapping.sealed = true;
+            }
+        }
+        table_desc.mappings.append(&mut new_mappings);
+        // the sealed and new partitions must be switched at once
+        if !meta_store.update_table(&table_desc, mod_revision).await? {
+            warn!(
+                "table {} has been changed during range rebalance, retry later",
+                name
+            );
+            // the new partitions are not in the mappings, nobody writes to their cells
+            Self::remove_partitions(db, name, &new_cells).await;
         }
         Ok(())
     }
 
-    fn random_choose_a_memory_node(&self) -> Result<Arc<MemoryNodeSDK>> {
-        if let Ok(local_state) = self.state.lock() {
-            if local_state.memory_nodes.is_empty() {
+    /// drop the cells of partitions from memory nodes, the failures are only logged
+    async fn remove_partitions(db: &str, name: &str, cells: &[(i32, Arc<MemoryNodeSDK>)]) {
+        for (pid, sdk) in cells {
+            if let Err(e) = sdk.remove_partition(db, name, *pid).await {
+                warn!(
+                    "fail to remove partition {} of table {} from {} with err {}",
+                    pid,
+                    name,
+                    sdk.endpoint(),
+                    e
+                );
+            }
+        }
+    }
+
+    /// drop the sealed partitions of table after their rows have been handed off. a sealed
+    /// partition is fenced and flushed, its files are taken over by the active partition
+    /// covering its range and then it is removed from the mappings and memory nodes. the
+    /// records rejected by a fenced partition are retried by frontends with the new mappings
+    async fn collect_sealed_partitions(
+        state: &Arc<Mutex<MetaServiceState>>,
+        meta_store: &Arc<MetaStore>,
+        db: &str,
+        name: &str,
+    ) -> Result<()> {
+        let (mut table_desc, mod_revision) = meta_store.get_table(db, name).await?;
+        let mut collected: Vec<(i32, Arc<MemoryNodeSDK>)> = Vec::new();
+        let mut collected_pids: Vec<i32> = Vec::new();
+        for sealed in table_desc.mappings.iter().filter(|m| m.sealed) {
+            let heir = match partition_utils::get_heir_partition(&table_desc, sealed) {
+                Some(heir) => heir,
+                None => continue,
+            };
+            let (nodes, heir_sdk) = match state.lock() {
+                Ok(s) => (
+                    sealed
+                        .node_list
+                        .iter()
+                        .map(|n| s.memory_nodes.get(n).cloned())
+                        .collect::<Option<Vec<Arc<MemoryNodeSDK>>>>(),
+                    heir.node_list
+                        .first()
+                        .and_then(|n| s.memory_nodes.get(n).cloned()),
+                ),
+                Err(_) => {
+                    return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+                }
+            };
+            // retry in the next round if any node is unknown
+            let (nodes, heir_sdk) = match (nodes, heir_sdk) {
+                (Some(nodes), Some(heir_sdk)) => (nodes, heir_sdk),
+                _ => continue,
+            };
+            let mut flushed = true;
+            for sdk in nodes.iter() {
+                if let Err(e) = sdk
+                    .fence_partition(db, name, sealed.partition_id, true)
+                    .await
+                {
+                    warn!(
+                        "fail to flush sealed partition {} of table {} on {} with err {}",
+                        sealed.partition_id,
+                        name,
+                        sdk.endpoint(),
+                        e
+                    );
+                    flushed = false;
+                    break;
+                }
+            }
+            if !flushed {
+                continue;
+            }
+            // the scans skip the files seen in both partitions until the sealed one is removed
+            if let Err(e) = heir_sdk
+                .inherit_partition(db, name, heir.partition_id, sealed.partition_id)
+                .await
+            {
+                warn!(
+                    "partition {} of table {} fails to inherit partition {} with err {}",
+                    heir.partition_id, name, sealed.partition_id, e
+                );
+                continue;
+            }
+            collected_pids.push(sealed.partition_id);
+            for sdk in nodes {
+                collected.push((sealed.partition_id, sdk));
+            }
+        }
+        if collected_pids.is_empty() {
+            return Ok(());
+        }
+        table_desc
+            .mappings
+            .retain(|m| !collected_pids.contains(&m.partition_id));
+        if !meta_store.update_table(&table_desc, mod_revision).await? {
+            // the files taken over are skipped in the next round
+            warn!(
+                "table {} has been changed during collecting sealed partitions, retry later",
+                name
+            );
+            return Ok(());
+        }
+        Self::remove_partitions(db, name, &collected).await;
+        info!(
+            "drop sealed partitions {:?} of table {}",
+            &collected_pids, name
+        );
+        Ok(())
+    }
+
+    /// move the partitions of a dead memory node to the live nodes
+    async fn failover_memory_node(
+        state: &Arc<Mutex<MetaServiceState>>,
+        meta_store: &Arc<MetaStore>,
+        endpoint: &str,
+    ) -> Result<()> {
+        for db in meta_store.get_dbs().await? {
+            for table in meta_store.get_tables(&db.db).await? {
+                if !table
+                    .mappings
+                    .iter()
+                    .any(|m| m.node_list.iter().any(|n| n == endpoint))
+                {
+                    continue;
+                }
+                // retry if the table is changed by others during failover
+                for _ in 0..3 {
+                    match Self::failover_table(state, meta_store, &db.db, &table.name, endpoint)
+                        .await
+                    {
+                        Ok(true) => {
+                            info!(
+                                "move partitions of table {} from node {} ok",
+                                &table.name, endpoint
+                            );
+                            break;
+                        }
+                        Ok(false) => {
+                            warn!(
+                                "table {} has been changed during failover, retry",
+                                &table.name
+                            );
+                        }
+                        Err(e) => {
+                            warn!(
+                                "fail to move partitions of table {} from node {} with err {}",
+                                &table.name, endpoint, e
+                            );
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// remove the dead node from the mappings of table. the first live replica of a partition is
+    /// promoted to primary and a new node takes the place of the dead one, a partition without
+    /// replica only keeps the rows which have been flushed to object storage
+    async fn failover_table(
+        state: &Arc<Mutex<MetaServiceState>>,
+        meta_store: &Arc<MetaStore>,
+        db: &str,
+        name: &str,
+        endpoint: &str,
+    ) -> Result<bool> {
+        let (mut table_desc, mod_revision) = meta_store.get_table(db, name).await?;
+        let sconfig = match &table_desc.storage_config {
+            Some(c) => c.clone(),
+            None => return Ok(true),
+        };
+        let replication_factor = partition_utils::get_replication_factor(&table_desc);
+        let changed_partitions: HashSet<i32> = table_desc
+            .mappings
+            .iter()
+            .filter(|m| m.node_list.iter().any(|n| n == endpoint))
+            .map(|m| m.partition_id)
+            .collect();
+        // replace the dead node with the nodes chosen by load, a new replica refuses the reads
+        // until the primary has flushed the rows it missed
+        let live_nodes: Vec<Arc<MemoryNodeSDK>> = match state.lock() {
+            Ok(s) => {
+                let mut scores = s.get_load_scores();
+                for mapping in table_desc.mappings.iter_mut() {
+                    if !mapping.node_list.iter().any(|n| n == endpoint) {
+                        continue;
+                    }
+                    mapping.node_list.retain(|n| n != endpoint);
+                    let count = replication_factor.saturating_sub(mapping.node_list.len());
+                    for sdk in s.choose_nodes_for_partition(&mut scores, &mapping.node_list, count)
+                    {
+                        mapping.node_list.push(sdk.endpoint().to_string());
+                    }
+                }
+                s.memory_nodes.values().cloned().collect()
+            }
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+            }
+        };
+        // endpoint -> (sdk, all partitions, replica partitions)
+        let mut node_to_partitions: HashMap<String, (Arc<MemoryNodeSDK>, Vec<i32>, Vec<i32>)> =
+            HashMap::new();
+        for mapping in table_desc.mappings.iter() {
+            if !changed_partitions.contains(&mapping.partition_id) {
+                continue;
+            }
+            if mapping.node_list.is_empty() {
                 return Err(DB3Error::MemoryNodeNotEnoughError);
             }
-            let mut rng = rand::thread_rng();
-            let rand_num: f64 = rng.gen();
-            let index: usize = (local_state.memory_nodes.len() as f64 * rand_num) as usize;
-            for (current_index, sdk) in local_state.memory_nodes.values().enumerate() {
-                if current_index == index {
-                    return Ok(sdk.clone());
+            for (index, node) in mapping.node_list.iter().enumerate() {
+                if let Some(sdk) = live_nodes.iter().find(|s| s.endpoint() == node) {
+                    let entry = node_to_partitions
+                        .entry(node.to_string())
+                        .or_insert_with(|| (sdk.clone(), Vec::new(), Vec::new()));
+                    entry.1.push(mapping.partition_id);
+                    if index > 0 {
+                        entry.2.push(mapping.partition_id);
+                    }
                 }
             }
         }
-        Err(DB3Error::MemoryNodeNotEnoughError)
+        for (_, (sdk, pids, replica_pids)) in node_to_partitions {
+            sdk.assign_partition(&pids, &replica_pids, &table_desc, &sconfig)
+                .await?;
+        }
+        // the catalogs on compute and frontend nodes watch the change of table
+        meta_store.update_table(&table_desc, mod_revision).await
+    }
+
+    /// change the retention of table, the memory nodes watching the table apply it to their
+    /// cells
+    pub async fn alter_table_retention(
+        &self,
+        db: &str,
+        name: &str,
+        retention_ms: i64,
+    ) -> Result<()> {
+        if retention_ms < 0 {
+            return Err(DB3Error::MetaRpcAlterTableError {
+                err: format!("invalid retention {}", retention_ms),
+            });
+        }
+        for _ in 0..ALTER_TABLE_RETRY_TIMES {
+            let (mut table_desc, mod_revision) = self.meta_store.get_table(db, name).await?;
+            table_desc.retention_ms = retention_ms;
+            if self
+                .meta_store
+                .update_table(&table_desc, mod_revision)
+                .await?
+            {
+                info!(
+                    "alter the retention of table {} to {} ms",
+                    name, retention_ms
+                );
+                return Ok(());
+            }
+        }
+        Err(DB3Error::MetaRpcAlterTableError {
+            err: format!("table {} is changed by others", name),
+        })
+    }
+
+    /// move a partition between memory nodes without losing rows. all the nodes of the
+    /// partition are fenced and flushed, so the new node starts with all the rows on object
+    /// storage. the records rejected during moving are retried by the frontend nodes. the new
+    /// node is assigned after flushing, so its file counter continues after the flushed files
+    pub async fn move_partition_between_nodes(
+        &self,
+        db: &str,
+        name: &str,
+        pid: i32,
+        from_node: &str,
+        to_node: &str,
+    ) -> Result<()> {
+        let (mut table_desc, mod_revision) = self.meta_store.get_table(db, name).await?;
+        let sconfig = table_desc.storage_config.clone().ok_or_else(|| {
+            DB3Error::PartitionMoveError(format!("table {} has no storage config", name))
+        })?;
+        let mapping = table_desc
+            .mappings
+            .iter()
+            .find(|m| m.partition_id == pid)
+            .ok_or_else(|| {
+                DB3Error::PartitionMoveError(format!("partition {} of {} not found", pid, name))
+            })?;
+        let position = mapping
+            .node_list
+            .iter()
+            .position(|n| n == from_node)
+            .ok_or_else(|| {
+                DB3Error::PartitionMoveError(format!("partition {} is not on {}", pid, from_node))
+            })?;
+        if mapping.node_list.iter().any(|n| n == to_node) {
+            return Err(DB3Error::PartitionMoveError(format!(
+                "partition {} is on {} already",
+                pid, to_node
+            )));
+        }
+        let (nodes, to_sdk) = match self.state.lock() {
+            Ok(s) => {
+                let nodes: Vec<Arc<MemoryNodeSDK>> = mapping
+                    .node_list
+                    .iter()
+                    .filter_map(|n| s.memory_nodes.get(n).cloned())
+                    .collect();
+                // the target is chosen by load if it is not specified
+                let to_sdk = if to_node.is_empty() {
+                    let mut scores = s.get_load_scores();
+                    s.choose_nodes_for_partition(&mut scores, &mapping.node_list, 1)
+                        .pop()
+                        .ok_or(DB3Error::MemoryNodeNotEnoughError)?
+                } else {
+                    s.memory_nodes
+                        .get(to_node)
+                        .cloned()
+                        .ok_or_else(|| DB3Error::NodeRPCError(to_node.to_string()))?
+                };
+                (nodes, to_sdk)
+            }
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+            }
+        };
+        let to_node = to_sdk.endpoint().to_string();
+        info!(
+            "move partition {} of {} from {} to {}",
+            pid, name, from_node, &to_node
+        );
+        // stop writing and flush the rows of the partition on all its nodes
+        for sdk in nodes.iter() {
+            if let Err(e) = sdk.fence_partition(db, name, pid, true).await {
+                Self::unfence_partition(&nodes, db, name, pid).await;
+                return Err(DB3Error::from(e));
+            }
+        }
+        let replica_pids = if position > 0 { vec![pid] } else { Vec::new() };
+        if let Err(e) = to_sdk
+            .assign_partition(&[pid], &replica_pids, &table_desc, &sconfig)
+            .await
+        {
+            Self::unfence_partition(&nodes, db, name, pid).await;
+            return Err(DB3Error::from(e));
+        }
+        if let Some(mapping) = table_desc
+            .mappings
+            .iter_mut()
+            .find(|m| m.partition_id == pid)
+        {
+            mapping.node_list[position] = to_node.clone();
+        }
+        if !matches!(
+            self.meta_store
+                .update_table(&table_desc, mod_revision)
+                .await,
+            Ok(true)
+        ) {
+            if let Err(e) = to_sdk.remove_partition(db, name, pid).await {
+                warn!(
+                    "fail to remove partition {} from {} with err {}",
+                    pid, to_node, e
+                );
+            }
+            Self::unfence_partition(&nodes, db, name, pid).await;
+            return Err(DB3Error::PartitionMoveError(format!(
+                "table {} has been changed during moving",
+                name
+            )));
+        }
+        // the other nodes of partition accept records again and the old cell is dropped
+        for sdk in nodes.iter() {
+            if sdk.endpoint() == from_node {
+                if let Err(e) = sdk.remove_partition(db, name, pid).await {
+                    warn!(
+                        "fail to remove partition {} from {} with err {}",
+                        pid, from_node, e
+                    );
+                }
+            } else if let Err(e) = sdk.fence_partition(db, name, pid, false).await {
+                warn!(
+                    "fail to unfence partition {} on {} with err {}",
+                    pid,
+                    sdk.endpoint(),
+                    e
+                );
+            }
+        }
+        info!(
+            "move partition {} of table {} from {} to {} ok",
+            pid, name, from_node, to_node
+        );
+        Ok(())
+    }
+
+    async fn unfence_partition(nodes: &[Arc<MemoryNodeSDK>], db: &str, name: &str, pid: i32) {
+        for sdk in nodes.iter() {
+            if let Err(e) = sdk.fence_partition(db, name, pid, false).await {
+                warn!(
+                    "fail to unfence partition {} on {} with err {}",
+                    pid,
+                    sdk.endpoint(),
+                    e
+                );
+            }
+        }
     }
 
     pub async fn init(&self) -> Result<()> {
         self.catalog.recover().await?;
+        Catalog::subscribe_changes(&self.catalog).await;
         let local_meta_store = self.meta_store.clone();
         self.meta_store.add_node(&self.config.node).await?;
+        self.start_leader_election();
         let local_state = self.state.clone();
+        let local_is_leader = self.is_leader.clone();
         tokio::task::spawn(async move {
             if let Ok(mut stream) = local_meta_store
                 .subscribe_node_events(&Db3NodeType::KMemoryNode)
@@ -193,8 +1110,14 @@ impl MetaServiceImpl {
                                     }
                                 }
                             }
-                            (EventType::Delete, Some(kv)) => {
-                                let buf = Bytes::from(kv.value().to_vec());
+                            (EventType::Delete, Some(_)) => {
+                                let buf = match event.prev_kv() {
+                                    Some(prev_kv) => Bytes::from(prev_kv.value().to_vec()),
+                                    None => {
+                                        warn!("no previous kv in delete event of node");
+                                        continue;
+                                    }
+                                };
                                 match Db3Node::decode(buf) {
                                     Ok(node) => {
                                         if Db3NodeType::KMemoryNode as i32 == node.node_type {
@@ -227,10 +1150,41 @@ impl MetaServiceImpl {
                     }
                     for node in deleted_nodes {
                         info!("delete node {}", node.endpoint);
+                        match local_state.lock() {
+                            Ok(mut state) => {
+                                state.remove_memory_node(&node.endpoint);
+                            }
+                            Err(_) => {
+                                warn!("fail to remove memory node {}", &node.endpoint);
+                                continue;
+                            }
+                        }
+                        if !local_is_leader.load(Ordering::Acquire) {
+                            continue;
+                        }
+                        // a drained node has moved all its partitions away before leaving
+                        if matches!(local_meta_store.is_node_drained(&node).await, Ok(true)) {
+                            info!("skip failover of drained node {}", &node.endpoint);
+                            continue;
+                        }
+                        if let Err(e) = Self::failover_memory_node(
+                            &local_state,
+                            &local_meta_store,
+                            &node.endpoint,
+                        )
+                        .await
+                        {
+                            warn!(
+                                "fail to failover memory node {} with err {}",
+                                &node.endpoint, e
+                            );
+                        }
                     }
                 }
             }
         });
+        self.start_heartbeat();
+        self.start_range_rebalance();
         Ok(())
     }
 }
@@ -242,6 +1196,11 @@ impl Meta for MetaServiceImpl {
         request: Request<CreateDbRequest>,
     ) -> std::result::Result<Response<CreateDbResponse>, Status> {
         let create_db_request = request.into_inner();
+        if !self.is_leader() {
+            let leader = self.get_leader_sdk().await?;
+            leader.create_db(&create_db_request.db).await?;
+            return Ok(Response::new(CreateDbResponse {}));
+        }
         self.catalog
             .create_db(&create_db_request.db, self.config.region.clone())
             .await?;
@@ -252,15 +1211,25 @@ impl Meta for MetaServiceImpl {
         request: Request<CreateTableRequest>,
     ) -> std::result::Result<Response<CreateTableResponse>, Status> {
         let create_request = request.into_inner();
+        if !self.is_leader() {
+            if let Some(table_desc) = create_request.table_desc {
+                let leader = self.get_leader_sdk().await?;
+                leader.create_table(table_desc).await?;
+                return Ok(Response::new(CreateTableResponse {}));
+            }
+        }
         let table_desc = match &create_request.table_desc {
             Some(t) => Ok(t),
             _ => Err(DB3Error::MetaRpcCreateTableError {
                 err: "input is invalid for empty table description".to_string(),
             }),
         }?;
+        // reject the partition columns which are not in the schema
+        partition_utils::get_partition_column_indexes(table_desc)?;
+        partition_utils::get_range_column_index(table_desc)?;
         let database = self.catalog.get_db(&table_desc.db)?;
         database.create_table(table_desc, false).await?;
-        let partitions = vec![0];
+        let partitions = partition_utils::get_partition_ids(table_desc);
         if let Err(e) = self
             .assign_partitions(&table_desc.name, &table_desc.db, &partitions)
             .await
@@ -272,6 +1241,60 @@ impl Meta for MetaServiceImpl {
         }
         Ok(Response::new(CreateTableResponse {}))
     }
+
+    async fn move_partition(
+        &self,
+        request: Request<MovePartitionRequest>,
+    ) -> std::result::Result<Response<MovePartitionResponse>, Status> {
+        let move_request = request.into_inner();
+        if !self.is_leader() {
+            let leader = self.get_leader_sdk().await?;
+            leader
+                .move_partition(
+                    &move_request.db,
+                    &move_request.table,
+                    move_request.partition_id,
+                    &move_request.from_node,
+                    &move_request.to_node,
+                )
+                .await?;
+            return Ok(Response::new(MovePartitionResponse {}));
+        }
+        self.move_partition_between_nodes(
+            &move_request.db,
+            &move_request.table,
+            move_request.partition_id,
+            &move_request.from_node,
+            &move_request.to_node,
+        )
+        .await?;
+        Ok(Response::new(MovePartitionResponse {}))
+    }
+
+    async fn alter_table(
+        &self,
+        request: Request<AlterTableRequest>,
+    ) -> std::result::Result<Response<AlterTableResponse>, Status> {
+        let alter_request = request.into_inner();
+        if !self.is_leader() {
+            let leader = self.get_leader_sdk().await?;
+            leader
+                .alter_table(
+                    &alter_request.db,
+                    &alter_request.table,
+                    alter_request.retention_ms,
+                )
+                .await?;
+            return Ok(Response::new(AlterTableResponse {}));
+        }
+        self.alter_table_retention(
+            &alter_request.db,
+            &alter_request.table,
+            alter_request.retention_ms,
+        )
+        .await?;
+        Ok(Response::new(AlterTableResponse {}))
+    }
 }
 
 #[cfg(test)]
@@ -321,11 +1344,43 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_choose_memory_nodes() {
+        let mut state = MetaServiceState::new();
+        for (endpoint, ns) in [
+            ("http://127.0.0.1:9191", "host1"),
+            ("http://127.0.0.1:9192", "host1"),
+            ("http://127.0.0.2:9191", "host2"),
+        ] {
+            let sdk = Arc::new(MemoryNodeSDK::connect(endpoint).await.unwrap());
+            assert!(state.add_memory_node(endpoint, &sdk).is_ok());
+            let status = NodeStatus {
+                endpoint: endpoint.to_string(),
+                ns: ns.to_string(),
+                partition_count: if endpoint == "http://127.0.0.1:9191" {
+                    0
+                } else {
+                    100
+                },
+                ..Default::default()
+            };
+            state.update_node_load(endpoint, &status);
+        }
+        assert!(state.choose_memory_nodes(1, 4).is_err());
+        let nodes = state.choose_memory_nodes(1, 2).unwrap();
+        // the primary is the node with least load and the replica is on another host
+        assert_eq!("http://127.0.0.1:9191", nodes[0][0].endpoint());
+        assert_eq!("http://127.0.0.2:9191", nodes[0][1].endpoint());
+        let nodes = state.choose_memory_nodes(3, 1).unwrap();
+        assert_eq!(3, nodes.len());
+    }
+
     fn create_simple_table_desc(db: &str, tname: &str) -> Db3TableDesc {
         let col1 = Db3ColumnDesc {
             name: "col1".to_string(),
             ctype: 0,
             null_allowed: true,
+            ..Default::default()
         };
         let schema = Db3SchemaDesc {
             columns: vec![col1],
@@ -338,6 +1393,8 @@ mod tests {
             db: db.to_string(),
             ctime: 0,
             mappings: Vec::new(),
+            storage_config: None,
+            retention_ms: 0,
         }
     }
 }
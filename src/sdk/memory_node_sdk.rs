@@ -17,10 +17,14 @@
 //
 
 use crate::codec::row_codec::{encode, RowRecordBatch};
-use crate::proto::db3_base_proto::{Db3TableDesc, FlightData, StorageBackendConfig};
+use crate::proto::db3_base_proto::{
+    Db3TableDesc, FlightData, NodeStatus, PartitionStatus, StorageBackendConfig,
+};
 use crate::proto::db3_memory_proto::memory_node_client::MemoryNodeClient;
 use crate::proto::db3_memory_proto::{
-    AppendRecordsRequest, AssignPartitionRequest, FetchPartitionRequest,
+    AppendRecordsRequest, AssignPartitionRequest, FencePartitionRequest, FetchPartitionRequest,
+    GetNodeStatusRequest, GetPartitionStatusRequest, InheritPartitionRequest,
+    RemovePartitionRequest,
 };
 
 use std::sync::Arc;
@@ -57,6 +61,7 @@ impl MemoryNodeSDK {
     pub async fn assign_partition(
         &self,
         partition_ids: &[i32],
+        replica_partition_ids: &[i32],
         table_desc: &Db3TableDesc,
         storage_config: &StorageBackendConfig,
     ) -> std::result::Result<(), Status> {
@@ -65,6 +70,7 @@ impl MemoryNodeSDK {
             partition_ids: partition_ids.to_vec(),
             table_desc: Some(table_desc.clone()),
             config: Some(storage_config.clone()),
+            replica_partition_ids: replica_partition_ids.to_vec(),
         };
         let request = tonic::Request::new(assign_req);
         client.assign_partition(request).await?;
@@ -92,7 +98,9 @@ impl MemoryNodeSDK {
         table_id: &str,
         partition_id: i32,
         record: &RowRecordBatch,
-    ) -> std::result::Result<(), Status> {
+        batch_id: u64,
+        sequence: u64,
+    ) -> std::result::Result<u64, Status> {
         let data = encode(record)?;
         let mut client = self.client.as_ref().clone();
         let append_records_req = AppendRecordsRequest {
@@ -100,9 +108,96 @@ impl MemoryNodeSDK {
             partition_id,
             records: data,
             db: db.to_string(),
+            batch_id,
+            sequence,
         };
         let request = tonic::Request::new(append_records_req);
-        client.append_records(request).await?;
+        let response = client.append_records(request).await?;
+        Ok(response.into_inner().sequence)
+    }
+
+    pub async fn get_partition_status(
+        &self,
+        db: &str,
+        table_id: &str,
+        partition_ids: &[i32],
+    ) -> std::result::Result<Vec<PartitionStatus>, Status> {
+        let mut client = self.client.as_ref().clone();
+        let status_req = GetPartitionStatusRequest {
+            table_id: table_id.to_string(),
+            db: db.to_string(),
+            partition_ids: partition_ids.to_vec(),
+        };
+        let request = tonic::Request::new(status_req);
+        let response = client.get_partition_status(request).await?;
+        Ok(response.into_inner().status_list)
+    }
+
+    pub async fn get_node_status(&self) -> std::result::Result<NodeStatus, Status> {
+        let mut client = self.client.as_ref().clone();
+        let request = tonic::Request::new(GetNodeStatusRequest {});
+        let response = client.get_node_status(request).await?;
+        response
+            .into_inner()
+            .status
+            .ok_or_else(|| Status::internal("node status is null".to_string()))
+    }
+
+    pub async fn fence_partition(
+        &self,
+        db: &str,
+        table_id: &str,
+        partition_id: i32,
+        fence: bool,
+    ) -> std::result::Result<(), Status> {
+        let mut client = self.client.as_ref().clone();
+        let fence_req = FencePartitionRequest {
+            table_id: table_id.to_string(),
+            db: db.to_string(),
+            partition_id,
+            fence,
+        };
+        client
+            .fence_partition(tonic::Request::new(fence_req))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_partition(
+        &self,
+        db: &str,
+        table_id: &str,
+        partition_id: i32,
+    ) -> std::result::Result<(), Status> {
+        let mut client = self.client.as_ref().clone();
+        let remove_req = RemovePartitionRequest {
+            table_id: table_id.to_string(),
+            db: db.to_string(),
+            partition_id,
+        };
+        client
+            .remove_partition(tonic::Request::new(remove_req))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn inherit_partition(
+        &self,
+        db: &str,
+        table_id: &str,
+        partition_id: i32,
+        sealed_partition_id: i32,
+    ) -> std::result::Result<(), Status> {
+        let mut client = self.client.as_ref().clone();
+        let inherit_req = InheritPartitionRequest {
+            table_id: table_id.to_string(),
+            db: db.to_string(),
+            partition_id,
+            sealed_partition_id,
+        };
+        client
+            .inherit_partition(tonic::Request::new(inherit_req))
+            .await?;
         Ok(())
     }
 
@@ -17,7 +17,9 @@
 //
 use crate::proto::db3_base_proto::Db3TableDesc;
 use crate::proto::db3_meta_proto::meta_client::MetaClient;
-use crate::proto::db3_meta_proto::{CreateDbRequest, CreateTableRequest};
+use crate::proto::db3_meta_proto::{
+    AlterTableRequest, CreateDbRequest, CreateTableRequest, MovePartitionRequest,
+};
 use std::sync::Arc;
 
 use tonic::transport::Endpoint;
@@ -68,4 +70,40 @@ impl MetaNodeSDK {
         client.create_table(request).await?;
         Ok(())
     }
+
+    pub async fn move_partition(
+        &self,
+        db: &str,
+        table: &str,
+        partition_id: i32,
+        from_node: &str,
+        to_node: &str,
+    ) -> std::result::Result<(), Status> {
+        let mut client = self.client.as_ref().clone();
+        let move_req = MovePartitionRequest {
+            db: db.to_string(),
+            table: table.to_string(),
+            partition_id,
+            from_node: from_node.to_string(),
+            to_node: to_node.to_string(),
+        };
+        client.move_partition(tonic::Request::new(move_req)).await?;
+        Ok(())
+    }
+
+    pub async fn alter_table(
+        &self,
+        db: &str,
+        table: &str,
+        retention_ms: i64,
+    ) -> std::result::Result<(), Status> {
+        let mut client = self.client.as_ref().clone();
+        let alter_req = AlterTableRequest {
+            db: db.to_string(),
+            table: table.to_string(),
+            retention_ms,
+        };
+        client.alter_table(tonic::Request::new(alter_req)).await?;
+        Ok(())
+    }
 }
@@ -24,56 +24,62 @@ pub mod meta_node_sdk;
 use crate::error::{DB3Error, Result};
 use crate::proto::db3_base_proto::Db3NodeType;
 use crate::store::meta_store::MetaStore;
-uselog!(info);
+uselog!(info, warn);
 
 pub async fn build_compute_node_sdk(
     meta_store: &MetaStore,
 ) -> Result<compute_node_sdk::ComputeNodeSDK> {
-    let nodes = meta_store.get_nodes(Db3NodeType::KComputeNode).await?;
-    if nodes.is_empty() {
-        return Err(DB3Error::MetaStoreNotFoundErr);
-    }
-    let addr = format!("http://{}:{}", nodes[0].ns, nodes[0].port);
-    info!("connect compute node {}", &addr);
-    match compute_node_sdk::ComputeNodeSDK::connect(&addr).await {
-        Ok(sdk) => Ok(sdk),
-        Err(e) => Err(DB3Error::NodeRPCError(format!(
-            "fail to connect compute node for err {}",
-            e
-        ))),
+    let nodes = meta_store.get_live_nodes(Db3NodeType::KComputeNode).await?;
+    let mut last_err = DB3Error::MetaStoreNotFoundErr;
+    for node in nodes {
+        let addr = format!("http://{}:{}", node.ns, node.port);
+        info!("connect compute node {}", &addr);
+        match compute_node_sdk::ComputeNodeSDK::connect(&addr).await {
+            Ok(sdk) => return Ok(sdk),
+            Err(e) => {
+                warn!("fail to connect compute node {} for err {}", &addr, e);
+                last_err =
+                    DB3Error::NodeRPCError(format!("fail to connect compute node for err {}", e));
+            }
+        }
     }
+    Err(last_err)
 }
 
 pub async fn build_memory_node_sdk(
     meta_store: &MetaStore,
 ) -> Result<memory_node_sdk::MemoryNodeSDK> {
-    let nodes = meta_store.get_nodes(Db3NodeType::KMemoryNode).await?;
-    if nodes.is_empty() {
-        return Err(DB3Error::MetaStoreNotFoundErr);
-    }
-    let addr = format!("http://{}:{}", nodes[0].ns, nodes[0].port);
-    info!("connect memory node {}", &addr);
-    match memory_node_sdk::MemoryNodeSDK::connect(&addr).await {
-        Ok(sdk) => Ok(sdk),
-        Err(e) => Err(DB3Error::NodeRPCError(format!(
-            "fail to connect memory node for err {}",
-            e
-        ))),
+    let nodes = meta_store.get_live_nodes(Db3NodeType::KMemoryNode).await?;
+    let mut last_err = DB3Error::MetaStoreNotFoundErr;
+    for node in nodes {
+        let addr = format!("http://{}:{}", node.ns, node.port);
+        info!("connect memory node {}", &addr);
+        match memory_node_sdk::MemoryNodeSDK::connect(&addr).await {
+            Ok(sdk) => return Ok(sdk),
+            Err(e) => {
+                warn!("fail to connect memory node {} for err {}", &addr, e);
+                last_err =
+                    DB3Error::NodeRPCError(format!("fail to connect memory node for err {}", e));
+            }
+        }
     }
+    Err(last_err)
 }
 
 pub async fn build_meta_node_sdk(meta_store: &MetaStore) -> Result<meta_node_sdk::MetaNodeSDK> {
-    let nodes = meta_store.get_nodes(Db3NodeType::KMetaNode).await?;
-    if nodes.is_empty() {
-        return Err(DB3Error::MetaStoreNotFoundErr);
-    }
-    let meta_addr = format!("http://{}:{}", nodes[0].ns, nodes[0].port);
-    info!("connect meta node {}", &meta_addr);
-    match meta_node_sdk::MetaNodeSDK::connect(&meta_addr).await {
-        Ok(sdk) => Ok(sdk),
-        Err(e) => Err(DB3Error::NodeRPCError(format!(
-            "fail to connect meta node for err {}",
-            e
-        ))),
+    let nodes = meta_store.get_live_nodes(Db3NodeType::KMetaNode).await?;
+    let mut last_err = DB3Error::MetaStoreNotFoundErr;
+    for node in nodes {
+        let meta_addr = format!("http://{}:{}", node.ns, node.port);
+        info!("connect meta node {}", &meta_addr);
+        match meta_node_sdk::MetaNodeSDK::connect(&meta_addr).await {
+            Ok(sdk) => return Ok(sdk),
+            Err(e) => {
+                warn!("fail to connect meta node {} for err {}", &meta_addr, e);
+                last_err =
+                    DB3Error::NodeRPCError(format!("fail to connect meta node for err {}", e));
+            }
+        }
     }
+    Err(last_err)
 }
@@ -18,24 +18,46 @@
 
 use crate::base::filesystem::{FileSystem, SyncPosixFileSystem};
 use crate::base::linked_list::LinkedList;
-use crate::base::{arrow_parquet_utils, log::LogWriter, strings};
-use crate::codec::row_codec::{encode, RowRecordBatch};
+use crate::base::log::{LogReader, LogWriter};
+use crate::base::{arrow_parquet_utils, partition_utils, strings};
+use crate::codec::row_codec::{decode, Data, RowRecordBatch};
 use crate::error::{DB3Error, Result};
+use crate::proto::db3_base_proto::{
+    BinlogDurability, CellManifest, CellStatus, CellType, ObsoleteFile,
+};
+use crate::store::memory_budget::{MemoryBudget, MemoryCharge};
 use crate::store::object_store::{BucketFileSystem, S3FileSystem};
 use arc_swap::ArcSwap;
 use arrow::datatypes::SchemaRef;
 use arrow::record_batch::RecordBatch;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use parquet::schema::types::ColumnPath;
+use prost::Message;
 use s3::creds::Credentials;
 use s3::region::Region;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fs;
+use std::io::Write;
 use std::path::Path;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tempdir::TempDir;
+use tokio::sync::{oneshot, Mutex as AsyncMutex};
+use tokio::time::sleep;
 uselog!(info, debug, warn);
 
+const BINLOG_EXTENSION: &str = "binlog";
+const CHECKPOINT_FILE_NAME: &str = "CHECKPOINT";
+const MANIFEST_FILE_NAME: &str = "MANIFEST";
+// the count of the latest batch ids a cell remembers for dropping the retried batches
+const RECENT_BATCH_LIMIT: usize = 16 * 1024;
+
 /// Config for CellStore
 /// TODO add config for compaction
+#[derive(Clone)]
 pub struct CellStoreConfig {
     // the bucket_name for cell store
     bucket_name: String,
@@ -55,7 +77,22 @@ pub struct CellStoreConfig {
     tmp_dir_path_prefix: String,
     // object key prefix
     object_key_prefix: String,
-    enable_binlog: bool,
+    // the durability of binlog
+    durability: BinlogDurability,
+    // the interval in millisecond for group commit
+    group_commit_interval_ms: u64,
+    // the max size in bytes of a binlog segment
+    binlog_segment_size: u64,
+    // the index of range column for range partitioned table
+    range_column_index: Option<usize>,
+    // a replica keeps the recent rows in memory but never uploads them to object storage
+    replica: bool,
+    // the memory shared with the other cells of node
+    memory_budget: Option<Arc<MemoryBudget>>,
+    // the rows older than it in millisecond are dropped, 0 means they never expire
+    retention_ms: u64,
+    // the properties for writing parquet files
+    writer_properties: WriterProperties,
 }
 
 impl CellStoreConfig {
@@ -67,7 +104,7 @@ impl CellStoreConfig {
         credentials: Credentials,
         tmp_dir_path_prefix: &str,
         object_key_prefix: &str,
-        enable_binlog: bool,
+        durability: BinlogDurability,
     ) -> Result<Self> {
         if bucket_name.is_empty() {
             return Err(DB3Error::CellStoreInvalidConfigError {
@@ -110,7 +147,16 @@ impl CellStoreConfig {
             l2_rows_limit: 10 * 1024 * 5,
             tmp_dir_path_prefix: tmp_dir_path_prefix.to_string(),
             object_key_prefix: object_key_prefix.to_string(),
-            enable_binlog,
+            durability,
+            group_commit_interval_ms: 10,
+            binlog_segment_size: 64 * 1024 * 1024,
+            range_column_index: None,
+            replica: false,
+            memory_budget: None,
+            retention_ms: 0,
+            writer_properties: WriterProperties::builder()
+                .set_compression(Compression::GZIP)
+                .build(),
         })
     }
 
@@ -121,11 +167,252 @@ impl CellStoreConfig {
     pub fn set_l2_rows_limit(&mut self, limit: u32) {
         self.l2_rows_limit = limit;
     }
+
+    pub fn set_binlog_segment_size(&mut self, size: u64) {
+        self.binlog_segment_size = size;
+    }
+
+    pub fn set_group_commit_interval_ms(&mut self, interval: u64) {
+        self.group_commit_interval_ms = interval;
+    }
+
+    pub fn set_range_column_index(&mut self, index: usize) {
+        self.range_column_index = Some(index);
+    }
+
+    pub fn set_memory_budget(&mut self, memory_budget: Arc<MemoryBudget>) {
+        self.memory_budget = Some(memory_budget);
+    }
+
+    pub fn set_replica(&mut self, replica: bool) {
+        self.replica = replica;
+    }
+
+    pub fn set_retention_ms(&mut self, retention_ms: u64) {
+        self.retention_ms = retention_ms;
+    }
+
+    pub fn set_writer_properties(&mut self, writer_properties: WriterProperties) {
+        self.writer_properties = writer_properties;
+    }
+}
+
+/// the position of a record in the binlog of cell store
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct LogPosition {
+    // the number of binlog file, eg 00001.binlog
+    pub log_number: u64,
+    // the count of records from the beginning of the binlog file
+    pub record_offset: u64,
+}
+
+/// the rows of a cell in memory at a moment
+pub struct CellSnapshot {
+    pub batches: Vec<RecordBatch>,
+    // the parquet files with a smaller sequence are committed and share no row with batches,
+    // none if the primary has not loaded its manifest
+    pub file_sequence: Option<u64>,
+    // the committed files, empty if file sequence is none
+    pub files: Vec<CellStatus>,
+}
+
+/// the persisted state of cell store which is used for recovery
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct CellCheckpoint {
+    // all the records before or at the position have been flushed to object storage
+    flushed_position: LogPosition,
+    // the counter for naming the next parquet file
+    parquet_file_counter: u64,
+    // the rows which have been flushed to object storage
+    flushed_rows: u64,
+    // the flush whose file may be in the manifest though the checkpoint was not saved
+    pending_flush: Option<PendingFlush>,
+    // the rows older than it in millisecond have been evicted, they are dropped on replay
+    expired_before_ms: i64,
+    // the batches with a smaller or equal sequence have been flushed by the primary
+    flushed_sequence: u64,
+}
+
+/// a flush which is saved before its file is put to the manifest, the replayed records of it
+/// wait in flushing memtable until the manifest tells whether the file is committed
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+struct PendingFlush {
+    // the position of the last record in the file
+    position: LogPosition,
+    // the sequence of the file
+    file_sequence: u64,
+    // the sequence of the last batch in the file
+    sequence: u64,
+}
+
+/// the record of binlog, the records written before the batches had ids are decoded without them
+#[derive(Serialize, Deserialize)]
+struct BinlogRecord<R> {
+    records: R,
+    // the id given by the writer, 0 means none
+    batch_id: u64,
+    // the sequence given by the primary, 0 means none
+    sequence: u64,
+    // a record written by a replica waits for the manifest of primary after a restart
+    replica: bool,
+}
+
+fn encode_binlog_record(
+    records: &RowRecordBatch,
+    batch_id: u64,
+    sequence: u64,
+    replica: bool,
+) -> Result<Vec<u8>> {
+    bincode::serialize(&BinlogRecord {
+        records,
+        batch_id,
+        sequence,
+        replica,
+    })
+    .map_err(DB3Error::RowCodecError)
+}
+
+fn decode_binlog_record(data: &[u8]) -> Result<BinlogRecord<RowRecordBatch>> {
+    match bincode::deserialize::<BinlogRecord<RowRecordBatch>>(data) {
+        Ok(record) => Ok(record),
+        Err(_) => Ok(BinlogRecord {
+            records: decode(data)?,
+            batch_id: 0,
+            sequence: 0,
+            replica: false,
+        }),
+    }
 }
 
 struct CellStoreLockData {
     // the writer of binlog
     log_writer: Box<LogWriter>,
+    // the position of the last record applied to the memtable
+    last_position: LogPosition,
+    // the position of the last record converted to the column memtable
+    column_memtable_position: LogPosition,
+    // the batches being written to a parquet file, they are visible to scans until the file
+    // is committed
+    flushing_memtable: Arc<LinkedList<RecordBatch>>,
+    flushing_rows: u64,
+    // the position of the last record in flushing memtable
+    flushing_position: LogPosition,
+    // the parquet files committed to object storage
+    manifest: CellManifest,
+    // the flushed position in the checkpoint
+    flushed_position: LogPosition,
+    // the replayed flush waiting for the manifest
+    pending_flush: Option<PendingFlush>,
+    // the expiry watermark in the checkpoint
+    expired_before_ms: i64,
+    // the ids of the latest batches applied with their sequences in the order of applying
+    recent_batch_ids: HashMap<u64, u64>,
+    recent_batch_order: VecDeque<u64>,
+    // the sequence of the last batch applied
+    last_sequence: u64,
+    // the sequence of the last batch converted to the column memtable
+    column_memtable_sequence: u64,
+    // the sequence of the last batch in flushing memtable
+    flushing_sequence: u64,
+    // the batches with a smaller or equal sequence have been flushed by the primary
+    flushed_sequence: u64,
+    // the batches of a replica by their sequences with their positions in binlog, they are
+    // dropped once the manifest of primary covers their sequences
+    replica_batches: BTreeMap<u64, (LogPosition, RecordBatch)>,
+    // the sequence of the first batch a replica applies after it is opened, the batches before
+    // it are only in the memory of primary until they are flushed
+    first_replica_sequence: u64,
+}
+
+impl CellStoreLockData {
+    /// the sequence of a batch which has been applied
+    fn applied_sequence(&self, batch_id: u64) -> Option<u64> {
+        if batch_id == 0 {
+            return None;
+        }
+        self.recent_batch_ids.get(&batch_id).copied()
+    }
+
+    /// remember the id of an applied batch
+    fn track_batch(&mut self, batch_id: u64, sequence: u64) {
+        if batch_id == 0 || self.recent_batch_ids.insert(batch_id, sequence).is_some() {
+            return;
+        }
+        self.recent_batch_order.push_back(batch_id);
+        if self.recent_batch_order.len() > RECENT_BATCH_LIMIT {
+            if let Some(id) = self.recent_batch_order.pop_front() {
+                self.recent_batch_ids.remove(&id);
+            }
+        }
+    }
+
+    fn next_position(&self) -> LogPosition {
+        let log_number = self.log_writer.get_log_number();
+        if self.last_position.log_number == log_number {
+            LogPosition {
+                log_number,
+                record_offset: self.last_position.record_offset + 1,
+            }
+        } else {
+            LogPosition {
+                log_number,
+                record_offset: 1,
+            }
+        }
+    }
+}
+
+/// a batch of records waiting to be written to binlog by the leader of group commit
+struct PendingWrite {
+    // the encoded records
+    data: Vec<u8>,
+    records: RowRecordBatch,
+    batch_id: u64,
+    sequence: u64,
+    replica: bool,
+    // notify the writer with the sequence of batch or the error of group commit
+    done: oneshot::Sender<std::result::Result<u64, String>>,
+}
+
+/// the metrics for the group commit of binlog
+#[derive(Default)]
+pub struct GroupCommitMetrics {
+    // the count of groups written to binlog
+    group_count: AtomicU64,
+    // the count of batches written to binlog
+    batch_count: AtomicU64,
+    // the max count of batches in a group
+    max_group_size: AtomicU64,
+}
+
+impl GroupCommitMetrics {
+    fn add_group(&self, group_size: u64) {
+        self.group_count.fetch_add(1, Ordering::Relaxed);
+        self.batch_count.fetch_add(group_size, Ordering::Relaxed);
+        self.max_group_size.fetch_max(group_size, Ordering::Relaxed);
+    }
+
+    pub fn get_group_count(&self) -> u64 {
+        self.group_count.load(Ordering::Relaxed)
+    }
+
+    pub fn get_batch_count(&self) -> u64 {
+        self.batch_count.load(Ordering::Relaxed)
+    }
+
+    pub fn get_max_group_size(&self) -> u64 {
+        self.max_group_size.load(Ordering::Relaxed)
+    }
+
+    /// the average count of batches in a group
+    pub fn get_avg_group_size(&self) -> f64 {
+        let group_count = self.get_group_count();
+        if group_count == 0 {
+            0.0
+        } else {
+            self.get_batch_count() as f64 / group_count as f64
+        }
+    }
 }
 
 /// the smallest unit for storing table data
@@ -135,6 +422,9 @@ pub struct CellStore {
     // the total rows that in memory
     total_rows_in_memory: AtomicU64,
     total_rows_on_external_storage: AtomicU64,
+    // the min and max value of range column
+    min_range_key: AtomicI64,
+    max_range_key: AtomicI64,
     // total data in bytes stored in memory
     total_data_in_memory: AtomicU64,
     // total data in bytes stored on external storage
@@ -145,6 +435,11 @@ pub struct CellStore {
     log_counter: AtomicU64,
     // lock for binlog
     lock_data: Arc<Mutex<CellStoreLockData>>,
+    // the batches waiting for group commit
+    pending_writes: Mutex<Vec<PendingWrite>>,
+    // only the leader of group commit holds the lock
+    commit_lock: AsyncMutex<()>,
+    group_commit_metrics: GroupCommitMetrics,
     // memory table for row store
     row_memtable: ArcSwap<LinkedList<RowRecordBatch>>,
     row_memtable_size: AtomicU64,
@@ -152,7 +447,21 @@ pub struct CellStore {
     column_memtable: ArcSwap<LinkedList<RecordBatch>>,
     column_memtable_size: AtomicU64,
     parquet_file_counter: AtomicU64,
+    // the primary gives the next batch the sequence after it
+    sequence_counter: AtomicU64,
     bucket_fs: BucketFileSystem,
+    // only the primary of a partition uploads parquet files
+    replica: AtomicBool,
+    // a fenced cell accepts no records
+    fenced: AtomicBool,
+    // only one l2 compaction runs at a time
+    flush_lock: AsyncMutex<()>,
+    // the manifest must be loaded from object storage before the cell uploads files
+    manifest_stale: AtomicBool,
+    // the time in millisecond of the oldest row not flushed, 0 means no such row
+    unflushed_since_ms: AtomicU64,
+    // the retention which could be altered after the cell is opened
+    retention_ms: AtomicU64,
 }
 
 unsafe impl Send for CellStore {}
@@ -160,6 +469,8 @@ unsafe impl Send for CellStore {}
 unsafe impl Sync for CellStore {}
 
 impl CellStore {
+    /// create a cell store and replay the records in binlog which have not been flushed to
+    /// object storage
     pub fn new(config: CellStoreConfig) -> Result<Self> {
         info!(
             "init a new cell store with bucket {} , region {}, tmp_dir_path_prefix {}, object_key_prefix {}",
@@ -169,30 +480,262 @@ impl CellStore {
         fs::create_dir_all(&config.tmp_dir_path_prefix)?;
         let s3_fs = S3FileSystem::new(config.region.clone(), config.credentials.clone());
         let bucket_fs = s3_fs.new_bucket_fs(&config.bucket_name);
-        let log_path_str = format!("{}/00000.binlog", config.local_binlog_path_prefix);
-        let log_path = Path::new(&log_path_str);
+        let checkpoint = Self::load_checkpoint(&config.local_binlog_path_prefix)?;
+        let log_numbers = Self::list_log_numbers(&config.local_binlog_path_prefix)?;
         let fs = SyncPosixFileSystem {};
-        let writer = fs.open_writable_file_writer(log_path)?;
-        let log_writer = Box::new(LogWriter::new(writer, 0));
-        //TODO recover some status data from persistence
-        Ok(CellStore {
+        // the last binlog segment is written after its last record if it is not full, the
+        // broken record at its tail is dropped
+        let (log_number, log_writer) = match log_numbers.last() {
+            Some(n) => {
+                let log_path_str = Self::log_path(&config.local_binlog_path_prefix, *n);
+                let log_path = Path::new(&log_path_str);
+                let end_offset = Self::log_end_offset(log_path)?;
+                if (end_offset as u64) < config.binlog_segment_size {
+                    let writer = fs.open_appendable_file_writer(log_path, end_offset)?;
+                    (*n, LogWriter::reopen(writer, *n))
+                } else {
+                    let log_path_str = Self::log_path(&config.local_binlog_path_prefix, n + 1);
+                    let writer = fs.open_writable_file_writer(Path::new(&log_path_str))?;
+                    (n + 1, LogWriter::new(writer, n + 1))
+                }
+            }
+            None => {
+                let log_path_str = Self::log_path(&config.local_binlog_path_prefix, 0);
+                let writer = fs.open_writable_file_writer(Path::new(&log_path_str))?;
+                (0, LogWriter::new(writer, 0))
+            }
+        };
+        let log_writer = Box::new(log_writer);
+        let lock_data = CellStoreLockData {
+            log_writer,
+            last_position: checkpoint.flushed_position,
+            column_memtable_position: checkpoint.flushed_position,
+            flushing_memtable: Arc::new(LinkedList::new()),
+            flushing_rows: 0,
+            flushing_position: checkpoint.flushed_position,
+            manifest: CellManifest::default(),
+            flushed_position: checkpoint.flushed_position,
+            pending_flush: None,
+            expired_before_ms: checkpoint.expired_before_ms,
+            recent_batch_ids: HashMap::new(),
+            recent_batch_order: VecDeque::new(),
+            last_sequence: checkpoint.flushed_sequence,
+            column_memtable_sequence: checkpoint.flushed_sequence,
+            flushing_sequence: checkpoint.flushed_sequence,
+            flushed_sequence: checkpoint.flushed_sequence,
+            replica_batches: BTreeMap::new(),
+            first_replica_sequence: 0,
+        };
+        let cell_store = CellStore {
+            // the fields read from config are taken before it is moved
+            retention_ms: AtomicU64::new(config.retention_ms),
+            replica: AtomicBool::new(config.replica),
             config,
             total_rows_in_memory: AtomicU64::new(0),
-            total_rows_on_external_storage: AtomicU64::new(0),
+            total_rows_on_external_storage: AtomicU64::new(checkpoint.flushed_rows),
+            min_range_key: AtomicI64::new(i64::MAX),
+            max_range_key: AtomicI64::new(i64::MIN),
             total_data_in_memory: AtomicU64::new(0),
             total_data_on_external_storage: AtomicU64::new(0),
             binlog_data_size: AtomicU64::new(0),
-            log_counter: AtomicU64::new(0),
-            lock_data: Arc::new(Mutex::new(CellStoreLockData { log_writer })),
+            log_counter: AtomicU64::new(log_number),
+            lock_data: Arc::new(Mutex::new(lock_data)),
+            pending_writes: Mutex::new(Vec::new()),
+            commit_lock: AsyncMutex::new(()),
+            group_commit_metrics: GroupCommitMetrics::default(),
             row_memtable: ArcSwap::from(Arc::new(LinkedList::new())),
             row_memtable_size: AtomicU64::new(0),
             column_memtable: ArcSwap::from(Arc::new(LinkedList::new())),
             column_memtable_size: AtomicU64::new(0),
-            parquet_file_counter: AtomicU64::new(0),
+            parquet_file_counter: AtomicU64::new(checkpoint.parquet_file_counter),
+            sequence_counter: AtomicU64::new(checkpoint.flushed_sequence),
             bucket_fs,
+            fenced: AtomicBool::new(false),
+            flush_lock: AsyncMutex::new(()),
+            manifest_stale: AtomicBool::new(true),
+            unflushed_since_ms: AtomicU64::new(0),
+        };
+        cell_store.recover(&log_numbers, &checkpoint)?;
+        cell_store.remove_obsolete_segments(checkpoint.flushed_position.log_number)?;
+        Ok(cell_store)
+    }
+
+    fn log_path(local_binlog_path_prefix: &str, log_number: u64) -> String {
+        format!(
+            "{}/{}.{}",
+            local_binlog_path_prefix,
+            strings::to_readable_num_str(log_number as usize, 5),
+            BINLOG_EXTENSION
+        )
+    }
+
+    /// the offset after the last record which can be read from a binlog file
+    fn log_end_offset(log_path: &Path) -> Result<usize> {
+        let fs = SyncPosixFileSystem {};
+        let mut log_reader = LogReader::new(fs.open_sequential_file(log_path)?);
+        let mut buffer: Vec<u8> = Vec::new();
+        while let Ok(true) = log_reader.read_record(&mut buffer) {}
+        Ok(log_reader.get_last_record_end_offset())
+    }
+
+    /// list the numbers of binlog files in ascending order
+    fn list_log_numbers(local_binlog_path_prefix: &str) -> Result<Vec<u64>> {
+        let fs = SyncPosixFileSystem {};
+        let mut log_numbers: Vec<u64> = Vec::new();
+        for path in fs.list_files(Path::new(local_binlog_path_prefix))? {
+            if path.extension().and_then(|e| e.to_str()) != Some(BINLOG_EXTENSION) {
+                continue;
+            }
+            if let Some(n) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.parse::<u64>().ok())
+            {
+                log_numbers.push(n);
+            }
+        }
+        log_numbers.sort_unstable();
+        Ok(log_numbers)
+    }
+
+    fn load_checkpoint(local_binlog_path_prefix: &str) -> Result<CellCheckpoint> {
+        let path_str = format!("{}/{}", local_binlog_path_prefix, CHECKPOINT_FILE_NAME);
+        let path = Path::new(&path_str);
+        if !path.exists() {
+            return Ok(CellCheckpoint::default());
+        }
+        let data = fs::read(path)?;
+        bincode::deserialize(&data).map_err(|e| {
+            DB3Error::CellStoreCheckpointError(format!("bad checkpoint {} for {}", path_str, e))
         })
     }
 
+    /// switch to a new binlog segment, the old one will be removed after its records have been
+    /// flushed to object storage
+    fn rotate_binlog(&self, lock_data: &mut CellStoreLockData) -> Result<()> {
+        lock_data.log_writer.fsync()?;
+        let log_number = self.log_counter.fetch_add(1, Ordering::Relaxed) + 1;
+        let log_path_str = Self::log_path(&self.config.local_binlog_path_prefix, log_number);
+        let fs = SyncPosixFileSystem {};
+        let writer = fs.open_writable_file_writer(Path::new(&log_path_str))?;
+        lock_data.log_writer = Box::new(LogWriter::new(writer, log_number));
+        info!("rotate binlog to {}", &log_path_str);
+        Ok(())
+    }
+
+    /// remove the binlog segments whose records have all been flushed to object storage
+    fn remove_obsolete_segments(&self, flushed_log_number: u64) -> Result<()> {
+        let posix_fs = SyncPosixFileSystem {};
+        let mut binlog_data_size: u64 = 0;
+        for log_number in Self::list_log_numbers(&self.config.local_binlog_path_prefix)? {
+            let log_path_str = Self::log_path(&self.config.local_binlog_path_prefix, log_number);
+            let log_path = Path::new(&log_path_str);
+            if log_number < flushed_log_number {
+                posix_fs.remove(log_path)?;
+                debug!("remove obsolete binlog {}", &log_path_str);
+            } else {
+                binlog_data_size += fs::metadata(log_path)?.len();
+            }
+        }
+        self.binlog_data_size
+            .store(binlog_data_size, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// write the checkpoint to a temp file and rename it, so a crash never leaves a broken one
+    fn save_checkpoint(&self, checkpoint: &CellCheckpoint) -> Result<()> {
+        let data = bincode::serialize(checkpoint)
+            .map_err(|e| DB3Error::CellStoreCheckpointError(format!("{}", e)))?;
+        let path_str = format!(
+            "{}/{}",
+            self.config.local_binlog_path_prefix, CHECKPOINT_FILE_NAME
+        );
+        let tmp_path_str = format!("{}.tmp", path_str);
+        let mut fd = fs::File::create(&tmp_path_str)?;
+        fd.write_all(&data)?;
+        fd.sync_all()?;
+        fs::rename(&tmp_path_str, &path_str)?;
+        Ok(())
+    }
+
+    /// replay the records in binlog files to memtable, the records which have been flushed
+    /// to object storage will be skipped
+    fn recover(&self, log_numbers: &[u64], checkpoint: &CellCheckpoint) -> Result<()> {
+        let fs = SyncPosixFileSystem {};
+        let mut guard = self
+            .lock_data
+            .lock()
+            .map_err(|_| DB3Error::BaseBusyError("fail to obtain lock".to_string()))?;
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut replayed_rows: u64 = 0;
+        let mut pending_flush = checkpoint.pending_flush;
+        for log_number in log_numbers {
+            if *log_number < checkpoint.flushed_position.log_number {
+                continue;
+            }
+            let log_path_str = Self::log_path(&self.config.local_binlog_path_prefix, *log_number);
+            let reader = fs.open_sequential_file(Path::new(&log_path_str))?;
+            let mut log_reader = LogReader::new(reader);
+            let mut record_offset: u64 = 0;
+            loop {
+                match log_reader.read_record(&mut buffer) {
+                    Ok(true) => {
+                        record_offset += 1;
+                        let position = LogPosition {
+                            log_number: *log_number,
+                            record_offset,
+                        };
+                        if position <= checkpoint.flushed_position {
+                            continue;
+                        }
+                        if let Some(pending) = pending_flush {
+                            if position > pending.position {
+                                self.stage_pending_flush(&mut guard, pending);
+                                pending_flush = None;
+                            }
+                        }
+                        let record = decode_binlog_record(&buffer)?;
+                        replayed_rows += record.records.batch.len() as u64;
+                        let sequence = self.apply_records(&mut guard, record, position)?;
+                        self.sequence_counter.fetch_max(sequence, Ordering::Relaxed);
+                    }
+                    Ok(false) | Err(DB3Error::FSIoEofError) => break,
+                    Err(e) => {
+                        warn!("stop replaying binlog {} for error {}", &log_path_str, e);
+                        break;
+                    }
+                }
+            }
+        }
+        if let Some(pending) = pending_flush {
+            self.stage_pending_flush(&mut guard, pending);
+        }
+        // the rows evicted before the restart do not come back
+        if let Some(index) = arrow_parquet_utils::get_time_column_index(&self.config.schema) {
+            if checkpoint.expired_before_ms > 0 {
+                self.do_l1_compaction(&mut guard, true);
+                self.evict_column_memtable(index, checkpoint.expired_before_ms)?;
+                self.evict_replica_batches(&mut guard, index, checkpoint.expired_before_ms)?;
+            }
+        }
+        info!(
+            "replay {} rows from binlog {} with flushed position {:?}",
+            replayed_rows, self.config.local_binlog_path_prefix, checkpoint.flushed_position
+        );
+        Ok(())
+    }
+
+    /// move the replayed records of a pending flush to flushing memtable, they are dropped if
+    /// the manifest has the file or flushed again
+    fn stage_pending_flush(&self, lock_data: &mut CellStoreLockData, pending: PendingFlush) {
+        self.do_l1_compaction(lock_data, true);
+        lock_data.flushing_memtable = self.column_memtable.swap(Arc::new(LinkedList::new()));
+        lock_data.flushing_rows = self.column_memtable_size.swap(0, Ordering::Relaxed);
+        lock_data.flushing_position = pending.position;
+        lock_data.flushing_sequence = pending.sequence;
+        lock_data.pending_flush = Some(pending);
+    }
+
     pub async fn create_bucket(&self) -> Result<()> {
         self.bucket_fs.create_bucket().await
     }
@@ -201,59 +744,433 @@ impl CellStore {
         self.row_memtable_size.load(Ordering::Relaxed)
     }
 
-    pub fn get_memory_batch_snapshot(&self) -> Result<Vec<RecordBatch>> {
-        let local_row_memtable = self.row_memtable.load();
+    /// the rows in memory and the parquet files committed at the same moment, so a scan reading
+    /// both sees every row exactly once. a replica takes the manifest of primary which its rows
+    /// are trimmed by
+    pub fn get_memory_batch_snapshot(&self) -> Result<CellSnapshot> {
+        let (
+            local_row_memtable,
+            local_column_memtable,
+            flushing_memtable,
+            replica_batches,
+            file_sequence,
+            files,
+        ) = match self.lock_data.lock() {
+            Ok(guard) => {
+                let file_sequence = if self.is_replica() {
+                    Some(guard.manifest.file_sequence)
+                } else if self.manifest_stale.load(Ordering::Acquire) {
+                    None
+                } else {
+                    Some(self.parquet_file_counter.load(Ordering::Acquire))
+                };
+                (
+                    self.row_memtable.load_full(),
+                    self.column_memtable.load_full(),
+                    guard.flushing_memtable.clone(),
+                    guard
+                        .replica_batches
+                        .values()
+                        .map(|(_, b)| b.clone())
+                        .collect::<Vec<RecordBatch>>(),
+                    file_sequence,
+                    guard.manifest.files.clone(),
+                )
+            }
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+            }
+        };
         let mut batches: Vec<RecordBatch> = Vec::new();
         let batch =
             arrow_parquet_utils::rows_to_columns(&self.config.schema, local_row_memtable.as_ref())?;
         batches.push(batch);
-        let local_column_memtable = self.column_memtable.load();
         for batch in local_column_memtable.iter() {
             batches.push(batch.clone());
         }
-        Ok(batches)
+        for batch in flushing_memtable.iter() {
+            batches.push(batch.clone());
+        }
+        batches.extend(replica_batches);
+        Ok(CellSnapshot {
+            batches,
+            file_sequence,
+            files: match file_sequence {
+                Some(_) => files,
+                None => Vec::new(),
+            },
+        })
     }
 
-    pub async fn put_records(&self, records: RowRecordBatch) -> Result<()> {
-        // load a row memtable reference
-        let table = self.row_memtable.load();
-        // encode row records to byte data
-        let data = encode(&records)?;
-        let size = records.batch.len();
-        table.push_front(records)?;
-        self.total_rows_in_memory
-            .fetch_add(size as u64, Ordering::Relaxed);
-        self.row_memtable_size
-            .fetch_add(size as u64, Ordering::Relaxed);
-        self.do_l1_compaction_maybe();
-        // save record to binlog
-        if self.config.enable_binlog {
-            if let Ok(mut guard) = self.lock_data.lock() {
-                guard.log_writer.add_record(&data)
+    /// the snapshot for a scan, the primary loads its manifest and a replica trims its rows by
+    /// the latest manifest of primary before taking it
+    pub async fn get_partition_snapshot(&self) -> Result<CellSnapshot> {
+        if self.is_replica() {
+            let _flushing = self.flush_lock.lock().await;
+            self.trim_flushed_batches().await?;
+            if !self.is_caught_up()? {
+                return Err(DB3Error::CellStoreRoleError(
+                    "the replica has not caught up with the primary".to_string(),
+                ));
+            }
+        } else if self.manifest_stale.load(Ordering::Acquire) {
+            let _flushing = self.flush_lock.lock().await;
+            if self.manifest_stale.load(Ordering::Acquire) {
+                self.load_manifest().await?;
+            }
+        }
+        self.get_memory_batch_snapshot()
+    }
+
+    /// a replica added to a running partition misses the rows in the memory of primary, it
+    /// has all the rows once the primary has flushed the batches before its first one
+    fn is_caught_up(&self) -> Result<bool> {
+        match self.lock_data.lock() {
+            Ok(guard) => Ok(guard.first_replica_sequence > 0
+                && guard.first_replica_sequence <= guard.flushed_sequence + 1),
+            Err(_) => Err(DB3Error::BaseBusyError("fail to obtain lock".to_string())),
+        }
+    }
+
+    /// write the records to binlog and memtable and return the sequence of the batch. the
+    /// primary gives the batch the next sequence and a replica takes the one given by the
+    /// primary. the batch with an id which has been applied is skipped, so the writer could retry
+    /// it safely
+    pub async fn put_records(
+        &self,
+        records: RowRecordBatch,
+        batch_id: u64,
+        sequence: u64,
+    ) -> Result<u64> {
+        if self.is_fenced() {
+            return Err(DB3Error::CellStoreFencedError);
+        }
+        if let Some(budget) = &self.config.memory_budget {
+            if budget.is_exceeded() {
+                return Err(DB3Error::CellStoreThrottledError(budget.get_used_bytes()));
+            }
+        }
+        let replica = self.is_replica();
+        if replica && sequence == 0 {
+            return Err(DB3Error::CellStoreRoleError(
+                "a replica takes the batches with the sequences of primary".to_string(),
+            ));
+        }
+        if !replica && sequence != 0 {
+            return Err(DB3Error::CellStoreRoleError(
+                "the primary gives the sequences of batches".to_string(),
+            ));
+        }
+        self.check_not_null(&records)?;
+        if self.config.durability == BinlogDurability::KNone {
+            return if let Ok(mut guard) = self.lock_data.lock() {
+                if self.is_fenced() {
+                    return Err(DB3Error::CellStoreFencedError);
+                }
+                let position = guard.next_position();
+                let sequence = match sequence {
+                    0 => self.sequence_counter.fetch_add(1, Ordering::Relaxed) + 1,
+                    sequence => sequence,
+                };
+                let record = BinlogRecord {
+                    records,
+                    batch_id,
+                    sequence,
+                    replica,
+                };
+                self.apply_records(&mut guard, record, position)
             } else {
                 Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()))
+            };
+        }
+        // the retried batch is not written to binlog again, a retry racing with the first try
+        // is written but skipped when it is applied. a replica skips the batches flushed by the
+        // primary
+        match self.lock_data.lock() {
+            Ok(guard) => {
+                if let Some(applied) = guard.applied_sequence(batch_id) {
+                    debug!("skip the applied batch {}", batch_id);
+                    return Ok(applied);
+                }
+                if replica && sequence <= guard.flushed_sequence {
+                    return Ok(sequence);
+                }
+            }
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+            }
+        }
+        let (sender, mut receiver) = oneshot::channel();
+        match self.pending_writes.lock() {
+            Ok(mut pending_writes) => {
+                // the batches are written to binlog in the order of the queue, so the sequences
+                // given by the primary grow with the positions
+                let sequence = match sequence {
+                    0 => self.sequence_counter.fetch_add(1, Ordering::Relaxed) + 1,
+                    sequence => sequence,
+                };
+                // encode row records to byte data
+                let data = encode_binlog_record(&records, batch_id, sequence, replica)?;
+                pending_writes.push(PendingWrite {
+                    data,
+                    records,
+                    batch_id,
+                    sequence,
+                    replica,
+                    done: sender,
+                })
+            }
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+            }
+        }
+        // the writers take the commit lock in order, the first one whose batch has not been
+        // written becomes the leader and writes all the pending batches as a group
+        let _leader = self.commit_lock.lock().await;
+        if let Ok(result) = receiver.try_recv() {
+            if result.is_err() && self.is_fenced() {
+                return Err(DB3Error::CellStoreFencedError);
+            }
+            return result.map_err(DB3Error::CellStoreBinlogError);
+        }
+        if self.config.durability == BinlogDurability::KGroupCommit {
+            // wait for more batches to join the group
+            sleep(Duration::from_millis(self.config.group_commit_interval_ms)).await;
+        }
+        self.commit_pending_writes().await;
+        match receiver.try_recv() {
+            Ok(Err(_)) if self.is_fenced() => Err(DB3Error::CellStoreFencedError),
+            Ok(result) => result.map_err(DB3Error::CellStoreBinlogError),
+            Err(_) => Err(DB3Error::CellStoreBinlogError(
+                "the batch is dropped by group commit".to_string(),
+            )),
+        }
+    }
+
+    /// the rows with null in not null columns are rejected before they reach the binlog
+    fn check_not_null(&self, records: &RowRecordBatch) -> Result<()> {
+        for (index, field) in self.config.schema.fields().iter().enumerate() {
+            if field.is_nullable() {
+                continue;
+            }
+            if records
+                .batch
+                .iter()
+                .any(|row| matches!(row.get(index), Some(Data::Null)))
+            {
+                return Err(DB3Error::TableColumnNotNullError {
+                    name: field.name().to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    async fn commit_pending_writes(&self) {
+        let pending_writes = match self.pending_writes.lock() {
+            Ok(mut pending_writes) => std::mem::take(&mut *pending_writes),
+            Err(_) => {
+                warn!("fail to obtain lock of pending writes");
+                return;
+            }
+        };
+        if pending_writes.is_empty() {
+            return;
+        }
+        // the rows must not be written after the cell has been flushed for moving
+        if self.is_fenced() {
+            for pending_write in pending_writes {
+                let _ = pending_write
+                    .done
+                    .send(Err("the cell store is fenced".to_string()));
+            }
+            return;
+        }
+        let group_size = pending_writes.len() as u64;
+        let mut senders = Vec::with_capacity(pending_writes.len());
+        let mut data_list = Vec::with_capacity(pending_writes.len());
+        let mut records_list = Vec::with_capacity(pending_writes.len());
+        for pending_write in pending_writes {
+            senders.push(pending_write.done);
+            data_list.push(pending_write.data);
+            records_list.push(BinlogRecord {
+                records: pending_write.records,
+                batch_id: pending_write.batch_id,
+                sequence: pending_write.sequence,
+                replica: pending_write.replica,
+            });
+        }
+        self.group_commit_metrics.add_group(group_size);
+        // save records to binlog before they can be seen in memtable. the write and fsync block,
+        // so they run out of the async workers
+        let lock_data = self.lock_data.clone();
+        let durability = self.config.durability;
+        let written = tokio::task::spawn_blocking(move || {
+            Self::write_binlog(&lock_data, &data_list, durability)
+        })
+        .await
+        .unwrap_or_else(|e| Err(DB3Error::CellStoreBinlogError(format!("{}", e))));
+        let results = match written {
+            Ok(data_size) => {
+                self.binlog_data_size
+                    .fetch_add(data_size, Ordering::Relaxed);
+                self.apply_group(records_list)
+            }
+            Err(e) => {
+                warn!("fail to write a group of {} batches for {}", group_size, e);
+                senders.iter().map(|_| Err(format!("{}", e))).collect()
+            }
+        };
+        for (sender, result) in senders.into_iter().zip(results) {
+            // the waiter may have gone
+            let _ = sender.send(result);
+        }
+    }
+
+    /// write the group to binlog and return the bytes written
+    fn write_binlog(
+        lock_data: &Mutex<CellStoreLockData>,
+        data_list: &[Vec<u8>],
+        durability: BinlogDurability,
+    ) -> Result<u64> {
+        if let Ok(mut guard) = lock_data.lock() {
+            let data_refs: Vec<&[u8]> = data_list.iter().map(|d| d.as_slice()).collect();
+            guard.log_writer.add_records(&data_refs)?;
+            if durability != BinlogDurability::KBuffered {
+                guard.log_writer.fsync()?;
+            }
+            Ok(data_list.iter().map(|d| d.len() as u64).sum())
+        } else {
+            Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()))
+        }
+    }
+
+    /// apply the batches written to binlog to the memtable, every batch gets its own result
+    fn apply_group(
+        &self,
+        records_list: Vec<BinlogRecord<RowRecordBatch>>,
+    ) -> Vec<std::result::Result<u64, String>> {
+        let mut guard = match self.lock_data.lock() {
+            Ok(guard) => guard,
+            Err(_) => {
+                return records_list
+                    .iter()
+                    .map(|_| Err("fail to obtain lock".to_string()))
+                    .collect();
+            }
+        };
+        // only the leader of group commit writes binlog, so the segment is not rotated between
+        // the write and the apply and the positions match the records in binlog
+        let results = records_list
+            .into_iter()
+            .map(|record| {
+                let position = guard.next_position();
+                self.apply_records(&mut guard, record, position)
+                    .map_err(|e| format!("{}", e))
+            })
+            .collect();
+        if guard.log_writer.get_file_size() as u64 >= self.config.binlog_segment_size {
+            // the records have been applied, the next group retries the rotation
+            if let Err(e) = self.rotate_binlog(&mut guard) {
+                warn!("fail to rotate binlog for {}", e);
+            }
+        }
+        results
+    }
+
+    /// apply a record to memtable and return the sequence of its batch, the batches written by
+    /// a replica wait in their own memtable until the primary flushes them
+    fn apply_records(
+        &self,
+        lock_data: &mut CellStoreLockData,
+        record: BinlogRecord<RowRecordBatch>,
+        position: LogPosition,
+    ) -> Result<u64> {
+        // the record of a retried batch keeps its position in binlog
+        if let Some(applied) = lock_data.applied_sequence(record.batch_id) {
+            lock_data.last_position = position;
+            return Ok(applied);
+        }
+        // the records written before the batches had sequences
+        let sequence = match record.sequence {
+            0 => lock_data.last_sequence + 1,
+            sequence => sequence,
+        };
+        if record.replica && lock_data.first_replica_sequence == 0 {
+            lock_data.first_replica_sequence = sequence;
+        }
+        if record.replica && sequence <= lock_data.flushed_sequence {
+            lock_data.last_position = position;
+            return Ok(sequence);
+        }
+        let records = record.records;
+        let size = records.batch.len();
+        if let Some(index) = self.config.range_column_index {
+            for row in records.batch.iter() {
+                if let Some(key) = row.get(index).and_then(partition_utils::data_to_range_key) {
+                    self.min_range_key.fetch_min(key, Ordering::Relaxed);
+                    self.max_range_key.fetch_max(key, Ordering::Relaxed);
+                }
             }
+        }
+        if record.replica {
+            let rows = LinkedList::new();
+            rows.push_front(records)?;
+            let batch = arrow_parquet_utils::rows_to_columns(&self.config.schema, &rows)?;
+            self.charge_memory(Self::get_batch_bytes(&batch));
+            lock_data
+                .replica_batches
+                .insert(sequence, (position, batch));
         } else {
-            Ok(())
+            // load a row memtable reference
+            let table = self.row_memtable.load();
+            table.push_front(records)?;
+            self.charge_memory(self.estimate_row_bytes(size as u64));
+            self.row_memtable_size
+                .fetch_add(size as u64, Ordering::Relaxed);
         }
+        let _ = self.unflushed_since_ms.compare_exchange(
+            0,
+            Self::now_ms(),
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        );
+        lock_data.last_position = position;
+        lock_data.last_sequence = lock_data.last_sequence.max(sequence);
+        lock_data.track_batch(record.batch_id, sequence);
+        self.total_rows_in_memory
+            .fetch_add(size as u64, Ordering::Relaxed);
+        self.do_l1_compaction_maybe(lock_data);
+        Ok(sequence)
+    }
+
+    fn do_l1_compaction_maybe(&self, lock_data: &mut CellStoreLockData) {
+        self.do_l1_compaction(lock_data, false);
     }
 
-    fn do_l1_compaction_maybe(&self) {
+    /// convert the row memtable to a column batch, force converts it whatever its size
+    fn do_l1_compaction(&self, lock_data: &mut CellStoreLockData, force: bool) {
         let local_row_memtable = self.row_memtable.load();
-        if self.row_memtable_size.load(Ordering::Acquire) as u32 >= self.config.l1_rows_limit {
+        let row_memtable_size = self.row_memtable_size.load(Ordering::Acquire);
+        if (force && row_memtable_size > 0) || row_memtable_size as u32 >= self.config.l1_rows_limit
+        {
             self.row_memtable.store(Arc::new(LinkedList::new()));
             self.row_memtable_size.store(0, Ordering::Relaxed);
+            self.release_memory(self.estimate_row_bytes(row_memtable_size));
             match arrow_parquet_utils::rows_to_columns(
                 &self.config.schema,
                 local_row_memtable.as_ref(),
             ) {
                 Ok(record_batch) => {
                     debug!("record batch row num {}", record_batch.num_rows());
+                    self.charge_memory(Self::get_batch_bytes(&record_batch));
                     // update size of memory table
                     self.column_memtable_size
                         .fetch_add(record_batch.num_rows() as u64, Ordering::Relaxed);
                     let local_column_memtable = self.column_memtable.load();
                     if local_column_memtable.push_front(record_batch).is_ok() {
+                        lock_data.column_memtable_position = lock_data.last_position;
+                        lock_data.column_memtable_sequence = lock_data.last_sequence;
                         debug!("compaction ok for cell store");
                     }
                 }
@@ -265,99 +1182,1127 @@ impl CellStore {
     }
 
     pub async fn do_l2_compaction(&self) -> Result<()> {
-        let local_column_memtable = self.column_memtable.load();
-        if self.column_memtable_size.load(Ordering::Acquire) as u32 >= self.config.l2_rows_limit {
-            self.column_memtable.store(Arc::new(LinkedList::new()));
-            let previous = self.column_memtable_size.swap(0, Ordering::Relaxed);
-            self.total_rows_in_memory
-                .fetch_sub(previous, Ordering::Relaxed);
-            // write record to local file
-            let tmp_dir = TempDir::new_in(&self.config.tmp_dir_path_prefix, "l2_compaction")
-                .expect("fail to create tmp dir for l2 compaction");
-            let file_path = tmp_dir.path().join("l2.parquet.gz");
-            if arrow_parquet_utils::dump_recordbatch(
-                &file_path,
-                local_column_memtable.as_ref(),
-                &self.config.schema,
-            )
-            .is_ok()
-            {
-                debug!("dump parquet to {} done", file_path.display());
-                let readable_str = strings::to_readable_num_str(
-                    self.parquet_file_counter.fetch_add(1, Ordering::Relaxed) as usize,
-                    8,
-                );
-                let object_key = format!(
-                    "{}/{}.gz.parquet",
-                    self.config.object_key_prefix, readable_str
-                );
-                debug!("plan to store file to {}", object_key);
-                self.bucket_fs
-                    .put_with_file(&file_path, &object_key)
-                    .await?;
+        self.l2_compaction(false).await
+    }
+
+    /// whether the column memtable reaches the l2 rows limit
+    pub fn is_l2_compaction_needed(&self) -> bool {
+        self.column_memtable_size.load(Ordering::Relaxed) as u32 >= self.config.l2_rows_limit
+    }
+
+    /// flush all the rows in memory to object storage whatever the limits
+    pub async fn flush(&self) -> Result<()> {
+        match self.lock_data.lock() {
+            Ok(mut guard) => self.do_l1_compaction(&mut guard, true),
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
             }
         }
-        Ok(())
+        self.l2_compaction(true).await
     }
 
-    #[inline(always)]
-    pub fn get_total_rows_in_memory(&self) -> u64 {
-        self.total_rows_in_memory.load(Ordering::Relaxed)
+    /// stop accepting records and flush all the rows in memory to object storage whatever the
+    /// limits, it is used before the partition moves to another node
+    pub async fn fence_and_flush(&self) -> Result<()> {
+        self.fenced.store(true, Ordering::Release);
+        // wait for the group which is being written
+        let _leader = self.commit_lock.lock().await;
+        self.flush().await
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::codec::row_codec::Data;
-    use arrow::datatypes::Schema;
-    use arrow::datatypes::*;
 
-    use crate::store::object_store::build_credentials;
-    #[test]
-    fn test_invalid_config() {
-        let valid_schema = Arc::new(Schema::new(vec![Field::new("c1", DataType::Int64, true)]));
-        let auth = Credentials::from_env_specific(
-            Some("AWS_ACCESS_KEY_ID"),
-            Some("AWS_SECRET_ACCESS_KEY"),
-            None,
-            None,
-        )
-        .unwrap();
-        let bucket_name = "test_bk";
-        let region = Region::Custom {
-            region: "".to_string(),
-            endpoint: "http://127.0.0.1:9000".to_string(),
-        };
+    fn now_ms() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0)
+    }
 
-        let schema = Arc::new(Schema::empty());
-        let local_binlog_path_prefix = "/test/binlog";
-        let tmp_dir_path_prefix = "/test/tmp";
-        let object_key_prefix = "/test/object";
-        if CellStoreConfig::new(
-            bucket_name,
-            region.clone(),
-            &schema,
-            local_binlog_path_prefix,
-            auth.clone(),
-            tmp_dir_path_prefix,
-            object_key_prefix,
-            false,
-        )
-        .is_ok()
-        {
-            panic!("should has some config error");
+    /// the age in millisecond of the oldest row in memory, 0 if there is no row in memory
+    pub fn get_unflushed_age_ms(&self) -> u64 {
+        match self.unflushed_since_ms.load(Ordering::Relaxed) {
+            0 => 0,
+            since => Self::now_ms().saturating_sub(since).max(1),
         }
+    }
 
-        if CellStoreConfig::new(
-            "",
+    /// accept records again after a failed move
+    pub fn unfence(&self) {
+        self.fenced.store(false, Ordering::Release);
+    }
+
+    #[inline(always)]
+    pub fn is_fenced(&self) -> bool {
+        self.fenced.load(Ordering::Acquire)
+    }
+
+    async fn l2_compaction(&self, force: bool) -> Result<()> {
+        let _flushing = self.flush_lock.lock().await;
+        // the primary flushes the same rows, a replica drops them after they are in its manifest
+        if self.is_replica() {
+            return self.trim_flushed_batches().await.map(|_| ());
+        }
+        // the manifest may drop a committed pending flush, so it is loaded before the snapshot
+        if self.manifest_stale.load(Ordering::Acquire) {
+            self.load_manifest().await?;
+        }
+        let column_memtable_size = self.column_memtable_size.load(Ordering::Acquire);
+        // move the column memtable to flushing memtable with lock to get the binlog position of
+        // its last record, the batches of a failed flush are flushed again
+        let (flushing_memtable, flushing_rows, flushed_position, flushed_sequence) =
+            match self.lock_data.lock() {
+                Ok(mut guard) => {
+                    if guard.flushing_rows == 0
+                        && (column_memtable_size == 0
+                            || (!force
+                                && (column_memtable_size as u32) < self.config.l2_rows_limit))
+                    {
+                        return Ok(());
+                    }
+                    let table = self.column_memtable.swap(Arc::new(LinkedList::new()));
+                    let size = self.column_memtable_size.swap(0, Ordering::Relaxed);
+                    if size > 0 {
+                        let flushing = LinkedList::new();
+                        for batch in guard.flushing_memtable.iter().chain(table.iter()) {
+                            flushing.push_front(batch.clone())?;
+                        }
+                        guard.flushing_memtable = Arc::new(flushing);
+                        guard.flushing_rows += size;
+                        guard.flushing_position = guard.column_memtable_position;
+                        guard.flushing_sequence = guard.column_memtable_sequence;
+                    }
+                    (
+                        guard.flushing_memtable.clone(),
+                        guard.flushing_rows,
+                        guard.flushing_position,
+                        guard.flushing_sequence,
+                    )
+                }
+                Err(_) => {
+                    return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+                }
+            };
+        let file_sequence = self.parquet_file_counter.load(Ordering::Acquire);
+        // write record to local file
+        let tmp_dir = TempDir::new_in(&self.config.tmp_dir_path_prefix, "l2_compaction")?;
+        let file_path = tmp_dir.path().join(arrow_parquet_utils::parquet_file_name(
+            file_sequence,
+            self.file_compression(),
+        ));
+        arrow_parquet_utils::dump_recordbatch(
+            &file_path,
+            flushing_memtable.as_ref(),
+            &self.config.schema,
+            &self.config.writer_properties,
+        )?;
+        debug!("dump parquet to {} done", file_path.display());
+        let object_key = Self::parquet_object_key(
+            &self.config.object_key_prefix,
+            file_sequence,
+            self.file_compression(),
+        );
+        debug!("plan to store file to {}", object_key);
+        self.bucket_fs
+            .put_with_file(&file_path, &object_key)
+            .await?;
+        let file_status = self.build_file_status(
+            file_sequence,
+            &object_key,
+            flushing_memtable.iter(),
+            flushing_rows,
+            &file_path,
+        )?;
+        // a crash after the manifest is put must not replay the rows of the file
+        self.save_checkpoint(&self.build_checkpoint(Some(PendingFlush {
+            position: flushed_position,
+            file_sequence,
+            sequence: flushed_sequence,
+        }))?)?;
+        // the file is committed once it is in the manifest, the replicas drop the rows of the
+        // batches up to the flushed sequence in it
+        let new_manifest = self
+            .put_manifest(Some(file_status), &[], flushed_sequence)
+            .await?;
+        // commit the file and drop the flushed batches at the same moment for scans
+        match self.lock_data.lock() {
+            Ok(mut guard) => {
+                guard.flushing_memtable = Arc::new(LinkedList::new());
+                guard.flushing_rows = 0;
+                guard.flushed_position = flushed_position;
+                guard.flushed_sequence = flushed_sequence;
+                guard.pending_flush = None;
+                // the age is reset only after the rows are durable. the rows left in memory keep
+                // the old time, so the age may be a bit larger than the real one
+                if self.row_memtable_size.load(Ordering::Relaxed) == 0
+                    && self.column_memtable_size.load(Ordering::Relaxed) == 0
+                {
+                    self.unflushed_since_ms.store(0, Ordering::Relaxed);
+                }
+                guard.manifest = new_manifest;
+                self.parquet_file_counter
+                    .store(file_sequence + 1, Ordering::Release);
+            }
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+            }
+        }
+        self.total_rows_in_memory
+            .fetch_sub(flushing_rows, Ordering::Relaxed);
+        self.release_memory(
+            flushing_memtable
+                .iter()
+                .map(Self::get_batch_bytes)
+                .sum::<u64>(),
+        );
+        self.total_rows_on_external_storage
+            .fetch_add(flushing_rows, Ordering::Relaxed);
+        // the rows are durable in object storage, they will not be replayed from binlog
+        self.save_checkpoint(&self.build_checkpoint(None)?)?;
+        self.remove_obsolete_segments(flushed_position.log_number)?;
+        Ok(())
+    }
+
+    fn build_file_status<'a>(
+        &self,
+        file_sequence: u64,
+        object_key: &str,
+        batches: impl Iterator<Item = &'a RecordBatch>,
+        num_rows: u64,
+        file_path: &Path,
+    ) -> Result<CellStatus> {
+        let (start_ts, end_ts) = arrow_parquet_utils::get_time_column_index(&self.config.schema)
+            .and_then(|index| arrow_parquet_utils::get_time_range(batches, index))
+            .unwrap_or((0, 0));
+        Ok(CellStatus {
+            start_ts,
+            end_ts,
+            num_rows: num_rows as i64,
+            cell_id: file_sequence as i32,
+            partition_id: 0,
+            display: self.config.object_key_prefix.to_string(),
+            cell_store_uri: format!("{}/{}", self.config.bucket_name, object_key),
+            ctype: CellType::KReadOnly as i32,
+            size_in_bytes: fs::metadata(file_path)?.len() as i64,
+        })
+    }
+
+    /// write a manifest with the new file and without the removed ones to object storage, the
+    /// flushed sequence only grows and 0 keeps it. the caller installs the returned manifest
+    async fn put_manifest(
+        &self,
+        file_status: Option<CellStatus>,
+        removed_sequences: &[i32],
+        flushed_sequence: u64,
+    ) -> Result<CellManifest> {
+        let mut manifest = match self.lock_data.lock() {
+            Ok(guard) => guard.manifest.clone(),
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+            }
+        };
+        // the removed files are deleted after the scans reading them finish
+        let now_ms = Self::now_ms();
+        let (removed, files): (Vec<CellStatus>, Vec<CellStatus>) = manifest
+            .files
+            .into_iter()
+            .partition(|f| removed_sequences.contains(&f.cell_id));
+        manifest.files = files;
+        for file in removed.iter() {
+            manifest.obsolete_files.push(ObsoleteFile {
+                object_key: self.object_key_of(file),
+                obsolete_ms: now_ms,
+            });
+        }
+        if let Some(file_status) = file_status {
+            manifest.file_sequence = manifest.file_sequence.max(file_status.cell_id as u64 + 1);
+            manifest.files.push(file_status);
+        }
+        manifest.flushed_sequence = manifest.flushed_sequence.max(flushed_sequence);
+        self.bucket_fs
+            .put_object(&self.manifest_key(), &manifest.encode_to_vec())
+            .await?;
+        Ok(manifest)
+    }
+
+    /// merge the small parquet files of cell into a time sorted one and replace them in the
+    /// manifest, the object keys of the replaced files are returned
+    pub async fn merge_small_files(
+        &self,
+        small_file_bytes: u64,
+        min_files: usize,
+        target_bytes: u64,
+    ) -> Result<Vec<String>> {
+        if self.is_replica() {
+            return Ok(Vec::new());
+        }
+        if matches!(&self.config.memory_budget, Some(budget) if budget.is_exceeded()) {
+            return Ok(Vec::new());
+        }
+        // only picking the files and swapping the manifest hold the flush lock, so the flushes
+        // are not blocked by the merge
+        let (candidates, file_sequence) = {
+            let _flushing = self.flush_lock.lock().await;
+            if self.manifest_stale.load(Ordering::Acquire) {
+                self.load_manifest().await?;
+            }
+            let files = match self.lock_data.lock() {
+                Ok(guard) => guard.manifest.files.clone(),
+                Err(_) => {
+                    return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+                }
+            };
+            let mut candidates: Vec<CellStatus> = Vec::new();
+            let mut total_bytes: u64 = 0;
+            for file in files
+                .into_iter()
+                .filter(|f| (f.size_in_bytes as u64) < small_file_bytes)
+            {
+                if total_bytes >= target_bytes {
+                    break;
+                }
+                total_bytes += file.size_in_bytes as u64;
+                candidates.push(file);
+            }
+            if candidates.len() < min_files.max(2) {
+                return Ok(Vec::new());
+            }
+            // the sequence is taken now for the flushes during the merge
+            let file_sequence = self.parquet_file_counter.fetch_add(1, Ordering::AcqRel);
+            (candidates, file_sequence)
+        };
+        let object_keys: Vec<String> = candidates.iter().map(|f| self.object_key_of(f)).collect();
+        let tmp_dir = TempDir::new_in(&self.config.tmp_dir_path_prefix, "merge_compaction")?;
+        // the batches being merged are charged to the memory budget of node
+        let mut charge = MemoryCharge::new(self.config.memory_budget.clone());
+        let mut batches: Vec<RecordBatch> = Vec::new();
+        for (i, object_key) in object_keys.iter().enumerate() {
+            let data = self.bucket_fs.download(object_key).await?;
+            let path = tmp_dir.path().join(format!("{}.parquet", i));
+            fs::write(&path, data)?;
+            let mut file_batches = arrow_parquet_utils::read_recordbatch(&path)?;
+            charge.charge(file_batches.iter().map(Self::get_batch_bytes).sum::<u64>());
+            batches.append(&mut file_batches);
+        }
+        let merged = arrow_parquet_utils::merge_and_sort_batches(
+            &self.config.schema,
+            &batches,
+            arrow_parquet_utils::get_time_column_index(&self.config.schema),
+        )?;
+        charge.charge(Self::get_batch_bytes(&merged));
+        let num_rows = merged.num_rows() as u64;
+        let merged_batches = LinkedList::new();
+        merged_batches.push_front(merged)?;
+        let file_path = tmp_dir.path().join(arrow_parquet_utils::parquet_file_name(
+            file_sequence,
+            self.file_compression(),
+        ));
+        arrow_parquet_utils::dump_recordbatch(
+            &file_path,
+            &merged_batches,
+            &self.config.schema,
+            &self.config.writer_properties,
+        )?;
+        let object_key = Self::parquet_object_key(
+            &self.config.object_key_prefix,
+            file_sequence,
+            self.file_compression(),
+        );
+        self.bucket_fs
+            .put_with_file(&file_path, &object_key)
+            .await?;
+        let file_status = self.build_file_status(
+            file_sequence,
+            &object_key,
+            merged_batches.iter(),
+            num_rows,
+            &file_path,
+        )?;
+        let removed: Vec<i32> = candidates.iter().map(|f| f.cell_id).collect();
+        let _flushing = self.flush_lock.lock().await;
+        // the files may be expired or the cell may be a replica during the merge
+        let unchanged = !self.is_replica()
+            && !self.manifest_stale.load(Ordering::Acquire)
+            && match self.lock_data.lock() {
+                Ok(guard) => removed
+                    .iter()
+                    .all(|id| guard.manifest.files.iter().any(|f| f.cell_id == *id)),
+                Err(_) => {
+                    return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+                }
+            };
+        if !unchanged {
+            info!(
+                "drop the merged file {} for the manifest of {} changed",
+                &object_key, &self.config.object_key_prefix
+            );
+            self.bucket_fs.delete_object(&object_key).await?;
+            return Ok(Vec::new());
+        }
+        let manifest = self.put_manifest(Some(file_status), &removed, 0).await?;
+        match self.lock_data.lock() {
+            Ok(mut guard) => guard.manifest = manifest,
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+            }
+        }
+        info!(
+            "merge {} files of {} into {}",
+            removed.len(),
+            &self.config.object_key_prefix,
+            &object_key
+        );
+        Ok(object_keys)
+    }
+
+    /// evict the expired rows from column memtable and drop the expired parquet files from the
+    /// manifest, the object keys of the dropped files are returned
+    pub async fn expire_data(&self) -> Result<Vec<String>> {
+        let index = match arrow_parquet_utils::get_time_column_index(&self.config.schema) {
+            Some(index) if self.get_retention_ms() > 0 => index,
+            _ => return Ok(Vec::new()),
+        };
+        let min_time_ms = Self::now_ms().saturating_sub(self.get_retention_ms()) as i64;
+        let _flushing = self.flush_lock.lock().await;
+        self.evict_expired_rows(index, min_time_ms)?;
+        if self.is_replica() {
+            return Ok(Vec::new());
+        }
+        if self.manifest_stale.load(Ordering::Acquire) {
+            self.load_manifest().await?;
+        }
+        let min_ts = arrow_parquet_utils::timestamp_from_ms(
+            self.config.schema.field(index).data_type(),
+            min_time_ms,
+        );
+        let expired: Vec<CellStatus> = match self.lock_data.lock() {
+            // the files without any time are kept
+            Ok(guard) => guard
+                .manifest
+                .files
+                .iter()
+                .filter(|f| f.end_ts < min_ts && !(f.start_ts == 0 && f.end_ts == 0))
+                .cloned()
+                .collect(),
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+            }
+        };
+        if expired.is_empty() {
+            return Ok(Vec::new());
+        }
+        let removed: Vec<i32> = expired.iter().map(|f| f.cell_id).collect();
+        let manifest = self.put_manifest(None, &removed, 0).await?;
+        match self.lock_data.lock() {
+            Ok(mut guard) => guard.manifest = manifest,
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+            }
+        }
+        let expired_rows: u64 = expired.iter().map(|f| f.num_rows as u64).sum();
+        let _ = self.total_rows_on_external_storage.fetch_update(
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+            |rows| Some(rows.saturating_sub(expired_rows)),
+        );
+        info!(
+            "drop {} expired files of {}",
+            expired.len(),
+            &self.config.object_key_prefix
+        );
+        Ok(expired.iter().map(|f| self.object_key_of(f)).collect())
+    }
+
+    /// drop the expired rows in column memtable, the rows in row memtable are dropped after
+    /// they are converted to columns
+    fn evict_expired_rows(&self, index: usize, min_time_ms: i64) -> Result<()> {
+        let evicted = match self.lock_data.lock() {
+            Ok(mut guard) => {
+                // the rows of binlog replayed after a restart are evicted with the watermark
+                guard.expired_before_ms = guard.expired_before_ms.max(min_time_ms);
+                self.evict_column_memtable(index, min_time_ms)?
+                    + self.evict_replica_batches(&mut guard, index, min_time_ms)?
+            }
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+            }
+        };
+        if evicted > 0 {
+            self.save_checkpoint(&self.build_checkpoint(None)?)?;
+        }
+        Ok(())
+    }
+
+    /// drop the rows older than the time from column memtable with lock data, the count of
+    /// evicted rows is returned
+    fn evict_column_memtable(&self, index: usize, min_time_ms: i64) -> Result<u64> {
+        let table = self.column_memtable.load_full();
+        let mut batches: Vec<RecordBatch> = Vec::new();
+        let mut evicted_rows: u64 = 0;
+        for batch in table.iter() {
+            let kept = arrow_parquet_utils::filter_batch_by_time(batch, index, min_time_ms)?;
+            evicted_rows += (batch.num_rows() - kept.num_rows()) as u64;
+            batches.push(kept);
+        }
+        if evicted_rows == 0 {
+            return Ok(0);
+        }
+        let kept_table = LinkedList::new();
+        // keep the order of batches
+        for batch in batches.into_iter().rev() {
+            if batch.num_rows() > 0 {
+                kept_table.push_front(batch)?;
+            }
+        }
+        self.release_memory(table.iter().map(Self::get_batch_bytes).sum::<u64>());
+        self.charge_memory(kept_table.iter().map(Self::get_batch_bytes).sum::<u64>());
+        self.column_memtable.store(Arc::new(kept_table));
+        self.column_memtable_size
+            .fetch_sub(evicted_rows, Ordering::Relaxed);
+        self.total_rows_in_memory
+            .fetch_sub(evicted_rows, Ordering::Relaxed);
+        debug!(
+            "evict {} expired rows of {}",
+            evicted_rows, &self.config.object_key_prefix
+        );
+        Ok(evicted_rows)
+    }
+
+    /// drop the rows older than the time from the batches of a replica, the count of evicted
+    /// rows is returned
+    fn evict_replica_batches(
+        &self,
+        lock_data: &mut CellStoreLockData,
+        index: usize,
+        min_time_ms: i64,
+    ) -> Result<u64> {
+        let mut evicted_rows: u64 = 0;
+        let mut emptied: Vec<u64> = Vec::new();
+        for (sequence, (_, batch)) in lock_data.replica_batches.iter_mut() {
+            let kept = arrow_parquet_utils::filter_batch_by_time(batch, index, min_time_ms)?;
+            if kept.num_rows() == batch.num_rows() {
+                continue;
+            }
+            evicted_rows += (batch.num_rows() - kept.num_rows()) as u64;
+            self.release_memory(Self::get_batch_bytes(batch));
+            self.charge_memory(Self::get_batch_bytes(&kept));
+            if kept.num_rows() == 0 {
+                emptied.push(*sequence);
+            }
+            *batch = kept;
+        }
+        for sequence in emptied {
+            lock_data.replica_batches.remove(&sequence);
+        }
+        self.total_rows_in_memory
+            .fetch_sub(evicted_rows, Ordering::Relaxed);
+        Ok(evicted_rows)
+    }
+
+    /// the object key of a committed parquet file
+    fn object_key_of(&self, file: &CellStatus) -> String {
+        let bucket_prefix = format!("{}/", self.config.bucket_name);
+        file.cell_store_uri
+            .strip_prefix(&bucket_prefix)
+            .unwrap_or(&file.cell_store_uri)
+            .to_string()
+    }
+
+    /// delete the objects dropped from the manifest for longer than the ttl and forget them,
+    /// the obsolete files kept in the manifest are collected after a restart as well
+    pub async fn collect_obsolete_files(&self, ttl_ms: u64) -> Result<usize> {
+        if self.is_replica() || self.manifest_stale.load(Ordering::Acquire) {
+            return Ok(0);
+        }
+        let deadline_ms = Self::now_ms().saturating_sub(ttl_ms);
+        let object_keys: Vec<String> = match self.lock_data.lock() {
+            Ok(guard) => guard
+                .manifest
+                .obsolete_files
+                .iter()
+                .filter(|f| f.obsolete_ms <= deadline_ms)
+                .map(|f| f.object_key.to_string())
+                .collect(),
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+            }
+        };
+        if object_keys.is_empty() {
+            return Ok(0);
+        }
+        // a crash before the manifest is put just deletes them again
+        for object_key in object_keys.iter() {
+            self.bucket_fs.delete_object(object_key).await?;
+        }
+        let _flushing = self.flush_lock.lock().await;
+        let mut manifest = match self.lock_data.lock() {
+            Ok(guard) => guard.manifest.clone(),
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+            }
+        };
+        manifest
+            .obsolete_files
+            .retain(|f| !object_keys.contains(&f.object_key));
+        self.bucket_fs
+            .put_object(&self.manifest_key(), &manifest.encode_to_vec())
+            .await?;
+        match self.lock_data.lock() {
+            Ok(mut guard) => guard.manifest = manifest,
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+            }
+        }
+        Ok(object_keys.len())
+    }
+
+    fn manifest_key(&self) -> String {
+        format!("{}/{}", self.config.object_key_prefix, MANIFEST_FILE_NAME)
+    }
+
+    /// read the manifest of cell from object storage, it is empty if nothing has been flushed
+    pub async fn read_manifest(&self) -> Result<CellManifest> {
+        self.read_manifest_of(&self.config.object_key_prefix).await
+    }
+
+    async fn read_manifest_of(&self, object_key_prefix: &str) -> Result<CellManifest> {
+        Ok(self
+            .get_manifest_of(object_key_prefix)
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// none if the cell has no manifest
+    async fn get_manifest_of(&self, object_key_prefix: &str) -> Result<Option<CellManifest>> {
+        let key = format!("{}/{}", object_key_prefix, MANIFEST_FILE_NAME);
+        match self.bucket_fs.get_object(&key).await? {
+            Some(data) => CellManifest::decode(data.as_slice())
+                .map(Some)
+                .map_err(|e| {
+                    DB3Error::CellStoreManifestError(format!("bad manifest {} for {}", &key, e))
+                }),
+            None => Ok(None),
+        }
+    }
+
+    /// the files uploaded before the cells kept manifests are only found by listing, they are
+    /// put to a new manifest once so the scans go on reading them
+    async fn seed_manifest_from_listing(&self) -> Result<CellManifest> {
+        let prefix = format!("{}/", self.config.object_key_prefix);
+        let mut keys: Vec<(u64, String)> = self
+            .bucket_fs
+            .list_keys(&prefix)
+            .await?
+            .into_iter()
+            .filter_map(|key| Self::parquet_file_sequence(&key).map(|seq| (seq, key)))
+            .collect();
+        if keys.is_empty() {
+            return Ok(CellManifest::default());
+        }
+        keys.sort_unstable();
+        let tmp_dir = TempDir::new_in(&self.config.tmp_dir_path_prefix, "seed_manifest")?;
+        let mut manifest = CellManifest::default();
+        for (file_sequence, object_key) in keys {
+            let file_path = tmp_dir.path().join(format!("{}.parquet", file_sequence));
+            fs::write(&file_path, self.bucket_fs.download(&object_key).await?)?;
+            let batches = arrow_parquet_utils::read_recordbatch(&file_path)?;
+            let num_rows = batches.iter().map(|b| b.num_rows() as u64).sum();
+            manifest.files.push(self.build_file_status(
+                file_sequence,
+                &object_key,
+                batches.iter(),
+                num_rows,
+                &file_path,
+            )?);
+            manifest.file_sequence = file_sequence + 1;
+        }
+        self.bucket_fs
+            .put_object(&self.manifest_key(), &manifest.encode_to_vec())
+            .await?;
+        info!(
+            "seed the manifest of {} with {} listed files",
+            &self.config.object_key_prefix,
+            manifest.files.len()
+        );
+        Ok(manifest)
+    }
+
+    /// load the manifest written by the last primary of cell
+    pub async fn load_manifest(&self) -> Result<()> {
+        let manifest = match self.get_manifest_of(&self.config.object_key_prefix).await? {
+            Some(manifest) => manifest,
+            None => self.seed_manifest_from_listing().await?,
+        };
+        let (committed, (trimmed_rows, trimmed_bytes)) = match self.lock_data.lock() {
+            Ok(mut guard) => {
+                // a promoted replica continues the sequence of the old primary
+                self.parquet_file_counter
+                    .fetch_max(manifest.file_sequence, Ordering::AcqRel);
+                let committed = match guard.pending_flush.take() {
+                    Some(pending) => {
+                        let object_key = Self::parquet_object_key(
+                            &self.config.object_key_prefix,
+                            pending.file_sequence,
+                            self.file_compression(),
+                        );
+                        manifest
+                            .files
+                            .iter()
+                            .any(|f| self.object_key_of(f) == object_key)
+                            .then(|| {
+                                let flushing = std::mem::replace(
+                                    &mut guard.flushing_memtable,
+                                    Arc::new(LinkedList::new()),
+                                );
+                                let rows = std::mem::take(&mut guard.flushing_rows);
+                                guard.flushed_position = pending.position;
+                                guard.flushed_sequence =
+                                    guard.flushed_sequence.max(pending.sequence);
+                                if self.row_memtable_size.load(Ordering::Relaxed) == 0
+                                    && self.column_memtable_size.load(Ordering::Relaxed) == 0
+                                {
+                                    self.unflushed_since_ms.store(0, Ordering::Relaxed);
+                                }
+                                (flushing, rows, pending.position)
+                            })
+                    }
+                    None => None,
+                };
+                // the next batches get the sequences after the ones in the files
+                self.sequence_counter
+                    .fetch_max(manifest.flushed_sequence, Ordering::Relaxed);
+                guard.last_sequence = guard.last_sequence.max(manifest.flushed_sequence);
+                let trimmed = self.trim_replica_batches(&mut guard, manifest.flushed_sequence);
+                // the batches replayed from the binlog written as a replica
+                if !self.is_replica() {
+                    self.absorb_replica_batches(&mut guard)?;
+                }
+                guard.manifest = manifest;
+                self.manifest_stale.store(false, Ordering::Release);
+                (committed, trimmed)
+            }
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+            }
+        };
+        // the file of the pending flush was committed before the crash, its replayed rows are
+        // dropped as a flush does
+        if let Some((flushing_memtable, flushing_rows, flushed_position)) = committed {
+            info!(
+                "drop {} replayed rows of {} committed before the crash",
+                flushing_rows, &self.config.object_key_prefix
+            );
+            self.total_rows_in_memory
+                .fetch_sub(flushing_rows, Ordering::Relaxed);
+            self.release_memory(
+                flushing_memtable
+                    .iter()
+                    .map(Self::get_batch_bytes)
+                    .sum::<u64>(),
+            );
+            self.total_rows_on_external_storage
+                .fetch_add(flushing_rows, Ordering::Relaxed);
+            self.save_checkpoint(&self.build_checkpoint(None)?)?;
+            self.remove_obsolete_segments(flushed_position.log_number)?;
+        }
+        if trimmed_rows > 0 {
+            self.release_trimmed_batches(trimmed_rows, trimmed_bytes)?;
+        }
+        Ok(())
+    }
+
+    /// make a replica the primary of cell. the manifest of the old primary is loaded before, so
+    /// the promoted cell flushes only the rows not in its files and gives the next batches the
+    /// sequences after them
+    pub async fn promote(&self) -> Result<()> {
+        let _flushing = self.flush_lock.lock().await;
+        self.load_manifest().await?;
+        match self.lock_data.lock() {
+            Ok(mut guard) => {
+                self.absorb_replica_batches(&mut guard)?;
+                self.replica.store(false, Ordering::Relaxed);
+            }
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// drop the rows of a replica which the primary has flushed, the count of dropped rows is
+    /// returned
+    pub async fn trim_replica(&self) -> Result<u64> {
+        let _flushing = self.flush_lock.lock().await;
+        if !self.is_replica() {
+            return Ok(0);
+        }
+        self.trim_flushed_batches().await
+    }
+
+    /// the manifest of primary tells the sequence of the last batch in its files, it runs with
+    /// flush lock
+    async fn trim_flushed_batches(&self) -> Result<u64> {
+        let manifest = match self.get_manifest_of(&self.config.object_key_prefix).await? {
+            Some(manifest) => manifest,
+            // the primary has flushed nothing
+            None => return Ok(0),
+        };
+        let (rows, bytes) = match self.lock_data.lock() {
+            Ok(mut guard) => {
+                let trimmed = self.trim_replica_batches(&mut guard, manifest.flushed_sequence);
+                guard.manifest = manifest;
+                trimmed
+            }
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+            }
+        };
+        if rows > 0 {
+            self.release_trimmed_batches(rows, bytes)?;
+        }
+        Ok(rows)
+    }
+
+    /// drop the replica batches up to the flushed sequence of primary, the rows and bytes
+    /// dropped are returned
+    fn trim_replica_batches(
+        &self,
+        lock_data: &mut CellStoreLockData,
+        flushed_sequence: u64,
+    ) -> (u64, u64) {
+        if flushed_sequence <= lock_data.flushed_sequence {
+            return (0, 0);
+        }
+        lock_data.flushed_sequence = flushed_sequence;
+        let kept = lock_data.replica_batches.split_off(&(flushed_sequence + 1));
+        let trimmed = std::mem::replace(&mut lock_data.replica_batches, kept);
+        // the records before the oldest batch kept have been flushed by the primary, unless
+        // the cell keeps the rows written as a primary
+        if self.row_memtable_size.load(Ordering::Relaxed) == 0
+            && self.column_memtable_size.load(Ordering::Relaxed) == 0
+            && lock_data.flushing_rows == 0
+        {
+            let position = match lock_data.replica_batches.values().map(|(p, _)| *p).min() {
+                Some(p) => LogPosition {
+                    log_number: p.log_number,
+                    record_offset: p.record_offset - 1,
+                },
+                None => {
+                    self.unflushed_since_ms.store(0, Ordering::Relaxed);
+                    lock_data.last_position
+                }
+            };
+            lock_data.flushed_position = lock_data.flushed_position.max(position);
+        }
+        (
+            trimmed.values().map(|(_, b)| b.num_rows() as u64).sum(),
+            trimmed
+                .values()
+                .map(|(_, b)| Self::get_batch_bytes(b))
+                .sum(),
+        )
+    }
+
+    /// give back the memory of the trimmed batches and save the new flushed position
+    fn release_trimmed_batches(&self, rows: u64, bytes: u64) -> Result<()> {
+        self.total_rows_in_memory.fetch_sub(rows, Ordering::Relaxed);
+        self.release_memory(bytes);
+        self.total_rows_on_external_storage
+            .fetch_add(rows, Ordering::Relaxed);
+        let checkpoint = self.build_checkpoint(None)?;
+        self.save_checkpoint(&checkpoint)?;
+        self.remove_obsolete_segments(checkpoint.flushed_position.log_number)
+    }
+
+    /// move the batches kept as a replica to the column memtable, so the promoted cell flushes
+    /// the rows which the old primary has not flushed
+    fn absorb_replica_batches(&self, lock_data: &mut CellStoreLockData) -> Result<()> {
+        if lock_data.replica_batches.is_empty() {
+            return Ok(());
+        }
+        self.do_l1_compaction(lock_data, true);
+        let table = self.column_memtable.load();
+        let mut rows: u64 = 0;
+        for (_, (_, batch)) in std::mem::take(&mut lock_data.replica_batches) {
+            rows += batch.num_rows() as u64;
+            table.push_front(batch)?;
+        }
+        self.column_memtable_size.fetch_add(rows, Ordering::Relaxed);
+        lock_data.column_memtable_position = lock_data.last_position;
+        lock_data.column_memtable_sequence = lock_data.last_sequence;
+        Ok(())
+    }
+
+    /// the checkpoint of the current state, it is saved with flush lock
+    fn build_checkpoint(&self, pending_flush: Option<PendingFlush>) -> Result<CellCheckpoint> {
+        match self.lock_data.lock() {
+            Ok(guard) => Ok(CellCheckpoint {
+                flushed_position: guard.flushed_position,
+                parquet_file_counter: self.parquet_file_counter.load(Ordering::Relaxed),
+                flushed_rows: self.total_rows_on_external_storage.load(Ordering::Relaxed),
+                pending_flush,
+                expired_before_ms: guard.expired_before_ms,
+                flushed_sequence: guard.flushed_sequence,
+            }),
+            Err(_) => Err(DB3Error::BaseBusyError("fail to obtain lock".to_string())),
+        }
+    }
+
+    /// take over the files of a sealed cell with the object key prefix, so they are scanned with
+    /// this cell after the sealed one is dropped. the files keep their object keys and get the
+    /// sequences of this cell, the files taken over already are skipped
+    pub async fn inherit_files(&self, sealed_object_key_prefix: &str) -> Result<usize> {
+        if self.is_replica() {
+            return Err(DB3Error::CellStoreManifestError(
+                "a replica can not change the manifest".to_string(),
+            ));
+        }
+        let _flushing = self.flush_lock.lock().await;
+        if self.manifest_stale.load(Ordering::Acquire) {
+            self.load_manifest().await?;
+        }
+        let sealed_manifest = self.read_manifest_of(sealed_object_key_prefix).await?;
+        let mut manifest = match self.lock_data.lock() {
+            Ok(guard) => guard.manifest.clone(),
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+            }
+        };
+        let mut inherited_rows: u64 = 0;
+        let mut count: usize = 0;
+        for mut file in sealed_manifest.files {
+            if manifest
+                .files
+                .iter()
+                .any(|f| f.cell_store_uri == file.cell_store_uri)
+            {
+                continue;
+            }
+            file.cell_id = self.parquet_file_counter.fetch_add(1, Ordering::AcqRel) as i32;
+            manifest.file_sequence = file.cell_id as u64 + 1;
+            inherited_rows += file.num_rows as u64;
+            manifest.files.push(file);
+            count += 1;
+        }
+        if count == 0 {
+            return Ok(0);
+        }
+        self.bucket_fs
+            .put_object(&self.manifest_key(), &manifest.encode_to_vec())
+            .await?;
+        match self.lock_data.lock() {
+            Ok(mut guard) => guard.manifest = manifest,
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+            }
+        }
+        self.total_rows_on_external_storage
+            .fetch_add(inherited_rows, Ordering::Relaxed);
+        Ok(count)
+    }
+
+    /// the object key of the parquet file with the sequence
+    pub fn parquet_object_key(
+        object_key_prefix: &str,
+        file_sequence: u64,
+        compression: Compression,
+    ) -> String {
+        format!(
+            "{}/{}",
+            object_key_prefix,
+            arrow_parquet_utils::parquet_file_name(file_sequence, compression)
+        )
+    }
+
+    /// the codec of the parquet files written by the cell
+    fn file_compression(&self) -> Compression {
+        self.config
+            .writer_properties
+            .compression(&ColumnPath::new(vec![]))
+    }
+
+    #[inline(always)]
+    pub fn get_retention_ms(&self) -> u64 {
+        self.retention_ms.load(Ordering::Relaxed)
+    }
+
+    /// the rows older than the retention are expired by the next expiry, 0 means never
+    pub fn set_retention_ms(&self, retention_ms: u64) {
+        self.retention_ms.store(retention_ms, Ordering::Relaxed);
+    }
+
+    #[inline(always)]
+    pub fn is_replica(&self) -> bool {
+        self.replica.load(Ordering::Relaxed)
+    }
+
+    /// change the role of cell, a replica to be the primary is promoted with promote which
+    /// takes over the batches it keeps
+    pub fn set_replica(&self, replica: bool) {
+        if self.replica.swap(replica, Ordering::Relaxed) && !replica {
+            // the primary may have committed files after the manifest was loaded
+            self.manifest_stale.store(true, Ordering::Release);
+        }
+    }
+
+    /// the sequence of parquet file from its object key
+    fn parquet_file_sequence(object_key: &str) -> Option<u64> {
+        object_key
+            .rsplit('/')
+            .next()
+            .and_then(|name| name.split('.').next())
+            .and_then(|seq| seq.parse::<u64>().ok())
+    }
+
+    /// continue the file counter after the parquet files in object storage, a cell opened on
+    /// a new node or promoted from replica has no checkpoint of the files uploaded by others
+    pub async fn seed_parquet_file_counter(&self) -> Result<()> {
+        let prefix = format!("{}/", self.config.object_key_prefix);
+        let next = self
+            .bucket_fs
+            .list_keys(&prefix)
+            .await?
+            .iter()
+            .filter_map(|key| Self::parquet_file_sequence(key))
+            .max()
+            .map_or(0, |seq| seq + 1);
+        let previous = self.parquet_file_counter.fetch_max(next, Ordering::Relaxed);
+        info!(
+            "seed parquet file counter of {} to {}",
+            &self.config.object_key_prefix,
+            std::cmp::max(previous, next)
+        );
+        Ok(())
+    }
+
+    pub fn get_group_commit_metrics(&self) -> &GroupCommitMetrics {
+        &self.group_commit_metrics
+    }
+
+    #[inline(always)]
+    pub fn get_binlog_data_size(&self) -> u64 {
+        self.binlog_data_size.load(Ordering::Relaxed)
+    }
+
+    /// the total rows in memory and on external storage
+    pub fn get_total_rows(&self) -> u64 {
+        self.total_rows_in_memory.load(Ordering::Relaxed)
+            + self.total_rows_on_external_storage.load(Ordering::Relaxed)
+    }
+
+    /// the min and max value of range column of the rows written after the cell was opened
+    pub fn get_range_keys(&self) -> Option<(i64, i64)> {
+        let min = self.min_range_key.load(Ordering::Relaxed);
+        let max = self.max_range_key.load(Ordering::Relaxed);
+        if min <= max {
+            Some((min, max))
+        } else {
+            None
+        }
+    }
+
+    #[inline(always)]
+    pub fn get_total_rows_in_memory(&self) -> u64 {
+        self.total_rows_in_memory.load(Ordering::Relaxed)
+    }
+
+    /// the estimated size in bytes of the rows in memory
+    #[inline(always)]
+    pub fn get_memory_size_in_bytes(&self) -> u64 {
+        self.total_data_in_memory.load(Ordering::Relaxed)
+    }
+
+    fn estimate_row_bytes(&self, rows: u64) -> u64 {
+        rows * (self.config.schema.fields().len() * std::mem::size_of::<Data>()) as u64
+    }
+
+    fn get_batch_bytes(batch: &RecordBatch) -> u64 {
+        batch
+            .columns()
+            .iter()
+            .map(|array| array.get_array_memory_size() as u64)
+            .sum()
+    }
+
+    fn charge_memory(&self, bytes: u64) {
+        self.total_data_in_memory
+            .fetch_add(bytes, Ordering::Relaxed);
+        if let Some(budget) = &self.config.memory_budget {
+            budget.charge(bytes);
+        }
+    }
+
+    fn release_memory(&self, bytes: u64) {
+        let _ =
+            self.total_data_in_memory
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                    Some(used.saturating_sub(bytes))
+                });
+        if let Some(budget) = &self.config.memory_budget {
+            budget.release(bytes);
+        }
+    }
+}
+
+impl Drop for CellStore {
+    /// a removed cell gives its memory back to the node
+    fn drop(&mut self) {
+        if let Some(budget) = &self.config.memory_budget {
+            budget.release(self.total_data_in_memory.load(Ordering::Relaxed));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::row_codec::{encode, Data};
+    use arrow::datatypes::Schema;
+    use arrow::datatypes::*;
+
+    use crate::store::object_store::build_credentials;
+    #[test]
+    fn test_invalid_config() {
+        let valid_schema = Arc::new(Schema::new(vec![Field::new("c1", DataType::Int64, true)]));
+        let auth = Credentials::from_env_specific(
+            Some("AWS_ACCESS_KEY_ID"),
+            Some("AWS_SECRET_ACCESS_KEY"),
+            None,
+            None,
+        )
+        .unwrap();
+        let bucket_name = "test_bk";
+        let region = Region::Custom {
+            region: "".to_string(),
+            endpoint: "http://127.0.0.1:9000".to_string(),
+        };
+
+        let schema = Arc::new(Schema::empty());
+        let local_binlog_path_prefix = "/test/binlog";
+        let tmp_dir_path_prefix = "/test/tmp";
+        let object_key_prefix = "/test/object";
+        if CellStoreConfig::new(
+            bucket_name,
+            region.clone(),
+            &schema,
+            local_binlog_path_prefix,
+            auth.clone(),
+            tmp_dir_path_prefix,
+            object_key_prefix,
+            BinlogDurability::KNone,
+        )
+        .is_ok()
+        {
+            panic!("should has some config error");
+        }
+
+        if CellStoreConfig::new(
+            "",
             region.clone(),
             &valid_schema,
             local_binlog_path_prefix,
             auth.clone(),
             tmp_dir_path_prefix,
             object_key_prefix,
-            false,
+            BinlogDurability::KNone,
         )
         .is_ok()
         {
@@ -372,7 +2317,7 @@ mod tests {
             auth.clone(),
             tmp_dir_path_prefix,
             object_key_prefix,
-            false,
+            BinlogDurability::KNone,
         )
         .is_ok()
         {
@@ -399,10 +2344,29 @@ mod tests {
             auth,
             tmp_dir_path_prefix,
             object_key_prefix,
-            false,
+            BinlogDurability::KNone,
         )
     }
 
+    #[test]
+    fn test_parquet_file_sequence() {
+        assert_eq!(
+            Some(12),
+            CellStore::parquet_file_sequence("db1/t1/0/00000012.gz.parquet")
+        );
+        assert_eq!(
+            Some(0),
+            CellStore::parquet_file_sequence("00000000.gz.parquet")
+        );
+        assert_eq!(None, CellStore::parquet_file_sequence("db1/t1/0/MANIFEST"));
+        let object_key = CellStore::parquet_object_key("db1/t1/0", 3, Compression::ZSTD);
+        assert_eq!("db1/t1/0/00000003.zstd.parquet", object_key);
+        assert_eq!(Some(3), CellStore::parquet_file_sequence(&object_key));
+        let object_key = CellStore::parquet_object_key("db1/t1/0", 4, Compression::UNCOMPRESSED);
+        assert_eq!("db1/t1/0/00000004.parquet", object_key);
+        assert_eq!(Some(4), CellStore::parquet_file_sequence(&object_key));
+    }
+
     #[test]
     fn test_normal_config() {
         if gen_a_normal_config().is_err() {
@@ -412,7 +2376,9 @@ mod tests {
 
     #[test]
     fn test_init_cell_store() {
-        let config = gen_a_normal_config().unwrap();
+        let mut config = gen_a_normal_config().unwrap();
+        let tmp_dir_path = TempDir::new("init_cell_store").expect("create temp dir");
+        config.local_binlog_path_prefix = tmp_dir_path.path().to_str().unwrap().to_string();
         if CellStore::new(config).is_err() {
             panic!("should be ok");
         }
@@ -426,7 +2392,7 @@ mod tests {
             config.local_binlog_path_prefix = tmp_dir_path_str.to_string();
             if let Ok(c) = CellStore::new(config) {
                 let batch = gen_sample_row_batch();
-                if c.put_records(batch).await.is_err() {
+                if c.put_records(batch, 0, 0).await.is_err() {
                     panic!("should be ok")
                 }
                 assert_eq!(3, c.get_total_rows_in_memory());
@@ -439,6 +2405,459 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_recover_from_binlog() -> Result<()> {
+        let mut config = gen_a_normal_config()?;
+        let tmp_dir_path = TempDir::new("recover_binlog").expect("create temp dir");
+        config.local_binlog_path_prefix = tmp_dir_path.path().to_str().unwrap().to_string();
+        config.durability = BinlogDurability::KBuffered;
+        {
+            let c = CellStore::new(config.clone())?;
+            for _ in 0..10 {
+                c.put_records(gen_sample_row_batch(), 0, 0).await?;
+            }
+            assert_eq!(30, c.get_total_rows_in_memory());
+        }
+        let c = CellStore::new(config.clone())?;
+        assert_eq!(30, c.get_total_rows_in_memory());
+        assert_eq!(30, c.get_total_rows());
+        c.put_records(gen_sample_row_batch(), 0, 0).await?;
+        drop(c);
+        let c = CellStore::new(config.clone())?;
+        assert_eq!(33, c.get_total_rows_in_memory());
+        // the segment under the size limit is written again after the restarts
+        assert_eq!(
+            vec![0],
+            CellStore::list_log_numbers(&config.local_binlog_path_prefix)?
+        );
+        let snapshot = c.get_memory_batch_snapshot()?;
+        assert_eq!(
+            33,
+            snapshot.batches.iter().map(|b| b.num_rows()).sum::<usize>()
+        );
+        // the manifest has not been loaded from object storage
+        assert!(snapshot.file_sequence.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retried_batch() -> Result<()> {
+        let mut config = gen_a_normal_config()?;
+        let tmp_dir_path = TempDir::new("retried_batch").expect("create temp dir");
+        config.local_binlog_path_prefix = tmp_dir_path.path().to_str().unwrap().to_string();
+        config.durability = BinlogDurability::KBuffered;
+        {
+            let c = CellStore::new(config.clone())?;
+            assert_eq!(1, c.put_records(gen_sample_row_batch(), 9, 0).await?);
+            // the retry gets the sequence of the first try
+            assert_eq!(1, c.put_records(gen_sample_row_batch(), 9, 0).await?);
+            assert_eq!(2, c.put_records(gen_sample_row_batch(), 0, 0).await?);
+            assert_eq!(3, c.put_records(gen_sample_row_batch(), 0, 0).await?);
+            assert_eq!(9, c.get_total_rows_in_memory());
+        }
+        // the ids and the sequences of the replayed batches are remembered
+        let c = CellStore::new(config)?;
+        assert_eq!(9, c.get_total_rows_in_memory());
+        assert_eq!(1, c.put_records(gen_sample_row_batch(), 9, 0).await?);
+        assert_eq!(9, c.get_total_rows_in_memory());
+        assert_eq!(4, c.put_records(gen_sample_row_batch(), 0, 0).await?);
+        // the records written before the batches had ids
+        let record = decode_binlog_record(&encode(&gen_sample_row_batch())?)?;
+        assert_eq!(gen_sample_row_batch(), record.records);
+        assert_eq!(0, record.batch_id);
+        assert_eq!(0, record.sequence);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rotate_binlog() -> Result<()> {
+        let mut config = gen_a_normal_config()?;
+        let tmp_dir_path = TempDir::new("rotate_binlog").expect("create temp dir");
+        config.local_binlog_path_prefix = tmp_dir_path.path().to_str().unwrap().to_string();
+        config.durability = BinlogDurability::KBuffered;
+        config.set_binlog_segment_size(1024);
+        {
+            let c = CellStore::new(config.clone())?;
+            for _ in 0..100 {
+                c.put_records(gen_sample_row_batch(), 0, 0).await?;
+            }
+            assert!(c.get_binlog_data_size() > 0);
+        }
+        let log_numbers = CellStore::list_log_numbers(&config.local_binlog_path_prefix)?;
+        assert!(log_numbers.len() > 1);
+        let c = CellStore::new(config)?;
+        assert_eq!(300, c.get_total_rows_in_memory());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_group_commit() -> Result<()> {
+        let mut config = gen_a_normal_config()?;
+        let tmp_dir_path = TempDir::new("group_commit").expect("create temp dir");
+        config.local_binlog_path_prefix = tmp_dir_path.path().to_str().unwrap().to_string();
+        config.durability = BinlogDurability::KGroupCommit;
+        config.set_group_commit_interval_ms(5);
+        {
+            let c = Arc::new(CellStore::new(config.clone())?);
+            let mut handles = Vec::new();
+            for _ in 0..10 {
+                let local_c = c.clone();
+                handles.push(tokio::spawn(async move {
+                    local_c.put_records(gen_sample_row_batch(), 0, 0).await
+                }));
+            }
+            for handle in handles {
+                assert!(handle.await.unwrap().is_ok());
+            }
+            assert_eq!(30, c.get_total_rows_in_memory());
+            let metrics = c.get_group_commit_metrics();
+            assert_eq!(10, metrics.get_batch_count());
+            assert!(metrics.get_group_count() <= 10);
+            assert!(metrics.get_max_group_size() >= 1);
+        }
+        let c = CellStore::new(config)?;
+        assert_eq!(30, c.get_total_rows_in_memory());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_range_keys() -> Result<()> {
+        let mut config = gen_a_normal_config()?;
+        let tmp_dir_path = TempDir::new("range_keys").expect("create temp dir");
+        config.local_binlog_path_prefix = tmp_dir_path.path().to_str().unwrap().to_string();
+        config.set_range_column_index(0);
+        let c = CellStore::new(config)?;
+        assert!(c.get_range_keys().is_none());
+        c.put_records(gen_sample_row_batch(), 0, 0).await?;
+        assert_eq!(Some((10, 12)), c.get_range_keys());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fence_and_flush() -> Result<()> {
+        let mut config = gen_a_normal_config()?;
+        let tmp_dir_path = TempDir::new("fence_and_flush").expect("create temp dir");
+        config.local_binlog_path_prefix = tmp_dir_path.path().to_str().unwrap().to_string();
+        config.object_key_prefix = format!(
+            "test/fence/{}",
+            tmp_dir_path.path().file_name().unwrap().to_str().unwrap()
+        );
+        let c = CellStore::new(config)?;
+        c.create_bucket().await?;
+        assert_eq!(0, c.get_unflushed_age_ms());
+        c.put_records(gen_sample_row_batch(), 0, 0).await?;
+        assert_eq!(3, c.get_total_rows_in_memory());
+        assert!(c.get_unflushed_age_ms() > 0);
+        c.fence_and_flush().await?;
+        assert!(c.is_fenced());
+        assert_eq!(0, c.get_total_rows_in_memory());
+        assert_eq!(0, c.get_unflushed_age_ms());
+        assert!(matches!(
+            c.put_records(gen_sample_row_batch(), 0, 0).await,
+            Err(DB3Error::CellStoreFencedError)
+        ));
+        c.unfence();
+        c.put_records(gen_sample_row_batch(), 0, 0).await?;
+        assert_eq!(3, c.get_total_rows_in_memory());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_inherit_files() -> Result<()> {
+        let tmp_dir_path = TempDir::new("inherit_files").expect("create temp dir");
+        let tmp_dir_str = tmp_dir_path.path().to_str().unwrap();
+        // the objects of the last run must not be inherited
+        let prefix = format!(
+            "test/inherit/{}",
+            tmp_dir_path.path().file_name().unwrap().to_str().unwrap()
+        );
+        let mut cells: Vec<CellStore> = Vec::new();
+        for pid in 0..2 {
+            let mut config = gen_a_normal_config()?;
+            config.local_binlog_path_prefix = format!("{}/{}", tmp_dir_str, pid);
+            config.object_key_prefix = format!("{}/{}", prefix, pid);
+            let c = CellStore::new(config)?;
+            c.create_bucket().await?;
+            c.load_manifest().await?;
+            cells.push(c);
+        }
+        cells[0].put_records(gen_sample_row_batch(), 0, 0).await?;
+        cells[0].flush().await?;
+        let sealed_prefix = format!("{}/0", prefix);
+        assert_eq!(1, cells[1].inherit_files(&sealed_prefix).await?);
+        assert_eq!(3, cells[1].get_total_rows());
+        assert_eq!(0, cells[1].inherit_files(&sealed_prefix).await?);
+        let files = cells[1].read_manifest().await?.files;
+        assert_eq!(1, files.len());
+        assert!(files[0].cell_store_uri.contains(&sealed_prefix));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_recover_pending_flush() -> Result<()> {
+        let mut config = gen_a_normal_config()?;
+        let tmp_dir_path = TempDir::new("pending_flush").expect("create temp dir");
+        config.local_binlog_path_prefix = tmp_dir_path.path().to_str().unwrap().to_string();
+        config.durability = BinlogDurability::KBuffered;
+        config.object_key_prefix = format!(
+            "test/pending/{}",
+            tmp_dir_path.path().file_name().unwrap().to_str().unwrap()
+        );
+        {
+            let c = CellStore::new(config.clone())?;
+            c.create_bucket().await?;
+            c.load_manifest().await?;
+            c.put_records(gen_sample_row_batch(), 0, 0).await?;
+            c.flush().await?;
+            c.put_records(gen_sample_row_batch(), 0, 0).await?;
+        }
+        let checkpoint_path = format!(
+            "{}/{}",
+            config.local_binlog_path_prefix, CHECKPOINT_FILE_NAME
+        );
+        let flushed = CellStore::load_checkpoint(&config.local_binlog_path_prefix)?;
+        // the checkpoint before the manifest put, the file of the flush may be committed or not
+        let save_pending = |file_sequence: u64| {
+            let checkpoint = CellCheckpoint {
+                pending_flush: Some(PendingFlush {
+                    position: flushed.flushed_position,
+                    file_sequence,
+                }),
+                ..Default::default()
+            };
+            fs::write(&checkpoint_path, bincode::serialize(&checkpoint).unwrap())
+        };
+        save_pending(0)?;
+        let c = CellStore::new(config.clone())?;
+        assert_eq!(6, c.get_total_rows_in_memory());
+        c.load_manifest().await?;
+        // the rows of the committed file are not replayed again
+        assert_eq!(3, c.get_total_rows_in_memory());
+        assert_eq!(6, c.get_total_rows());
+        drop(c);
+        save_pending(1)?;
+        let c = CellStore::new(config)?;
+        c.load_manifest().await?;
+        // the file is not committed, so the rows are flushed again
+        assert_eq!(6, c.get_total_rows_in_memory());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_seed_manifest_from_listing() -> Result<()> {
+        let mut config = gen_a_normal_config()?;
+        let tmp_dir_path = TempDir::new("seed_manifest").expect("create temp dir");
+        config.object_key_prefix = format!(
+            "test/seed/{}",
+            tmp_dir_path.path().file_name().unwrap().to_str().unwrap()
+        );
+        config.local_binlog_path_prefix = format!("{}/0", tmp_dir_path.path().to_str().unwrap());
+        let c = CellStore::new(config.clone())?;
+        c.create_bucket().await?;
+        c.load_manifest().await?;
+        c.put_records(gen_sample_row_batch(), 0, 0).await?;
+        c.flush().await?;
+        // the files uploaded before manifests are kept
+        c.bucket_fs.delete_object(&c.manifest_key()).await?;
+        config.local_binlog_path_prefix = format!("{}/1", tmp_dir_path.path().to_str().unwrap());
+        let c = CellStore::new(config)?;
+        c.load_manifest().await?;
+        let manifest = c.read_manifest().await?;
+        assert_eq!(1, manifest.file_sequence);
+        assert_eq!(1, manifest.files.len());
+        assert_eq!(3, manifest.files[0].num_rows);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_merge_small_files() -> Result<()> {
+        let mut config = gen_a_normal_config()?;
+        let tmp_dir_path = TempDir::new("merge_small_files").expect("create temp dir");
+        config.local_binlog_path_prefix = tmp_dir_path.path().to_str().unwrap().to_string();
+        config.object_key_prefix = format!(
+            "test/merge/{}",
+            tmp_dir_path.path().file_name().unwrap().to_str().unwrap()
+        );
+        let budget = Arc::new(MemoryBudget::new(1024 * 1024 * 1024));
+        config.set_memory_budget(budget.clone());
+        let c = CellStore::new(config.clone())?;
+        c.create_bucket().await?;
+        c.load_manifest().await?;
+        for _ in 0..3 {
+            c.put_records(gen_sample_row_batch(), 0, 0).await?;
+            c.flush().await?;
+        }
+        let merged_keys = c.merge_small_files(1024 * 1024, 2, 1024 * 1024).await?;
+        assert_eq!(3, merged_keys.len());
+        // the memory of merging is given back
+        assert_eq!(0, budget.get_used_bytes());
+        let manifest = c.read_manifest().await?;
+        assert_eq!(1, manifest.files.len());
+        assert_eq!(9, manifest.files[0].num_rows);
+        assert_eq!(3, manifest.obsolete_files.len());
+        // the obsolete files are kept for the running scans
+        assert_eq!(0, c.collect_obsolete_files(60 * 1000).await?);
+        // a restarted cell collects the obsolete files left in the manifest
+        drop(c);
+        let c = CellStore::new(config)?;
+        c.load_manifest().await?;
+        assert_eq!(3, c.collect_obsolete_files(0).await?);
+        assert!(c.read_manifest().await?.obsolete_files.is_empty());
+        for key in merged_keys {
+            assert!(c.bucket_fs.get_object(&key).await?.is_none());
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_memory_budget() -> Result<()> {
+        let mut config = gen_a_normal_config()?;
+        let tmp_dir_path = TempDir::new("memory_budget").expect("create temp dir");
+        config.local_binlog_path_prefix = tmp_dir_path.path().to_str().unwrap().to_string();
+        config.object_key_prefix = format!(
+            "test/budget/{}",
+            tmp_dir_path.path().file_name().unwrap().to_str().unwrap()
+        );
+        let budget = Arc::new(MemoryBudget::new(1));
+        config.set_memory_budget(budget.clone());
+        {
+            let c = CellStore::new(config)?;
+            c.create_bucket().await?;
+            c.put_records(gen_sample_row_batch(), 0, 0).await?;
+            assert_eq!(c.get_memory_size_in_bytes(), budget.get_used_bytes());
+            assert!(matches!(
+                c.put_records(gen_sample_row_batch(), 0, 0).await,
+                Err(DB3Error::CellStoreThrottledError(_))
+            ));
+            c.flush().await?;
+            assert_eq!(0, c.get_memory_size_in_bytes());
+            assert_eq!(0, budget.get_used_bytes());
+            c.put_records(gen_sample_row_batch(), 0, 0).await?;
+            assert!(budget.is_exceeded());
+        }
+        // a dropped cell gives back its memory
+        assert_eq!(0, budget.get_used_bytes());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_replica_trim() -> Result<()> {
+        let tmp_dir_path = TempDir::new("replica_trim").expect("create temp dir");
+        let tmp_dir_str = tmp_dir_path.path().to_str().unwrap();
+        let mut primary_config = gen_a_normal_config()?;
+        primary_config.local_binlog_path_prefix = format!("{}/primary", tmp_dir_str);
+        primary_config.object_key_prefix = format!(
+            "test/replica/{}",
+            tmp_dir_path.path().file_name().unwrap().to_str().unwrap()
+        );
+        primary_config.durability = BinlogDurability::KBuffered;
+        let mut config = primary_config.clone();
+        config.local_binlog_path_prefix = format!("{}/replica", tmp_dir_str);
+        config.set_replica(true);
+        let primary = CellStore::new(primary_config)?;
+        primary.create_bucket().await?;
+        primary.load_manifest().await?;
+        let last_sequence = {
+            let c = CellStore::new(config.clone())?;
+            // the primary gives the sequences and the replica takes them
+            assert!(matches!(
+                c.put_records(gen_sample_row_batch(), 1, 0).await,
+                Err(DB3Error::CellStoreRoleError(_))
+            ));
+            assert!(matches!(
+                primary.put_records(gen_sample_row_batch(), 1, 5).await,
+                Err(DB3Error::CellStoreRoleError(_))
+            ));
+            let sequence = primary.put_records(gen_sample_row_batch(), 1, 0).await?;
+            assert_eq!(
+                sequence,
+                c.put_records(gen_sample_row_batch(), 1, sequence).await?
+            );
+            // the replica keeps the rows until the primary has flushed them
+            c.do_l2_compaction().await?;
+            assert_eq!(3, c.get_total_rows_in_memory());
+            // a replica added later misses the rows in the memory of primary
+            let mut late_config = config.clone();
+            late_config.local_binlog_path_prefix = format!("{}/late", tmp_dir_str);
+            let late = CellStore::new(late_config)?;
+            assert!(matches!(
+                late.get_partition_snapshot().await,
+                Err(DB3Error::CellStoreRoleError(_))
+            ));
+            primary.flush().await?;
+            let sequence = primary.put_records(gen_sample_row_batch(), 2, 0).await?;
+            c.put_records(gen_sample_row_batch(), 2, sequence).await?;
+            late.put_records(gen_sample_row_batch(), 2, sequence)
+                .await?;
+            let snapshot = late.get_partition_snapshot().await?;
+            assert_eq!(1, snapshot.files.len());
+            assert_eq!(3, c.trim_replica().await?);
+            assert_eq!(3, c.get_total_rows_in_memory());
+            assert_eq!(6, c.get_total_rows());
+            // the rows kept by the replica and the files of the manifest they are trimmed by
+            let snapshot = c.get_partition_snapshot().await?;
+            assert_eq!(Some(1), snapshot.file_sequence);
+            assert_eq!(1, snapshot.files.len());
+            let rows: usize = snapshot.batches.iter().map(|b| b.num_rows()).sum();
+            assert_eq!(3, rows);
+            sequence
+        };
+        // the trimmed rows are not replayed
+        let c = CellStore::new(config)?;
+        assert_eq!(3, c.get_total_rows_in_memory());
+        // the promoted replica flushes the rows which the old primary has not flushed
+        c.promote().await?;
+        assert!(!c.is_replica());
+        assert!(c.put_records(gen_sample_row_batch(), 3, 0).await? > last_sequence);
+        c.flush().await?;
+        assert_eq!(0, c.get_total_rows_in_memory());
+        let manifest = c.read_manifest().await?;
+        assert_eq!(9, manifest.files.iter().map(|f| f.num_rows).sum::<i64>());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_expire_rows() -> Result<()> {
+        let mut config = gen_a_normal_config()?;
+        let tmp_dir_path = TempDir::new("expire_rows").expect("create temp dir");
+        config.local_binlog_path_prefix = tmp_dir_path.path().to_str().unwrap().to_string();
+        config.schema = Arc::new(Schema::new(vec![
+            Field::new("c1", DataType::Int64, true),
+            Field::new("ts", DataType::Timestamp(TimeUnit::Millisecond, None), true),
+        ]));
+        config.set_l1_rows_limit(3);
+        config.set_replica(true);
+        config.set_retention_ms(3600 * 1000);
+        config.durability = BinlogDurability::KBuffered;
+        let c = CellStore::new(config.clone())?;
+        let now = CellStore::now_ms() as i64;
+        let batch = vec![
+            vec![Data::Int64(1), Data::Timestamp(now - 7200 * 1000)],
+            vec![Data::Int64(2), Data::Timestamp(now - 3700 * 1000)],
+            vec![Data::Int64(3), Data::Timestamp(now)],
+        ];
+        c.put_records(
+            RowRecordBatch {
+                batch,
+                schema_version: 1,
+            },
+            0,
+            1,
+        )
+        .await?;
+        assert_eq!(3, c.get_total_rows_in_memory());
+        assert!(c.expire_data().await?.is_empty());
+        assert_eq!(1, c.get_total_rows_in_memory());
+        let snapshot = c.get_memory_batch_snapshot()?;
+        let rows: usize = snapshot.batches.iter().map(|b| b.num_rows()).sum();
+        assert_eq!(1, rows);
+        // the evicted rows are not replayed after a restart
+        drop(c);
+        let c = CellStore::new(config)?;
+        assert_eq!(1, c.get_total_rows_in_memory());
+        Ok(())
+    }
+
     fn gen_sample_row_batch() -> RowRecordBatch {
         let batch = vec![
             vec![Data::Int64(12)],
@@ -453,11 +2872,13 @@ mod tests {
 
     #[tokio::test]
     async fn test_l1_compaction() {
-        let config = gen_a_normal_config().unwrap();
+        let mut config = gen_a_normal_config().unwrap();
+        let tmp_dir_path = TempDir::new("l1_compaction").expect("create temp dir");
+        config.local_binlog_path_prefix = tmp_dir_path.path().to_str().unwrap().to_string();
         if let Ok(c) = CellStore::new(config) {
             for _ in 1..20480 {
                 let batch = gen_sample_row_batch();
-                if c.put_records(batch).await.is_err() {
+                if c.put_records(batch, 0, 0).await.is_err() {
                     panic!("should be ok")
                 }
             }
@@ -470,7 +2891,9 @@ mod tests {
 
     #[tokio::test]
     async fn test_l2_compaction() {
-        let config = gen_a_normal_config().unwrap();
+        let mut config = gen_a_normal_config().unwrap();
+        let tmp_dir_path = TempDir::new("l2_compaction").expect("create temp dir");
+        config.local_binlog_path_prefix = tmp_dir_path.path().to_str().unwrap().to_string();
         if let Ok(c) = CellStore::new(config) {
             if let Err(e) = c.create_bucket().await {
                 panic!("should not be here {}", e);
@@ -478,7 +2901,7 @@ mod tests {
             let range_end = 1024 * 10 * 20;
             for _ in 1..range_end {
                 let batch = gen_sample_row_batch();
-                if c.put_records(batch).await.is_err() {
+                if c.put_records(batch, 0, 0).await.is_err() {
                     panic!("should be ok")
                 }
             }
@@ -0,0 +1,130 @@
+//
+//
+// memory_budget.rs
+// Copyright (C) 2022 db3.network Author imotai <codego.me@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+// flushes start early when the usage reaches this percent of limit
+const EARLY_FLUSH_PERCENT: u64 = 80;
+
+/// the memory shared by all the cells of a memory node, every cell charges the bytes of its
+/// rows in memory and releases them after flushing
+pub struct MemoryBudget {
+    limit_bytes: u64,
+    used_bytes: AtomicU64,
+}
+
+impl MemoryBudget {
+    pub fn new(limit_bytes: u64) -> Self {
+        Self {
+            limit_bytes,
+            used_bytes: AtomicU64::new(0),
+        }
+    }
+
+    pub fn charge(&self, bytes: u64) {
+        self.used_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn release(&self, bytes: u64) {
+        let _ = self
+            .used_bytes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                Some(used.saturating_sub(bytes))
+            });
+    }
+
+    #[inline(always)]
+    pub fn get_used_bytes(&self) -> u64 {
+        self.used_bytes.load(Ordering::Relaxed)
+    }
+
+    #[inline(always)]
+    pub fn get_limit_bytes(&self) -> u64 {
+        self.limit_bytes
+    }
+
+    /// the usage above which cells should be flushed whatever their own triggers
+    pub fn get_early_flush_bytes(&self) -> u64 {
+        self.limit_bytes / 100 * EARLY_FLUSH_PERCENT
+    }
+
+    /// writes are throttled when the budget is used up
+    pub fn is_exceeded(&self) -> bool {
+        self.get_used_bytes() >= self.limit_bytes
+    }
+}
+
+/// the bytes charged to a budget by the work outside memtables, eg. merging files, they are
+/// released when it is dropped
+pub struct MemoryCharge {
+    budget: Option<Arc<MemoryBudget>>,
+    bytes: u64,
+}
+
+impl MemoryCharge {
+    pub fn new(budget: Option<Arc<MemoryBudget>>) -> Self {
+        Self { budget, bytes: 0 }
+    }
+
+    pub fn charge(&mut self, bytes: u64) {
+        if let Some(budget) = &self.budget {
+            budget.charge(bytes);
+            self.bytes += bytes;
+        }
+    }
+}
+
+impl Drop for MemoryCharge {
+    fn drop(&mut self) {
+        if let Some(budget) = &self.budget {
+            budget.release(self.bytes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_charge_and_release() {
+        let budget = MemoryBudget::new(1000);
+        assert_eq!(800, budget.get_early_flush_bytes());
+        budget.charge(600);
+        assert!(!budget.is_exceeded());
+        budget.charge(400);
+        assert!(budget.is_exceeded());
+        budget.release(500);
+        assert_eq!(500, budget.get_used_bytes());
+        budget.release(600);
+        assert_eq!(0, budget.get_used_bytes());
+    }
+
+    #[test]
+    fn test_memory_charge() {
+        let budget = Arc::new(MemoryBudget::new(1000));
+        {
+            let mut charge = MemoryCharge::new(Some(budget.clone()));
+            charge.charge(300);
+            charge.charge(200);
+            assert_eq!(500, budget.get_used_bytes());
+        }
+        assert_eq!(0, budget.get_used_bytes());
+    }
+}
@@ -19,12 +19,20 @@
 use crate::error::{DB3Error, Result};
 use crate::proto::db3_base_proto::{Db3Database, Db3Node, Db3NodeType, Db3TableDesc};
 use bytes::{Bytes, BytesMut};
-use etcd_client::{Client, GetOptions, WatchOptions, WatchStream};
+use etcd_client::{
+    Client, Compare, CompareOp, GetOptions, PutOptions, Txn, TxnOp, WatchOptions, WatchStream,
+};
 use prost::Message;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::sleep;
 uselog!(info, warn);
 
 const BUFFER_SIZE: usize = 4 * 1024;
+// a node is removed from etcd if it does not keep its lease alive in the ttl
+const NODE_LEASE_TTL_SECS: i64 = 10;
+const NODE_LEASE_KEEP_ALIVE_INTERVAL_SECS: u64 = 3;
 
 pub enum MetaStoreType {
     ImmutableMetaStore,
@@ -39,6 +47,8 @@ pub struct MetaStoreConfig {
 pub struct MetaStore {
     config: MetaStoreConfig,
     client: Arc<Client>,
+    // the key of registered node -> its lease
+    node_leases: Arc<Mutex<HashMap<String, i64>>>,
 }
 
 unsafe impl Send for MetaStore {}
@@ -50,6 +60,7 @@ impl MetaStore {
         Self {
             config,
             client: Arc::new(client),
+            node_leases: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -70,34 +81,189 @@ impl MetaStore {
         }
     }
 
+    fn table_key(&self, db: &str, name: &str) -> String {
+        format!("{}/tables/{}_{}", self.config.root_path, db, name)
+    }
+
+    fn encode_table(table_desc: &Db3TableDesc) -> Result<Bytes> {
+        let mut buf = BytesMut::with_capacity(BUFFER_SIZE);
+        if let Err(e) = table_desc.encode(&mut buf) {
+            return Err(DB3Error::MetaRpcCreateTableError {
+                err: format!(
+                    "encode descriptor of table {} with err {} ",
+                    table_desc.name, e
+                ),
+            });
+        }
+        Ok(buf.freeze())
+    }
+
     pub async fn add_table(&self, table_desc: &Db3TableDesc) -> Result<()> {
         if let MetaStoreType::MutableMetaStore = self.config.store_type {
-            let key = format!(
-                "{}/tables/{}_{}",
-                self.config.root_path, table_desc.db, table_desc.name
-            );
+            let key = self.table_key(&table_desc.db, &table_desc.name);
             info!("add table with key {}", &key);
-            let mut buf = BytesMut::with_capacity(BUFFER_SIZE);
-            if let Err(e) = table_desc.encode(&mut buf) {
-                return Err(DB3Error::MetaRpcCreateTableError {
-                    err: format!(
-                        "encode descriptor of table {} with err {} ",
-                        table_desc.name, e
-                    ),
-                });
-            }
-            let buf = buf.freeze();
+            let buf = Self::encode_table(table_desc)?;
             self._put(key.as_bytes(), buf.as_ref()).await
         } else {
             Err(DB3Error::MetaStoreTypeMisatchErr)
         }
     }
 
-    pub async fn add_node(&self, node: &Db3Node) -> Result<()> {
-        let key = format!(
+    /// get a table with the revision of its last modification
+    pub async fn get_table(&self, db: &str, name: &str) -> Result<(Db3TableDesc, i64)> {
+        let key = self.table_key(db, name);
+        let mut kv_client = self.client.kv_client();
+        let resp = kv_client.get(key.as_bytes(), None).await?;
+        match resp.kvs().first() {
+            Some(kv) => {
+                let buf = Bytes::from(kv.value().to_vec());
+                let table_desc = Db3TableDesc::decode(buf)
+                    .map_err(|e| DB3Error::EtcdCodecError(format!("decode table err {}", e)))?;
+                Ok((table_desc, kv.mod_revision()))
+            }
+            None => Err(DB3Error::TableNotFoundError {
+                tname: name.to_string(),
+            }),
+        }
+    }
+
+    /// update a table only if it has not been modified since the revision, return false if
+    /// the table has been changed by others
+    pub async fn update_table(&self, table_desc: &Db3TableDesc, mod_revision: i64) -> Result<bool> {
+        if let MetaStoreType::MutableMetaStore = self.config.store_type {
+            let key = self.table_key(&table_desc.db, &table_desc.name);
+            let buf = Self::encode_table(table_desc)?;
+            let txn = Txn::new()
+                .when(vec![Compare::mod_revision(
+                    key.as_bytes(),
+                    CompareOp::Equal,
+                    mod_revision,
+                )])
+                .and_then(vec![TxnOp::put(key.as_bytes(), buf.as_ref(), None)]);
+            let mut kv_client = self.client.kv_client();
+            let resp = kv_client.txn(txn).await?;
+            info!(
+                "update table with key {} and revision {} result {}",
+                &key,
+                mod_revision,
+                resp.succeeded()
+            );
+            Ok(resp.succeeded())
+        } else {
+            Err(DB3Error::MetaStoreTypeMisatchErr)
+        }
+    }
+
+    fn node_key(&self, node: &Db3Node) -> String {
+        format!(
             "{}/nodes_{}/{}_{}",
             self.config.root_path, node.node_type as i32, node.ns, node.port
-        );
+        )
+    }
+
+    fn drained_node_key(&self, node: &Db3Node) -> String {
+        format!(
+            "{}/drained_nodes/{}_{}_{}",
+            self.config.root_path, node.node_type as i32, node.ns, node.port
+        )
+    }
+
+    /// register a node with a lease and keep the lease alive in background, the node is
+    /// removed from etcd automatically after it crashes
+    pub async fn add_node(&self, node: &Db3Node) -> Result<i64> {
+        let key = self.node_key(node);
+        let value = Self::encode_node(node)?;
+        // the node drained in its last run must be failed over if it crashes this time
+        let mut kv_client = self.client.kv_client();
+        kv_client
+            .delete(self.drained_node_key(node).as_bytes(), None)
+            .await?;
+        let lease_id = Self::put_with_lease(&self.client, &key, &value).await?;
+        info!("add node with key {} and lease {}", &key, lease_id);
+        match self.node_leases.lock() {
+            Ok(mut leases) => {
+                leases.insert(key.to_string(), lease_id);
+            }
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+            }
+        }
+        let client = self.client.clone();
+        let node_leases = self.node_leases.clone();
+        tokio::task::spawn(async move {
+            let mut lease_id = lease_id;
+            loop {
+                if let Err(e) = Self::keep_alive(&client, lease_id).await {
+                    warn!("fail to keep lease {} alive with err {}", lease_id, e);
+                }
+                sleep(Duration::from_secs(NODE_LEASE_KEEP_ALIVE_INTERVAL_SECS)).await;
+                // the node has been removed on purpose
+                if !matches!(node_leases.lock(), Ok(leases) if leases.contains_key(&key)) {
+                    info!("stop keeping lease of node with key {}", &key);
+                    break;
+                }
+                // the keepalive may fail when etcd is not reachable for a while, the lease is
+                // kept if it has not expired
+                if matches!(Self::lease_ttl(&client, lease_id).await, Ok(ttl) if ttl > 0) {
+                    continue;
+                }
+                // the lease is lost, the old one is revoked before the same key is registered
+                // with a new lease, so a node never holds two leases
+                let mut lease_client = client.lease_client();
+                if let Err(e) = lease_client.revoke(lease_id).await {
+                    info!("fail to revoke lease {} with err {}", lease_id, e);
+                }
+                match Self::put_with_lease(&client, &key, &value).await {
+                    Ok(id) => {
+                        info!("register node with key {} again with lease {}", &key, id);
+                        lease_id = id;
+                        if let Ok(mut leases) = node_leases.lock() {
+                            leases.insert(key.to_string(), id);
+                        }
+                    }
+                    Err(e) => {
+                        warn!("fail to register node with key {} with err {}", &key, e);
+                    }
+                }
+            }
+        });
+        Ok(lease_id)
+    }
+
+    /// deregister a node by revoking its lease, the node will not be registered again
+    pub async fn remove_node(&self, node: &Db3Node) -> Result<()> {
+        let key = self.node_key(node);
+        let lease_id = match self.node_leases.lock() {
+            Ok(mut leases) => leases.remove(&key),
+            Err(_) => {
+                return Err(DB3Error::BaseBusyError("fail to obtain lock".to_string()));
+            }
+        };
+        if let Some(id) = lease_id {
+            let mut lease_client = self.client.lease_client();
+            lease_client.revoke(id).await?;
+            info!("remove node with key {} and lease {}", &key, id);
+        }
+        Ok(())
+    }
+
+    /// mark a node drained before it deregisters, the meta node does not fail over a node which
+    /// has handed all its partitions over
+    pub async fn mark_node_drained(&self, node: &Db3Node) -> Result<()> {
+        let value = Self::encode_node(node)?;
+        self._put(self.drained_node_key(node), value).await
+    }
+
+    /// whether the node has been drained before it left
+    pub async fn is_node_drained(&self, node: &Db3Node) -> Result<bool> {
+        let mut kv_client = self.client.kv_client();
+        let resp = kv_client
+            .get(self.drained_node_key(node).as_bytes(), None)
+            .await?;
+        Ok(!resp.kvs().is_empty())
+    }
+
+    fn encode_node(node: &Db3Node) -> Result<Vec<u8>> {
         let mut buf = BytesMut::with_capacity(BUFFER_SIZE);
         if let Err(e) = node.encode(&mut buf) {
             return Err(DB3Error::EtcdCodecError(format!(
@@ -105,18 +271,111 @@ impl MetaStore {
                 e
             )));
         }
-        let buf = buf.freeze();
-        self._put(key.as_bytes(), buf.as_ref()).await
+        Ok(buf.freeze().to_vec())
+    }
+
+    fn leader_key(&self) -> String {
+        format!("{}/meta_leader", self.config.root_path)
+    }
+
+    /// try to become the leader of meta nodes, return the lease of leader key on success. the
+    /// leader must keep the lease alive with keep_lease_alive
+    pub async fn campaign_leader(&self, node: &Db3Node) -> Result<Option<i64>> {
+        let key = self.leader_key();
+        let value = Self::encode_node(node)?;
+        let mut lease_client = self.client.lease_client();
+        let lease = lease_client.grant(NODE_LEASE_TTL_SECS, None).await?;
+        let options = PutOptions::new().with_lease(lease.id());
+        let txn = Txn::new()
+            .when(vec![Compare::create_revision(
+                key.as_bytes(),
+                CompareOp::Equal,
+                0,
+            )])
+            .and_then(vec![TxnOp::put(key.as_bytes(), value, Some(options))]);
+        let mut kv_client = self.client.kv_client();
+        let resp = kv_client.txn(txn).await?;
+        if resp.succeeded() {
+            info!(
+                "become the leader with key {} and lease {}",
+                &key,
+                lease.id()
+            );
+            Ok(Some(lease.id()))
+        } else {
+            lease_client.revoke(lease.id()).await?;
+            Ok(None)
+        }
+    }
+
+    /// give up the leadership by revoking the lease of leader key
+    pub async fn resign_leader(&self, lease_id: i64) -> Result<()> {
+        let mut lease_client = self.client.lease_client();
+        lease_client.revoke(lease_id).await?;
+        Ok(())
+    }
+
+    /// the current leader of meta nodes
+    pub async fn get_leader(&self) -> Result<Option<Db3Node>> {
+        let key = self.leader_key();
+        let mut kv_client = self.client.kv_client();
+        let resp = kv_client.get(key.as_bytes(), None).await?;
+        match resp.kvs().first() {
+            Some(kv) => {
+                let buf = Bytes::from(kv.value().to_vec());
+                let node = Db3Node::decode(buf)
+                    .map_err(|e| DB3Error::EtcdCodecError(format!("decode node err {}", e)))?;
+                Ok(Some(node))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// keep the lease alive, it returns when the lease is lost
+    pub async fn keep_lease_alive(&self, lease_id: i64) -> Result<()> {
+        Self::keep_alive(&self.client, lease_id).await
+    }
+
+    async fn put_with_lease(client: &Client, key: &str, value: &[u8]) -> Result<i64> {
+        let mut lease_client = client.lease_client();
+        let lease = lease_client.grant(NODE_LEASE_TTL_SECS, None).await?;
+        let mut kv_client = client.kv_client();
+        let options = PutOptions::new().with_lease(lease.id());
+        kv_client.put(key, value, Some(options)).await?;
+        Ok(lease.id())
+    }
+
+    /// the seconds left of the lease, it is not positive for an expired lease
+    async fn lease_ttl(client: &Client, lease_id: i64) -> Result<i64> {
+        let mut lease_client = client.lease_client();
+        let resp = lease_client.time_to_live(lease_id, None).await?;
+        Ok(resp.ttl())
+    }
+
+    /// keep the lease alive until it expires or etcd is not reachable
+    async fn keep_alive(client: &Client, lease_id: i64) -> Result<()> {
+        let mut lease_client = client.lease_client();
+        let (mut keeper, mut stream) = lease_client.keep_alive(lease_id).await?;
+        loop {
+            keeper.keep_alive().await?;
+            match stream.message().await? {
+                Some(resp) if resp.ttl() > 0 => {}
+                _ => return Err(DB3Error::MetaStoreLeaseExpiredErr(lease_id)),
+            }
+            sleep(Duration::from_secs(NODE_LEASE_KEEP_ALIVE_INTERVAL_SECS)).await;
+        }
     }
 
-    pub async fn get_nodes(&self, node_type: Db3NodeType) -> Result<Vec<Db3Node>> {
+    /// the nodes which keep their leases alive, the keys without lease are left by old
+    /// versions and they are ignored
+    pub async fn get_live_nodes(&self, node_type: Db3NodeType) -> Result<Vec<Db3Node>> {
         let key = format!("{}/nodes_{}/", self.config.root_path, node_type as i32);
         let options = GetOptions::new().with_prefix();
         let mut kv_client = self.client.kv_client();
         match kv_client.get(key.as_bytes(), Some(options)).await {
             Ok(resp) => {
                 let mut nodes: Vec<Db3Node> = Vec::new();
-                for kv in resp.kvs() {
+                for kv in resp.kvs().iter().filter(|kv| kv.lease() != 0) {
                     let buf = Bytes::from(kv.value().to_vec());
                     match Db3Node::decode(buf) {
                         Ok(node) => nodes.push(node),
@@ -230,7 +489,8 @@ impl MetaStore {
     #[inline]
     pub async fn subscribe_node_events(&self, node_type: &Db3NodeType) -> Result<WatchStream> {
         let key = format!("{}/nodes_{}", self.config.root_path, *node_type as i32);
-        let options = WatchOptions::new().with_prefix();
+        // the delete event carries the node with previous kv
+        let options = WatchOptions::new().with_prefix().with_prev_key();
         let mut watch_client = self.client.watch_client();
         let (_, stream) = watch_client.watch(key.to_string(), Some(options)).await?;
         Ok(stream)
@@ -297,9 +557,42 @@ mod tests {
             port: 8989,
         };
         assert!(meta_store.add_node(&db3_node).await.is_ok());
-        let nodes = meta_store.get_nodes(Db3NodeType::KComputeNode).await?;
+        let nodes = meta_store.get_live_nodes(Db3NodeType::KComputeNode).await?;
         assert_eq!(1, nodes.len());
         assert_eq!(db3_node.ns, nodes[0].ns);
+        assert!(!meta_store.is_node_drained(&db3_node).await?);
+        meta_store.mark_node_drained(&db3_node).await?;
+        meta_store.remove_node(&db3_node).await?;
+        let nodes = meta_store.get_live_nodes(Db3NodeType::KComputeNode).await?;
+        assert!(nodes.is_empty());
+        assert!(meta_store.is_node_drained(&db3_node).await?);
+        // the node joins again
+        meta_store.add_node(&db3_node).await?;
+        assert!(!meta_store.is_node_drained(&db3_node).await?);
+        meta_store.remove_node(&db3_node).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_campaign_leader() -> Result<()> {
+        let meta_store = create_meta_store().await?;
+        let db3_node = Db3Node {
+            endpoint: "http://127.0.0.1:9292".to_string(),
+            node_type: Db3NodeType::KMetaNode as i32,
+            ns: "127.0.0.1".to_string(),
+            port: 9292,
+        };
+        let lease_id = meta_store.campaign_leader(&db3_node).await?;
+        assert!(lease_id.is_some());
+        // only one leader at the same time
+        assert!(meta_store.campaign_leader(&db3_node).await?.is_none());
+        let leader = meta_store.get_leader().await?;
+        assert_eq!(
+            Some(db3_node.endpoint.to_string()),
+            leader.map(|n| n.endpoint)
+        );
+        meta_store.resign_leader(lease_id.unwrap()).await?;
+        assert!(meta_store.get_leader().await?.is_none());
         Ok(())
     }
 
@@ -308,6 +601,7 @@ mod tests {
             name: "col1".to_string(),
             ctype: Db3Type::KBigInt as i32,
             null_allowed: true,
+            ..Default::default()
         };
         let schema = Db3SchemaDesc {
             columns: vec![col1],
@@ -320,6 +614,8 @@ mod tests {
             db: db.to_string(),
             ctime: 0,
             mappings: Vec::new(),
+            storage_config: None,
+            retention_ms: 0,
         }
     }
 }
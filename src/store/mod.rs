@@ -20,6 +20,7 @@ use crate::error::{DB3Error, Result};
 use etcd_client::Client;
 uselog!(info);
 pub mod cell_store;
+pub mod memory_budget;
 pub mod meta_store;
 pub mod object_store;
 
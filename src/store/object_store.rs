@@ -150,6 +150,50 @@ impl BucketFileSystem {
         request.response_data(false).await?;
         Ok(())
     }
+
+    /// list the keys of objects with the prefix
+    pub async fn list_keys(&self, prefix: &str) -> Result<Vec<String>> {
+        let results = self.bucket.list(prefix.to_string(), None).await?;
+        Ok(results
+            .into_iter()
+            .flat_map(|r| r.contents.into_iter().map(|o| o.key))
+            .collect())
+    }
+
+    pub async fn put_object(&self, object_key: &str, data: &[u8]) -> Result<()> {
+        self.bucket.put_object(object_key, data).await?;
+        Ok(())
+    }
+
+    pub async fn download(&self, object_key: &str) -> Result<Vec<u8>> {
+        let (data, code) = self.bucket.get_object(object_key).await?;
+        if code != 200 {
+            return Err(DB3Error::StoreS3Error(format!(
+                "fail to get object {} with code {}",
+                object_key, code
+            )));
+        }
+        Ok(data)
+    }
+
+    pub async fn delete_object(&self, object_key: &str) -> Result<()> {
+        self.bucket.delete_object(object_key).await?;
+        Ok(())
+    }
+
+    /// get the content of object, none if the object does not exist
+    pub async fn get_object(&self, object_key: &str) -> Result<Option<Vec<u8>>> {
+        let (data, code) = self.bucket.get_object(object_key).await?;
+        match code {
+            200 => Ok(Some(data)),
+            // the body of 404 is a NoSuchKey error
+            404 => Ok(None),
+            _ => Err(DB3Error::StoreS3Error(format!(
+                "fail to get object {} with code {}",
+                object_key, code
+            ))),
+        }
+    }
 }
 
 struct S3FileReader {